@@ -0,0 +1,30 @@
+use std::process::Command;
+
+/// Run the command and return its trimmed output,
+/// if the command was successful.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|out| out.trim().to_string())
+}
+
+fn main() {
+    // Git SHA can be passed explicitly in case if the
+    // build happens outside of the git repository.
+    let git_sha = std::env::var("ROBOTLB_GIT_SHA")
+        .ok()
+        .or_else(|| command_output("git", &["rev-parse", "--short", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version =
+        command_output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=ROBOTLB_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=ROBOTLB_RUSTC_VERSION={rustc_version}");
+    println!("cargo:rerun-if-env-changed=ROBOTLB_GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{extract::State, routing::post, Json, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use k8s_openapi::{
+    api::core::v1::Service,
+    serde_json::{self, json},
+};
+use kube::core::{
+    admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation},
+    DynamicObject,
+};
+
+use crate::{
+    config::AdmissionWebhookArgs,
+    consts,
+    crds::lb_policy,
+    defaults,
+    error::{RobotLBError, RobotLBResult},
+    is_managed,
+    lb::{self, LoadBalancer},
+    server, CurrentContext,
+};
+
+/// Run the mutating admission webhook until the process is stopped.
+///
+/// Defaults of services are watched, so the annotations are resolved
+/// the same way the operator would resolve them.
+pub async fn run(args: &AdmissionWebhookArgs, context: Arc<CurrentContext>) -> RobotLBResult<()> {
+    // Nobody reconciles services here, so changes of the defaults
+    // don't need to be reported.
+    let (updates, _) = futures::channel::mpsc::channel(1);
+    tokio::spawn(defaults::watch(context.clone(), updates.clone()));
+    if context.config.enable_crds {
+        tokio::spawn(lb_policy::watch(context.clone(), vec![updates]));
+    }
+
+    let tls_config = server::tls_config(&args.tls_cert, &args.tls_key, None)?;
+    let app = Router::new()
+        .route("/mutate", post(mutate))
+        .with_state(context);
+    tracing::info!("Serving admission webhook on {}", args.bind_address);
+    axum_server::bind_rustls(
+        args.bind_address,
+        RustlsConfig::from_config(Arc::new(tls_config)),
+    )
+    .serve(app.into_make_service())
+    .await?;
+    Ok(())
+}
+
+async fn mutate(
+    State(context): State<Arc<CurrentContext>>,
+    Json(review): Json<AdmissionReview<Service>>,
+) -> Json<AdmissionReview<DynamicObject>> {
+    let request: AdmissionRequest<Service> = match review.try_into() {
+        Ok(request) => request,
+        Err(err) => return Json(AdmissionResponse::invalid(err).into_review()),
+    };
+    let mut response = AdmissionResponse::from(&request);
+    let Some(mut svc) = request
+        .object
+        .filter(|_| request.operation == Operation::Create)
+    else {
+        return Json(response.into_review());
+    };
+    if !is_managed(&svc, &context.config) {
+        return Json(response.into_review());
+    }
+    // Namespace isn't always set in the object at creation.
+    svc.metadata.namespace = svc.metadata.namespace.or(request.namespace);
+    // Services are never rejected, the operator reports
+    // the problems once the service is created.
+    let patched = defaults_patch(&svc, &context).and_then(|patch| {
+        response
+            .clone()
+            .with_patch(patch)
+            .map_err(|err| RobotLBError::SerializationError(err.to_string()))
+    });
+    match patched {
+        Ok(patched) => response = patched,
+        Err(err) => response.warnings = Some(vec![format!("robotlb: {err}")]),
+    }
+    Json(response.into_review())
+}
+
+/// Patch adding resolved name, location and type of the load balancer
+/// to annotations of the service, unless they are already set.
+fn defaults_patch(svc: &Service, context: &CurrentContext) -> RobotLBResult<json_patch::Patch> {
+    let lb = LoadBalancer::try_from_svc(svc, context)?;
+    let mut operations = vec![];
+    // Legacy names of the annotations count as set too.
+    let own = svc.metadata.annotations.clone().map(|mut own| {
+        lb::resolve_aliases(&mut own);
+        own
+    });
+    let own = own.as_ref();
+    if own.is_none() {
+        operations.push(json!({"op": "add", "path": "/metadata/annotations", "value": {}}));
+    }
+    for (name, value) in [
+        (consts::LB_NAME_LABEL_NAME, &lb.name),
+        (consts::LB_LOCATION_LABEL_NAME, &lb.location),
+        (consts::LB_BALANCER_TYPE_LABEL_NAME, &lb.balancer_type),
+    ] {
+        if own.is_some_and(|own| own.contains_key(name)) {
+            continue;
+        }
+        let path = format!(
+            "/metadata/annotations/{}",
+            name.replace('~', "~0").replace('/', "~1")
+        );
+        operations.push(json!({"op": "add", "path": path, "value": value}));
+    }
+    serde_json::from_value(operations.into())
+        .map_err(|err| RobotLBError::SerializationError(err.to_string()))
+}
@@ -0,0 +1,57 @@
+use std::fmt::Display;
+
+use crate::hcloud_span::HcloudResponse;
+
+/// Tracing target of audit records.
+/// Audit records can be written to a separate file, see
+/// `ROBOTLB_AUDIT_LOG_FILE`.
+pub const TARGET: &str = "robotlb::audit";
+
+/// Mutating `HCloud` API call made on behalf of a service,
+/// or of the operator itself, e.g. updates of the node ports firewall.
+pub struct Entry<'a> {
+    pub namespace: &'a str,
+    pub service: &'a str,
+    /// Name of the load balancer, or of the firewall for firewall calls.
+    pub lb_name: &'a str,
+    pub endpoint: &'static str,
+    pub lb_id: Option<i64>,
+    /// Short description of the changes, e.g. `listen_port=80`.
+    pub summary: &'a str,
+}
+
+/// Emit the audit record of the call with its result.
+pub fn record<T: HcloudResponse, E: Display>(entry: &Entry, result: &Result<T, E>) {
+    match result {
+        Ok(response) => tracing::info!(
+            target: TARGET,
+            namespace = entry.namespace,
+            service = entry.service,
+            lb_name = entry.lb_name,
+            endpoint = entry.endpoint,
+            lb_id = entry.lb_id,
+            summary = entry.summary,
+            action_id = response.action_id(),
+            outcome = "success",
+            "{} {}: {}",
+            entry.endpoint,
+            entry.lb_name,
+            entry.summary,
+        ),
+        Err(err) => tracing::warn!(
+            target: TARGET,
+            namespace = entry.namespace,
+            service = entry.service,
+            lb_name = entry.lb_name,
+            endpoint = entry.endpoint,
+            lb_id = entry.lb_id,
+            summary = entry.summary,
+            outcome = "failure",
+            error = %err,
+            "{} {}: {}",
+            entry.endpoint,
+            entry.lb_name,
+            entry.summary,
+        ),
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use kube::{Resource, ResourceExt};
+
+/// Kind, namespace and name of a resource.
+type ResourceKey = (String, String, String);
+
+/// Exponential backoff of services and other resources
+/// that keep failing to reconcile.
+///
+/// Every consecutive failure of a resource doubles the delay before
+/// the next attempt, until it reaches the maximum.
+#[derive(Clone)]
+pub struct ErrorBackoff {
+    /// Delay after the first failure.
+    base: Duration,
+    /// Upper bound of the delay.
+    max: Duration,
+    /// Number of consecutive failures per resource.
+    failures: Arc<Mutex<HashMap<ResourceKey, u32>>>,
+}
+
+impl ErrorBackoff {
+    #[must_use]
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            failures: Arc::default(),
+        }
+    }
+
+    /// Record a failure of the resource and return
+    /// the delay before the next attempt.
+    pub fn next_delay<K: Resource<DynamicType = ()>>(&self, resource: &K) -> Duration {
+        let key = key(resource);
+        let failures = {
+            let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+            let count = failures.entry(key).or_default();
+            *count = count.saturating_add(1);
+            let count = *count;
+            drop(failures);
+            count
+        };
+        let factor = 2u32.saturating_pow(failures - 1);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    /// Reset the backoff of the resource after it was successfully reconciled
+    /// or deleted.
+    pub fn reset<K: Resource<DynamicType = ()>>(&self, resource: &K) {
+        self.failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&key(resource));
+    }
+}
+
+/// Services and other resources with the same name are backed off separately.
+fn key<K: Resource<DynamicType = ()>>(resource: &K) -> ResourceKey {
+    (
+        K::kind(&()).to_string(),
+        resource.namespace().unwrap_or_default(),
+        resource.name_any(),
+    )
+}
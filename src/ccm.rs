@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Service;
+
+use crate::{
+    config::OperatorConfig,
+    consts,
+    duration::parse_duration,
+    error::{RobotLBError, RobotLBResult},
+    lb,
+};
+
+/// Add annotations of robotlb translated from the `load-balancer.hetzner.cloud/*`
+/// annotations of hcloud-cloud-controller-manager set on the service.
+///
+/// Own robotlb annotations of the service take precedence
+/// over the translated ones, and they take precedence over the defaults.
+/// Options robotlb doesn't have are ignored, unless ignoring them
+/// would change how the traffic is balanced.
+pub fn apply(
+    svc: &Service,
+    annotations: &mut BTreeMap<String, String>,
+    config: &OperatorConfig,
+) -> RobotLBResult<()> {
+    let mut own = svc.metadata.annotations.clone().unwrap_or_default();
+    lb::resolve_aliases(&mut own);
+    for (name, value) in &own {
+        if !name.starts_with(consts::CCM_ANNOTATION_PREFIX) {
+            continue;
+        }
+        let (robotlb_name, value) = match name.as_str() {
+            consts::CCM_NAME_ANN_NAME => (consts::LB_NAME_LABEL_NAME, value.clone()),
+            consts::CCM_LOCATION_ANN_NAME => (consts::LB_LOCATION_LABEL_NAME, value.clone()),
+            consts::CCM_TYPE_ANN_NAME => (consts::LB_BALANCER_TYPE_LABEL_NAME, value.clone()),
+            // `round_robin` and `least_connections` in hcloud CCM.
+            consts::CCM_ALGORITHM_ANN_NAME => {
+                (consts::LB_ALGORITHM_LABEL_NAME, value.replace('_', "-"))
+            }
+            consts::CCM_PROXY_PROTOCOL_ANN_NAME => {
+                (consts::LB_PROXY_MODE_LABEL_NAME, value.clone())
+            }
+            consts::CCM_PRIVATE_IPV4_ANN_NAME => (consts::LB_PRIVATE_IP_LABEL_NAME, value.clone()),
+            consts::CCM_CHECK_INTERVAL_ANN_NAME => {
+                (consts::LB_CHECK_INTERVAL_ANN_NAME, seconds(value)?)
+            }
+            consts::CCM_CHECK_TIMEOUT_ANN_NAME => (consts::LB_TIMEOUT_ANN_NAME, seconds(value)?),
+            consts::CCM_CHECK_RETRIES_ANN_NAME => (consts::LB_RETRIES_ANN_NAME, value.clone()),
+            consts::CCM_PROTOCOL_ANN_NAME => {
+                check_protocol(value)?;
+                continue;
+            }
+            consts::CCM_USE_PRIVATE_IP_ANN_NAME => {
+                check_private_ip(value, annotations, config)?;
+                continue;
+            }
+            _ => {
+                tracing::debug!("Annotation {} isn't supported, ignoring", name);
+                continue;
+            }
+        };
+        if !own.contains_key(robotlb_name) {
+            annotations.insert(robotlb_name.to_string(), value);
+        }
+    }
+    Ok(())
+}
+
+/// Health check durations are written like `15s` in hcloud CCM,
+/// while robotlb expects whole seconds.
+fn seconds(value: &str) -> RobotLBResult<String> {
+    Ok(parse_duration(value)?.as_secs().to_string())
+}
+
+/// Only TCP is balanced by robotlb. HTTP is passed through as is,
+/// but HTTPS would require terminating TLS on the balancer.
+fn check_protocol(value: &str) -> RobotLBResult<()> {
+    match value {
+        "tcp" => Ok(()),
+        "http" => {
+            tracing::warn!(
+                "{} is http, but robotlb balances TCP connections",
+                consts::CCM_PROTOCOL_ANN_NAME
+            );
+            Ok(())
+        }
+        _ => Err(RobotLBError::UnsupportedCCMAnnotation(format!(
+            "{} {value}, only tcp and http are supported",
+            consts::CCM_PROTOCOL_ANN_NAME
+        ))),
+    }
+}
+
+/// Private IPs of nodes are used as targets whenever the load balancer
+/// is attached to a network, so the annotation only has to agree with that.
+fn check_private_ip(
+    value: &str,
+    annotations: &BTreeMap<String, String>,
+    config: &OperatorConfig,
+) -> RobotLBResult<()> {
+    let has_network =
+        annotations.contains_key(consts::LB_NETWORK_LABEL_NAME) || config.default_network.is_some();
+    match (value.parse::<bool>()?, has_network) {
+        (true, false) => Err(RobotLBError::UnsupportedCCMAnnotation(format!(
+            "{} requires a network, set {} or ROBOTLB_DEFAULT_NETWORK",
+            consts::CCM_USE_PRIVATE_IP_ANN_NAME,
+            consts::LB_NETWORK_LABEL_NAME
+        ))),
+        (false, true) => Err(RobotLBError::UnsupportedCCMAnnotation(format!(
+            "{} is false, but targets attached to a network always use private IPs",
+            consts::CCM_USE_PRIVATE_IP_ANN_NAME
+        ))),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use kube::config::{AuthInfo, KubeConfigOptions, Kubeconfig};
+
+use crate::{
+    config::OperatorConfig,
+    error::{RobotLBError, RobotLBResult},
+};
+
+/// Cluster reconciled by the operator, in `<name>=<kubeconfig>[#<context>]` format.
+/// If the context isn't set, the current context of the kubeconfig is used.
+#[derive(Debug, Clone)]
+pub struct ClusterSpec {
+    pub name: String,
+    pub kubeconfig: PathBuf,
+    pub context: Option<String>,
+}
+
+impl FromStr for ClusterSpec {
+    type Err = RobotLBError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, kubeconfig) = value
+            .split_once('=')
+            .filter(|(name, kubeconfig)| !name.is_empty() && !kubeconfig.is_empty())
+            .ok_or_else(|| {
+                RobotLBError::InvalidCluster(format!(
+                    "{value}, expected <name>=<kubeconfig>[#<context>]"
+                ))
+            })?;
+        let (kubeconfig, context) = match kubeconfig.split_once('#') {
+            Some((kubeconfig, context)) => (kubeconfig, Some(context.to_string())),
+            None => (kubeconfig, None),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            kubeconfig: PathBuf::from(kubeconfig),
+            context,
+        })
+    }
+}
+
+impl ClusterSpec {
+    /// Connect to the cluster.
+    pub async fn client(&self, config: &OperatorConfig) -> RobotLBResult<kube::Client> {
+        kube_client(Some(&self.kubeconfig), self.context.as_deref(), config).await
+    }
+}
+
+/// Connect to a cluster with the kubeconfig and its context.
+///
+/// If neither of them is set, the usual lookup is used: `KUBECONFIG`,
+/// `~/.kube/config` and then the in-cluster configuration.
+/// Credentials are replaced by the token file and the impersonation of the operator's config.
+pub async fn kube_client(
+    kubeconfig: Option<&Path>,
+    context: Option<&str>,
+    operator_config: &OperatorConfig,
+) -> RobotLBResult<kube::Client> {
+    let options = KubeConfigOptions {
+        context: context.map(ToString::to_string),
+        ..Default::default()
+    };
+    let mut config = match kubeconfig {
+        Some(path) => {
+            kube::Config::from_custom_kubeconfig(Kubeconfig::read_from(path)?, &options).await?
+        }
+        None if context.is_some() => kube::Config::from_kubeconfig(&options).await?,
+        None => kube::Config::infer()
+            .await
+            .map_err(kube::Error::InferConfig)?,
+    };
+    if let Some(token_file) = &operator_config.kube_token_file {
+        // Other credentials, e.g. client certificates, would be sent along with the token.
+        config.auth_info = AuthInfo {
+            token_file: Some(token_file.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+    }
+    if let Some(user) = &operator_config.kube_impersonate {
+        tracing::info!("Impersonating {} in requests to Kubernetes", user);
+        config.auth_info.impersonate = Some(user.clone());
+        config.auth_info.impersonate_groups = Some(operator_config.kube_impersonate_groups.clone())
+            .filter(|groups| !groups.is_empty());
+    }
+    Ok(kube::Client::try_from(config)?)
+}
@@ -0,0 +1,91 @@
+use std::{sync::Arc, time::Duration};
+
+use hcloud::{
+    apis::load_balancers_api::GetMetricsForLoadbalancerParams, models::MetricsTimeSeriesValue,
+};
+use k8s_openapi::{
+    api::core::v1::Service,
+    chrono::{SecondsFormat, Utc},
+};
+
+use crate::{
+    error::RobotLBResult, hcloud_span::traced, metrics::ManagedLoadBalancer, CurrentContext,
+};
+
+/// Metric types requested from the `HCloud` API.
+/// Each type may contain several time series.
+const LB_METRIC_TYPES: &str = "open_connections,requests_per_second,bandwidth";
+
+/// Hetzner doesn't have data points more often than once a minute.
+const MIN_SCRAPE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Periodically scrape traffic metrics of all managed load balancers
+/// from Hetzner Cloud and export them as Prometheus metrics.
+pub async fn run(context: Arc<CurrentContext>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        for lb in context.metrics.managed_lbs() {
+            if let Err(err) = collect_lb_traffic(&context, &lb, interval).await {
+                tracing::warn!(
+                    "Cannot collect traffic metrics of load balancer {}: {}",
+                    lb.lb_name,
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// Fetch the latest traffic metrics of the load balancer.
+///
+/// The balancer is queried with the credentials of its service,
+/// since it may belong to another project.
+async fn collect_lb_traffic(
+    context: &CurrentContext,
+    lb: &ManagedLoadBalancer,
+    interval: Duration,
+) -> RobotLBResult<()> {
+    let Some(svc) = kube::Api::<Service>::namespaced(context.client.clone(), &lb.namespace)
+        .get_opt(&lb.service)
+        .await?
+    else {
+        // The service was deleted, its balancer is untracked by the reconcile.
+        return Ok(());
+    };
+    let hcloud_config = context.credentials.hcloud_config(&svc, context).await?;
+    let period = interval.max(MIN_SCRAPE_PERIOD);
+    let end = Utc::now();
+    let start = end - period;
+    let response = traced(
+        "get_metrics_for_loadbalancer",
+        Some(lb.lb_id),
+        hcloud::apis::load_balancers_api::get_metrics_for_loadbalancer(
+            &hcloud_config,
+            GetMetricsForLoadbalancerParams {
+                id: lb.lb_id,
+                r#type: LB_METRIC_TYPES.to_string(),
+                start: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+                end: end.to_rfc3339_opts(SecondsFormat::Secs, true),
+                step: Some(period.as_secs().to_string()),
+            },
+        ),
+    )
+    .await?;
+    for (series, time_series) in &response.metrics.time_series {
+        // Each value is a pair of timestamp and the value itself.
+        let Some(value) = time_series
+            .values
+            .last()
+            .and_then(|point| point.get(1))
+            .and_then(|value| match value {
+                MetricsTimeSeriesValue::Number(value) => Some(*value),
+                MetricsTimeSeriesValue::String(value) => value.parse().ok(),
+            })
+        else {
+            continue;
+        };
+        context.metrics.set_lb_traffic(lb, series, value);
+    }
+    Ok(())
+}
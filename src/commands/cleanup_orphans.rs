@@ -0,0 +1,119 @@
+use hcloud::apis::load_balancers_api::DeleteLoadBalancerParams;
+use k8s_openapi::api::core::v1::Service;
+
+use crate::{
+    audit, config::CleanupOrphansArgs, consts, error::RobotLBResult, hcloud_span::traced,
+    inventory, is_managed, lb, lb::LoadBalancer, CurrentContext,
+};
+
+/// List load balancers of the cluster that don't belong to any service
+/// and delete them if confirmed.
+pub async fn run(args: &CleanupOrphansArgs, context: &CurrentContext) -> RobotLBResult<bool> {
+    let mut orphans = vec![];
+    for hcloud_lb in
+        lb::list_managed(context.hcloud_api().as_ref(), &context.config.cluster_name).await?
+    {
+        if is_orphan(&hcloud_lb, context).await? {
+            orphans.push(hcloud_lb);
+        }
+    }
+    if orphans.is_empty() {
+        println!("No orphaned load balancers found");
+        return Ok(true);
+    }
+
+    println!("Orphaned load balancers:");
+    for hcloud_lb in &orphans {
+        let (namespace, service) = owner(hcloud_lb);
+        println!(
+            "  - {} (id {}) of service {namespace}/{service}",
+            hcloud_lb.name, hcloud_lb.id
+        );
+    }
+    if !args.confirm {
+        println!("Run with --confirm to delete them");
+        return Ok(true);
+    }
+
+    for hcloud_lb in &orphans {
+        delete(hcloud_lb, context).await?;
+        println!("Deleted load balancer {}", hcloud_lb.name);
+    }
+    Ok(true)
+}
+
+/// Namespace and name of the service the load balancer was created for.
+fn owner(hcloud_lb: &hcloud::models::LoadBalancer) -> (&str, &str) {
+    let label = |name| hcloud_lb.labels.get(name).map_or("", String::as_str);
+    (
+        label(consts::LB_NAMESPACE_LABEL_NAME),
+        label(consts::LB_SERVICE_LABEL_NAME),
+    )
+}
+
+/// Check that the service of the load balancer no longer exists,
+/// is no longer managed by robotlb or uses another load balancer now.
+async fn is_orphan(
+    hcloud_lb: &hcloud::models::LoadBalancer,
+    context: &CurrentContext,
+) -> RobotLBResult<bool> {
+    let (namespace, service) = owner(hcloud_lb);
+    // Balancers without an owner might have been labeled by hand,
+    // it's safer to leave them alone. The ones adopted by `HetznerLoadBalancer`
+    // resources are deleted along with the resources.
+    if namespace.is_empty()
+        || service.is_empty()
+        || hcloud_lb
+            .labels
+            .contains_key(consts::LB_RESOURCE_LABEL_NAME)
+    {
+        return Ok(false);
+    }
+    let api = kube::Api::<Service>::namespaced(context.client.clone(), namespace);
+    let Some(svc) = api.get_opt(service).await? else {
+        return Ok(true);
+    };
+    if !is_managed(&svc, &context.config) {
+        return Ok(true);
+    }
+    // If annotations of the service are broken, it's not clear
+    // which balancer it wants, so the current one is kept.
+    Ok(LoadBalancer::try_from_svc(&svc, context).is_ok_and(|lb| lb.name != hcloud_lb.name))
+}
+
+async fn delete(
+    hcloud_lb: &hcloud::models::LoadBalancer,
+    context: &CurrentContext,
+) -> RobotLBResult<()> {
+    let (namespace, service) = owner(hcloud_lb);
+    let result = traced(
+        "delete_load_balancer",
+        Some(hcloud_lb.id),
+        hcloud::apis::load_balancers_api::delete_load_balancer(
+            &context.hcloud_config(),
+            DeleteLoadBalancerParams { id: hcloud_lb.id },
+        ),
+    )
+    .await;
+    audit::record(
+        &audit::Entry {
+            namespace,
+            service,
+            lb_name: &hcloud_lb.name,
+            endpoint: "delete_load_balancer",
+            lb_id: Some(hcloud_lb.id),
+            summary: "orphaned",
+        },
+        &result,
+    );
+    result?;
+    // The service might have another balancer recorded by now.
+    if context.config.inventory
+        && inventory::get(context, namespace, service)
+            .await?
+            .is_some_and(|entry| entry.id == hcloud_lb.id)
+    {
+        inventory::forget(context, namespace, service).await?;
+    }
+    Ok(())
+}
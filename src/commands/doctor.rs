@@ -0,0 +1,282 @@
+use std::str::FromStr;
+
+use hcloud::apis::{
+    load_balancer_types_api::ListLoadBalancerTypesParams,
+    load_balancers_api::ReplaceLoadBalancerParams, locations_api::ListLocationsParams,
+    networks_api::ListNetworksParams,
+};
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+};
+use kube::api::PostParams;
+
+use crate::{
+    config::OperatorConfig, error::RobotLBResult, hcloud_span::traced, lb::LBAlgorithm,
+    CurrentContext,
+};
+
+/// Permissions the operator needs in the cluster:
+/// API group, resource and verb.
+const PERMISSIONS: &[(&str, &str, &str)] = &[
+    ("", "services", "list"),
+    ("", "services", "watch"),
+    ("", "services", "patch"),
+    ("", "services/status", "patch"),
+    ("", "pods", "list"),
+    ("", "nodes", "list"),
+    ("", "configmaps", "watch"),
+    ("events.k8s.io", "events", "create"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Check that the operator can work with the cluster, `HCloud`
+/// and its configuration, and print the report.
+/// Returns whether none of the checks failed.
+pub async fn run(context: &CurrentContext) -> RobotLBResult<bool> {
+    let mut report = Report::default();
+    check_kube(context, &mut report).await;
+    check_hcloud(context, &mut report).await;
+    check_config(&context.config, &mut report);
+    println!(
+        "{} passed, {} warnings, {} failed",
+        report.count(Status::Pass),
+        report.count(Status::Warn),
+        report.count(Status::Fail)
+    );
+    Ok(report.count(Status::Fail) == 0)
+}
+
+#[derive(Default)]
+struct Report {
+    statuses: Vec<Status>,
+}
+
+impl Report {
+    fn add(&mut self, status: Status, message: impl AsRef<str>) {
+        let label = match status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        println!("[{label}] {}", message.as_ref());
+        self.statuses.push(status);
+    }
+
+    fn check(&mut self, passed: bool, message: impl AsRef<str>) {
+        let status = if passed { Status::Pass } else { Status::Fail };
+        self.add(status, message);
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.statuses.iter().filter(|s| **s == status).count()
+    }
+}
+
+async fn check_kube(context: &CurrentContext, report: &mut Report) {
+    match context.client.apiserver_version().await {
+        Ok(version) => report.add(
+            Status::Pass,
+            format!(
+                "Kubernetes API is reachable, version {}",
+                version.git_version
+            ),
+        ),
+        Err(err) => {
+            report.add(
+                Status::Fail,
+                format!("Kubernetes API is unreachable: {err}"),
+            );
+            return;
+        }
+    }
+    let api = kube::Api::<SelfSubjectAccessReview>::all(context.client.clone());
+    for (group, resource, verb) in PERMISSIONS {
+        let (resource, subresource) = resource
+            .split_once('/')
+            .map_or((*resource, None), |(resource, sub)| (resource, Some(sub)));
+        let review = SelfSubjectAccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes: Some(ResourceAttributes {
+                    group: Some((*group).to_string()),
+                    resource: Some(resource.to_string()),
+                    subresource: subresource.map(ToString::to_string),
+                    verb: Some((*verb).to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let target = subresource.map_or_else(
+            || resource.to_string(),
+            |subresource| format!("{resource}/{subresource}"),
+        );
+        match api.create(&PostParams::default(), &review).await {
+            Ok(review) => report.check(
+                review.status.is_some_and(|status| status.allowed),
+                format!("Permission to {verb} {target}"),
+            ),
+            Err(err) => report.add(
+                Status::Fail,
+                format!("Cannot check permission to {verb} {target}: {err}"),
+            ),
+        }
+    }
+}
+
+async fn check_hcloud(context: &CurrentContext, report: &mut Report) {
+    let config = &context.config;
+    let locations = traced(
+        "list_locations",
+        None,
+        hcloud::apis::locations_api::list_locations(
+            &context.hcloud_config(),
+            ListLocationsParams::default(),
+        ),
+    )
+    .await;
+    match locations {
+        Ok(locations) => {
+            report.add(
+                Status::Pass,
+                "HCloud API is reachable and the token is valid",
+            );
+            report.check(
+                locations
+                    .locations
+                    .iter()
+                    .any(|location| location.name == config.default_lb_location),
+                format!("Default location {} exists", config.default_lb_location),
+            );
+        }
+        Err(err) => {
+            report.add(Status::Fail, format!("HCloud API check failed: {err}"));
+            return;
+        }
+    }
+
+    match traced(
+        "list_load_balancer_types",
+        None,
+        hcloud::apis::load_balancer_types_api::list_load_balancer_types(
+            &context.hcloud_config(),
+            ListLoadBalancerTypesParams::default(),
+        ),
+    )
+    .await
+    {
+        Ok(types) => report.check(
+            types
+                .load_balancer_types
+                .iter()
+                .any(|lb_type| lb_type.name == config.default_balancer_type),
+            format!(
+                "Default load balancer type {} exists",
+                config.default_balancer_type
+            ),
+        ),
+        Err(err) => report.add(
+            Status::Fail,
+            format!("Cannot list load balancer types: {err}"),
+        ),
+    }
+
+    if let Some(network) = &config.default_network {
+        match traced(
+            "list_networks",
+            None,
+            hcloud::apis::networks_api::list_networks(
+                &context.hcloud_config(),
+                ListNetworksParams {
+                    name: Some(network.clone()),
+                    ..Default::default()
+                },
+            ),
+        )
+        .await
+        {
+            Ok(networks) => report.check(
+                networks.networks.len() == 1,
+                format!("Default network {network} exists"),
+            ),
+            Err(err) => report.add(Status::Fail, format!("Cannot list networks: {err}")),
+        }
+    }
+
+    check_write_access(context, report).await;
+}
+
+/// Check that the token isn't read-only. The load balancer with ID 0
+/// never exists, so the update is refused either for the lack of permissions
+/// or because nothing is found, without changing anything.
+async fn check_write_access(context: &CurrentContext, report: &mut Report) {
+    let result = traced(
+        "replace_load_balancer",
+        Some(0),
+        hcloud::apis::load_balancers_api::replace_load_balancer(
+            &context.hcloud_config(),
+            ReplaceLoadBalancerParams {
+                id: 0,
+                replace_load_balancer_request: None,
+            },
+        ),
+    )
+    .await;
+    match result {
+        Err(hcloud::apis::Error::ResponseError(response)) if response.status == 403 => {
+            report.add(Status::Fail, "HCloud token is read-only");
+        }
+        Err(hcloud::apis::Error::ResponseError(_)) | Ok(_) => {
+            report.add(Status::Pass, "HCloud token has write access");
+        }
+        Err(err) => report.add(
+            Status::Warn,
+            format!("Cannot check write access of the HCloud token: {err}"),
+        ),
+    }
+}
+
+fn check_config(config: &OperatorConfig, report: &mut Report) {
+    report.check(
+        LBAlgorithm::from_str(&config.default_lb_algorithm).is_ok(),
+        format!(
+            "Default load balancer algorithm {} is valid",
+            config.default_lb_algorithm
+        ),
+    );
+    report.check(
+        config.check_requeue_intervals().is_ok(),
+        "Requeue intervals are consistent: the drift check interval is not less than \
+         the resync one, the maximum error requeue delay is not less than the permanent one",
+    );
+    report.check(
+        config.check_watchdog_window().is_ok(),
+        "Watchdog window is not less than the resync and drift check intervals",
+    );
+    report.check(
+        config.metrics_tls_cert.is_some() == config.metrics_tls_key.is_some(),
+        "Both TLS certificate and key of the metrics server are set, or none",
+    );
+    for path in [
+        &config.metrics_tls_cert,
+        &config.metrics_tls_key,
+        &config.metrics_tls_client_ca,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        report.check(path.is_file(), format!("File {} exists", path.display()));
+    }
+    if !config.dynamic_node_selector {
+        report.add(
+            Status::Warn,
+            "Dynamic node selector is disabled, every service needs robotlb/node-selector annotation",
+        );
+    }
+}
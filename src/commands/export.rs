@@ -0,0 +1,127 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use k8s_openapi::{api::core::v1::Service, chrono::Utc, serde_json};
+use kube::{api::ListParams, ResourceExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ExportArgs, ExportFormat},
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    is_managed, lb,
+    lb::LoadBalancer,
+    resolve_targets_and_services,
+    state::DesiredSpec,
+    CurrentContext,
+};
+
+/// Version of the backup format, bumped on incompatible changes.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Desired and actual configuration of all load balancers of the cluster.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    /// Time of the export in RFC 3339 format.
+    pub exported_at: String,
+    pub cluster_name: String,
+    pub load_balancers: Vec<BackupEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub namespace: String,
+    pub service: String,
+    /// Configuration resolved from the service. Missing if the service
+    /// no longer exists or its configuration can't be resolved.
+    pub desired: Option<DesiredSpec>,
+    /// The load balancer in `HCloud`. Missing if it doesn't exist.
+    pub hcloud_lb: Option<hcloud::models::LoadBalancer>,
+    /// Why the desired configuration couldn't be resolved.
+    pub error: Option<String>,
+}
+
+/// Export configuration of all managed load balancers, including the ones
+/// whose services no longer exist.
+pub async fn run(args: &ExportArgs, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    let mut orphans = lb::list_managed(context.hcloud_api().as_ref(), &context.config.cluster_name)
+        .await?
+        .into_iter()
+        .map(|hcloud_lb| (hcloud_lb.name.clone(), hcloud_lb))
+        .collect::<HashMap<_, _>>();
+    let services = kube::Api::<Service>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?;
+
+    let mut load_balancers = vec![];
+    for svc in services
+        .into_iter()
+        .filter(|svc| is_managed(svc, &context.config))
+    {
+        let entry = export_service(Arc::new(svc), &context).await?;
+        if let Some(hcloud_lb) = &entry.hcloud_lb {
+            orphans.remove(&hcloud_lb.name);
+        }
+        load_balancers.push(entry);
+    }
+    for hcloud_lb in orphans.into_values() {
+        let label = |name| hcloud_lb.labels.get(name).cloned().unwrap_or_default();
+        load_balancers.push(BackupEntry {
+            namespace: label(consts::LB_NAMESPACE_LABEL_NAME),
+            service: label(consts::LB_SERVICE_LABEL_NAME),
+            desired: None,
+            hcloud_lb: Some(hcloud_lb),
+            error: None,
+        });
+    }
+
+    let backup = Backup {
+        version: BACKUP_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        cluster_name: context.config.cluster_name.clone(),
+        load_balancers,
+    };
+    let serialized = match args.format {
+        ExportFormat::Yaml => serde_yaml::to_string(&backup)
+            .map_err(|err| RobotLBError::SerializationError(err.to_string()))?,
+        ExportFormat::Json => serde_json::to_string_pretty(&backup)
+            .map_err(|err| RobotLBError::SerializationError(err.to_string()))?,
+    };
+    if args.output == Path::new("-") {
+        println!("{serialized}");
+    } else {
+        std::fs::write(&args.output, serialized)?;
+        eprintln!(
+            "Exported {} load balancers to {}",
+            backup.load_balancers.len(),
+            args.output.display()
+        );
+    }
+    Ok(true)
+}
+
+async fn export_service(
+    svc: Arc<Service>,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<BackupEntry> {
+    let mut entry = BackupEntry {
+        namespace: svc.namespace().unwrap_or_default(),
+        service: svc.name_any(),
+        desired: None,
+        hcloud_lb: None,
+        error: None,
+    };
+    let mut lb = match LoadBalancer::resolve(&svc, context).await {
+        Ok(lb) => lb,
+        Err(err) => {
+            entry.error = Some(err.to_string());
+            return Ok(entry);
+        }
+    };
+    entry.hcloud_lb = lb.get_hcloud_lb().await?;
+    match resolve_targets_and_services(&mut lb, &svc, context).await {
+        Ok(()) => entry.desired = Some(DesiredSpec::from(&lb)),
+        Err(err) => entry.error = Some(err.to_string()),
+    }
+    Ok(entry)
+}
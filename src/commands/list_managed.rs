@@ -0,0 +1,119 @@
+use k8s_openapi::{api::core::v1::Service, serde_json};
+use kube::{api::ListParams, ResourceExt};
+use serde::Serialize;
+
+use super::print_table;
+use crate::{
+    config::{ListManagedArgs, OutputFormat},
+    error::{RobotLBError, RobotLBResult},
+    is_managed,
+    lb::LoadBalancer,
+    CurrentContext,
+};
+
+/// Managed service along with its load balancer in `HCloud`.
+#[derive(Debug, Serialize)]
+struct ManagedService {
+    namespace: String,
+    service: String,
+    lb_name: Option<String>,
+    lb_id: Option<i64>,
+    location: Option<String>,
+    lb_type: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    targets: Option<usize>,
+    /// Why the service has no load balancer.
+    error: Option<String>,
+}
+
+/// Print load balancers of all managed services in the cluster.
+pub async fn run(args: &ListManagedArgs, context: &CurrentContext) -> RobotLBResult<bool> {
+    let services = kube::Api::<Service>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?;
+    let mut managed = vec![];
+    for svc in services
+        .iter()
+        .filter(|svc| is_managed(svc, &context.config))
+    {
+        managed.push(describe(svc, context).await?);
+    }
+
+    match args.output {
+        OutputFormat::Json => {
+            let output = serde_json::to_string_pretty(&managed)
+                .map_err(|err| RobotLBError::SerializationError(err.to_string()))?;
+            println!("{output}");
+        }
+        OutputFormat::Table => {
+            let rows = managed.iter().map(table_row).collect::<Vec<_>>();
+            print_table(
+                [
+                    "NAMESPACE",
+                    "SERVICE",
+                    "LOAD BALANCER",
+                    "ID",
+                    "LOCATION",
+                    "TYPE",
+                    "IPV4",
+                    "IPV6",
+                    "TARGETS",
+                    "STATUS",
+                ],
+                &rows,
+            );
+        }
+    }
+    Ok(true)
+}
+
+async fn describe(svc: &Service, context: &CurrentContext) -> RobotLBResult<ManagedService> {
+    let mut managed = ManagedService {
+        namespace: svc.namespace().unwrap_or_default(),
+        service: svc.name_any(),
+        lb_name: None,
+        lb_id: None,
+        location: None,
+        lb_type: None,
+        ipv4: None,
+        ipv6: None,
+        targets: None,
+        error: None,
+    };
+    let lb = match LoadBalancer::resolve(svc, context).await {
+        Ok(lb) => lb,
+        Err(err) => {
+            managed.error = Some(err.to_string());
+            return Ok(managed);
+        }
+    };
+    managed.lb_name = Some(lb.name.clone());
+    let Some(hcloud_lb) = lb.get_hcloud_lb().await? else {
+        managed.error = Some("load balancer doesn't exist".to_string());
+        return Ok(managed);
+    };
+    managed.lb_id = Some(hcloud_lb.id);
+    managed.location = Some(hcloud_lb.location.name);
+    managed.lb_type = Some(hcloud_lb.load_balancer_type.name);
+    managed.ipv4 = hcloud_lb.public_net.ipv4.ip.flatten();
+    managed.ipv6 = hcloud_lb.public_net.ipv6.ip.flatten();
+    managed.targets = Some(hcloud_lb.targets.len());
+    Ok(managed)
+}
+
+fn table_row(managed: &ManagedService) -> [String; 10] {
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    [
+        managed.namespace.clone(),
+        managed.service.clone(),
+        or_dash(managed.lb_name.clone()),
+        or_dash(managed.lb_id.map(|id| id.to_string())),
+        or_dash(managed.location.clone()),
+        or_dash(managed.lb_type.clone()),
+        or_dash(managed.ipv4.clone()),
+        or_dash(managed.ipv6.clone()),
+        or_dash(managed.targets.map(|targets| targets.to_string())),
+        managed.error.clone().unwrap_or_else(|| "ok".to_string()),
+    ]
+}
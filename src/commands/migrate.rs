@@ -0,0 +1,159 @@
+use std::{path::Path, sync::Arc};
+
+use k8s_openapi::{
+    api::core::v1::Service, apimachinery::pkg::apis::meta::v1::ObjectMeta, serde_json::json,
+};
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    ResourceExt,
+};
+
+use crate::{
+    config::MigrateArgs,
+    consts,
+    crds::{
+        hetzner_lb::{HetznerLoadBalancer, HetznerLoadBalancerSpec, ServicePort},
+        robotlb_config::HealthCheckSpec,
+        type_meta,
+    },
+    error::{RobotLBError, RobotLBResult},
+    finalizers, is_managed,
+    lb::{algorithm_name, LoadBalancer},
+    resolve_targets_and_services, CurrentContext,
+};
+
+/// Generate a `HetznerLoadBalancer` resource for every managed service
+/// and apply them if asked.
+///
+/// Resources get names and namespaces of the services. Targets are
+/// the nodes selected for the services at the moment of migration.
+pub async fn run(args: &MigrateArgs, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    let services = kube::Api::<Service>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?;
+    let mut manifests = vec![];
+    let mut failed = 0;
+    for svc in services
+        .into_iter()
+        .filter(|svc| is_managed(svc, &context.config))
+    {
+        let name = format!("{}/{}", svc.namespace().unwrap_or_default(), svc.name_any());
+        // Resources always use the operator's token, so the balancer
+        // would be recreated in another project.
+        if context.annotations(&svc).is_ok_and(|annotations| {
+            annotations.contains_key(consts::HCLOUD_TOKEN_SECRET_ANN_NAME)
+                || annotations.contains_key(consts::HCLOUD_PROJECT_ANN_NAME)
+        }) {
+            eprintln!("{name}: skipped, the service uses its own HCloud token");
+            failed += 1;
+            continue;
+        }
+        let svc = Arc::new(svc);
+        let resource = match to_resource(&svc, &context).await {
+            Ok(resource) => resource,
+            Err(err) => {
+                eprintln!("{name}: skipped, {err}");
+                failed += 1;
+                continue;
+            }
+        };
+        if args.apply {
+            apply(&svc, &resource, &context).await?;
+            eprintln!("{name}: migrated");
+        }
+        manifests.push(
+            serde_yaml::to_string(&resource)
+                .map_err(|err| RobotLBError::SerializationError(err.to_string()))?,
+        );
+    }
+
+    let output = manifests.join("---\n");
+    if args.output == Path::new("-") {
+        print!("{output}");
+    } else {
+        std::fs::write(&args.output, output)?;
+        eprintln!(
+            "Saved {} manifests to {}",
+            manifests.len(),
+            args.output.display()
+        );
+    }
+    Ok(failed == 0)
+}
+
+async fn to_resource(
+    svc: &Arc<Service>,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<HetznerLoadBalancer> {
+    let mut lb = LoadBalancer::try_from_svc(svc, context)?;
+    resolve_targets_and_services(&mut lb, svc, context).await?;
+    let mut services = lb
+        .services
+        .iter()
+        .map(|(listen_port, destination_port)| ServicePort {
+            listen_port: *listen_port,
+            destination_port: *destination_port,
+        })
+        .collect::<Vec<_>>();
+    services.sort_by_key(|service| service.listen_port);
+    Ok(HetznerLoadBalancer {
+        types: Some(type_meta("HetznerLoadBalancer")),
+        metadata: ObjectMeta {
+            name: Some(svc.name_any()),
+            namespace: svc.namespace(),
+            ..Default::default()
+        },
+        spec: HetznerLoadBalancerSpec {
+            name: Some(lb.name.clone()),
+            location: Some(lb.location.clone()),
+            balancer_type: Some(lb.balancer_type.clone()),
+            algorithm: Some(algorithm_name(lb.algorithm.r#type).to_string()),
+            network: lb.network_name.clone(),
+            private_ip: lb.private_ip.clone(),
+            proxy_mode: Some(lb.proxy_mode),
+            health_check: HealthCheckSpec {
+                interval: Some(lb.check_interval),
+                timeout: Some(lb.timeout),
+                retries: Some(lb.retries),
+            },
+            services,
+            targets: lb.targets,
+        },
+        status: None,
+    })
+}
+
+/// Create the resource and hand the load balancer over to it.
+async fn apply(
+    svc: &Service,
+    resource: &HetznerLoadBalancer,
+    context: &CurrentContext,
+) -> RobotLBResult<()> {
+    let namespace = svc.namespace().unwrap_or_default();
+    kube::Api::<HetznerLoadBalancer>::namespaced(context.client.clone(), &namespace)
+        .patch(
+            &resource.name_any(),
+            &PatchParams::apply("robotlb").force(),
+            &Patch::Apply(resource),
+        )
+        .await?;
+    kube::Api::<Service>::namespaced(context.client.clone(), &namespace)
+        .patch(
+            &svc.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({
+                "metadata": {
+                    "annotations": {
+                        consts::EXTERNALLY_MANAGED_ANN_NAME: "true"
+                    }
+                }
+            })),
+        )
+        .await?;
+    // The operator skips the service from now on, so it would never
+    // remove the finalizer and the service couldn't be deleted.
+    if finalizers::check(svc) {
+        finalizers::remove(context.client.clone(), svc).await?;
+    }
+    Ok(())
+}
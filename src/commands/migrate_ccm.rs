@@ -0,0 +1,149 @@
+use std::{collections::HashMap, sync::Arc};
+
+use hcloud::{
+    apis::load_balancers_api::ReplaceLoadBalancerParams, models::ReplaceLoadBalancerRequest,
+};
+use k8s_openapi::{api::core::v1::Service, serde_json::json};
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    ResourceExt,
+};
+
+use crate::{
+    audit,
+    config::MigrateCcmArgs,
+    consts,
+    error::RobotLBResult,
+    hcloud_span::traced,
+    is_managed,
+    lb::{self, LoadBalancer},
+    reconcile_service, CurrentContext,
+};
+
+/// Find load balancers of hcloud-cloud-controller-manager whose services
+/// are managed by robotlb, and adopt them if asked.
+///
+/// Balancers are matched to services by the service UID label set by the
+/// controller manager. They are never recreated, so their IPs are kept.
+pub async fn run(args: &MigrateCcmArgs, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    let mut ccm_lbs = lb::list_ccm(context.hcloud_api().as_ref())
+        .await?
+        .into_iter()
+        .filter_map(|hcloud_lb| {
+            let uid = hcloud_lb
+                .labels
+                .get(consts::CCM_SERVICE_UID_LABEL_NAME)?
+                .clone();
+            Some((uid, hcloud_lb))
+        })
+        .collect::<HashMap<_, _>>();
+    if ccm_lbs.is_empty() {
+        println!("No load balancers of hcloud-cloud-controller-manager found");
+        return Ok(true);
+    }
+
+    let services = kube::Api::<Service>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?;
+    let mut found = 0;
+    let mut failed = 0;
+    for svc in services
+        .into_iter()
+        .filter(|svc| is_managed(svc, &context.config))
+    {
+        let Some(hcloud_lb) = svc.uid().and_then(|uid| ccm_lbs.remove(&uid)) else {
+            continue;
+        };
+        found += 1;
+        let name = format!("{}/{}", svc.namespace().unwrap_or_default(), svc.name_any());
+        println!(
+            "{name}: load balancer {} (id {})",
+            hcloud_lb.name, hcloud_lb.id
+        );
+        if !args.apply {
+            continue;
+        }
+        match adopt(&svc, &hcloud_lb, &context).await {
+            Ok(()) => println!("{name}: adopted"),
+            Err(err) => {
+                println!("{name}: cannot adopt, {err}");
+                failed += 1;
+            }
+        }
+    }
+    for hcloud_lb in ccm_lbs.values() {
+        println!(
+            "Load balancer {} (id {}) is left to hcloud-cloud-controller-manager, \
+            its service doesn't exist or isn't managed by robotlb",
+            hcloud_lb.name, hcloud_lb.id
+        );
+    }
+    if found > 0 && !args.apply {
+        println!(
+            "Stop the service controller of hcloud-cloud-controller-manager, \
+            e.g. with --controllers=*,-service, and run with --apply to adopt them"
+        );
+    }
+    Ok(failed == 0)
+}
+
+/// Point the service to the balancer, relabel the balancer
+/// as robotlb's and reconcile the service.
+async fn adopt(
+    svc: &Service,
+    hcloud_lb: &hcloud::models::LoadBalancer,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<()> {
+    let namespace = svc.namespace().unwrap_or_default();
+    // The name is set explicitly, otherwise robotlb would create
+    // a balancer named after the service.
+    let svc = kube::Api::<Service>::namespaced(context.client.clone(), &namespace)
+        .patch(
+            &svc.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({
+                "metadata": {
+                    "annotations": {
+                        consts::LB_NAME_LABEL_NAME: hcloud_lb.name,
+                        consts::LB_ID_ANN_NAME: hcloud_lb.id.to_string(),
+                    }
+                }
+            })),
+        )
+        .await?;
+    let lb = LoadBalancer::try_from_svc(&svc, context)?;
+
+    // Without the label the controller manager doesn't consider the balancer its own.
+    let mut labels = hcloud_lb.labels.clone();
+    labels.remove(consts::CCM_SERVICE_UID_LABEL_NAME);
+    labels.extend(lb.labels.clone());
+    let result = traced(
+        "replace_load_balancer",
+        Some(hcloud_lb.id),
+        context
+            .hcloud_api()
+            .replace_load_balancer(ReplaceLoadBalancerParams {
+                id: hcloud_lb.id,
+                replace_load_balancer_request: Some(ReplaceLoadBalancerRequest {
+                    labels: Some(labels),
+                    name: None,
+                }),
+            }),
+    )
+    .await;
+    audit::record(
+        &audit::Entry {
+            namespace: &namespace,
+            service: &svc.name_any(),
+            lb_name: &hcloud_lb.name,
+            endpoint: "replace_load_balancer",
+            lb_id: Some(hcloud_lb.id),
+            summary: "adopted from hcloud-cloud-controller-manager",
+        },
+        &result,
+    );
+    result?;
+
+    reconcile_service(Arc::new(svc), context.clone()).await?;
+    Ok(())
+}
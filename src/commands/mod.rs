@@ -0,0 +1,65 @@
+use std::{io::Read, path::Path, sync::Arc};
+
+use crate::{config::ToolCommand, error::RobotLBResult, CurrentContext};
+
+pub mod cleanup_orphans;
+pub mod doctor;
+pub mod export;
+pub mod list_managed;
+pub mod migrate;
+pub mod migrate_ccm;
+pub mod plan;
+pub mod reconcile;
+pub mod restore;
+pub mod validate;
+
+/// Run the tooling command.
+/// Returns whether the command succeeded, so the process can exit
+/// with a non-zero code otherwise.
+pub async fn run(command: ToolCommand, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    match command {
+        ToolCommand::Plan(args) => plan::run(&args, context).await,
+        ToolCommand::Validate(args) => validate::run(&args, &context).await,
+        ToolCommand::CleanupOrphans(args) => cleanup_orphans::run(&args, &context).await,
+        ToolCommand::ListManaged(args) => list_managed::run(&args, &context).await,
+        ToolCommand::Export(args) => export::run(&args, context).await,
+        ToolCommand::Restore(args) => restore::run(&args, context).await,
+        ToolCommand::Doctor => doctor::run(&context).await,
+        ToolCommand::Reconcile(args) => reconcile::run(&args, context).await,
+        ToolCommand::Migrate(args) => migrate::run(&args, context).await,
+        ToolCommand::MigrateCcm(args) => migrate_ccm::run(&args, context).await,
+    }
+}
+
+/// Read the whole file, or stdin if the path is `-`.
+fn read_input(path: &Path) -> RobotLBResult<String> {
+    if path == Path::new("-") {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        return Ok(input);
+    }
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Print rows as a table with columns aligned to the widest value.
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.len());
+        }
+    }
+    let print_row = |row: [&str; N]| {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(value, width)| format!("{value:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    print_row(header);
+    for row in rows {
+        print_row(row.each_ref().map(String::as_str));
+    }
+}
@@ -0,0 +1,89 @@
+use std::{path::Path, sync::Arc};
+
+use k8s_openapi::api::core::v1::Service;
+use kube::ResourceExt;
+
+use super::read_input;
+use crate::{
+    config::PlanArgs, error::RobotLBResult, is_managed, lb::LoadBalancer,
+    resolve_targets_and_services, CurrentContext,
+};
+
+/// Print the changes a reconcile of the service from the manifest would make.
+/// Nothing is changed in the cluster or in `HCloud`.
+pub async fn run(args: &PlanArgs, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    let mut svc = read_manifest(&args.manifest)?;
+    if svc.metadata.namespace.is_none() {
+        svc.metadata.namespace = Some(context.client.default_namespace().to_string());
+    }
+    let name = format!("{}/{}", svc.namespace().unwrap_or_default(), svc.name_any());
+    if !is_managed(&svc, &context.config) {
+        println!("Service {name} is not managed by robotlb");
+        return Ok(true);
+    }
+    fill_node_ports(&mut svc, &context).await?;
+    print_changes(Arc::new(svc), &context).await?;
+    Ok(true)
+}
+
+/// Print the changes a reconcile of the managed service would make
+/// to its load balancer.
+pub async fn print_changes(svc: Arc<Service>, context: &Arc<CurrentContext>) -> RobotLBResult<()> {
+    let name = format!("{}/{}", svc.namespace().unwrap_or_default(), svc.name_any());
+    let mut lb = LoadBalancer::resolve(&svc, context).await?;
+    resolve_targets_and_services(&mut lb, &svc, context).await?;
+    let changes = lb.diff().await?;
+    if changes.is_empty() {
+        println!("Load balancer {} of service {name} is up to date", lb.name);
+        return Ok(());
+    }
+    println!(
+        "Load balancer {} of service {name} will be changed:",
+        lb.name
+    );
+    for change in changes {
+        println!("  - {change}");
+    }
+    Ok(())
+}
+
+/// Read the service manifest from the file, or from stdin if the path is `-`.
+pub fn read_manifest(path: &Path) -> RobotLBResult<Service> {
+    let manifest = read_input(path)?;
+    Ok(serde_yaml::from_str(&manifest)?)
+}
+
+/// Node ports are allocated by Kubernetes once the service is created,
+/// so manifests usually don't have them. They are taken from the service
+/// in the cluster, if it already exists.
+async fn fill_node_ports(svc: &mut Service, context: &CurrentContext) -> RobotLBResult<()> {
+    let api = kube::Api::<Service>::namespaced(
+        context.client.clone(),
+        &svc.namespace().unwrap_or_default(),
+    );
+    let Some(existing) = api.get_opt(&svc.name_any()).await? else {
+        return Ok(());
+    };
+    let existing_ports = existing
+        .spec
+        .and_then(|spec| spec.ports)
+        .unwrap_or_default();
+    let protocol = |protocol: &Option<String>| protocol.as_deref().unwrap_or("TCP").to_string();
+    for port in svc
+        .spec
+        .iter_mut()
+        .flat_map(|spec| spec.ports.iter_mut().flatten())
+    {
+        if port.node_port.is_some() {
+            continue;
+        }
+        port.node_port = existing_ports
+            .iter()
+            .find(|existing| {
+                existing.port == port.port
+                    && protocol(&existing.protocol) == protocol(&port.protocol)
+            })
+            .and_then(|existing| existing.node_port);
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::Service;
+
+use super::plan::print_changes;
+use crate::{
+    config::ReconcileArgs,
+    error::{RobotLBError, RobotLBResult},
+    is_managed, reconcile_service, CurrentContext,
+};
+
+/// Reconcile the service once, or print the changes
+/// the reconcile would make in dry-run mode.
+pub async fn run(args: &ReconcileArgs, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    let (namespace, name) = args
+        .service
+        .split_once('/')
+        .unwrap_or_else(|| (context.client.default_namespace(), &args.service));
+    let api = kube::Api::<Service>::namespaced(context.client.clone(), namespace);
+    let Some(svc) = api.get_opt(name).await? else {
+        println!("Service {namespace}/{name} doesn't exist");
+        return Ok(false);
+    };
+    if !is_managed(&svc, &context.config) {
+        println!("Service {namespace}/{name} is not managed by robotlb");
+        return Ok(false);
+    }
+    let svc = Arc::new(svc);
+    if args.dry_run {
+        print_changes(svc, &context).await?;
+        return Ok(true);
+    }
+    match reconcile_service(svc, context.clone()).await {
+        Ok(_) => {
+            println!("Service {namespace}/{name} is reconciled");
+            Ok(true)
+        }
+        Err(RobotLBError::SkipService) => {
+            println!("Service {namespace}/{name} was skipped, see the logs");
+            Ok(false)
+        }
+        Err(err) => {
+            println!("Cannot reconcile service {namespace}/{name}: {err}");
+            Ok(false)
+        }
+    }
+}
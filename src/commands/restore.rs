@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::Service;
+
+use super::{
+    export::{Backup, BackupEntry, BACKUP_VERSION},
+    read_input,
+};
+use crate::{
+    config::RestoreArgs,
+    error::{RobotLBError, RobotLBResult},
+    is_managed,
+    lb::LoadBalancer,
+    resolve_targets_and_services, CurrentContext,
+};
+
+/// Re-create load balancers from the backup, which don't exist in `HCloud`.
+///
+/// Load balancers of services that still exist are configured from
+/// the services, as the operator would. The rest are configured as they
+/// were at the time of the export.
+pub async fn run(args: &RestoreArgs, context: Arc<CurrentContext>) -> RobotLBResult<bool> {
+    let backup: Backup = serde_yaml::from_str(&read_input(&args.input)?)
+        .map_err(|err| RobotLBError::InvalidBackup(err.to_string()))?;
+    if backup.version != BACKUP_VERSION {
+        return Err(RobotLBError::InvalidBackup(format!(
+            "unsupported version {}, expected {BACKUP_VERSION}",
+            backup.version
+        )));
+    }
+    if backup.cluster_name != context.config.cluster_name {
+        tracing::warn!(
+            "Backup was exported from cluster {}, restoring into {}",
+            backup.cluster_name,
+            context.config.cluster_name
+        );
+    }
+
+    let mut missing = vec![];
+    for entry in backup.load_balancers {
+        let name = format!("{}/{}", entry.namespace, entry.service);
+        match resolve(entry, &context).await? {
+            Ok(lb) if lb.get_hcloud_lb().await?.is_some() => {
+                println!("{name}: load balancer {} exists", lb.name);
+            }
+            Ok(lb) => {
+                println!("{name}: load balancer {} is missing", lb.name);
+                missing.push((name, lb));
+            }
+            Err(reason) => println!("{name}: skipped, {reason}"),
+        }
+    }
+    if missing.is_empty() {
+        println!("No load balancers to restore");
+        return Ok(true);
+    }
+    if !args.confirm {
+        println!("Run with --confirm to re-create them");
+        return Ok(true);
+    }
+
+    let mut failed = 0;
+    for (name, mut lb) in missing {
+        match lb.reconcile().await {
+            Ok(_) => println!("{name}: load balancer {} is re-created", lb.name),
+            Err(err) => {
+                println!("{name}: cannot re-create load balancer {}: {err}", lb.name);
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed == 0)
+}
+
+/// Get the desired load balancer of the backup entry,
+/// or the reason why it can't be restored.
+async fn resolve(
+    entry: BackupEntry,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<Result<LoadBalancer, String>> {
+    let api = kube::Api::<Service>::namespaced(context.client.clone(), &entry.namespace);
+    let svc = if entry.service.is_empty() {
+        None
+    } else {
+        api.get_opt(&entry.service).await?
+    };
+    if let Some(svc) = svc {
+        if !is_managed(&svc, &context.config) {
+            return Ok(Err("service is no longer managed by robotlb".to_string()));
+        }
+        let svc = Arc::new(svc);
+        let mut lb = match LoadBalancer::resolve(&svc, context).await {
+            Ok(lb) => lb,
+            Err(err) => return Ok(Err(err.to_string())),
+        };
+        if let Err(err) = resolve_targets_and_services(&mut lb, &svc, context).await {
+            return Ok(Err(err.to_string()));
+        }
+        return Ok(Ok(lb));
+    }
+    let Some(desired) = entry.desired else {
+        return Ok(Err(
+            "service doesn't exist and its configuration wasn't exported".to_string(),
+        ));
+    };
+    Ok(Ok(LoadBalancer::from_desired(
+        desired,
+        &entry.namespace,
+        &entry.service,
+        context,
+    )))
+}
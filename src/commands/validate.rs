@@ -0,0 +1,279 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use hcloud::apis::{
+    load_balancer_types_api::ListLoadBalancerTypesParams, locations_api::ListLocationsParams,
+    networks_api::ListNetworksParams,
+};
+use k8s_openapi::api::core::v1::Service;
+use kube::{api::ListParams, ResourceExt};
+use serde::Deserialize;
+
+use super::read_input;
+use crate::{
+    config::ValidateArgs,
+    consts,
+    credentials::{project_secret, SecretRef},
+    duration::parse_duration,
+    error::RobotLBResult,
+    hcloud_span::traced,
+    is_managed,
+    label_filter::LabelFilter,
+    lb::{renamed_annotation, LBAlgorithm},
+    CurrentContext,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    /// The service can't be reconciled.
+    Error,
+    /// The service is reconciled, but probably not as the user expects.
+    Warning,
+}
+
+/// Problem found in annotations of a service.
+struct Finding {
+    severity: Severity,
+    message: String,
+}
+
+impl Finding {
+    const fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    const fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+/// Values accepted by `HCloud`, fetched once for all the services.
+struct Catalog {
+    locations: Vec<String>,
+    lb_types: Vec<String>,
+    /// Whether the network with the name exists.
+    networks: HashMap<String, bool>,
+}
+
+/// Validate robotlb annotations of the services from the manifests,
+/// or of all services in the cluster, and print the findings.
+/// Returns whether no errors were found.
+pub async fn run(args: &ValidateArgs, context: &CurrentContext) -> RobotLBResult<bool> {
+    let mut services = vec![];
+    for path in &args.manifests {
+        services.extend(read_services(path)?);
+    }
+    if args.manifests.is_empty() {
+        services = kube::Api::<Service>::all(context.client.clone())
+            .list(&ListParams::default())
+            .await?
+            .items;
+    }
+
+    let mut catalog = Catalog::fetch(context).await?;
+    let mut errors = 0;
+    let mut warnings = 0;
+    let mut validated = 0;
+    for svc in services
+        .iter()
+        .filter(|svc| is_managed(svc, &context.config))
+    {
+        validated += 1;
+        let name = format!(
+            "{}/{}",
+            svc.namespace()
+                .unwrap_or_else(|| context.client.default_namespace().to_string()),
+            svc.name_any()
+        );
+        for finding in validate(svc, context, &mut catalog).await? {
+            match finding.severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+            let severity = match finding.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            println!("{severity}: {name}: {}", finding.message);
+        }
+    }
+    println!("Validated {validated} services: {errors} errors, {warnings} warnings");
+    Ok(errors == 0)
+}
+
+/// Read all services from a multi-document manifest,
+/// or from stdin if the path is `-`.
+fn read_services(path: &Path) -> RobotLBResult<Vec<Service>> {
+    let manifest = read_input(path)?;
+    let mut services = vec![];
+    for document in serde_yaml::Deserializer::from_str(&manifest) {
+        let value = serde_yaml::Value::deserialize(document)?;
+        if value.get("kind").and_then(serde_yaml::Value::as_str) == Some("Service") {
+            services.push(serde_yaml::from_value(value)?);
+        }
+    }
+    Ok(services)
+}
+
+/// Check annotations of the service one by one,
+/// so all the problems are reported at once.
+async fn validate(
+    svc: &Service,
+    context: &CurrentContext,
+    catalog: &mut Catalog,
+) -> RobotLBResult<Vec<Finding>> {
+    let mut findings = vec![];
+    let annotations = svc.annotations();
+    for (key, value) in annotations {
+        if !key.starts_with(consts::ANNOTATION_PREFIX) {
+            continue;
+        }
+        let name = renamed_annotation(key).unwrap_or(key);
+        if name != key {
+            findings.push(Finding::warning(format!(
+                "annotation {key} is deprecated, use {name} instead"
+            )));
+        }
+        let problem = match name {
+            consts::LB_CHECK_INTERVAL_ANN_NAME
+            | consts::LB_TIMEOUT_ANN_NAME
+            | consts::LB_RETRIES_ANN_NAME => i32::from_str(value).err().map(|e| e.to_string()),
+            consts::LB_PROXY_MODE_LABEL_NAME => bool::from_str(value).err().map(|e| e.to_string()),
+            consts::LB_ALGORITHM_LABEL_NAME => LBAlgorithm::from_str(value)
+                .err()
+                .map(|_| "expected `round-robin` or `least-connections`".to_string()),
+            consts::RESYNC_INTERVAL_ANN_NAME => parse_duration(value).err().map(|e| e.to_string()),
+            consts::LB_NODE_SELECTOR => LabelFilter::from_str(value).err().map(|e| e.to_string()),
+            consts::LB_NODE_SELECTOR_JSON => LabelFilter::from_label_selector(value)
+                .err()
+                .map(|e| e.to_string()),
+            consts::LB_LOCATION_LABEL_NAME => one_of(value, &catalog.locations, "known locations"),
+            consts::LB_BALANCER_TYPE_LABEL_NAME | consts::MAX_LB_TYPE_ANN_NAME => {
+                one_of(value, &catalog.lb_types, "known load balancer types")
+            }
+            consts::LB_NETWORK_LABEL_NAME => (!catalog.network_exists(value, context).await?)
+                .then(|| "network doesn't exist".to_string()),
+            consts::PROFILE_ANN_NAME => {
+                (!context.defaults.has_profile(value)).then(|| "profile isn't defined".to_string())
+            }
+            consts::HCLOUD_TOKEN_SECRET_ANN_NAME => SecretRef::for_service(
+                value,
+                &svc.namespace().unwrap_or_default(),
+                &context.config.hcloud_token_secret_namespaces,
+            )
+            .err()
+            .map(|e| e.to_string()),
+            consts::HCLOUD_PROJECT_ANN_NAME => project_secret(value, context)
+                .err()
+                .map(|_| "project isn't configured".to_string()),
+            _ if !consts::ANNOTATIONS.contains(&name) => {
+                findings.push(Finding::warning(format!(
+                    "unknown annotation {key} is ignored"
+                )));
+                continue;
+            }
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            findings.push(Finding::error(format!(
+                "invalid value {value:?} for {key}: {problem}"
+            )));
+        }
+    }
+    if !context.config.dynamic_node_selector
+        && context.config.default_node_selector.is_none()
+        && !annotations.contains_key(consts::LB_NODE_SELECTOR)
+        && !annotations.contains_key(consts::LB_NODE_SELECTOR_JSON)
+    {
+        findings.push(Finding::error(format!(
+            "{} or {} is required, because dynamic node selector is disabled \
+             and there's no default node selector",
+            consts::LB_NODE_SELECTOR,
+            consts::LB_NODE_SELECTOR_JSON
+        )));
+    }
+    if annotations.contains_key(consts::LB_PRIVATE_IP_LABEL_NAME)
+        && !annotations.contains_key(consts::LB_NETWORK_LABEL_NAME)
+        && context.config.default_network.is_none()
+    {
+        findings.push(Finding::warning(format!(
+            "{} has no effect without a network",
+            consts::LB_PRIVATE_IP_LABEL_NAME
+        )));
+    }
+    Ok(findings)
+}
+
+/// Describe the problem if the value isn't one of the valid ones.
+fn one_of(value: &str, valid: &[String], what: &str) -> Option<String> {
+    if valid.iter().any(|valid| valid == value) {
+        return None;
+    }
+    Some(format!("{what} are {}", valid.join(", ")))
+}
+
+impl Catalog {
+    async fn fetch(context: &CurrentContext) -> RobotLBResult<Self> {
+        let locations = traced(
+            "list_locations",
+            None,
+            hcloud::apis::locations_api::list_locations(
+                &context.hcloud_config(),
+                ListLocationsParams::default(),
+            ),
+        )
+        .await?;
+        let lb_types = traced(
+            "list_load_balancer_types",
+            None,
+            hcloud::apis::load_balancer_types_api::list_load_balancer_types(
+                &context.hcloud_config(),
+                ListLoadBalancerTypesParams::default(),
+            ),
+        )
+        .await?;
+        Ok(Self {
+            locations: locations
+                .locations
+                .into_iter()
+                .map(|location| location.name)
+                .collect(),
+            lb_types: lb_types
+                .load_balancer_types
+                .into_iter()
+                .map(|lb_type| lb_type.name)
+                .collect(),
+            networks: HashMap::new(),
+        })
+    }
+
+    async fn network_exists(
+        &mut self,
+        name: &str,
+        context: &CurrentContext,
+    ) -> RobotLBResult<bool> {
+        if let Some(exists) = self.networks.get(name) {
+            return Ok(*exists);
+        }
+        let response = traced(
+            "list_networks",
+            None,
+            hcloud::apis::networks_api::list_networks(
+                &context.hcloud_config(),
+                ListNetworksParams {
+                    name: Some(name.to_string()),
+                    ..Default::default()
+                },
+            ),
+        )
+        .await?;
+        let exists = !response.networks.is_empty();
+        self.networks.insert(name.to_string(), exists);
+        Ok(exists)
+    }
+}
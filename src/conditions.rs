@@ -0,0 +1,72 @@
+use k8s_openapi::{
+    api::core::v1::Service, apimachinery::pkg::apis::meta::v1::Condition as ServiceCondition,
+    chrono::Utc, serde_json::json,
+};
+use kube::{api::PatchParams, ResourceExt};
+
+use crate::error::RobotLBResult;
+
+/// Condition the operator sets in the status of a service.
+pub struct Condition<'a> {
+    /// Type of the condition with the `robotlb/` prefix.
+    pub type_: &'a str,
+    pub status: bool,
+    pub reason: &'a str,
+    pub message: String,
+}
+
+/// Condition of the service with the type, if it's set.
+#[must_use]
+pub fn find<'a>(svc: &'a Service, type_: &str) -> Option<&'a ServiceCondition> {
+    svc.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())?
+        .iter()
+        .find(|condition| condition.type_ == type_)
+}
+
+/// Set the condition of the service, keeping the other ones.
+/// Returns whether the condition has changed.
+///
+/// Nothing is patched if the condition is already set the same way.
+/// False conditions are only set on services which had them before,
+/// so services without problems don't get them at all.
+pub async fn update(
+    client: kube::Client,
+    svc: &Service,
+    condition: Condition<'_>,
+) -> RobotLBResult<bool> {
+    let status = if condition.status { "True" } else { "False" };
+    let current = find(svc, condition.type_);
+    let unchanged = current.map_or(!condition.status, |current| {
+        current.status == status && current.message == condition.message
+    });
+    if unchanged {
+        return Ok(false);
+    }
+    // The time only changes when the status does, not the message.
+    let last_transition_time = current
+        .filter(|current| current.status == status)
+        .map_or_else(Utc::now, |current| current.last_transition_time.0);
+    let svc_api = kube::Api::<Service>::namespaced(client, &svc.namespace().unwrap_or_default());
+    // Conditions are merged by their type, so other conditions are kept.
+    svc_api
+        .patch_status(
+            &svc.name_any(),
+            &PatchParams::default(),
+            &kube::api::Patch::Strategic(json!({
+                "status": {
+                    "conditions": [{
+                        "type": condition.type_,
+                        "status": status,
+                        "reason": condition.reason,
+                        "message": condition.message,
+                        "lastTransitionTime": last_transition_time.to_rfc3339(),
+                        "observedGeneration": svc.metadata.generation,
+                    }]
+                }
+            })),
+        )
+        .await?;
+    Ok(true)
+}
@@ -1,22 +1,247 @@
-use clap::Parser;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::{parser::ValueSource, ArgMatches, Args, Parser, Subcommand, ValueEnum};
 use tracing::level_filters::LevelFilter;
 
+use crate::{
+    clusters::ClusterSpec,
+    credentials::HCloudProject,
+    error::{RobotLBError, RobotLBResult},
+    faults::Fault,
+    label_filter::LabelFilter,
+};
+
+/// Command line of robotlb. Options of the operator are shared
+/// by all commands and must be passed before the command.
 #[derive(Debug, Clone, Parser)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: OperatorConfig,
+
+    /// Command to run. If omitted, the operator is run.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Command to run, `run` if none is given.
+    #[must_use]
+    pub fn resolved_command(&self) -> Command {
+        self.command.clone().unwrap_or(Command::Run)
+    }
+}
+
+// Command line flags are naturally represented as bools.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Args)]
 pub struct OperatorConfig {
     /// `HCloud` API token.
-    #[arg(short = 't', long, env = "ROBOTLB_HCLOUD_TOKEN")]
-    pub hcloud_token: String,
+    #[arg(
+        short = 't',
+        long,
+        env = "ROBOTLB_HCLOUD_TOKEN",
+        required_unless_present_any = ["hcloud_token_file", "vault_address"]
+    )]
+    pub hcloud_token: Option<String>,
+
+    /// Path to a file with the `HCloud` API token, e.g. a mounted secret.
+    /// The file is re-read when it changes, so the token can be rotated
+    /// without restarting the operator.
+    #[arg(
+        long,
+        env = "ROBOTLB_HCLOUD_TOKEN_FILE",
+        conflicts_with = "hcloud_token"
+    )]
+    pub hcloud_token_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub vault: VaultConfig,
+
+    /// Base URL of the `HCloud` API, e.g. of a proxy
+    /// or of a fake API in end-to-end tests.
+    #[arg(
+        long,
+        env = "ROBOTLB_HCLOUD_ENDPOINT",
+        default_value = "https://api.hetzner.cloud/v1"
+    )]
+    pub hcloud_endpoint: String,
+
+    /// How long in seconds `HCloud` tokens read from secrets referenced
+    /// by `robotlb/hcloud-token-secret` are cached before being read again.
+    #[arg(long, env = "ROBOTLB_HCLOUD_TOKEN_CACHE_TTL", default_value = "300")]
+    pub hcloud_token_cache_ttl: u64,
+
+    /// Namespaces whose secrets services of other namespaces may reference
+    /// in `robotlb/hcloud-token-secret`, as a comma-separated list.
+    /// Secrets are read with the operator's permissions, so by default
+    /// services may only reference secrets of their own namespace.
+    #[arg(
+        long,
+        env = "ROBOTLB_HCLOUD_TOKEN_SECRET_NAMESPACES",
+        value_delimiter = ','
+    )]
+    pub hcloud_token_secret_namespaces: Vec<String>,
+
+    /// Named `HCloud` projects services can select with `robotlb/hcloud-project`,
+    /// as comma-separated `<name>=<namespace>/<secret>#<key>` entries.
+    /// Tokens of the projects are read from the referenced secrets.
+    #[arg(long, env = "ROBOTLB_HCLOUD_PROJECTS", value_delimiter = ',')]
+    pub hcloud_projects: Vec<HCloudProject>,
+
+    /// Name of the cluster. Load balancers are labeled with it, so the ones
+    /// that belong to this cluster can be told apart from others
+    /// in the same `HCloud` project.
+    #[arg(long, env = "ROBOTLB_CLUSTER_NAME", default_value = "default")]
+    pub cluster_name: String,
+
+    /// Clusters to reconcile, as comma-separated `<name>=<kubeconfig>[#<context>]`
+    /// entries. If set, the operator runs outside of the clusters and reconciles
+    /// services of all of them. Their names replace the cluster name and are
+    /// prepended to default names of load balancers. Commands other than `run`
+    /// work with the first cluster.
+    #[arg(long, env = "ROBOTLB_CLUSTERS", value_delimiter = ',')]
+    pub clusters: Vec<ClusterSpec>,
+
+    /// Path to the kubeconfig to connect to the cluster with. If not set,
+    /// `KUBECONFIG`, `~/.kube/config` and then the in-cluster configuration are tried.
+    #[arg(long, env = "ROBOTLB_KUBECONFIG", conflicts_with = "clusters")]
+    pub kubeconfig: Option<PathBuf>,
+
+    /// Context of the kubeconfig to use. Defaults to its current context.
+    #[arg(long, env = "ROBOTLB_KUBE_CONTEXT", conflicts_with = "clusters")]
+    pub kube_context: Option<String>,
+
+    /// User to impersonate in requests to Kubernetes, e.g.
+    /// `system:serviceaccount:robotlb:robotlb`, so the operator only has
+    /// the permissions of that account. Its own credentials must allow impersonation.
+    #[arg(long, env = "ROBOTLB_KUBE_IMPERSONATE")]
+    pub kube_impersonate: Option<String>,
+
+    /// Comma-separated groups to impersonate along with the user.
+    #[arg(
+        long,
+        env = "ROBOTLB_KUBE_IMPERSONATE_GROUPS",
+        value_delimiter = ',',
+        requires = "kube_impersonate"
+    )]
+    pub kube_impersonate_groups: Vec<String>,
+
+    /// File with a bearer token to authenticate to Kubernetes with, instead of
+    /// the credentials of the kubeconfig or the in-cluster service account.
+    /// The file is re-read periodically, so rotated tokens are picked up.
+    #[arg(long, env = "ROBOTLB_KUBE_TOKEN_FILE")]
+    pub kube_token_file: Option<PathBuf>,
 
     /// Default network to use for load balancers.
     /// If not set, then only network from the service annotation will be used.
     #[arg(long, env = "ROBOTLB_DEFAULT_NETWORK", default_value = None)]
     pub default_network: Option<String>,
 
+    /// Name of the `ConfigMap` with default annotations of services.
+    /// Its keys are annotation names without the `robotlb/` prefix,
+    /// e.g. `lb-type: lb21`. Annotations of a service take precedence.
+    /// If the `ConfigMap` doesn't exist, there are no defaults.
+    #[arg(
+        long,
+        env = "ROBOTLB_DEFAULTS_CONFIGMAP",
+        default_value = "robotlb-defaults"
+    )]
+    pub defaults_configmap: String,
+
+    /// Namespace of the defaults `ConfigMap`.
+    /// If not set, the namespace of the operator is used.
+    #[arg(long, env = "ROBOTLB_DEFAULTS_CONFIGMAP_NAMESPACE")]
+    pub defaults_configmap_namespace: Option<String>,
+
+    /// Record every managed load balancer with its ID, service and time
+    /// of the last successful reconcile in the inventory `ConfigMap`,
+    /// so they can be looked up without `HCloud` access.
+    /// Disabled with `ROBOTLB_INVENTORY=false`.
+    #[arg(long, env = "ROBOTLB_INVENTORY", default_value = "true")]
+    pub inventory: bool,
+
+    /// Name of the inventory `ConfigMap`. It's created on the first reconcile.
+    #[arg(
+        long,
+        env = "ROBOTLB_INVENTORY_CONFIGMAP",
+        default_value = "robotlb-inventory"
+    )]
+    pub inventory_configmap: String,
+
+    /// Namespace of the inventory `ConfigMap`.
+    /// If not set, the namespace of the operator is used.
+    #[arg(long, env = "ROBOTLB_INVENTORY_CONFIGMAP_NAMESPACE")]
+    pub inventory_configmap_namespace: Option<String>,
+
+    /// Service of the ingress controller in `<namespace>/<name>` format,
+    /// usually of `NodePort` type. If set, load balancers are provisioned
+    /// for ingresses, forwarding to node ports of the service on the nodes
+    /// running its pods, and their IPs are published in statuses of the ingresses.
+    #[arg(long, env = "ROBOTLB_INGRESS_CONTROLLER_SERVICE")]
+    pub ingress_controller_service: Option<String>,
+
+    /// Class of ingresses load balancers are provisioned for.
+    /// Ingresses of other classes only get them with `robotlb/ingress: "true"`.
+    #[arg(long, env = "ROBOTLB_INGRESS_CLASS")]
+    pub ingress_class: Option<String>,
+
+    /// Watch custom resources of robotlb. Their definitions
+    /// must be installed in the cluster.
+    #[arg(long, env = "ROBOTLB_ENABLE_CRDS", default_value = "false")]
+    pub enable_crds: bool,
+
+    /// Name of the `RobotLBConfig` resource with cluster-wide defaults.
+    /// They take precedence over the defaults set by the options.
+    #[arg(long, env = "ROBOTLB_CONFIG_NAME", default_value = "default")]
+    pub robotlb_config_name: String,
+
+    /// Path to a YAML file with named profiles of load balancers.
+    /// Every profile maps annotation names without the `robotlb/` prefix
+    /// to values. A service picks a profile with `robotlb/profile` annotation.
+    #[arg(long, env = "ROBOTLB_PROFILES_FILE")]
+    pub profiles_file: Option<PathBuf>,
+
+    /// Understand the `service.beta.kubernetes.io/*` annotations of other
+    /// cloud providers which have an equivalent in robotlb, e.g. proxy protocol
+    /// and health checks. Annotations of robotlb take precedence over them.
+    /// Disabled with `ROBOTLB_PROVIDER_ANNOTATIONS=false`.
+    #[arg(long, env = "ROBOTLB_PROVIDER_ANNOTATIONS", default_value = "true")]
+    pub provider_annotations: bool,
+
+    /// Understand the `load-balancer.hetzner.cloud/*` annotations
+    /// of hcloud-cloud-controller-manager, so services don't have to be
+    /// annotated again after migrating from it. Annotations of robotlb
+    /// take precedence over them.
+    #[arg(long, env = "ROBOTLB_CCM_COMPAT", default_value = "false")]
+    pub ccm_compat: bool,
+
+    /// Prefixes of service labels copied to their load balancers,
+    /// e.g. `team,cost-center,example.com/`, to keep cost allocation tags
+    /// of the cluster and the `HCloud` project in sync. Nothing is copied if not set.
+    #[arg(long, env = "ROBOTLB_PROPAGATE_LABELS", value_delimiter = ',')]
+    pub propagate_labels: Vec<String>,
+
+    /// Only services with labels matching the selector are managed,
+    /// e.g. `robotlb.io/enabled=true`. The format is the same
+    /// as of the node selector. If not set, all services are managed.
+    #[arg(long, env = "ROBOTLB_SERVICE_SELECTOR")]
+    pub service_selector: Option<LabelFilter>,
+
     /// If enabled, the operator will try to find target nodes based on where the target pods are actually deployed.
     /// If disabled, the operator will try to find target nodes based on the node selector.
     #[arg(long, env = "ROBOTLB_DYNAMIC_NODE_SELECTOR", default_value = "true")]
     pub dynamic_node_selector: bool,
 
+    /// Number of pods listed per request when nodes are found by pods,
+    /// so services selecting thousands of pods are listed in pages.
+    #[arg(long, env = "ROBOTLB_POD_LIST_PAGE_SIZE", default_value = "500")]
+    pub pod_list_page_size: u32,
+
+    /// Node selector for services without `robotlb/node-selector`,
+    /// if dynamic node selector is disabled. If not set, such services are skipped.
+    #[arg(long, env = "ROBOTLB_DEFAULT_NODE_SELECTOR")]
+    pub default_node_selector: Option<LabelFilter>,
+
     /// Default load balancer healthcheck retries cound.
     #[arg(long, env = "ROBOTLB_DEFAULT_LB_RETRIES", default_value = "3")]
     pub default_lb_retries: i32,
@@ -30,13 +255,13 @@ pub struct OperatorConfig {
     pub default_lb_interval: i32,
 
     /// Default location of a load balancer.
-    /// https://docs.hetzner.com/cloud/general/locations/
+    /// <https://docs.hetzner.com/cloud/general/locations/>
     #[arg(long, env = "ROBOTLB_DEFAULT_LB_LOCATION", default_value = "hel1")]
     pub default_lb_location: String,
 
     /// Type of a load balancer. It differs in price, number of connections,
     /// target servers, etc. The default value is the smallest balancer.
-    /// https://docs.hetzner.com/cloud/load-balancers/overview#pricing
+    /// <https://docs.hetzner.com/cloud/load-balancers/overview#pricing>
     #[arg(long, env = "ROBOTLB_DEFAULT_LB_TYPE", default_value = "lb11")]
     pub default_balancer_type: String,
 
@@ -44,7 +269,8 @@ pub struct OperatorConfig {
     /// Possible values:
     /// * `least-connections`
     /// * `round-robin`
-    /// https://docs.hetzner.com/cloud/load-balancers/overview#load-balancers
+    ///
+    /// <https://docs.hetzner.com/cloud/load-balancers/overview#load-balancers>
     #[arg(
         long,
         env = "ROBOTLB_DEFAULT_LB_ALGORITHM",
@@ -54,7 +280,7 @@ pub struct OperatorConfig {
 
     /// Default load balancer proxy mode. If enabled, the load balancer will
     /// act as a proxy for the target servers. The default value is `false`.
-    /// https://docs.hetzner.com/cloud/load-balancers/faq/#what-does-proxy-protocol-mean-and-should-i-enable-it
+    /// <https://docs.hetzner.com/cloud/load-balancers/faq/#what-does-proxy-protocol-mean-and-should-i-enable-it>
     #[arg(
         long,
         env = "ROBOTLB_DEFAULT_LB_PROXY_MODE_ENABLED",
@@ -64,10 +290,676 @@ pub struct OperatorConfig {
 
     /// Whether to enable IPv6 ingress for the load balancer.
     /// If enabled, the load balancer's IPv6 will be attached to the service as an external IP along with IPv4.
+    /// Only applies to single-stack IPv4 services, the families of other services are published as they are.
     #[arg(long, env = "ROBOTLB_IPV6_INGRESS", default_value = "false")]
     pub ipv6_ingress: bool,
 
+    /// Name of an `HCloud` firewall to keep the node ports of load balancers in.
+    /// Each node port is allowed only from the public IPs of its balancer.
+    /// The firewall must exist and be applied to the nodes, other rules of it are kept.
+    /// If not set, firewalls are not managed.
+    #[arg(long, env = "ROBOTLB_NODEPORT_FIREWALL")]
+    pub nodeport_firewall: Option<String>,
+
+    /// Interval in seconds between updates of the node ports firewall.
+    #[arg(
+        long,
+        env = "ROBOTLB_NODEPORT_FIREWALL_INTERVAL",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub nodeport_firewall_interval: u64,
+
+    /// Label nodes targeted by load balancers with `robotlb/lb-target: "true"`
+    /// and annotate them with `robotlb/load-balancers` listing the balancers,
+    /// so drain automation can tell which nodes carry public traffic.
+    #[arg(long, env = "ROBOTLB_NODE_LB_LABELS", default_value = "false")]
+    pub node_lb_labels: bool,
+
+    /// Interval in seconds between updates of the labels of nodes.
+    #[arg(
+        long,
+        env = "ROBOTLB_NODE_LB_LABELS_INTERVAL",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub node_lb_labels_interval: u64,
+
+    /// `reconcile` makes load balancers match their services.
+    /// `observe` only compares them and reports the drift through metrics,
+    /// events and logs, without ever changing anything in `HCloud`
+    /// or on the services.
+    #[arg(long, env = "ROBOTLB_MODE", value_enum, default_value = "reconcile")]
+    pub mode: OperatorMode,
+
+    /// Log the resolved configuration on startup, with the source of every
+    /// value: command line, environment or default. Secrets are masked.
+    #[arg(long, env = "ROBOTLB_PRINT_CONFIG", default_value = "false")]
+    pub print_config: bool,
+
+    /// Make the `run` command reconcile all services once and exit
+    /// instead of watching them.
+    /// The exit code is non-zero if any service failed to reconcile.
+    /// Meant for CI pipelines and cron jobs.
+    #[arg(long, env = "ROBOTLB_ONCE", default_value = "false")]
+    pub once: bool,
+
+    /// Check the `HCloud` token, the default location, load balancer type
+    /// and network before the `run` command starts, and exit if any of them is wrong.
+    /// Disabled with `ROBOTLB_PREFLIGHT=false`.
+    #[arg(long, env = "ROBOTLB_PREFLIGHT", default_value = "true")]
+    pub preflight: bool,
+
+    /// Reconcile services of the load balancers found in `HCloud` before
+    /// the controller starts watching services, so drift accumulated while
+    /// the operator was down is corrected first.
+    /// Disabled with `ROBOTLB_STARTUP_RESYNC=false`.
+    #[arg(long, env = "ROBOTLB_STARTUP_RESYNC", default_value = "true")]
+    pub startup_resync: bool,
+
+    /// Maximum number of services reconciled concurrently.
+    /// `0` means unbounded, `1` forces strictly serial reconciles.
+    /// Higher values speed up large clusters, but hit `HCloud` API rate limits sooner.
+    #[arg(long, env = "ROBOTLB_MAX_CONCURRENT_RECONCILES", default_value = "0")]
+    pub max_concurrent_reconciles: u16,
+
+    /// Interval in seconds between periodic reconciles of successfully
+    /// reconciled services, which pick up changes of their targets.
+    /// Low-churn clusters can increase it to save API quota.
+    #[arg(
+        long,
+        env = "ROBOTLB_RESYNC_INTERVAL",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub resync_interval: u64,
+
+    /// Interval in seconds between checks that a load balancer, which already
+    /// matches the desired state, hasn't drifted from it in `HCloud`.
+    /// Periodic reconciles in between don't fetch the balancer,
+    /// unless its service or targets have changed.
+    /// Must not be less than the resync interval.
+    #[arg(
+        long,
+        env = "ROBOTLB_DRIFT_CHECK_INTERVAL",
+        default_value = "600",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub drift_check_interval: u64,
+
+    /// Delay in seconds before retrying a service that failed
+    /// to reconcile because of a transient error, e.g. `HCloud` API outage.
+    #[arg(
+        long,
+        env = "ROBOTLB_ERROR_REQUEUE_DELAY",
+        default_value = "30",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub error_requeue_delay: u64,
+
+    /// Delay in seconds before retrying a service that failed
+    /// to reconcile, because `HCloud` has rate limited the requests.
+    #[arg(
+        long,
+        env = "ROBOTLB_RATE_LIMIT_REQUEUE_DELAY",
+        default_value = "120",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub rate_limit_requeue_delay: u64,
+
+    /// Delay in seconds before the first retry of a service that failed
+    /// to reconcile because of an error which is unlikely to go away by itself,
+    /// e.g. a missing network. The delay doubles with every consecutive failure.
+    /// Services with invalid annotations are retried only after they change.
+    #[arg(
+        long,
+        env = "ROBOTLB_PERMANENT_ERROR_REQUEUE_DELAY",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub permanent_error_requeue_delay: u64,
+
+    /// Maximum delay in seconds between retries of a failing service.
+    /// Must not be less than the permanent error requeue delay.
+    #[arg(long, env = "ROBOTLB_MAX_ERROR_REQUEUE_DELAY", default_value = "3600")]
+    pub max_error_requeue_delay: u64,
+
+    /// Maximum random delay added to requeue intervals, in percent of the interval.
+    /// Spreads periodic reconciles of services created at the same time.
+    /// Must be between 0 and 100.
+    #[arg(
+        long,
+        env = "ROBOTLB_REQUEUE_JITTER",
+        default_value = "10",
+        value_parser = parse_percent
+    )]
+    pub requeue_jitter: f64,
+
+    /// Address of the HTTP server that exposes Prometheus metrics on `/metrics`.
+    #[arg(
+        long,
+        env = "ROBOTLB_METRICS_BIND_ADDRESS",
+        default_value = "0.0.0.0:9090"
+    )]
+    pub metrics_bind_address: SocketAddr,
+
+    /// Address of the HTTP server with liveness (`/healthz`)
+    /// and readiness (`/readyz`) probes.
+    #[arg(
+        long,
+        env = "ROBOTLB_PROBES_BIND_ADDRESS",
+        default_value = "0.0.0.0:8081"
+    )]
+    pub probes_bind_address: SocketAddr,
+
+    /// Interval in seconds between checks that the `HCloud` token is valid
+    /// and the API is reachable. The operator isn't ready while the check fails.
+    #[arg(
+        long,
+        env = "ROBOTLB_HCLOUD_CHECK_INTERVAL",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub hcloud_check_interval: u64,
+
+    /// Window in seconds within which at least one reconcile must complete
+    /// while there is work to do. Otherwise the controller is considered
+    /// stuck and the liveness probe fails. Must not be less than the resync
+    /// and drift check intervals with jitter, or the operator doesn't start.
+    #[arg(
+        long,
+        env = "ROBOTLB_WATCHDOG_WINDOW",
+        default_value = "900",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub watchdog_window: u64,
+
+    /// Path to a PEM encoded TLS certificate of the metrics server.
+    /// If set along with the key, metrics are served over HTTPS.
+    #[arg(long, env = "ROBOTLB_METRICS_TLS_CERT", default_value = None)]
+    pub metrics_tls_cert: Option<PathBuf>,
+
+    /// Path to a PEM encoded private key of the metrics server.
+    #[arg(long, env = "ROBOTLB_METRICS_TLS_KEY", default_value = None)]
+    pub metrics_tls_key: Option<PathBuf>,
+
+    /// Path to a PEM encoded CA bundle. If set, the metrics server
+    /// requires clients to present a certificate signed by this CA (mTLS).
+    #[arg(long, env = "ROBOTLB_METRICS_TLS_CLIENT_CA", default_value = None)]
+    pub metrics_tls_client_ca: Option<PathBuf>,
+
+    /// If set, requests to the metrics server must carry
+    /// `Authorization: Bearer <token>` header with this token.
+    #[arg(long, env = "ROBOTLB_METRICS_BEARER_TOKEN", default_value = None)]
+    pub metrics_bearer_token: Option<String>,
+
+    /// Expose `/debug/state` on the metrics server with a JSON dump of
+    /// the managed services, their desired load balancers, cached `HCloud`
+    /// objects and results of the last reconciles.
+    #[arg(long, env = "ROBOTLB_DEBUG_ENDPOINT", default_value = "false")]
+    pub debug_endpoint: bool,
+
+    /// Interval in seconds between scrapes of load balancer traffic metrics
+    /// (connections, requests, bandwidth) from the `HCloud` API.
+    /// If not set, traffic metrics are not collected.
+    #[arg(long, env = "ROBOTLB_LB_METRICS_INTERVAL", default_value = None)]
+    pub lb_metrics_interval: Option<u64>,
+
+    /// Percentage of the traffic included into the load balancer price,
+    /// after which a warning event is emitted for the service.
+    /// Traffic above the included quota is billed separately.
+    #[arg(long, env = "ROBOTLB_TRAFFIC_WARNING_THRESHOLD", default_value = "80")]
+    pub traffic_warning_threshold: f64,
+
     // Log level of the operator.
     #[arg(long, env = "ROBOTLB_LOG_LEVEL", default_value = "INFO")]
     pub log_level: LevelFilter,
+
+    /// Format of the logs. `json` is meant for log pipelines
+    /// like Loki or Elasticsearch.
+    #[arg(long, env = "ROBOTLB_LOG_FORMAT", value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Window in seconds within which the same reconcile error is logged only
+    /// once, the rest are counted and summarized. `0` disables sampling.
+    #[arg(long, env = "ROBOTLB_LOG_SAMPLING_WINDOW", default_value = "300")]
+    pub log_sampling_window: u64,
+
+    /// OTLP gRPC endpoint, e.g. `http://otel-collector:4317`, where traces
+    /// of reconciles are exported. If not set, traces are not exported.
+    #[arg(long, env = "ROBOTLB_OTLP_ENDPOINT", default_value = None)]
+    pub otlp_endpoint: Option<String>,
+
+    /// URL of a webhook which receives a JSON payload whenever a load balancer
+    /// is created, deleted, resized or its IP changes. Slack incoming
+    /// webhooks are supported.
+    #[arg(long, env = "ROBOTLB_WEBHOOK_URL", default_value = None)]
+    pub webhook_url: Option<String>,
+
+    /// Start tokio-console server, so async tasks can be inspected live.
+    /// Listens on the address from `TOKIO_CONSOLE_BIND`, `127.0.0.1:6669` by default.
+    #[cfg(feature = "tokio-console")]
+    #[arg(long, env = "ROBOTLB_TOKIO_CONSOLE", default_value = "false")]
+    pub tokio_console: bool,
+
+    /// Sentry DSN. If set, reconcile errors are reported to Sentry
+    /// along with the service and load balancer they relate to.
+    #[arg(long, env = "ROBOTLB_SENTRY_DSN", default_value = None)]
+    pub sentry_dsn: Option<String>,
+
+    /// Environment reported to Sentry, e.g. name of the cluster.
+    #[arg(long, env = "ROBOTLB_SENTRY_ENVIRONMENT", default_value = None)]
+    pub sentry_environment: Option<String>,
+
+    /// Path to a file where logs are written in addition to stdout.
+    /// Useful when the operator runs as a systemd service instead of a pod.
+    #[arg(long, env = "ROBOTLB_LOG_FILE", default_value = None)]
+    pub log_file: Option<PathBuf>,
+
+    /// Path to a file where audit records of every change made in `HCloud`
+    /// are written as JSON lines. The file is rotated as the log file.
+    #[arg(long, env = "ROBOTLB_AUDIT_LOG_FILE", default_value = None)]
+    pub audit_log_file: Option<PathBuf>,
+
+    /// How often the log file is rotated. Rotated files get
+    /// the date and time appended to their name.
+    /// Ignored if the log file is rotated by size.
+    #[arg(
+        long,
+        env = "ROBOTLB_LOG_ROTATION",
+        value_enum,
+        default_value = "daily"
+    )]
+    pub log_rotation: LogRotation,
+
+    /// Maximum size of the log file, e.g. `100M`, after which it's rotated
+    /// instead of rotating it by time. Rotated files get `.1`, `.2` and so on
+    /// appended to their name, `.1` being the latest one.
+    /// Accepts bytes or `K`, `M` and `G` suffixes.
+    #[arg(
+        long,
+        env = "ROBOTLB_LOG_MAX_SIZE",
+        default_value = None,
+        value_parser = parse_size,
+        conflicts_with = "log_rotation"
+    )]
+    pub log_max_size: Option<u64>,
+
+    /// Maximum number of rotated log files to keep.
+    /// If not set, old files are never removed.
+    #[arg(long, env = "ROBOTLB_LOG_MAX_FILES", default_value = None)]
+    pub log_max_files: Option<usize>,
+
+    /// Failures to inject for testing, as comma-separated `<kind>=<value>` entries:
+    /// `hcloud-errors=0.1`, `hcloud-delay=5s` and `watch-disconnect=1m`.
+    /// Never set it in production.
+    #[arg(
+        long,
+        env = "ROBOTLB_INJECT_FAULTS",
+        value_delimiter = ',',
+        hide = true
+    )]
+    pub inject_faults: Vec<Fault>,
+}
+
+/// Options of reading the `HCloud` token from `HashiCorp` Vault.
+#[derive(Debug, Clone, Args)]
+pub struct VaultConfig {
+    /// Address of `HashiCorp` Vault to read the `HCloud` token from,
+    /// e.g. `https://vault.example.com:8200`. The token is read
+    /// from a KV v2 secret and re-read periodically.
+    #[arg(
+        long,
+        env = "ROBOTLB_VAULT_ADDRESS",
+        conflicts_with_all = ["hcloud_token", "hcloud_token_file"]
+    )]
+    pub vault_address: Option<String>,
+
+    /// How the operator authenticates in Vault.
+    #[arg(
+        long,
+        env = "ROBOTLB_VAULT_AUTH",
+        value_enum,
+        default_value = "kubernetes"
+    )]
+    pub vault_auth: VaultAuthMethod,
+
+    /// Path the auth method is mounted at. Defaults to the name of the method.
+    #[arg(long, env = "ROBOTLB_VAULT_AUTH_MOUNT")]
+    pub vault_auth_mount: Option<String>,
+
+    /// Role to log in with Kubernetes auth. The pod's service account token is used.
+    #[arg(long, env = "ROBOTLB_VAULT_ROLE")]
+    pub vault_role: Option<String>,
+
+    /// Role ID to log in with `AppRole` auth.
+    #[arg(long, env = "ROBOTLB_VAULT_ROLE_ID")]
+    pub vault_role_id: Option<String>,
+
+    /// Secret ID to log in with `AppRole` auth.
+    #[arg(long, env = "ROBOTLB_VAULT_SECRET_ID")]
+    pub vault_secret_id: Option<String>,
+
+    /// Path the KV v2 secrets engine is mounted at.
+    #[arg(long, env = "ROBOTLB_VAULT_KV_MOUNT", default_value = "secret")]
+    pub vault_kv_mount: String,
+
+    /// Path of the secret with the `HCloud` token in the secrets engine.
+    #[arg(long, env = "ROBOTLB_VAULT_SECRET_PATH", default_value = "robotlb")]
+    pub vault_secret_path: String,
+
+    /// Key of the `HCloud` token in the secret.
+    #[arg(long, env = "ROBOTLB_VAULT_SECRET_KEY", default_value = "hcloud-token")]
+    pub vault_secret_key: String,
+
+    /// Interval in seconds between reads of the `HCloud` token from Vault.
+    #[arg(long, env = "ROBOTLB_VAULT_REFRESH_INTERVAL", default_value = "60")]
+    pub vault_refresh_interval: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VaultAuthMethod {
+    Kubernetes,
+    #[value(name = "approle")]
+    AppRole,
+}
+
+impl VaultAuthMethod {
+    /// Default path the auth method is mounted at.
+    #[must_use]
+    pub const fn mount(self) -> &'static str {
+        match self {
+            Self::Kubernetes => "kubernetes",
+            Self::AppRole => "approle",
+        }
+    }
+}
+
+/// Parse a percentage between 0 and 100.
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("{value} is not between 0 and 100"));
+    }
+    Ok(percent)
+}
+
+/// Parse a positive size in bytes, optionally with a `K`, `M` or `G` suffix.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let upper = value.trim().to_ascii_uppercase();
+    let (number, multiplier) = [("K", 1 << 10), ("M", 1 << 20), ("G", 1 << 30)]
+        .into_iter()
+        .find_map(|(suffix, multiplier)| Some((upper.strip_suffix(suffix)?, multiplier)))
+        .unwrap_or((upper.as_str(), 1));
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| format!("{value} is not a positive size, e.g. 100M"))
+}
+
+/// Arguments holding secrets, whose values are never printed.
+const SECRET_ARGS: &[&str] = &[
+    "hcloud_token",
+    "vault_secret_id",
+    "metrics_bearer_token",
+    "sentry_dsn",
+    "webhook_url",
+];
+
+impl OperatorConfig {
+    /// Longest interval between periodic reconciles of a service,
+    /// including the jitter.
+    #[must_use]
+    pub fn max_requeue_interval(&self) -> Duration {
+        Duration::from_secs(self.resync_interval.max(self.drift_check_interval))
+            .mul_f64(1.0 + self.requeue_jitter / 100.0)
+    }
+
+    /// Check that the requeue intervals are consistent with each other.
+    pub fn check_requeue_intervals(&self) -> RobotLBResult<()> {
+        if self.drift_check_interval < self.resync_interval {
+            return Err(RobotLBError::InvalidInterval(format!(
+                "drift check interval of {} seconds is less than the resync interval of {} seconds",
+                self.drift_check_interval, self.resync_interval
+            )));
+        }
+        if self.max_error_requeue_delay < self.permanent_error_requeue_delay {
+            return Err(RobotLBError::InvalidInterval(format!(
+                "maximum error requeue delay of {} seconds is less than the permanent error requeue delay of {} seconds",
+                self.max_error_requeue_delay, self.permanent_error_requeue_delay
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that periodic reconciles complete often enough
+    /// for the watchdog not to consider the controller stuck.
+    pub fn check_watchdog_window(&self) -> RobotLBResult<()> {
+        let interval = self.max_requeue_interval();
+        if Duration::from_secs(self.watchdog_window) < interval {
+            return Err(RobotLBError::InvalidWatchdogWindow(format!(
+                "{} seconds is less than the longest requeue interval of {} seconds",
+                self.watchdog_window,
+                interval.as_secs()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Describe every option of the configuration parsed from the `matches`:
+    /// its value and where the value came from. Secrets are masked.
+    #[must_use]
+    pub fn describe(matches: &ArgMatches) -> Vec<String> {
+        Self::augment_args(clap::Command::new("robotlb"))
+            .get_arguments()
+            .filter_map(|arg| {
+                let id = arg.get_id().as_str();
+                let name = arg.get_long()?;
+                let source = match matches.value_source(id) {
+                    Some(ValueSource::CommandLine) => "command line",
+                    Some(ValueSource::EnvVariable) => "env",
+                    Some(ValueSource::DefaultValue) => "default",
+                    Some(_) => "unknown",
+                    None => return Some(format!("--{name} is not set")),
+                };
+                let value = if SECRET_ARGS.contains(&id) {
+                    "<masked>".to_string()
+                } else {
+                    matches
+                        .get_raw(id)
+                        .into_iter()
+                        .flatten()
+                        .map(|value| value.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                };
+                Some(format!("--{name}={value} (from {source})"))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run the operator. This is the default command.
+    Run,
+    /// Run the mutating admission webhook, which writes the resolved
+    /// name, location and type of the load balancer to annotations
+    /// of services at creation.
+    AdmissionWebhook(AdmissionWebhookArgs),
+    #[command(flatten)]
+    Tool(ToolCommand),
+}
+
+/// Operational commands, which do their job once and exit.
+#[derive(Debug, Clone, Subcommand)]
+pub enum ToolCommand {
+    /// Print the changes a reconcile of the service would make
+    /// to its load balancer, without making them.
+    Plan(PlanArgs),
+    /// Check robotlb annotations of services for mistakes.
+    Validate(ValidateArgs),
+    /// Find load balancers of this cluster whose services no longer exist
+    /// and delete them.
+    CleanupOrphans(CleanupOrphansArgs),
+    /// Print load balancers of all managed services.
+    ListManaged(ListManagedArgs),
+    /// Save desired and actual configuration of all managed
+    /// load balancers to a file.
+    Export(ExportArgs),
+    /// Re-create load balancers missing in `HCloud` from an exported file.
+    Restore(RestoreArgs),
+    /// Check permissions in the cluster, access to `HCloud`
+    /// and the configuration before deploying the operator.
+    Doctor,
+    /// Reconcile a single service right away.
+    Reconcile(ReconcileArgs),
+    /// Generate `HetznerLoadBalancer` resources equivalent
+    /// to load balancers of annotated services.
+    Migrate(MigrateArgs),
+    /// Adopt load balancers created by hcloud-cloud-controller-manager
+    /// for services managed by robotlb, keeping their IPs.
+    MigrateCcm(MigrateCcmArgs),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct AdmissionWebhookArgs {
+    /// Address to serve the webhook on.
+    #[arg(
+        long,
+        env = "ROBOTLB_ADMISSION_BIND_ADDRESS",
+        default_value = "0.0.0.0:8443"
+    )]
+    pub bind_address: SocketAddr,
+
+    /// Path to the PEM-encoded TLS certificate of the webhook.
+    #[arg(long, env = "ROBOTLB_ADMISSION_TLS_CERT")]
+    pub tls_cert: PathBuf,
+
+    /// Path to the PEM-encoded private key of the webhook.
+    #[arg(long, env = "ROBOTLB_ADMISSION_TLS_KEY")]
+    pub tls_key: PathBuf,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PlanArgs {
+    /// Path to the service manifest in YAML or JSON. `-` reads it from stdin.
+    #[arg(default_value = "-")]
+    pub manifest: PathBuf,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ValidateArgs {
+    /// Paths to manifests in YAML or JSON. `-` reads them from stdin.
+    /// Objects other than services are ignored. If no manifests are given,
+    /// all services in the cluster are validated.
+    pub manifests: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CleanupOrphansArgs {
+    /// Delete the orphaned load balancers.
+    /// Without it they are only listed.
+    #[arg(long)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ListManagedArgs {
+    #[arg(short, long, value_enum, default_value = "table")]
+    pub output: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable table.
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ExportArgs {
+    /// Path to the file. `-` writes to stdout.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+
+    #[arg(short, long, value_enum, default_value = "yaml")]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Yaml,
+    Json,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct RestoreArgs {
+    /// Path to the file created by `export`. `-` reads it from stdin.
+    #[arg(default_value = "-")]
+    pub input: PathBuf,
+
+    /// Create the missing load balancers.
+    /// Without it they are only listed.
+    #[arg(long)]
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ReconcileArgs {
+    /// Service in `namespace/name` format.
+    /// If the namespace is omitted, the default one is used.
+    pub service: String,
+
+    /// Only print the changes the reconcile would make.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct MigrateArgs {
+    /// Path to the file with the manifests. `-` writes to stdout.
+    #[arg(short, long, default_value = "-")]
+    pub output: PathBuf,
+
+    /// Create the resources in the cluster and mark the services
+    /// as externally managed, so the operator leaves them alone.
+    #[arg(long)]
+    pub apply: bool,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct MigrateCcmArgs {
+    /// Hand the load balancers over to robotlb and reconcile their services.
+    /// The service controller of hcloud-cloud-controller-manager must be
+    /// stopped first, otherwise it takes them back.
+    #[arg(long)]
+    pub apply: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OperatorMode {
+    /// Create, update and delete load balancers.
+    Reconcile,
+    /// Report drift of load balancers from their services, read-only.
+    Observe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable logs.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Weekly,
+    /// Write to a single file that is never rotated.
+    Never,
 }
@@ -1,5 +1,11 @@
 pub const LB_NAME_LABEL_NAME: &str = "robotlb/balancer";
 pub const LB_NODE_SELECTOR: &str = "robotlb/node-selector";
+pub const LB_NODE_SELECTOR_JSON: &str = "robotlb/node-selector-json";
+
+/// Node fields which can be matched by node selectors along with labels.
+pub const NODE_NAME_FIELD: &str = "metadata.name";
+pub const NODE_PROVIDER_ID_FIELD: &str = "spec.providerID";
+pub const NODE_ADDRESS_FIELD_PREFIX: &str = "status.addresses.";
 pub const LB_NODE_IP_LABEL_NAME: &str = "robotlb/node-ip";
 
 // LB config
@@ -12,7 +18,148 @@ pub const LB_PRIVATE_IP_LABEL_NAME: &str = "robotlb/lb-private-ip";
 
 pub const LB_LOCATION_LABEL_NAME: &str = "robotlb/lb-location";
 pub const LB_ALGORITHM_LABEL_NAME: &str = "robotlb/lb-algorithm";
-pub const LB_BALANCER_TYPE_LABEL_NAME: &str = "robotlb/balancer-type";
+pub const LB_BALANCER_TYPE_LABEL_NAME: &str = "robotlb/lb-type";
+/// The largest type the balancer is upgraded to when targets or services don't fit.
+pub const MAX_LB_TYPE_ANN_NAME: &str = "robotlb/max-lb-type";
+/// Custom labels of the balancer in `key=value,key=value` format.
+pub const LB_LABELS_ANN_NAME: &str = "robotlb/lb-labels";
+
+// Operator behaviour
+pub const RESYNC_INTERVAL_ANN_NAME: &str = "robotlb/resync-interval";
+pub const PROFILE_ANN_NAME: &str = "robotlb/profile";
+/// Marks services whose load balancers are managed by other means,
+/// e.g. migrated to `HetznerLoadBalancer` resources.
+pub const EXTERNALLY_MANAGED_ANN_NAME: &str = "robotlb/externally-managed";
+/// Provisions a load balancer for the ingress, whatever its class is.
+pub const INGRESS_ANN_NAME: &str = "robotlb/ingress";
+
+/// ID of the service's load balancer, written by the operator after reconciles,
+/// so the balancer is fetched by ID rather than searched by name.
+pub const LB_ID_ANN_NAME: &str = "robotlb/lb-id";
+/// IDs of `HCloud` actions started by the last reconcile which were still running,
+/// so the next reconcile, e.g. after a crash, waits for them instead of repeating them.
+pub const PENDING_ACTIONS_ANN_NAME: &str = "robotlb/pending-actions";
+/// Keys of the labels from `robotlb/lb-labels` set by the last reconcile,
+/// so the ones removed from the annotation are removed from the balancer.
+pub const APPLIED_LB_LABELS_ANN_NAME: &str = "robotlb/applied-lb-labels";
+
+// Credentials
+/// Secret with the `HCloud` token of the service in `<namespace>/<name>#<key>` format.
+pub const HCLOUD_TOKEN_SECRET_ANN_NAME: &str = "robotlb/hcloud-token-secret";
+/// Name of the `HCloud` project of the service, one of configured in the operator.
+pub const HCLOUD_PROJECT_ANN_NAME: &str = "robotlb/hcloud-project";
+
+// Annotations of hcloud-cloud-controller-manager
+pub const CCM_ANNOTATION_PREFIX: &str = "load-balancer.hetzner.cloud/";
+pub const CCM_NAME_ANN_NAME: &str = "load-balancer.hetzner.cloud/name";
+pub const CCM_LOCATION_ANN_NAME: &str = "load-balancer.hetzner.cloud/location";
+pub const CCM_TYPE_ANN_NAME: &str = "load-balancer.hetzner.cloud/type";
+pub const CCM_ALGORITHM_ANN_NAME: &str = "load-balancer.hetzner.cloud/algorithm-type";
+pub const CCM_PROXY_PROTOCOL_ANN_NAME: &str = "load-balancer.hetzner.cloud/uses-proxyprotocol";
+pub const CCM_PRIVATE_IPV4_ANN_NAME: &str = "load-balancer.hetzner.cloud/private-ipv4";
+pub const CCM_USE_PRIVATE_IP_ANN_NAME: &str = "load-balancer.hetzner.cloud/use-private-ip";
+pub const CCM_PROTOCOL_ANN_NAME: &str = "load-balancer.hetzner.cloud/protocol";
+pub const CCM_CHECK_INTERVAL_ANN_NAME: &str = "load-balancer.hetzner.cloud/health-check-interval";
+pub const CCM_CHECK_TIMEOUT_ANN_NAME: &str = "load-balancer.hetzner.cloud/health-check-timeout";
+pub const CCM_CHECK_RETRIES_ANN_NAME: &str = "load-balancer.hetzner.cloud/health-check-retries";
+/// Label hcloud-cloud-controller-manager sets on its load balancers
+/// with the UID of their service.
+pub const CCM_SERVICE_UID_LABEL_NAME: &str = "hcloud-ccm/service-uid";
+
+// Annotations of other cloud providers
+pub const PROVIDER_ANNOTATION_PREFIX: &str = "service.beta.kubernetes.io/";
+/// Enables proxy protocol when set to `*`.
+pub const AWS_PROXY_PROTOCOL_ANN_NAME: &str =
+    "service.beta.kubernetes.io/aws-load-balancer-proxy-protocol";
+/// Algorithm written as `round_robin` or `least_connections`.
+pub const DO_ALGORITHM_ANN_NAME: &str = "service.beta.kubernetes.io/do-loadbalancer-algorithm";
+/// Annotations of other cloud providers, which mean the same
+/// as annotations of robotlb and have values in the same format.
+pub const PROVIDER_ANNOTATIONS: &[(&str, &str)] = &[
+    (
+        "service.beta.kubernetes.io/aws-load-balancer-healthcheck-interval",
+        LB_CHECK_INTERVAL_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/aws-load-balancer-healthcheck-timeout",
+        LB_TIMEOUT_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/aws-load-balancer-healthcheck-unhealthy-threshold",
+        LB_RETRIES_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/do-loadbalancer-enable-proxy-protocol",
+        LB_PROXY_MODE_LABEL_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/do-loadbalancer-healthcheck-check-interval-seconds",
+        LB_CHECK_INTERVAL_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/do-loadbalancer-healthcheck-response-timeout-seconds",
+        LB_TIMEOUT_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/do-loadbalancer-healthcheck-unhealthy-threshold",
+        LB_RETRIES_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/azure-load-balancer-health-probe-interval",
+        LB_CHECK_INTERVAL_ANN_NAME,
+    ),
+    (
+        "service.beta.kubernetes.io/azure-load-balancer-health-probe-num-of-probe",
+        LB_RETRIES_ANN_NAME,
+    ),
+];
+
+// Labels of load balancers in HCloud
+pub const LB_CLUSTER_LABEL_NAME: &str = "robotlb/cluster";
+pub const LB_NAMESPACE_LABEL_NAME: &str = "robotlb/namespace";
+pub const LB_SERVICE_LABEL_NAME: &str = "robotlb/service";
+pub const LB_RESOURCE_LABEL_NAME: &str = "robotlb/hetzner-load-balancer";
+pub const LB_INGRESS_LABEL_NAME: &str = "robotlb/ingress";
+
+// Labels and annotations of nodes targeted by load balancers
+pub const NODE_LB_TARGET_LABEL_NAME: &str = "robotlb/lb-target";
+/// Comma-separated names of the load balancers targeting the node.
+pub const NODE_LB_NAMES_ANN_NAME: &str = "robotlb/load-balancers";
+
+/// Annotations the operator understands.
+/// Other annotations with the `robotlb/` prefix are most likely typos.
+pub const ANNOTATIONS: &[&str] = &[
+    LB_NAME_LABEL_NAME,
+    LB_NODE_SELECTOR,
+    LB_NODE_SELECTOR_JSON,
+    LB_CHECK_INTERVAL_ANN_NAME,
+    LB_TIMEOUT_ANN_NAME,
+    LB_RETRIES_ANN_NAME,
+    LB_PROXY_MODE_LABEL_NAME,
+    LB_NETWORK_LABEL_NAME,
+    LB_PRIVATE_IP_LABEL_NAME,
+    LB_LOCATION_LABEL_NAME,
+    LB_ALGORITHM_LABEL_NAME,
+    LB_BALANCER_TYPE_LABEL_NAME,
+    MAX_LB_TYPE_ANN_NAME,
+    LB_LABELS_ANN_NAME,
+    RESYNC_INTERVAL_ANN_NAME,
+    PROFILE_ANN_NAME,
+    EXTERNALLY_MANAGED_ANN_NAME,
+    INGRESS_ANN_NAME,
+    LB_ID_ANN_NAME,
+    PENDING_ACTIONS_ANN_NAME,
+    APPLIED_LB_LABELS_ANN_NAME,
+    HCLOUD_TOKEN_SECRET_ANN_NAME,
+    HCLOUD_PROJECT_ANN_NAME,
+];
+
+/// Renamed annotations, as pairs of the legacy and the current name.
+/// Legacy names are still honored, but reported as deprecated.
+pub const ANNOTATION_ALIASES: &[(&str, &str)] =
+    &[("robotlb/balancer-type", LB_BALANCER_TYPE_LABEL_NAME)];
+
+pub const ANNOTATION_PREFIX: &str = "robotlb/";
 
 pub const DEFAULT_LB_RETRIES: i32 = 3;
 pub const DEFAULT_LB_TIMEOUT: i32 = 10;
@@ -22,5 +169,11 @@ pub const DEFAULT_LB_LOCATION: &str = "hel1";
 pub const DEFAULT_LB_ALGORITHM: &str = "least-connections";
 pub const DEFAULT_LB_BALANCER_TYPE: &str = "lb11";
 
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_SHA: &str = env!("ROBOTLB_GIT_SHA");
+pub const RUSTC_VERSION: &str = env!("ROBOTLB_RUSTC_VERSION");
+
 pub const FINALIZER_NAME: &str = "robotlb/finalizer";
+pub const LIMIT_EXCEEDED_CONDITION: &str = "robotlb/LimitExceeded";
+pub const UNSUPPORTED_FIELDS_CONDITION: &str = "robotlb/UnsupportedFields";
 pub const ROBOTLB_LB_CLASS: &str = "robotlb";
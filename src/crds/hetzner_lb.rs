@@ -0,0 +1,188 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{channel::mpsc::Receiver, StreamExt};
+use k8s_openapi::{
+    apimachinery::pkg::apis::meta::v1::ObjectMeta, serde_json::json, NamespaceResourceScope,
+};
+use kube::{
+    api::{Patch, PatchParams},
+    core::TypeMeta,
+    runtime::{controller::Action, finalizer, watcher, Controller},
+    ResourceExt,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{impl_resource, robotlb_config::HealthCheckSpec};
+use crate::{
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    lb::{LoadBalancer, Reconciled},
+    requeue_after_error, requeue_with_jitter, CurrentContext,
+};
+
+/// Load balancer in `HCloud` which isn't tied to any service.
+///
+/// Targets and services are listed explicitly, so it can balance traffic
+/// to servers outside of the cluster as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HetznerLoadBalancer {
+    #[serde(flatten, default)]
+    pub types: Option<TypeMeta>,
+    pub metadata: ObjectMeta,
+    pub spec: HetznerLoadBalancerSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<HetznerLoadBalancerStatus>,
+}
+
+impl_resource!(
+    HetznerLoadBalancer,
+    "HetznerLoadBalancer",
+    "hetznerloadbalancers",
+    NamespaceResourceScope
+);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HetznerLoadBalancerSpec {
+    /// Name of the load balancer in `HCloud`.
+    /// Defaults to the name of the resource.
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub balancer_type: Option<String>,
+    pub algorithm: Option<String>,
+    pub network: Option<String>,
+    pub private_ip: Option<String>,
+    pub proxy_mode: Option<bool>,
+    #[serde(default)]
+    pub health_check: HealthCheckSpec,
+    #[serde(default)]
+    pub services: Vec<ServicePort>,
+    /// IPs of the targets.
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServicePort {
+    pub listen_port: i32,
+    pub destination_port: i32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HetznerLoadBalancerStatus {
+    pub observed_generation: Option<i64>,
+    pub id: Option<i64>,
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+    /// Why the last reconcile has failed.
+    pub error: Option<String>,
+}
+
+/// Run the controller of `HetznerLoadBalancer` resources
+/// until the process is stopped.
+///
+/// All resources are reconciled whenever `policy_updates` receives a message,
+/// so the ones violating a policy are retried once the policy is changed.
+pub async fn run(context: Arc<CurrentContext>, policy_updates: Receiver<()>) {
+    tracing::info!("Starting the HetznerLoadBalancer controller");
+    let api = kube::Api::<HetznerLoadBalancer>::all(context.client.clone());
+    Controller::new(api, watcher::Config::default())
+        .reconcile_all_on(policy_updates)
+        .run(reconcile, on_error, context)
+        .for_each(|result| {
+            match result {
+                Ok((resource, _)) => {
+                    tracing::info!(
+                        "Reconcilation of HetznerLoadBalancer {} was successful",
+                        resource.name
+                    );
+                }
+                Err(err) => tracing::warn!("Error reconciling HetznerLoadBalancer: {}", err),
+            }
+            futures::future::ready(())
+        })
+        .await;
+}
+
+#[tracing::instrument(
+    skip(resource, context),
+    fields(resource = resource.name_any(), namespace = resource.namespace().unwrap_or_default())
+)]
+async fn reconcile(
+    resource: Arc<HetznerLoadBalancer>,
+    context: Arc<CurrentContext>,
+) -> RobotLBResult<Action> {
+    let api = kube::Api::<HetznerLoadBalancer>::namespaced(
+        context.client.clone(),
+        &resource.namespace().unwrap_or_default(),
+    );
+    finalizer::finalizer(&api, consts::FINALIZER_NAME, resource, |event| async {
+        match event {
+            finalizer::Event::Apply(resource) => apply(&resource, &api, &context).await,
+            finalizer::Event::Cleanup(resource) => {
+                LoadBalancer::from_resource(&resource, &context)?
+                    .cleanup()
+                    .await?;
+                context.error_backoff.reset(resource.as_ref());
+                Ok(Action::await_change())
+            }
+        }
+    })
+    .await
+    .map_err(|err| RobotLBError::FinalizerError(Box::new(err)))
+}
+
+async fn apply(
+    resource: &HetznerLoadBalancer,
+    api: &kube::Api<HetznerLoadBalancer>,
+    context: &CurrentContext,
+) -> RobotLBResult<Action> {
+    let mut status = HetznerLoadBalancerStatus {
+        observed_generation: resource.metadata.generation,
+        ..resource.status.clone().unwrap_or_default()
+    };
+    // Policies are enforced when the balancer is applied, but not on cleanup,
+    // so resources created before a policy can still be deleted.
+    let result = match LoadBalancer::from_resource(resource, context)
+        .and_then(|lb| context.policies.check(&lb).map(|()| lb))
+    {
+        Ok(mut lb) => lb.reconcile().await,
+        Err(err) => Err(err),
+    };
+    match &result {
+        Ok(Reconciled { hcloud_lb, .. }) => {
+            status.id = Some(hcloud_lb.id);
+            status.ipv4 = hcloud_lb.public_net.ipv4.ip.clone().flatten();
+            status.ipv6 = hcloud_lb.public_net.ipv6.ip.clone().flatten();
+            status.error = None;
+        }
+        Err(err) => status.error = Some(err.to_string()),
+    }
+    if resource.status.as_ref() != Some(&status) {
+        api.patch_status(
+            &resource.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({ "status": status })),
+        )
+        .await?;
+    }
+    result?;
+    context.error_backoff.reset(resource);
+    Ok(requeue_with_jitter(
+        Duration::from_secs(context.config.resync_interval),
+        context.config.requeue_jitter,
+    ))
+}
+
+// The signature is dictated by the controller.
+#[allow(clippy::needless_pass_by_value)]
+fn on_error(
+    resource: Arc<HetznerLoadBalancer>,
+    err: &RobotLBError,
+    context: Arc<CurrentContext>,
+) -> Action {
+    tracing::warn!("Cannot reconcile HetznerLoadBalancer: {}", err);
+    requeue_after_error(resource.as_ref(), err, &context)
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use futures::{channel::mpsc::Sender, StreamExt};
+use k8s_openapi::{apimachinery::pkg::apis::meta::v1::ObjectMeta, NamespaceResourceScope};
+use kube::{
+    api::ListParams,
+    core::TypeMeta,
+    runtime::{watcher, WatchStreamExt},
+    ResourceExt,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{impl_resource, robotlb_config::RobotLBConfigSpec};
+use crate::{
+    error::{RobotLBError, RobotLBResult},
+    lb::LoadBalancer,
+    CurrentContext,
+};
+
+/// Constraints and defaults of load balancers created
+/// for services in the namespace of the policy.
+///
+/// All policies of the namespace are enforced. Their defaults are merged
+/// in the order of names, so the last one wins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadBalancerPolicy {
+    #[serde(flatten, default)]
+    pub types: Option<TypeMeta>,
+    pub metadata: ObjectMeta,
+    pub spec: LoadBalancerPolicySpec,
+}
+
+impl_resource!(
+    LoadBalancerPolicy,
+    "LoadBalancerPolicy",
+    "loadbalancerpolicies",
+    NamespaceResourceScope
+);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadBalancerPolicySpec {
+    /// Types of load balancers the services may use. Empty allows any.
+    #[serde(default)]
+    pub allowed_types: Vec<String>,
+    /// Locations the services may use. Empty allows any.
+    #[serde(default)]
+    pub allowed_locations: Vec<String>,
+    /// Whether load balancers must use the proxy protocol.
+    #[serde(default)]
+    pub require_proxy_mode: bool,
+    /// Defaults of load balancers, in the same format as `RobotLBConfig`.
+    /// They take precedence over the cluster-wide defaults.
+    pub defaults: Option<RobotLBConfigSpec>,
+}
+
+impl LoadBalancerPolicySpec {
+    /// Describe how the load balancer violates the policy, if it does.
+    fn violation(&self, lb: &LoadBalancer) -> Option<String> {
+        if !self.allowed_types.is_empty() && !self.allowed_types.contains(&lb.balancer_type) {
+            return Some(format!(
+                "type {} isn't allowed, allowed types are {}",
+                lb.balancer_type,
+                self.allowed_types.join(", ")
+            ));
+        }
+        if !self.allowed_locations.is_empty() && !self.allowed_locations.contains(&lb.location) {
+            return Some(format!(
+                "location {} isn't allowed, allowed locations are {}",
+                lb.location,
+                self.allowed_locations.join(", ")
+            ));
+        }
+        if self.require_proxy_mode && !lb.proxy_mode {
+            return Some("proxy mode is required".to_string());
+        }
+        None
+    }
+}
+
+/// Policies of all namespaces, keyed by namespace and name.
+#[derive(Clone, Default)]
+pub struct PolicyStore {
+    policies: Arc<RwLock<BTreeMap<(String, String), LoadBalancerPolicySpec>>>,
+}
+
+impl PolicyStore {
+    /// Defaults of all policies of the namespace as annotations.
+    #[must_use]
+    pub fn defaults(&self, namespace: &str) -> BTreeMap<String, String> {
+        self.policies
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|((policy_namespace, _), _)| policy_namespace == namespace)
+            .filter_map(|(_, spec)| spec.defaults.as_ref())
+            .flat_map(RobotLBConfigSpec::annotations)
+            .collect()
+    }
+
+    /// Types allowed by all policies of the namespace, if any of them restricts types.
+    #[must_use]
+    pub fn allowed_types(&self, namespace: &str) -> Option<BTreeSet<String>> {
+        self.policies
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|((policy_namespace, _), spec)| {
+                policy_namespace == namespace && !spec.allowed_types.is_empty()
+            })
+            .map(|(_, spec)| spec.allowed_types.iter().cloned().collect::<BTreeSet<_>>())
+            .reduce(|allowed, types| allowed.intersection(&types).cloned().collect())
+    }
+
+    /// Check that the load balancer satisfies all policies of its namespace.
+    pub fn check(&self, lb: &LoadBalancer) -> RobotLBResult<()> {
+        let violation = self
+            .policies
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|((namespace, _), _)| *namespace == lb.namespace)
+            .find_map(|((namespace, name), spec)| {
+                spec.violation(lb)
+                    .map(|violation| format!("{namespace}/{name}: {violation}"))
+            });
+        violation.map_or(Ok(()), |violation| {
+            Err(RobotLBError::PolicyViolation(violation))
+        })
+    }
+
+    /// Replace or remove the policy.
+    /// Returns whether the policies have changed.
+    fn set(&self, policy: &LoadBalancerPolicy, spec: Option<LoadBalancerPolicySpec>) -> bool {
+        let key = (policy.namespace().unwrap_or_default(), policy.name_any());
+        let mut policies = self
+            .policies
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let changed = match spec {
+            Some(spec) if policies.get(&key) == Some(&spec) => false,
+            Some(spec) => {
+                policies.insert(key, spec);
+                true
+            }
+            None => policies.remove(&key).is_some(),
+        };
+        if changed {
+            tracing::info!(
+                "LoadBalancerPolicy {}/{} is updated",
+                policy.namespace().unwrap_or_default(),
+                policy.name_any()
+            );
+        }
+        changed
+    }
+
+    /// Replace all policies. Returns whether they have changed.
+    fn replace_all(&self, policies: Vec<LoadBalancerPolicy>) -> bool {
+        let policies = policies
+            .into_iter()
+            .map(|policy| {
+                (
+                    (policy.namespace().unwrap_or_default(), policy.name_any()),
+                    policy.spec,
+                )
+            })
+            .collect();
+        let mut current = self
+            .policies
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if *current == policies {
+            return false;
+        }
+        *current = policies;
+        true
+    }
+}
+
+/// Read all policies once.
+pub async fn load(context: &CurrentContext) -> RobotLBResult<()> {
+    let policies = kube::Api::<LoadBalancerPolicy>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?;
+    context.policies.replace_all(policies.items);
+    Ok(())
+}
+
+/// Keep the policies up to date. Every time they change, a message
+/// is sent to each of `updates`, so all services and resources are reconciled.
+pub async fn watch(context: Arc<CurrentContext>, mut updates: Vec<Sender<()>>) {
+    let api = kube::Api::<LoadBalancerPolicy>::all(context.client.clone());
+    let mut events = watcher(api, watcher::Config::default())
+        .default_backoff()
+        .boxed();
+    // Policies are replaced once all of them are listed, so the ones
+    // deleted while the watch was re-established aren't enforced anymore.
+    let mut listed = vec![];
+    while let Some(event) = events.next().await {
+        let changed = match event {
+            Ok(watcher::Event::Init) => {
+                listed.clear();
+                false
+            }
+            Ok(watcher::Event::InitApply(policy)) => {
+                listed.push(policy);
+                false
+            }
+            Ok(watcher::Event::InitDone) => {
+                context.policies.replace_all(std::mem::take(&mut listed))
+            }
+            Ok(watcher::Event::Apply(policy)) => {
+                context.policies.set(&policy, Some(policy.spec.clone()))
+            }
+            Ok(watcher::Event::Delete(policy)) => context.policies.set(&policy, None),
+            Err(err) => {
+                tracing::warn!("Watch of LoadBalancerPolicies has failed: {}", err);
+                false
+            }
+        };
+        if changed {
+            for updates in &mut updates {
+                updates.try_send(()).ok();
+            }
+        }
+    }
+}
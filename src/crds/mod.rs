@@ -0,0 +1,61 @@
+//! Custom resources of robotlb.
+//!
+//! They are written by hand instead of being derived,
+//! and their definitions live in `helm/crds`.
+
+pub mod hetzner_lb;
+pub mod lb_policy;
+pub mod robotlb_config;
+
+/// API group of all custom resources.
+pub const GROUP: &str = "robotlb.io";
+/// API version of all custom resources.
+pub const VERSION: &str = "v1alpha1";
+
+/// Implement `kube::Resource` for a custom resource with
+/// `metadata` field and static kind, plural name and scope.
+macro_rules! impl_resource {
+    ($resource:ty, $kind:literal, $plural:literal, $scope:ty) => {
+        impl kube::Resource for $resource {
+            type DynamicType = ();
+            type Scope = $scope;
+
+            fn kind(_: &()) -> std::borrow::Cow<'_, str> {
+                $kind.into()
+            }
+
+            fn group(_: &()) -> std::borrow::Cow<'_, str> {
+                crate::crds::GROUP.into()
+            }
+
+            fn version(_: &()) -> std::borrow::Cow<'_, str> {
+                crate::crds::VERSION.into()
+            }
+
+            fn plural(_: &()) -> std::borrow::Cow<'_, str> {
+                $plural.into()
+            }
+
+            fn meta(&self) -> &k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                &self.metadata
+            }
+
+            fn meta_mut(
+                &mut self,
+            ) -> &mut k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+                &mut self.metadata
+            }
+        }
+    };
+}
+
+pub(crate) use impl_resource;
+
+/// Type information written to manifests of custom resources.
+#[must_use]
+pub fn type_meta(kind: &str) -> kube::core::TypeMeta {
+    kube::core::TypeMeta {
+        api_version: format!("{GROUP}/{VERSION}"),
+        kind: kind.to_string(),
+    }
+}
@@ -0,0 +1,201 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use futures::{channel::mpsc::Sender, StreamExt};
+use k8s_openapi::{
+    apimachinery::pkg::apis::meta::v1::ObjectMeta, serde_json::json, ClusterResourceScope,
+};
+use kube::{
+    api::{Patch, PatchParams},
+    core::TypeMeta,
+    runtime::{controller::Action, finalizer, watcher, Controller},
+    ResourceExt,
+};
+use serde::{Deserialize, Serialize};
+
+use super::impl_resource;
+use crate::{
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    CurrentContext,
+};
+
+/// Cluster-wide defaults of load balancers.
+///
+/// Only the one named by `--robotlb-config-name` is used. Its values
+/// take precedence over the defaults set by environment variables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RobotLBConfig {
+    #[serde(flatten, default)]
+    pub types: Option<TypeMeta>,
+    pub metadata: ObjectMeta,
+    pub spec: RobotLBConfigSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<RobotLBConfigStatus>,
+}
+
+impl_resource!(
+    RobotLBConfig,
+    "RobotLBConfig",
+    "robotlbconfigs",
+    ClusterResourceScope
+);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotLBConfigSpec {
+    pub location: Option<String>,
+    pub balancer_type: Option<String>,
+    pub algorithm: Option<String>,
+    pub network: Option<String>,
+    pub proxy_mode: Option<bool>,
+    pub node_selector: Option<String>,
+    #[serde(default)]
+    pub health_check: HealthCheckSpec,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheckSpec {
+    pub interval: Option<i32>,
+    pub timeout: Option<i32>,
+    pub retries: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotLBConfigStatus {
+    pub observed_generation: Option<i64>,
+    /// Whether the operator uses this configuration.
+    pub active: bool,
+}
+
+impl RobotLBConfigSpec {
+    /// The defaults as annotations of a service.
+    #[must_use]
+    pub fn annotations(&self) -> BTreeMap<String, String> {
+        [
+            (consts::LB_LOCATION_LABEL_NAME, self.location.clone()),
+            (
+                consts::LB_BALANCER_TYPE_LABEL_NAME,
+                self.balancer_type.clone(),
+            ),
+            (consts::LB_ALGORITHM_LABEL_NAME, self.algorithm.clone()),
+            (consts::LB_NETWORK_LABEL_NAME, self.network.clone()),
+            (
+                consts::LB_PROXY_MODE_LABEL_NAME,
+                self.proxy_mode.map(|enabled| enabled.to_string()),
+            ),
+            (consts::LB_NODE_SELECTOR, self.node_selector.clone()),
+            (
+                consts::LB_CHECK_INTERVAL_ANN_NAME,
+                self.health_check.interval.map(|value| value.to_string()),
+            ),
+            (
+                consts::LB_TIMEOUT_ANN_NAME,
+                self.health_check.timeout.map(|value| value.to_string()),
+            ),
+            (
+                consts::LB_RETRIES_ANN_NAME,
+                self.health_check.retries.map(|value| value.to_string()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value?)))
+        .collect()
+    }
+}
+
+struct ConfigContext {
+    context: Arc<CurrentContext>,
+    /// Notified when the defaults change, so all services are reconciled.
+    updates: Sender<()>,
+}
+
+/// Read the used `RobotLBConfig` once.
+pub async fn load(context: &CurrentContext) -> RobotLBResult<()> {
+    let config = kube::Api::<RobotLBConfig>::all(context.client.clone())
+        .get_opt(&context.config.robotlb_config_name)
+        .await?;
+    let _ = context.defaults.set_cluster_config(
+        config
+            .map(|config| config.spec.annotations())
+            .unwrap_or_default(),
+    );
+    Ok(())
+}
+
+/// Run the controller of `RobotLBConfig` resources
+/// until the process is stopped.
+pub async fn run(context: Arc<CurrentContext>, updates: Sender<()>) {
+    tracing::info!("Starting the RobotLBConfig controller");
+    let api = kube::Api::<RobotLBConfig>::all(context.client.clone());
+    Controller::new(api, watcher::Config::default())
+        .run(
+            reconcile,
+            on_error,
+            Arc::new(ConfigContext { context, updates }),
+        )
+        .for_each(|result| {
+            if let Err(err) = result {
+                tracing::warn!("Error reconciling RobotLBConfig: {}", err);
+            }
+            futures::future::ready(())
+        })
+        .await;
+}
+
+async fn reconcile(config: Arc<RobotLBConfig>, ctx: Arc<ConfigContext>) -> RobotLBResult<Action> {
+    let api = kube::Api::<RobotLBConfig>::all(ctx.context.client.clone());
+    finalizer::finalizer(&api, consts::FINALIZER_NAME, config, |event| async {
+        match event {
+            finalizer::Event::Apply(config) => apply(&config, &ctx).await,
+            finalizer::Event::Cleanup(config) => Ok(cleanup(&config, &ctx)),
+        }
+    })
+    .await
+    .map_err(|err| RobotLBError::FinalizerError(Box::new(err)))
+}
+
+fn is_active(config: &RobotLBConfig, ctx: &ConfigContext) -> bool {
+    config.name_any() == ctx.context.config.robotlb_config_name
+}
+
+async fn apply(config: &RobotLBConfig, ctx: &ConfigContext) -> RobotLBResult<Action> {
+    let active = is_active(config, ctx);
+    if active
+        && ctx
+            .context
+            .defaults
+            .set_cluster_config(config.spec.annotations())
+    {
+        // The channel is only full if a reconcile of all services
+        // is already pending, so the message isn't needed.
+        ctx.updates.clone().try_send(()).ok();
+    }
+    let status = RobotLBConfigStatus {
+        observed_generation: config.metadata.generation,
+        active,
+    };
+    if config.status.as_ref() != Some(&status) {
+        kube::Api::<RobotLBConfig>::all(ctx.context.client.clone())
+            .patch_status(
+                &config.name_any(),
+                &PatchParams::default(),
+                &Patch::Merge(json!({ "status": status })),
+            )
+            .await?;
+    }
+    Ok(Action::await_change())
+}
+
+fn cleanup(config: &RobotLBConfig, ctx: &ConfigContext) -> Action {
+    if is_active(config, ctx) && ctx.context.defaults.set_cluster_config(BTreeMap::new()) {
+        ctx.updates.clone().try_send(()).ok();
+    }
+    Action::await_change()
+}
+
+fn on_error(_: Arc<RobotLBConfig>, err: &RobotLBError, _: Arc<ConfigContext>) -> Action {
+    tracing::warn!("Cannot apply RobotLBConfig: {}", err);
+    Action::requeue(Duration::from_secs(30))
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use hcloud::apis::configuration::Configuration as HCloudConfig;
+use k8s_openapi::api::core::v1::{Secret, Service};
+use kube::ResourceExt;
+
+use crate::{
+    config::OperatorConfig,
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    vault::Vault,
+    CurrentContext,
+};
+
+/// Reference to a key of a `Secret` in `<namespace>/<name>#<key>` format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SecretRef {
+    pub namespace: String,
+    pub name: String,
+    pub key: String,
+}
+
+impl FromStr for SecretRef {
+    type Err = RobotLBError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            RobotLBError::InvalidSecretReference(format!(
+                "{value}, expected <namespace>/<name>#<key>"
+            ))
+        };
+        let (secret, key) = value.split_once('#').ok_or_else(invalid)?;
+        let (namespace, name) = secret.split_once('/').ok_or_else(invalid)?;
+        if namespace.is_empty() || name.is_empty() || key.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+impl SecretRef {
+    /// Parse the reference set in `robotlb/hcloud-token-secret` of the service.
+    ///
+    /// The secret is read with the operator's permissions, so it must be
+    /// in the namespace of the service or in one of the `allowed_namespaces`.
+    /// Otherwise, anyone creating services could use tokens of other teams.
+    pub fn for_service(
+        value: &str,
+        namespace: &str,
+        allowed_namespaces: &[String],
+    ) -> RobotLBResult<Self> {
+        let reference = Self::from_str(value)?;
+        if reference.namespace != namespace && !allowed_namespaces.contains(&reference.namespace) {
+            return Err(RobotLBError::InvalidSecretReference(format!(
+                "{reference}, secrets of namespace {} can't be referenced from namespace {namespace}",
+                reference.namespace
+            )));
+        }
+        Ok(reference)
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}#{}", self.namespace, self.name, self.key)
+    }
+}
+
+/// `HCloud` project configured in the operator, in `<name>=<secret reference>` format.
+#[derive(Debug, Clone)]
+pub struct HCloudProject {
+    pub name: String,
+    pub secret: SecretRef,
+}
+
+impl FromStr for HCloudProject {
+    type Err = RobotLBError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, secret) = value
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or_else(|| {
+                RobotLBError::InvalidSecretReference(format!(
+                    "{value}, expected <name>=<namespace>/<name>#<key>"
+                ))
+            })?;
+        Ok(Self {
+            name: name.to_string(),
+            secret: SecretRef::from_str(secret)?,
+        })
+    }
+}
+
+/// `HCloud` tokens read from secrets, along with the time they were read.
+#[derive(Clone, Default)]
+pub struct Credentials {
+    tokens: Arc<Mutex<HashMap<SecretRef, (String, Instant)>>>,
+}
+
+impl Credentials {
+    /// `HCloud` configuration used for the load balancer of the service.
+    ///
+    /// If the service references a secret with `robotlb/hcloud-token-secret`,
+    /// the token is taken from it. If it selects a project configured
+    /// in the operator with `robotlb/hcloud-project`, the token is taken
+    /// from the secret of the project. Otherwise, the operator's token is used.
+    pub async fn hcloud_config(
+        &self,
+        svc: &Service,
+        context: &CurrentContext,
+    ) -> RobotLBResult<Arc<HCloudConfig>> {
+        let annotations = context.annotations(svc)?;
+        let reference =
+            if let Some(reference) = annotations.get(consts::HCLOUD_TOKEN_SECRET_ANN_NAME) {
+                SecretRef::for_service(
+                    reference,
+                    &svc.namespace().unwrap_or_default(),
+                    &context.config.hcloud_token_secret_namespaces,
+                )?
+            } else if let Some(project) = annotations.get(consts::HCLOUD_PROJECT_ANN_NAME) {
+                project_secret(project, context)?.clone()
+            } else {
+                return Ok(context.hcloud_config());
+            };
+        let mut hcloud_config = HCloudConfig::clone(&context.hcloud_config());
+        hcloud_config.bearer_access_token = Some(self.token(&reference, context).await?);
+        Ok(Arc::new(hcloud_config))
+    }
+
+    /// Read the token from the secret, unless it was read recently.
+    async fn token(
+        &self,
+        reference: &SecretRef,
+        context: &CurrentContext,
+    ) -> RobotLBResult<String> {
+        let ttl = Duration::from_secs(context.config.hcloud_token_cache_ttl);
+        let cached = self
+            .tokens
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(reference)
+            .filter(|(_, read_at)| read_at.elapsed() < ttl)
+            .map(|(token, _)| token.clone());
+        if let Some(token) = cached {
+            return Ok(token);
+        }
+
+        let secret = kube::Api::<Secret>::namespaced(context.client.clone(), &reference.namespace)
+            .get_opt(&reference.name)
+            .await?
+            .ok_or_else(|| {
+                RobotLBError::HCloudTokenSecretError(format!("{reference}: secret doesn't exist"))
+            })?;
+        let token = secret
+            .data
+            .unwrap_or_default()
+            .remove(&reference.key)
+            .ok_or_else(|| {
+                RobotLBError::HCloudTokenSecretError(format!("{reference}: key doesn't exist"))
+            })
+            .and_then(|value| {
+                String::from_utf8(value.0).map_err(|_| {
+                    RobotLBError::HCloudTokenSecretError(format!("{reference}: token isn't UTF-8"))
+                })
+            })?
+            .trim()
+            .to_string();
+        self.tokens
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(reference.clone(), (token.clone(), Instant::now()));
+        Ok(token)
+    }
+}
+
+/// Secret with the token of the project configured in the operator.
+pub fn project_secret<'a>(
+    project: &str,
+    context: &'a CurrentContext,
+) -> RobotLBResult<&'a SecretRef> {
+    context
+        .config
+        .hcloud_projects
+        .iter()
+        .find(|configured| configured.name == project)
+        .map(|configured| &configured.secret)
+        .ok_or_else(|| RobotLBError::UnknownHCloudProject(project.to_string()))
+}
+
+/// How often the token file is checked for changes.
+const TOKEN_FILE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Backend the operator's `HCloud` token is read from,
+/// when it isn't passed in the configuration directly.
+pub enum SecretBackend {
+    /// File with the token, e.g. a mounted secret.
+    File(PathBuf),
+    /// KV secret in `HashiCorp` Vault.
+    Vault(Box<Vault>),
+}
+
+impl SecretBackend {
+    /// Backend set up in the configuration, if any.
+    pub fn from_config(config: &OperatorConfig) -> RobotLBResult<Option<Self>> {
+        if let Some(path) = &config.hcloud_token_file {
+            return Ok(Some(Self::File(path.clone())));
+        }
+        if let Some(address) = &config.vault.vault_address {
+            return Ok(Some(Self::Vault(Box::new(Vault::new(
+                address,
+                &config.vault,
+            )?))));
+        }
+        Ok(None)
+    }
+
+    /// Read the current token.
+    pub async fn token(&mut self) -> RobotLBResult<String> {
+        match self {
+            Self::File(path) => read_token_file(path),
+            Self::Vault(vault) => vault.token().await,
+        }
+    }
+
+    /// How often the token is read again.
+    const fn refresh_interval(&self, config: &OperatorConfig) -> Duration {
+        match self {
+            Self::File(_) => TOKEN_FILE_POLL_INTERVAL,
+            Self::Vault(_) => Duration::from_secs(config.vault.vault_refresh_interval),
+        }
+    }
+}
+
+/// Read the operator's `HCloud` token from the file.
+pub fn read_token_file(path: &Path) -> RobotLBResult<String> {
+    let token = std::fs::read_to_string(path)?.trim().to_string();
+    if token.is_empty() {
+        return Err(RobotLBError::HCloudTokenSecretError(format!(
+            "{} is empty",
+            path.display()
+        )));
+    }
+    Ok(token)
+}
+
+/// Periodically read the token from the backend and replace
+/// the operator's token when it changes.
+///
+/// Mounted secrets are updated by swapping symlinks, so token files
+/// are polled instead of being watched for events.
+pub async fn watch_token(context: Arc<CurrentContext>, mut backend: SecretBackend) {
+    let mut ticker = tokio::time::interval(backend.refresh_interval(&context.config));
+    // The first tick is immediate, and the token has just been read.
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        match backend.token().await {
+            Ok(token) if context.hcloud_config().bearer_access_token.as_ref() != Some(&token) => {
+                tracing::info!("HCloud token was changed");
+                context.set_hcloud_token(token);
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!("Cannot read HCloud token: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecretRef;
+
+    #[test]
+    fn services_reference_secrets_of_their_namespace() {
+        let reference = SecretRef::for_service("team-a/hcloud#token", "team-a", &[]).unwrap();
+        assert_eq!(reference.to_string(), "team-a/hcloud#token");
+    }
+
+    #[test]
+    fn secrets_of_other_namespaces_must_be_allowed() {
+        assert!(SecretRef::for_service("team-b/hcloud#token", "team-a", &[]).is_err());
+        let allowed = vec!["robotlb".to_string()];
+        assert!(SecretRef::for_service("robotlb/hcloud#token", "team-a", &allowed).is_ok());
+        assert!(SecretRef::for_service("team-b/hcloud#token", "team-a", &allowed).is_err());
+    }
+}
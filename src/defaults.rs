@@ -0,0 +1,261 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use futures::{channel::mpsc::Sender, StreamExt};
+use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use kube::runtime::{watcher, WatchStreamExt};
+
+use crate::{
+    consts,
+    crds::{lb_policy, robotlb_config},
+    error::{RobotLBError, RobotLBResult},
+    lb, CurrentContext,
+};
+
+/// Named sets of annotations, keyed by the profile name.
+pub type Profiles = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Default annotations of services, read from a `ConfigMap`
+/// and `RobotLBConfig` resource, and profiles of load balancers,
+/// read from a file.
+///
+/// Keys of the `ConfigMap` are annotation names without the `robotlb/`
+/// prefix, because slashes aren't allowed in them. The defaults are merged
+/// under annotations of every service, so annotations of the service win.
+/// The profile selected by the service sits between the two.
+/// The `ConfigMap` takes precedence over the `RobotLBConfig`,
+/// and `LoadBalancerPolicy` defaults take precedence over both.
+#[derive(Clone, Default)]
+pub struct AnnotationDefaults {
+    cluster_config: Arc<RwLock<BTreeMap<String, String>>>,
+    annotations: Arc<RwLock<BTreeMap<String, String>>>,
+    profiles: Arc<RwLock<Profiles>>,
+}
+
+/// Replace the annotations behind the lock.
+/// Returns whether they have changed.
+fn replace(lock: &RwLock<BTreeMap<String, String>>, annotations: BTreeMap<String, String>) -> bool {
+    let mut current = lock.write().unwrap_or_else(PoisonError::into_inner);
+    if *current == annotations {
+        return false;
+    }
+    tracing::info!(
+        "Default annotations of services are updated: {:?}",
+        annotations
+    );
+    *current = annotations;
+    true
+}
+
+impl AnnotationDefaults {
+    /// Annotations of the service with its profile
+    /// and the defaults merged under them. Defaults of the namespace
+    /// take precedence over the cluster-wide ones.
+    pub fn merged(
+        &self,
+        svc: &Service,
+        namespace_defaults: BTreeMap<String, String>,
+    ) -> RobotLBResult<BTreeMap<String, String>> {
+        let mut annotations = self
+            .cluster_config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        annotations.extend(
+            self.annotations
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        );
+        annotations.extend(namespace_defaults);
+        let mut own = svc.metadata.annotations.clone().unwrap_or_default();
+        for (legacy, current) in lb::resolve_aliases(&mut own) {
+            tracing::warn!(
+                "Annotation {} is deprecated, use {} instead",
+                legacy,
+                current
+            );
+        }
+        // The profile can be selected by the defaults as well.
+        if let Some(name) = own
+            .get(consts::PROFILE_ANN_NAME)
+            .or_else(|| annotations.get(consts::PROFILE_ANN_NAME))
+            .cloned()
+        {
+            let profile = self
+                .profiles
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&name)
+                .cloned()
+                .ok_or(RobotLBError::UnknownProfile(name))?;
+            annotations.extend(profile);
+        }
+        annotations.extend(own);
+        Ok(annotations)
+    }
+
+    /// Check that the profile is defined.
+    #[must_use]
+    pub fn has_profile(&self, name: &str) -> bool {
+        self.profiles
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(name)
+    }
+
+    pub fn set_profiles(&self, profiles: Profiles) {
+        tracing::info!(
+            "Loaded load balancer profiles: {}",
+            profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        );
+        *self
+            .profiles
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = profiles;
+    }
+
+    /// Replace the defaults with the data of the `ConfigMap`.
+    /// Returns whether the defaults have changed.
+    pub fn set(&self, config_map: Option<&ConfigMap>) -> bool {
+        let mut annotations = BTreeMap::new();
+        for (key, value) in config_map
+            .and_then(|cm| cm.data.as_ref())
+            .into_iter()
+            .flatten()
+        {
+            let name = format!("{}{key}", consts::ANNOTATION_PREFIX);
+            if !consts::ANNOTATIONS.contains(&name.as_str())
+                && lb::renamed_annotation(&name).is_none()
+            {
+                tracing::warn!("Unknown key {} in the defaults ConfigMap, ignoring", key);
+                continue;
+            }
+            annotations.insert(name, value.clone());
+        }
+        for (legacy, current) in lb::resolve_aliases(&mut annotations) {
+            tracing::warn!(
+                "Key {} in the defaults ConfigMap is deprecated, use {} instead",
+                legacy.trim_start_matches(consts::ANNOTATION_PREFIX),
+                current.trim_start_matches(consts::ANNOTATION_PREFIX)
+            );
+        }
+        replace(&self.annotations, annotations)
+    }
+
+    /// Replace the defaults from the `RobotLBConfig` resource.
+    /// Returns whether the defaults have changed.
+    #[must_use]
+    pub fn set_cluster_config(&self, annotations: BTreeMap<String, String>) -> bool {
+        replace(&self.cluster_config, annotations)
+    }
+}
+
+/// Read profiles from the YAML file. Values may be written as YAML
+/// scalars of any type, e.g. `lb-proxy-mode: true`.
+pub fn read_profiles(path: &Path) -> RobotLBResult<Profiles> {
+    let invalid = |err: &dyn std::fmt::Display| {
+        RobotLBError::InvalidProfiles(format!("{}: {err}", path.display()))
+    };
+    let raw: BTreeMap<String, BTreeMap<String, serde_yaml::Value>> =
+        serde_yaml::from_str(&std::fs::read_to_string(path)?).map_err(|err| invalid(&err))?;
+    let mut profiles = Profiles::new();
+    for (name, values) in raw {
+        let mut annotations = BTreeMap::new();
+        for (key, value) in values {
+            let annotation = format!("{}{key}", consts::ANNOTATION_PREFIX);
+            if annotation == consts::PROFILE_ANN_NAME
+                || (!consts::ANNOTATIONS.contains(&annotation.as_str())
+                    && lb::renamed_annotation(&annotation).is_none())
+            {
+                return Err(invalid(&format!("unknown key {key} in profile {name}")));
+            }
+            let value = match value {
+                serde_yaml::Value::String(value) => value,
+                serde_yaml::Value::Bool(value) => value.to_string(),
+                serde_yaml::Value::Number(value) => value.to_string(),
+                _ => {
+                    return Err(invalid(&format!(
+                        "value of {key} in profile {name} isn't a scalar"
+                    )))
+                }
+            };
+            annotations.insert(annotation, value);
+        }
+        for (legacy, current) in lb::resolve_aliases(&mut annotations) {
+            tracing::warn!(
+                "Key {} in profile {} is deprecated, use {} instead",
+                legacy.trim_start_matches(consts::ANNOTATION_PREFIX),
+                name,
+                current.trim_start_matches(consts::ANNOTATION_PREFIX)
+            );
+        }
+        profiles.insert(name, annotations);
+    }
+    Ok(profiles)
+}
+
+fn config_map_api(context: &CurrentContext) -> kube::Api<ConfigMap> {
+    let namespace = context
+        .config
+        .defaults_configmap_namespace
+        .as_deref()
+        .unwrap_or_else(|| context.client.default_namespace());
+    kube::Api::namespaced(context.client.clone(), namespace)
+}
+
+/// Read the defaults once. A missing `ConfigMap` means there are no defaults.
+pub async fn load(context: &CurrentContext) -> RobotLBResult<()> {
+    if context.config.enable_crds {
+        robotlb_config::load(context).await?;
+        lb_policy::load(context).await?;
+    }
+    let config_map = config_map_api(context)
+        .get_opt(&context.config.defaults_configmap)
+        .await?;
+    context.defaults.set(config_map.as_ref());
+    Ok(())
+}
+
+/// Keep the defaults up to date with the `ConfigMap`.
+/// Every time they change, a message is sent to `updates`,
+/// so all services can be reconciled with the new defaults.
+pub async fn watch(context: Arc<CurrentContext>, mut updates: Sender<()>) {
+    let config = watcher::Config::default().fields(&format!(
+        "metadata.name={}",
+        context.config.defaults_configmap
+    ));
+    let mut events = watcher(config_map_api(&context), config)
+        .default_backoff()
+        .boxed();
+    // The `ConfigMap` could be deleted while the watch was re-established,
+    // so the defaults are cleared if it's not listed again.
+    let mut listed = false;
+    while let Some(event) = events.next().await {
+        let changed = match event {
+            Ok(watcher::Event::Init) => {
+                listed = false;
+                false
+            }
+            Ok(watcher::Event::InitApply(config_map) | watcher::Event::Apply(config_map)) => {
+                listed = true;
+                context.defaults.set(Some(&config_map))
+            }
+            Ok(watcher::Event::InitDone) if !listed => context.defaults.set(None),
+            Ok(watcher::Event::InitDone) => false,
+            Ok(watcher::Event::Delete(_)) => context.defaults.set(None),
+            Err(err) => {
+                tracing::warn!("Watch of the defaults ConfigMap has failed: {}", err);
+                false
+            }
+        };
+        if changed {
+            // The channel is only full if a reconcile of all services
+            // is already pending, so the message isn't needed.
+            updates.try_send(()).ok();
+        }
+    }
+}
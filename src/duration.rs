@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use crate::error::{RobotLBError, RobotLBResult};
+
+/// Parse human readable duration.
+/// The string is a sequence of numbers with units, like `1h30m` or `90s`.
+/// Supported units are `s`, `m`, `h` and `d`.
+/// A number without a unit is treated as seconds.
+pub fn parse_duration(value: &str) -> RobotLBResult<Duration> {
+    let invalid = || RobotLBError::InvalidDuration(value.to_string());
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for ch in value.chars() {
+        if ch.is_ascii_digit() {
+            number.push(ch);
+            continue;
+        }
+        let multiplier = match ch {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount = number.parse::<u64>().map_err(|_| invalid())?;
+        total = amount
+            .checked_mul(multiplier)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    // Trailing number without a unit, like `1m30`.
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
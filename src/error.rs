@@ -14,14 +14,101 @@ pub enum RobotLBError {
     PaseIntError(#[from] std::num::ParseIntError),
     #[error("Cannot parse boolean value: {0}")]
     PaseBoolError(#[from] std::str::ParseBoolError),
-    #[error("HCloud error: {0}")]
-    HCloudError(String),
+    #[error("HCloud error: {message}")]
+    HCloudError {
+        kind: HCloudErrorKind,
+        message: String,
+    },
     #[error("Kube error: {0}")]
     KubeError(#[from] kube::Error),
     #[error("Unknown LoadBalancing alorithm")]
     UnknownLBAlgorithm,
+    #[error("Unknown load balancer type: {0}")]
+    UnknownBalancerType(String),
+    #[error("Unknown location: {0}")]
+    UnknownLocation(String),
     #[error("Cannot get target nodes, because the service has no selector")]
     ServiceWithoutSelector,
+    #[error("Cannot parse duration: {0}")]
+    InvalidDuration(String),
+    #[error("{source} (service {namespace}/{service}, load balancer {balancer})")]
+    WithLoadBalancer {
+        namespace: String,
+        service: String,
+        balancer: String,
+        source: Box<Self>,
+    },
+    #[error("Invalid value '{value}' for {name}: {reason}")]
+    InvalidAnnotation {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    #[error("Invalid service manifest: {0}")]
+    InvalidManifest(#[from] serde_yaml::Error),
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+    #[error("Invalid profiles: {0}")]
+    InvalidProfiles(String),
+    #[error("Unknown profile: {0}")]
+    UnknownProfile(String),
+    #[error("Load balancer limit exceeded: {0}")]
+    LimitExceeded(String),
+    #[error("Load balancer name {name} is already used by {kind} {namespace}/{owner} of cluster {cluster}")]
+    LBNameConflict {
+        name: String,
+        /// Kind of the owner: service, ingress or `HetznerLoadBalancer`.
+        kind: &'static str,
+        cluster: String,
+        namespace: String,
+        owner: String,
+    },
+    #[error("Load balancer violates policy {0}")]
+    PolicyViolation(String),
+    #[error("Cannot use the requested load balancer IP {0}")]
+    UnavailableLoadBalancerIP(String),
+    #[error("Unsupported hcloud-cloud-controller-manager annotation: {0}")]
+    UnsupportedCCMAnnotation(String),
+    #[error("Invalid secret reference: {0}")]
+    InvalidSecretReference(String),
+    #[error("Unknown HCloud project: {0}")]
+    UnknownHCloudProject(String),
+    #[error("Invalid cluster: {0}")]
+    InvalidCluster(String),
+    #[error("Invalid fault: {0}")]
+    InvalidFault(String),
+    #[error("Invalid watchdog window: {0}")]
+    InvalidWatchdogWindow(String),
+    #[error("Invalid interval: {0}")]
+    InvalidInterval(String),
+    #[error("HCloud action {0} of the previous reconcile is still running")]
+    ActionInProgress(i64),
+    #[error("Preflight check failed: {0}")]
+    PreflightFailed(String),
+    #[error("Invalid kubeconfig: {0}")]
+    KubeconfigError(#[from] kube::config::KubeconfigError),
+    #[error("Cannot read HCloud token: {0}")]
+    HCloudTokenSecretError(String),
+    #[error("Vault error: {0}")]
+    VaultError(String),
+    #[error("Cannot serialize output: {0}")]
+    SerializationError(String),
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Invalid TLS configuration: {0}")]
+    InvalidTlsConfig(String),
+    #[error("TLS error: {0}")]
+    TlsError(#[from] rustls::Error),
+    #[error("Invalid log file path: {0}")]
+    InvalidLogFile(String),
+    #[error("Cannot open log file: {0}")]
+    LogFileError(#[from] tracing_appender::rolling::InitError),
+    #[error("Cannot set up traces export: {0}")]
+    TracingError(#[from] opentelemetry::trace::TraceError),
+    #[error("Metrics error: {0}")]
+    MetricsError(#[from] prometheus::Error),
+    #[error("Finalizer error: {0}")]
+    FinalizerError(#[source] Box<kube::runtime::finalizer::Error<Self>>),
 
     // HCloud API errors
     #[error("Cannot attach load balancer to a network. Reason: {0}")]
@@ -58,10 +145,18 @@ pub enum RobotLBError {
     HcloudLBDeleteError(
         #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::DeleteLoadBalancerError>,
     ),
+    #[error("Cannot update load balancer. Reason: {0}")]
+    HcloudLBReplaceError(
+        #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::ReplaceLoadBalancerError>,
+    ),
     #[error("Cannot get load balancer. Reason: {0}")]
     HcloudLBGetError(
         #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::GetLoadBalancerError>,
     ),
+    #[error("Cannot get load balancer action. Reason: {0}")]
+    HcloudLBGetActionError(
+        #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::GetLoadBalancerActionError>,
+    ),
     #[error("Cannot update service. Reason: {0}")]
     HcloudLBUpdateServiceError(
         #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::UpdateServiceError>,
@@ -75,6 +170,11 @@ pub enum RobotLBError {
     HcloudLBChangeAlgorithm(
         #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::ChangeAlgorithmError>,
     ),
+    #[error("Cannot get load balancer metrics. Reason: {0}")]
+    HcloudLBMetricsError(
+        #[from]
+        hcloud::apis::Error<hcloud::apis::load_balancers_api::GetMetricsForLoadbalancerError>,
+    ),
     #[error("Cannot list networks. Reason: {0}")]
     HcloudListNetworksError(
         #[from] hcloud::apis::Error<hcloud::apis::networks_api::ListNetworksError>,
@@ -83,4 +183,265 @@ pub enum RobotLBError {
     HcloudListLoadBalancersError(
         #[from] hcloud::apis::Error<hcloud::apis::load_balancers_api::ListLoadBalancersError>,
     ),
+    #[error("Cannot list locations. Reason: {0}")]
+    HcloudListLocationsError(
+        #[from] hcloud::apis::Error<hcloud::apis::locations_api::ListLocationsError>,
+    ),
+    #[error("Cannot list load balancer types. Reason: {0}")]
+    HcloudListLoadBalancerTypesError(
+        #[from]
+        hcloud::apis::Error<hcloud::apis::load_balancer_types_api::ListLoadBalancerTypesError>,
+    ),
+    #[error("Cannot list firewalls. Reason: {0}")]
+    HcloudListFirewallsError(
+        #[from] hcloud::apis::Error<hcloud::apis::firewalls_api::ListFirewallsError>,
+    ),
+    #[error("Cannot set firewall rules. Reason: {0}")]
+    HcloudSetFirewallRulesError(
+        #[from] hcloud::apis::Error<hcloud::apis::firewalls_api::SetRulesError>,
+    ),
+}
+
+/// Class of an error, which determines how the failed reconcile is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The service is misconfigured. Retrying won't help
+    /// until the service is changed.
+    Config,
+    /// Temporary failure, e.g. network issues or an API outage.
+    /// Retried shortly.
+    Transient,
+    /// Failure that is unlikely to go away by itself,
+    /// e.g. a missing network or a rejected request.
+    /// Retried with exponential backoff.
+    Permanent,
+    /// `HCloud` has rate limited the requests. Retried once
+    /// the limit is likely to be replenished.
+    RateLimited,
+}
+
+/// Kind of a failed `HCloud` request, which determines
+/// how the reconcile is retried and how the failure is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HCloudErrorKind {
+    /// Too many requests were made.
+    RateLimited,
+    /// The resource is locked by another action or was changed concurrently.
+    Conflict,
+    /// The resource doesn't exist.
+    NotFound,
+    /// The request was rejected, e.g. an invalid or ambiguous value.
+    InvalidInput,
+    /// `HCloud` has failed to handle the request.
+    ServerError,
+}
+
+impl HCloudErrorKind {
+    /// Classify the failure by the HTTP status code of the API response.
+    const fn from_status(status: u16) -> Self {
+        match status {
+            429 => Self::RateLimited,
+            409 | 423 => Self::Conflict,
+            404 => Self::NotFound,
+            408 | 500..=599 => Self::ServerError,
+            _ => Self::InvalidInput,
+        }
+    }
+
+    /// Kind of the failure, if `HCloud` has responded at all.
+    // `StatusCode::as_u16` isn't const in the `http` version used by `reqwest`.
+    #[allow(clippy::missing_const_for_fn)]
+    fn from_hcloud<T>(err: &hcloud::apis::Error<T>) -> Option<Self> {
+        match err {
+            hcloud::apis::Error::ResponseError(response) => {
+                Some(Self::from_status(response.status.as_u16()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reason of the events reporting the failure.
+    #[must_use]
+    pub const fn reason(self) -> &'static str {
+        match self {
+            Self::RateLimited => "HCloudRateLimited",
+            Self::Conflict => "HCloudConflict",
+            Self::NotFound => "HCloudNotFound",
+            Self::InvalidInput => "HCloudInvalidInput",
+            Self::ServerError => "HCloudServerError",
+        }
+    }
+
+    /// Whether the failure is expected to happen from time to time
+    /// and go away by itself, so it's not worth a warning.
+    #[must_use]
+    pub const fn is_expected(self) -> bool {
+        matches!(self, Self::RateLimited | Self::Conflict)
+    }
+}
+
+impl From<HCloudErrorKind> for ErrorClass {
+    /// Conflicts are resolved once the concurrent action is finished,
+    /// and outages are retried shortly, like unreachable APIs.
+    fn from(kind: HCloudErrorKind) -> Self {
+        match kind {
+            HCloudErrorKind::RateLimited => Self::RateLimited,
+            HCloudErrorKind::Conflict | HCloudErrorKind::ServerError => Self::Transient,
+            HCloudErrorKind::NotFound | HCloudErrorKind::InvalidInput => Self::Permanent,
+        }
+    }
+}
+
+impl ErrorClass {
+    fn from_hcloud<T>(err: &hcloud::apis::Error<T>) -> Self {
+        match err {
+            hcloud::apis::Error::ResponseError(response) => {
+                HCloudErrorKind::from_status(response.status.as_u16()).into()
+            }
+            hcloud::apis::Error::Serde(_) => Self::Permanent,
+            hcloud::apis::Error::Reqwest(_) | hcloud::apis::Error::Io(_) => Self::Transient,
+        }
+    }
+
+    fn from_kube(err: &kube::Error) -> Self {
+        match err {
+            // The Kubernetes API uses the same status codes.
+            kube::Error::Api(response) => HCloudErrorKind::from_status(response.code).into(),
+            _ => Self::Transient,
+        }
+    }
+}
+
+impl RobotLBError {
+    /// The error without the service and the load balancer it relates to.
+    #[must_use]
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::WithLoadBalancer { source, .. } => source.root(),
+            _ => self,
+        }
+    }
+
+    /// Whether the `HCloud` token of the service can't be read,
+    /// e.g. because the referenced secret doesn't exist.
+    #[must_use]
+    pub fn is_missing_credentials(&self) -> bool {
+        matches!(
+            self.root(),
+            Self::HCloudTokenSecretError(_)
+                | Self::InvalidSecretReference(_)
+                | Self::UnknownHCloudProject(_)
+        )
+    }
+
+    /// Kind of the failed `HCloud` request, if the error is one.
+    #[must_use]
+    pub fn hcloud_kind(&self) -> Option<HCloudErrorKind> {
+        match self {
+            Self::WithLoadBalancer { source, .. } => source.hcloud_kind(),
+            Self::HCloudError { kind, .. } => Some(*kind),
+            Self::HCloudLBAttachToNetworkError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBDetachFromNetworkError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBAddTargetError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBRemoveTargetError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBAddServiceError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBRemoveServiceError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBCreateError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBDeleteError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBReplaceError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBGetError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBGetActionError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBUpdateServiceError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBChangeType(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBChangeAlgorithm(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudLBMetricsError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudListNetworksError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudListLoadBalancersError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudListLocationsError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudListLoadBalancerTypesError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudListFirewallsError(err) => HCloudErrorKind::from_hcloud(err),
+            Self::HcloudSetFirewallRulesError(err) => HCloudErrorKind::from_hcloud(err),
+            _ => None,
+        }
+    }
+
+    /// Classify the error to decide how to retry the reconcile.
+    #[must_use]
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::WithLoadBalancer { source, .. } => source.class(),
+            Self::InvalidNodeFilter(_)
+            | Self::UnsupportedServiceType
+            | Self::SkipService
+            | Self::PaseIntError(_)
+            | Self::PaseBoolError(_)
+            | Self::InvalidDuration(_)
+            | Self::InvalidAnnotation { .. }
+            | Self::InvalidManifest(_)
+            | Self::InvalidBackup(_)
+            | Self::InvalidProfiles(_)
+            | Self::UnknownProfile(_)
+            | Self::PolicyViolation(_)
+            | Self::UnsupportedCCMAnnotation(_)
+            | Self::UnavailableLoadBalancerIP(_)
+            | Self::InvalidSecretReference(_)
+            | Self::UnknownHCloudProject(_)
+            | Self::InvalidCluster(_)
+            | Self::InvalidFault(_)
+            | Self::InvalidWatchdogWindow(_)
+            | Self::InvalidInterval(_)
+            | Self::PreflightFailed(_)
+            | Self::KubeconfigError(_)
+            | Self::UnknownLBAlgorithm
+            | Self::UnknownBalancerType(_)
+            | Self::UnknownLocation(_)
+            | Self::ServiceWithoutSelector => ErrorClass::Config,
+            Self::HCloudError { kind, .. } => (*kind).into(),
+            Self::LimitExceeded(_)
+            | Self::LBNameConflict { .. }
+            | Self::HCloudTokenSecretError(_)
+            | Self::VaultError(_)
+            | Self::InvalidTlsConfig(_)
+            | Self::TlsError(_)
+            | Self::InvalidLogFile(_)
+            | Self::TracingError(_)
+            | Self::SerializationError(_) => ErrorClass::Permanent,
+            Self::IOError(_)
+            | Self::MetricsError(_)
+            | Self::LogFileError(_)
+            | Self::ActionInProgress(_) => ErrorClass::Transient,
+            Self::KubeError(err) => ErrorClass::from_kube(err),
+            Self::FinalizerError(err) => match err.as_ref() {
+                kube::runtime::finalizer::Error::ApplyFailed(err)
+                | kube::runtime::finalizer::Error::CleanupFailed(err) => err.class(),
+                kube::runtime::finalizer::Error::AddFinalizer(err)
+                | kube::runtime::finalizer::Error::RemoveFinalizer(err) => {
+                    ErrorClass::from_kube(err)
+                }
+                kube::runtime::finalizer::Error::UnnamedObject
+                | kube::runtime::finalizer::Error::InvalidFinalizer => ErrorClass::Permanent,
+            },
+            Self::HCloudLBAttachToNetworkError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBDetachFromNetworkError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBAddTargetError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBRemoveTargetError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBAddServiceError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBRemoveServiceError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBCreateError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBDeleteError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBReplaceError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBGetError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBGetActionError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBUpdateServiceError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBChangeType(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBChangeAlgorithm(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudLBMetricsError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudListNetworksError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudListLoadBalancersError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudListLocationsError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudListLoadBalancerTypesError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudListFirewallsError(err) => ErrorClass::from_hcloud(err),
+            Self::HcloudSetFirewallRulesError(err) => ErrorClass::from_hcloud(err),
+        }
+    }
 }
@@ -0,0 +1,47 @@
+use k8s_openapi::api::core::v1::Service;
+use kube::{
+    runtime::events::{Event, EventType, Recorder, Reporter},
+    Client, Resource,
+};
+
+use crate::error::RobotLBResult;
+
+/// Name of the controller that reports events.
+const REPORTER_NAME: &str = "robotlb";
+
+/// Publish a warning event attached to the service.
+pub async fn warn(
+    client: Client,
+    svc: &Service,
+    reason: &str,
+    action: &str,
+    note: String,
+) -> RobotLBResult<()> {
+    publish(client, svc, EventType::Warning, reason, action, note).await
+}
+
+/// Publish an event attached to the service.
+pub async fn publish(
+    client: Client,
+    svc: &Service,
+    type_: EventType,
+    reason: &str,
+    action: &str,
+    note: String,
+) -> RobotLBResult<()> {
+    let reporter = Reporter {
+        controller: REPORTER_NAME.to_string(),
+        instance: std::env::var("HOSTNAME").ok(),
+    };
+    let recorder = Recorder::new(client, reporter, svc.object_ref(&()));
+    recorder
+        .publish(Event {
+            type_,
+            reason: reason.to_string(),
+            note: Some(note),
+            action: action.to_string(),
+            secondary: None,
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! Failures injected on purpose, so retries, backoff and cleanup
+//! of the operator can be exercised before it's trusted in production.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use futures::future::BoxFuture;
+use hcloud::{
+    apis::{
+        load_balancer_types_api::ListLoadBalancerTypesParams,
+        load_balancers_api::{
+            AddServiceParams, AddTargetParams, AttachLoadBalancerToNetworkParams,
+            ChangeAlgorithmParams, ChangeTypeOfLoadBalancerParams, CreateLoadBalancerParams,
+            DeleteLoadBalancerParams, DeleteServiceParams, DetachLoadBalancerFromNetworkParams,
+            GetLoadBalancerActionParams, GetLoadBalancerParams, ListLoadBalancersParams,
+            RemoveTargetParams, ReplaceLoadBalancerParams, UpdateServiceParams,
+        },
+        locations_api::ListLocationsParams,
+        networks_api::ListNetworksParams,
+    },
+    models::{
+        AddServiceResponse, AddTargetResponse, AttachLoadBalancerToNetworkResponse,
+        ChangeAlgorithmResponse, ChangeTypeOfLoadBalancerResponse, CreateLoadBalancerResponse,
+        DeleteServiceResponse, DetachLoadBalancerFromNetworkResponse, GetActionResponse,
+        GetLoadBalancerResponse, ListLoadBalancerTypesResponse, ListLoadBalancersResponse,
+        ListLocationsResponse, ListNetworksResponse, RemoveTargetResponse,
+        ReplaceLoadBalancerResponse, UpdateServiceResponse,
+    },
+};
+
+use crate::{
+    duration::parse_duration,
+    error::{HCloudErrorKind, RobotLBError, RobotLBResult},
+    hcloud_api::HcloudApi,
+};
+
+/// Failure to inject, in `<kind>=<value>` format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// `hcloud-errors=0.1`: share of `HCloud` calls failing with a server error.
+    /// Half of the failures happen after the call was made,
+    /// as if the response was lost.
+    HCloudErrors(f64),
+    /// `hcloud-delay=5s`: mutating `HCloud` calls are delayed
+    /// by a random time up to the duration.
+    HCloudDelay(Duration),
+    /// `watch-disconnect=1m`: watches of services are closed
+    /// by the API server after the duration and re-established.
+    WatchDisconnect(Duration),
+}
+
+impl FromStr for Fault {
+    type Err = RobotLBError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| RobotLBError::InvalidFault(format!("{value}, {reason}"));
+        let (kind, argument) = value
+            .split_once('=')
+            .ok_or_else(|| invalid("expected <kind>=<value>"))?;
+        match kind {
+            "hcloud-errors" => {
+                let rate = argument
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|rate| (0.0..=1.0).contains(rate))
+                    .ok_or_else(|| invalid("the rate must be between 0 and 1"))?;
+                Ok(Self::HCloudErrors(rate))
+            }
+            "hcloud-delay" => Ok(Self::HCloudDelay(parse_duration(argument)?)),
+            "watch-disconnect" => {
+                let duration = parse_duration(argument)?;
+                if duration.is_zero() {
+                    return Err(invalid("the duration must be positive"));
+                }
+                Ok(Self::WatchDisconnect(duration))
+            }
+            _ => Err(invalid(
+                "known kinds are hcloud-errors, hcloud-delay and watch-disconnect",
+            )),
+        }
+    }
+}
+
+/// Wrap the `HCloud` API, so its calls fail or are delayed as configured.
+/// The API is returned as is, if no such faults are configured.
+#[must_use]
+pub fn wrap_hcloud(faults: &[Fault], hcloud: Arc<dyn HcloudApi>) -> Arc<dyn HcloudApi> {
+    let mut error_rate = 0.0;
+    let mut max_delay = Duration::ZERO;
+    for fault in faults {
+        match *fault {
+            Fault::HCloudErrors(rate) => error_rate = rate,
+            Fault::HCloudDelay(delay) => max_delay = delay,
+            Fault::WatchDisconnect(_) => {}
+        }
+    }
+    if error_rate == 0.0 && max_delay.is_zero() {
+        return hcloud;
+    }
+    Arc::new(FaultyHcloud {
+        inner: hcloud,
+        error_rate,
+        max_delay,
+    })
+}
+
+/// Timeout in seconds of watches of services, if their disconnects are injected.
+#[must_use]
+pub fn watch_timeout(faults: &[Fault]) -> Option<u32> {
+    faults.iter().find_map(|fault| match fault {
+        Fault::WatchDisconnect(duration) => {
+            Some(u32::try_from(duration.as_secs().max(1)).unwrap_or(u32::MAX))
+        }
+        _ => None,
+    })
+}
+
+/// [`HcloudApi`] failing and delaying calls of another one.
+#[derive(Debug)]
+struct FaultyHcloud {
+    inner: Arc<dyn HcloudApi>,
+    error_rate: f64,
+    max_delay: Duration,
+}
+
+impl FaultyHcloud {
+    /// Make the call, unless it's chosen to fail before it.
+    async fn call<T>(&self, name: &str, call: BoxFuture<'_, RobotLBResult<T>>) -> RobotLBResult<T> {
+        let read_only = name.starts_with("list_") || name.starts_with("get_");
+        if !read_only && !self.max_delay.is_zero() {
+            tokio::time::sleep(self.max_delay.mul_f64(rand::random::<f64>())).await;
+        }
+        let fails = rand::random::<f64>() < self.error_rate;
+        if fails && rand::random::<bool>() {
+            return Err(injected(name));
+        }
+        let result = call.await;
+        if fails {
+            return Err(injected(name));
+        }
+        result
+    }
+}
+
+fn injected(name: &str) -> RobotLBError {
+    tracing::warn!("Injected failure of {}", name);
+    RobotLBError::HCloudError {
+        kind: HCloudErrorKind::ServerError,
+        message: format!("injected failure of {name}"),
+    }
+}
+
+macro_rules! inject {
+    ($($method:ident($params:ty) -> $response:ty;)*) => {
+        impl HcloudApi for FaultyHcloud {
+            $(
+                fn $method(&self, params: $params) -> BoxFuture<'_, RobotLBResult<$response>> {
+                    Box::pin(self.call(stringify!($method), self.inner.$method(params)))
+                }
+            )*
+        }
+    };
+}
+
+inject! {
+    list_load_balancers(ListLoadBalancersParams) -> ListLoadBalancersResponse;
+    get_load_balancer(GetLoadBalancerParams) -> GetLoadBalancerResponse;
+    create_load_balancer(CreateLoadBalancerParams) -> CreateLoadBalancerResponse;
+    replace_load_balancer(ReplaceLoadBalancerParams) -> ReplaceLoadBalancerResponse;
+    delete_load_balancer(DeleteLoadBalancerParams) -> ();
+    change_algorithm(ChangeAlgorithmParams) -> ChangeAlgorithmResponse;
+    change_type_of_load_balancer(ChangeTypeOfLoadBalancerParams) -> ChangeTypeOfLoadBalancerResponse;
+    add_service(AddServiceParams) -> AddServiceResponse;
+    update_service(UpdateServiceParams) -> UpdateServiceResponse;
+    delete_service(DeleteServiceParams) -> DeleteServiceResponse;
+    add_target(AddTargetParams) -> AddTargetResponse;
+    remove_target(RemoveTargetParams) -> RemoveTargetResponse;
+    attach_load_balancer_to_network(AttachLoadBalancerToNetworkParams) -> AttachLoadBalancerToNetworkResponse;
+    detach_load_balancer_from_network(DetachLoadBalancerFromNetworkParams) -> DetachLoadBalancerFromNetworkResponse;
+    list_networks(ListNetworksParams) -> ListNetworksResponse;
+    list_load_balancer_types(ListLoadBalancerTypesParams) -> ListLoadBalancerTypesResponse;
+    list_locations(ListLocationsParams) -> ListLocationsResponse;
+    get_load_balancer_action(GetLoadBalancerActionParams) -> GetActionResponse;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{watch_timeout, Fault};
+
+    #[test]
+    fn parses_faults() {
+        assert_eq!(
+            "hcloud-errors=0.25".parse::<Fault>().unwrap(),
+            Fault::HCloudErrors(0.25)
+        );
+        assert_eq!(
+            "hcloud-delay=2s".parse::<Fault>().unwrap(),
+            Fault::HCloudDelay(Duration::from_secs(2))
+        );
+        assert_eq!(
+            watch_timeout(&["watch-disconnect=1m".parse().unwrap()]),
+            Some(60)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_faults() {
+        for value in [
+            "hcloud-errors",
+            "hcloud-errors=2",
+            "hcloud-delay=soon",
+            "watch-disconnect=0",
+            "node-errors=0.5",
+        ] {
+            assert!(value.parse::<Fault>().is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::{sync::Arc, time::Duration};
+
+use hcloud::{
+    apis::firewalls_api::{ListFirewallsParams, SetRulesParams},
+    models::{
+        rule::{Direction, Protocol},
+        Rule, RuleResponse, SetRulesRequest,
+    },
+};
+use k8s_openapi::serde_json;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    audit,
+    error::{HCloudErrorKind, RobotLBError, RobotLBResult},
+    hcloud_span::traced,
+    lb::list_managed,
+    CurrentContext,
+};
+
+/// Descriptions of the rules managed by the operator start with
+/// `robotlb:<cluster>:`, so rules added by hand and rules of other
+/// clusters sharing the firewall are left as they are.
+const RULE_PREFIX: &str = "robotlb";
+
+/// Periodically update the rules of the firewall, so node ports
+/// of the load balancers are only open to the balancers themselves.
+///
+/// The firewall is created and applied to servers by users,
+/// along with the rules for the rest of the traffic.
+pub async fn run(context: Arc<CurrentContext>, name: String, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = sync(&context, &name).await {
+            tracing::warn!("Cannot update rules of firewall {}: {}", name, err);
+        }
+    }
+}
+
+async fn sync(context: &CurrentContext, name: &str) -> RobotLBResult<()> {
+    let hcloud_config = context.hcloud_config();
+    let firewall = traced(
+        "list_firewalls",
+        None,
+        hcloud::apis::firewalls_api::list_firewalls(
+            &hcloud_config,
+            ListFirewallsParams {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+        ),
+    )
+    .await?
+    .firewalls
+    .into_iter()
+    .next()
+    .ok_or_else(|| RobotLBError::HCloudError {
+        kind: HCloudErrorKind::NotFound,
+        message: format!("Firewall {name} not found"),
+    })?;
+
+    let prefix = format!("{RULE_PREFIX}:{}:", context.config.cluster_name);
+    let current = firewall
+        .rules
+        .iter()
+        .map(to_rule)
+        .collect::<RobotLBResult<Vec<_>>>()?;
+    let mut desired = current
+        .iter()
+        .filter(|rule| !description(rule).starts_with(&prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+    // Balancers are listed from `HCloud`, so the rules don't depend
+    // on services reconciled since the operator has started.
+    for hcloud_lb in
+        list_managed(context.hcloud_api().as_ref(), &context.config.cluster_name).await?
+    {
+        desired.extend(node_port_rules(&hcloud_lb, &prefix));
+    }
+    if desired == current {
+        return Ok(());
+    }
+    let summary = format!(
+        "{} rules of load balancers",
+        desired
+            .iter()
+            .filter(|rule| description(rule).starts_with(&prefix))
+            .count()
+    );
+    tracing::info!("Updating rules of firewall {}: {}", name, summary);
+    let result = traced(
+        "set_rules",
+        None,
+        hcloud::apis::firewalls_api::set_rules(
+            &hcloud_config,
+            SetRulesParams {
+                id: firewall.id,
+                set_rules_request: Some(SetRulesRequest::new(desired)),
+            },
+        ),
+    )
+    .await;
+    // The firewall isn't owned by any service.
+    audit::record(
+        &audit::Entry {
+            namespace: "",
+            service: "",
+            lb_name: name,
+            endpoint: "set_rules",
+            lb_id: None,
+            summary: &summary,
+        },
+        &result,
+    );
+    result?;
+    Ok(())
+}
+
+/// Rules allowing the destination ports of the balancer's services
+/// only from the public IPs of the balancer.
+fn node_port_rules(hcloud_lb: &hcloud::models::LoadBalancer, prefix: &str) -> Vec<Rule> {
+    let ipv4 = hcloud_lb.public_net.ipv4.ip.clone().flatten();
+    let ipv6 = hcloud_lb.public_net.ipv6.ip.clone().flatten();
+    let source_ips = ipv4
+        .map(|ip| format!("{ip}/32"))
+        .into_iter()
+        .chain(ipv6.map(|ip| format!("{ip}/128")))
+        .collect::<Vec<_>>();
+    if source_ips.is_empty() {
+        return vec![];
+    }
+    hcloud_lb
+        .services
+        .iter()
+        .map(|service| Rule {
+            description: Some(Some(format!("{prefix}{}", hcloud_lb.name))),
+            direction: Direction::In,
+            protocol: Protocol::Tcp,
+            port: Some(Some(service.destination_port.to_string())),
+            source_ips: Some(source_ips.clone()),
+            destination_ips: None,
+        })
+        .collect()
+}
+
+/// Convert the rule returned by `HCloud` to the one it accepts,
+/// so the current rules can be compared with the desired ones.
+fn to_rule(rule: &RuleResponse) -> RobotLBResult<Rule> {
+    let ips = |ips: &Vec<String>| Some(ips.clone()).filter(|ips| !ips.is_empty());
+    Ok(Rule {
+        description: rule.description.clone(),
+        direction: convert(&rule.direction)?,
+        protocol: convert(&rule.protocol)?,
+        port: rule.port.clone().map(Some),
+        source_ips: ips(&rule.source_ips),
+        destination_ips: ips(&rule.destination_ips),
+    })
+}
+
+/// Enums of responses and requests are distinct types of the same shape.
+fn convert<T: Serialize, U: DeserializeOwned>(value: &T) -> RobotLBResult<U> {
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .map_err(|err| RobotLBError::SerializationError(err.to_string()))
+}
+
+fn description(rule: &Rule) -> &str {
+    rule.description
+        .as_ref()
+        .and_then(Option::as_deref)
+        .unwrap_or_default()
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use hcloud::{
+    apis::{
+        configuration::Configuration as HcloudConfig,
+        load_balancer_types_api::ListLoadBalancerTypesParams,
+        load_balancers_api::{
+            AddServiceParams, AddTargetParams, AttachLoadBalancerToNetworkParams,
+            ChangeAlgorithmParams, ChangeTypeOfLoadBalancerParams, CreateLoadBalancerParams,
+            DeleteLoadBalancerParams, DeleteServiceParams, DetachLoadBalancerFromNetworkParams,
+            GetLoadBalancerActionParams, GetLoadBalancerParams, ListLoadBalancersParams,
+            RemoveTargetParams, ReplaceLoadBalancerParams, UpdateServiceParams,
+        },
+        locations_api::ListLocationsParams,
+        networks_api::ListNetworksParams,
+    },
+    models::{
+        AddServiceResponse, AddTargetResponse, AttachLoadBalancerToNetworkResponse,
+        ChangeAlgorithmResponse, ChangeTypeOfLoadBalancerResponse, CreateLoadBalancerResponse,
+        DeleteServiceResponse, DetachLoadBalancerFromNetworkResponse, GetActionResponse,
+        GetLoadBalancerResponse, ListLoadBalancerTypesResponse, ListLoadBalancersResponse,
+        ListLocationsResponse, ListNetworksResponse, RemoveTargetResponse,
+        ReplaceLoadBalancerResponse, UpdateServiceResponse,
+    },
+};
+
+use crate::error::RobotLBResult;
+
+/// Operations on load balancers and networks in `HCloud`,
+/// used by [`LoadBalancer`](crate::lb::LoadBalancer).
+///
+/// [`HcloudClient`] calls `HCloud` itself. Other implementations, e.g. an in-memory fake,
+/// let the load balancer logic run without `HCloud`.
+pub trait HcloudApi: Send + Sync + std::fmt::Debug {
+    fn list_load_balancers(
+        &self,
+        params: ListLoadBalancersParams,
+    ) -> BoxFuture<'_, RobotLBResult<ListLoadBalancersResponse>>;
+
+    fn get_load_balancer(
+        &self,
+        params: GetLoadBalancerParams,
+    ) -> BoxFuture<'_, RobotLBResult<GetLoadBalancerResponse>>;
+
+    fn create_load_balancer(
+        &self,
+        params: CreateLoadBalancerParams,
+    ) -> BoxFuture<'_, RobotLBResult<CreateLoadBalancerResponse>>;
+
+    fn replace_load_balancer(
+        &self,
+        params: ReplaceLoadBalancerParams,
+    ) -> BoxFuture<'_, RobotLBResult<ReplaceLoadBalancerResponse>>;
+
+    fn delete_load_balancer(
+        &self,
+        params: DeleteLoadBalancerParams,
+    ) -> BoxFuture<'_, RobotLBResult<()>>;
+
+    fn change_algorithm(
+        &self,
+        params: ChangeAlgorithmParams,
+    ) -> BoxFuture<'_, RobotLBResult<ChangeAlgorithmResponse>>;
+
+    fn change_type_of_load_balancer(
+        &self,
+        params: ChangeTypeOfLoadBalancerParams,
+    ) -> BoxFuture<'_, RobotLBResult<ChangeTypeOfLoadBalancerResponse>>;
+
+    fn add_service(
+        &self,
+        params: AddServiceParams,
+    ) -> BoxFuture<'_, RobotLBResult<AddServiceResponse>>;
+
+    fn update_service(
+        &self,
+        params: UpdateServiceParams,
+    ) -> BoxFuture<'_, RobotLBResult<UpdateServiceResponse>>;
+
+    fn delete_service(
+        &self,
+        params: DeleteServiceParams,
+    ) -> BoxFuture<'_, RobotLBResult<DeleteServiceResponse>>;
+
+    fn add_target(
+        &self,
+        params: AddTargetParams,
+    ) -> BoxFuture<'_, RobotLBResult<AddTargetResponse>>;
+
+    fn remove_target(
+        &self,
+        params: RemoveTargetParams,
+    ) -> BoxFuture<'_, RobotLBResult<RemoveTargetResponse>>;
+
+    fn attach_load_balancer_to_network(
+        &self,
+        params: AttachLoadBalancerToNetworkParams,
+    ) -> BoxFuture<'_, RobotLBResult<AttachLoadBalancerToNetworkResponse>>;
+
+    fn detach_load_balancer_from_network(
+        &self,
+        params: DetachLoadBalancerFromNetworkParams,
+    ) -> BoxFuture<'_, RobotLBResult<DetachLoadBalancerFromNetworkResponse>>;
+
+    fn list_networks(
+        &self,
+        params: ListNetworksParams,
+    ) -> BoxFuture<'_, RobotLBResult<ListNetworksResponse>>;
+
+    fn list_load_balancer_types(
+        &self,
+        params: ListLoadBalancerTypesParams,
+    ) -> BoxFuture<'_, RobotLBResult<ListLoadBalancerTypesResponse>>;
+
+    fn list_locations(
+        &self,
+        params: ListLocationsParams,
+    ) -> BoxFuture<'_, RobotLBResult<ListLocationsResponse>>;
+
+    fn get_load_balancer_action(
+        &self,
+        params: GetLoadBalancerActionParams,
+    ) -> BoxFuture<'_, RobotLBResult<GetActionResponse>>;
+}
+
+/// [`HcloudApi`] calling `HCloud` with the hcloud crate.
+#[derive(Debug, Clone)]
+pub struct HcloudClient {
+    config: Arc<HcloudConfig>,
+}
+
+impl HcloudClient {
+    /// Client with the configuration, which can be shared with other clients.
+    #[must_use]
+    pub fn new(config: impl Into<Arc<HcloudConfig>>) -> Self {
+        Self {
+            config: config.into(),
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($($api:ident::$method:ident($params:ty) -> $response:ty;)*) => {
+        impl HcloudApi for HcloudClient {
+            $(
+                fn $method(&self, params: $params) -> BoxFuture<'_, RobotLBResult<$response>> {
+                    Box::pin(async move {
+                        Ok(hcloud::apis::$api::$method(&self.config, params).await?)
+                    })
+                }
+            )*
+        }
+    };
+}
+
+delegate! {
+    load_balancers_api::list_load_balancers(ListLoadBalancersParams) -> ListLoadBalancersResponse;
+    load_balancers_api::get_load_balancer(GetLoadBalancerParams) -> GetLoadBalancerResponse;
+    load_balancers_api::create_load_balancer(CreateLoadBalancerParams) -> CreateLoadBalancerResponse;
+    load_balancers_api::replace_load_balancer(ReplaceLoadBalancerParams) -> ReplaceLoadBalancerResponse;
+    load_balancers_api::delete_load_balancer(DeleteLoadBalancerParams) -> ();
+    load_balancers_api::change_algorithm(ChangeAlgorithmParams) -> ChangeAlgorithmResponse;
+    load_balancers_api::change_type_of_load_balancer(ChangeTypeOfLoadBalancerParams) -> ChangeTypeOfLoadBalancerResponse;
+    load_balancers_api::add_service(AddServiceParams) -> AddServiceResponse;
+    load_balancers_api::update_service(UpdateServiceParams) -> UpdateServiceResponse;
+    load_balancers_api::delete_service(DeleteServiceParams) -> DeleteServiceResponse;
+    load_balancers_api::add_target(AddTargetParams) -> AddTargetResponse;
+    load_balancers_api::remove_target(RemoveTargetParams) -> RemoveTargetResponse;
+    load_balancers_api::attach_load_balancer_to_network(AttachLoadBalancerToNetworkParams) -> AttachLoadBalancerToNetworkResponse;
+    load_balancers_api::detach_load_balancer_from_network(DetachLoadBalancerFromNetworkParams) -> DetachLoadBalancerFromNetworkResponse;
+    networks_api::list_networks(ListNetworksParams) -> ListNetworksResponse;
+    load_balancer_types_api::list_load_balancer_types(ListLoadBalancerTypesParams) -> ListLoadBalancerTypesResponse;
+    locations_api::list_locations(ListLocationsParams) -> ListLocationsResponse;
+    load_balancers_api::get_load_balancer_action(GetLoadBalancerActionParams) -> GetActionResponse;
+}
@@ -0,0 +1,109 @@
+use std::{future::Future, time::Instant};
+
+use hcloud::models;
+use tracing::{field::Empty, Instrument};
+
+/// Response of an `HCloud` API call.
+/// Mutating calls start an action, which is recorded in the span.
+pub trait HcloudResponse {
+    fn action_id(&self) -> Option<i64>;
+}
+
+macro_rules! with_action {
+    ($($response:ty),* $(,)?) => {
+        $(
+            impl HcloudResponse for $response {
+                fn action_id(&self) -> Option<i64> {
+                    Some(self.action.id)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! without_action {
+    ($($response:ty),* $(,)?) => {
+        $(
+            impl HcloudResponse for $response {
+                fn action_id(&self) -> Option<i64> {
+                    None
+                }
+            }
+        )*
+    };
+}
+
+with_action!(
+    models::AddServiceResponse,
+    models::AddTargetResponse,
+    models::AttachLoadBalancerToNetworkResponse,
+    models::ChangeAlgorithmResponse,
+    models::ChangeTypeOfLoadBalancerResponse,
+    models::CreateLoadBalancerResponse,
+    models::DeleteServiceResponse,
+    models::DetachLoadBalancerFromNetworkResponse,
+    models::RemoveTargetResponse,
+    models::UpdateServiceResponse,
+);
+
+/// Rules of a firewall are applied to each of its resources by a separate action.
+impl HcloudResponse for models::SetRulesResponse {
+    fn action_id(&self) -> Option<i64> {
+        self.actions.first().map(|action| action.id)
+    }
+}
+
+without_action!(
+    (),
+    models::ListFirewallsResponse,
+    models::GetActionResponse,
+    models::GetLoadBalancerResponse,
+    models::GetMetricsForLoadbalancerResponse,
+    models::ListLoadBalancerTypesResponse,
+    models::ListLoadBalancersResponse,
+    models::ListLocationsResponse,
+    models::ListNetworksResponse,
+    models::ReplaceLoadBalancerResponse,
+);
+
+/// Run the `HCloud` API call in its own span.
+///
+/// The span carries the called endpoint, the ID of the load balancer
+/// and the ID of the action started by the call, so traces show which
+/// external call a slow or failed reconcile spent its time on.
+pub async fn traced<T, E>(
+    endpoint: &'static str,
+    lb_id: Option<i64>,
+    call: impl Future<Output = Result<T, E>>,
+) -> Result<T, E>
+where
+    T: HcloudResponse,
+    E: std::fmt::Display,
+{
+    let span = tracing::info_span!("hcloud", endpoint, lb_id, action_id = Empty, error = Empty);
+    let started = Instant::now();
+    let result = call.instrument(span.clone()).await;
+    let _entered = span.enter();
+    match &result {
+        Ok(response) => {
+            if let Some(action_id) = response.action_id() {
+                span.record("action_id", action_id);
+            }
+            tracing::debug!(
+                "HCloud call {} finished in {:?}",
+                endpoint,
+                started.elapsed()
+            );
+        }
+        Err(err) => {
+            span.record("error", tracing::field::display(err));
+            tracing::debug!(
+                "HCloud call {} failed in {:?}: {}",
+                endpoint,
+                started.elapsed(),
+                err
+            );
+        }
+    }
+    result
+}
@@ -0,0 +1,201 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use hcloud::apis::load_balancers_api::ListLoadBalancersParams;
+
+use crate::{
+    error::{RobotLBError, RobotLBResult},
+    hcloud_span::traced,
+    CurrentContext,
+};
+
+/// Health state of the operator, used by liveness and readiness probes.
+///
+/// The struct is cheap to clone, all the clones share the same state.
+#[derive(Clone)]
+pub struct Health {
+    /// Whether the initial list of services was received from
+    /// the Kubernetes API and the watch is established.
+    watch_ready: Arc<AtomicBool>,
+    /// Reason why the `HCloud` API cannot be used,
+    /// if the last check has failed.
+    hcloud_error: Arc<RwLock<Option<String>>>,
+    /// Time when the controller loop has completed the last reconcile.
+    last_heartbeat: Arc<RwLock<Instant>>,
+    /// Reason why the operator is considered stuck by the watchdog.
+    stuck: Arc<RwLock<Option<String>>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            watch_ready: Arc::default(),
+            hcloud_error: Arc::default(),
+            last_heartbeat: Arc::new(RwLock::new(Instant::now())),
+            stuck: Arc::default(),
+        }
+    }
+}
+
+impl Health {
+    /// Record that the controller loop has completed a reconcile.
+    pub fn heartbeat(&self) {
+        *self
+            .last_heartbeat
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    /// Check if the operator is alive.
+    /// Returns the reason if it's not.
+    pub fn liveness(&self) -> Result<(), String> {
+        self.stuck
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map_or(Ok(()), |reason| Err(reason.clone()))
+    }
+
+    /// Mark the watch of services as established.
+    pub fn set_watch_ready(&self) {
+        self.watch_ready.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if the operator is ready to reconcile services.
+    /// Returns the reason if it's not.
+    pub fn readiness(&self) -> Result<(), String> {
+        if !self.watch_ready.load(Ordering::Relaxed) {
+            return Err("Watch of services is not established yet".to_string());
+        }
+        if let Some(reason) = self
+            .hcloud_error
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            return Err(reason.clone());
+        }
+        Ok(())
+    }
+
+    /// Record the result of the `HCloud` API check.
+    /// Changes of the state are logged.
+    fn set_hcloud_error(&self, error: Option<String>) {
+        let mut hcloud_error = self
+            .hcloud_error
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        match (hcloud_error.as_ref(), error.as_ref()) {
+            (None, Some(reason)) => tracing::error!("Marking operator as not ready: {}", reason),
+            (Some(_), None) => tracing::info!("HCloud API is reachable again"),
+            _ => {}
+        }
+        *hcloud_error = error;
+    }
+}
+
+/// Periodically check that the `HCloud` token is valid and
+/// the API is reachable. The operator is not ready while it's not.
+///
+/// The API is taken from the context on every check,
+/// so the rotated token and injected faults apply to it.
+pub async fn watch_hcloud(context: Arc<CurrentContext>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let hcloud = context.hcloud_api();
+        let response = traced(
+            "list_load_balancers",
+            None,
+            hcloud.list_load_balancers(ListLoadBalancersParams {
+                per_page: Some(1),
+                ..Default::default()
+            }),
+        )
+        .await;
+        let error = match response {
+            Ok(_) => None,
+            Err(RobotLBError::HcloudListLoadBalancersError(
+                hcloud::apis::Error::ResponseError(response),
+            )) => match response.status.as_u16() {
+                401 => Some("HCloud token is invalid or was revoked".to_string()),
+                403 => Some("HCloud token has no permission to list load balancers".to_string()),
+                status => Some(format!("HCloud API responded with status {status}")),
+            },
+            Err(err @ RobotLBError::HCloudError { .. }) => {
+                Some(format!("HCloud API has failed: {err}"))
+            }
+            Err(err) => Some(format!("HCloud API is unreachable: {err}")),
+        };
+        context.health.set_hcloud_error(error);
+    }
+}
+
+/// Watch that the controller makes progress.
+///
+/// If there's work to do (some reconciles are running or are due to run),
+/// but no reconcile has completed within the `window`, the operator
+/// is considered stuck and the liveness probe starts failing,
+/// so the operator gets restarted.
+pub async fn watchdog(context: Arc<CurrentContext>, window: Duration) {
+    let health = &context.health;
+    let mut ticker = tokio::time::interval(window / 4);
+    loop {
+        ticker.tick().await;
+        let since_heartbeat = health
+            .last_heartbeat
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed();
+        let has_work =
+            context.metrics.reconciles_in_flight() > 0 || context.metrics.reconciles_queued() > 0;
+        let stuck = (has_work && since_heartbeat > window).then(|| {
+            format!(
+                "No reconcile has completed in the last {} seconds",
+                since_heartbeat.as_secs()
+            )
+        });
+        let mut current = health.stuck.write().unwrap_or_else(PoisonError::into_inner);
+        match (current.as_ref(), stuck.as_ref()) {
+            (None, Some(reason)) => tracing::error!("Controller seems to be stuck: {}", reason),
+            (Some(_), None) => tracing::info!("Controller is making progress again"),
+            _ => {}
+        }
+        *current = stuck;
+    }
+}
+
+/// Run HTTP server with liveness (`/healthz`) and readiness (`/readyz`) probes.
+pub async fn run(addr: SocketAddr, health: Health) -> RobotLBResult<()> {
+    let app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("Serving health probes on {}", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Liveness probe. Fails if the watchdog considers the controller stuck.
+async fn healthz(State(health): State<Health>) -> (StatusCode, String) {
+    match health.liveness() {
+        Ok(()) => (StatusCode::OK, "ok".to_string()),
+        Err(reason) => (StatusCode::SERVICE_UNAVAILABLE, reason),
+    }
+}
+
+/// Readiness probe.
+async fn readyz(State(health): State<Health>) -> (StatusCode, String) {
+    match health.readiness() {
+        Ok(()) => (StatusCode::OK, "ok".to_string()),
+        Err(reason) => (StatusCode::SERVICE_UNAVAILABLE, reason),
+    }
+}
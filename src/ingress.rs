@@ -0,0 +1,184 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::StreamExt;
+use k8s_openapi::{
+    api::{core::v1::Service, networking::v1::Ingress},
+    serde_json::json,
+};
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::{controller::Action, finalizer, watcher, Controller},
+    ResourceExt,
+};
+
+use crate::{
+    config::OperatorConfig,
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    ip_families,
+    lb::{LoadBalancer, Reconciled},
+    requeue_with_jitter, resolve_targets_and_services, CurrentContext, IPV4_FAMILY, IPV6_FAMILY,
+};
+
+/// Run the controller of ingresses until the process is stopped.
+///
+/// Ingresses of the configured class, or annotated with `robotlb/ingress: "true"`,
+/// get load balancers pointing to the ingress controller, so it doesn't
+/// need a `LoadBalancer` service of its own.
+pub async fn run(context: Arc<CurrentContext>, controller_service: String) {
+    let Some((namespace, name)) = controller_service.split_once('/') else {
+        tracing::error!(
+            "Ingress controller service {} isn't in <namespace>/<name> format, ingresses are ignored",
+            controller_service
+        );
+        return;
+    };
+    let controller_svc = kube::Api::<Service>::namespaced(context.client.clone(), namespace);
+    let name = Arc::new(name.to_string());
+    tracing::info!("Starting the ingress controller");
+    let api = kube::Api::<Ingress>::all(context.client.clone());
+    Controller::new(api, watcher::Config::default())
+        .run(
+            move |ingress, context| {
+                let controller_svc = controller_svc.clone();
+                let name = name.clone();
+                async move { reconcile(ingress, &controller_svc, &name, context).await }
+            },
+            on_error,
+            context,
+        )
+        .for_each(|result| {
+            match result {
+                Ok((ingress, _)) => {
+                    tracing::debug!("Reconcilation of ingress {} was successful", ingress.name);
+                }
+                Err(err) => tracing::warn!("Error reconciling ingress: {}", err),
+            }
+            futures::future::ready(())
+        })
+        .await;
+}
+
+/// Check that the ingress is of the configured class or asks for a load balancer explicitly.
+#[must_use]
+pub fn is_managed(ingress: &Ingress, config: &OperatorConfig) -> bool {
+    let class = ingress
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.ingress_class_name.as_ref());
+    (class.is_some() && class == config.ingress_class.as_ref())
+        || ingress
+            .annotations()
+            .get(consts::INGRESS_ANN_NAME)
+            .is_some_and(|value| value == "true")
+}
+
+#[tracing::instrument(
+    skip(ingress, controller_svc, controller_name, context),
+    fields(ingress = ingress.name_any(), namespace = ingress.namespace().unwrap_or_default())
+)]
+async fn reconcile(
+    ingress: Arc<Ingress>,
+    controller_svc: &kube::Api<Service>,
+    controller_name: &str,
+    context: Arc<CurrentContext>,
+) -> RobotLBResult<Action> {
+    // Ingresses which never had a balancer don't get the finalizer.
+    let has_finalizer = ingress
+        .finalizers()
+        .contains(&consts::FINALIZER_NAME.to_string());
+    if !is_managed(&ingress, &context.config) && !has_finalizer {
+        return Ok(Action::await_change());
+    }
+    let api = kube::Api::<Ingress>::namespaced(
+        context.client.clone(),
+        &ingress.namespace().unwrap_or_default(),
+    );
+    finalizer::finalizer(&api, consts::FINALIZER_NAME, ingress, |event| async {
+        match event {
+            finalizer::Event::Apply(ingress) if is_managed(&ingress, &context.config) => {
+                let controller_svc = Arc::new(controller_svc.get(controller_name).await?);
+                apply(&ingress, &controller_svc, &api, &context).await
+            }
+            // The ingress doesn't want a balancer anymore.
+            finalizer::Event::Apply(ingress) | finalizer::Event::Cleanup(ingress) => {
+                match LoadBalancer::from_ingress(&ingress, &context).await {
+                    Ok(lb) => {
+                        lb.cleanup().await?;
+                    }
+                    // Without the token the balancer can't be deleted,
+                    // but the ingress must still be deletable.
+                    Err(err)
+                        if ingress.metadata.deletion_timestamp.is_some()
+                            && err.is_missing_credentials() =>
+                    {
+                        tracing::warn!(
+                            "Cannot read HCloud token of the deleted ingress, its load balancer is left intact: {}",
+                            err
+                        );
+                    }
+                    Err(err) => return Err(err),
+                }
+                Ok(Action::await_change())
+            }
+        }
+    })
+    .await
+    .map_err(|err| RobotLBError::FinalizerError(Box::new(err)))
+}
+
+async fn apply(
+    ingress: &Ingress,
+    controller_svc: &Arc<Service>,
+    api: &kube::Api<Ingress>,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<Action> {
+    let mut lb = LoadBalancer::from_ingress(ingress, context).await?;
+    resolve_targets_and_services(&mut lb, controller_svc, context).await?;
+    let Reconciled { hcloud_lb, .. } = lb.reconcile().await?;
+
+    let families = ip_families(controller_svc, &context.config);
+    let mut ips = vec![];
+    if families.contains(&IPV4_FAMILY) {
+        ips.extend(hcloud_lb.public_net.ipv4.ip.clone().flatten());
+    }
+    if families.contains(&IPV6_FAMILY) {
+        ips.extend(hcloud_lb.public_net.ipv6.ip.clone().flatten());
+    }
+    let current_ips = ingress
+        .status
+        .iter()
+        .filter_map(|status| status.load_balancer.as_ref())
+        .flat_map(|load_balancer| load_balancer.ingress.iter().flatten())
+        .filter_map(|ingress| ingress.ip.clone())
+        .collect::<Vec<_>>();
+    if !ips.is_empty() && current_ips != ips {
+        let ingress_ips = ips.iter().map(|ip| json!({ "ip": ip })).collect::<Vec<_>>();
+        api.patch_status(
+            &ingress.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({
+                "status": {
+                    "loadBalancer": {
+                        "ingress": ingress_ips
+                    }
+                }
+            })),
+        )
+        .await?;
+    }
+
+    // Targets of the ingress controller are resolved on every reconcile,
+    // so they are picked up with the resync interval.
+    let interval = lb
+        .resync_interval
+        .unwrap_or_else(|| Duration::from_secs(context.config.resync_interval));
+    Ok(requeue_with_jitter(interval, context.config.requeue_jitter))
+}
+
+// The signature is dictated by the controller.
+#[allow(clippy::needless_pass_by_value)]
+fn on_error(_: Arc<Ingress>, err: &RobotLBError, context: Arc<CurrentContext>) -> Action {
+    tracing::warn!("Cannot reconcile ingress: {}", err);
+    Action::requeue(Duration::from_secs(context.config.error_requeue_delay))
+}
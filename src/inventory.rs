@@ -0,0 +1,139 @@
+use k8s_openapi::{
+    api::core::v1::{ConfigMap, Service},
+    chrono::Utc,
+    serde_json::{self, json, Value},
+};
+use kube::{
+    api::{Patch, PatchParams, PostParams},
+    ResourceExt,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{RobotLBError, RobotLBResult},
+    CurrentContext,
+};
+
+/// Load balancer recorded in the inventory `ConfigMap`.
+///
+/// Entries are stored as JSON under `<namespace>.<service>` keys,
+/// so the balancers of the cluster can be looked up without `HCloud` access.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub id: i64,
+    pub name: String,
+    pub namespace: String,
+    pub service: String,
+    /// Time of the last successful reconcile in RFC 3339 format.
+    pub last_sync: String,
+}
+
+/// Key of the service's entry. Names of namespaces
+/// and services can't contain dots, so keys are unique.
+fn key(namespace: &str, service: &str) -> String {
+    format!("{namespace}.{service}")
+}
+
+fn config_map_api(context: &CurrentContext) -> kube::Api<ConfigMap> {
+    let namespace = context
+        .config
+        .inventory_configmap_namespace
+        .as_deref()
+        .unwrap_or_else(|| context.client.default_namespace());
+    kube::Api::namespaced(context.client.clone(), namespace)
+}
+
+/// Entry of the service, if it's recorded.
+/// Entries which can't be parsed are treated as missing.
+pub async fn get(
+    context: &CurrentContext,
+    namespace: &str,
+    service: &str,
+) -> RobotLBResult<Option<Entry>> {
+    let Some(config_map) = config_map_api(context)
+        .get_opt(&context.config.inventory_configmap)
+        .await?
+    else {
+        return Ok(None);
+    };
+    Ok(config_map
+        .data
+        .as_ref()
+        .and_then(|data| data.get(&key(namespace, service)))
+        .and_then(|entry| serde_json::from_str(entry).ok()))
+}
+
+/// Record the load balancer of the service after a successful reconcile.
+pub async fn record(
+    context: &CurrentContext,
+    svc: &Service,
+    hcloud_lb: &hcloud::models::LoadBalancer,
+) -> RobotLBResult<()> {
+    let entry = Entry {
+        id: hcloud_lb.id,
+        name: hcloud_lb.name.clone(),
+        namespace: svc.namespace().unwrap_or_default(),
+        service: svc.name_any(),
+        last_sync: Utc::now().to_rfc3339(),
+    };
+    let value = serde_json::to_string(&entry)
+        .map_err(|err| RobotLBError::SerializationError(err.to_string()))?;
+    let key = key(&entry.namespace, &entry.service);
+    if merge(context, &json!({ "data": { &key: value } })).await? {
+        return Ok(());
+    }
+    let config_map = ConfigMap {
+        metadata: kube::api::ObjectMeta {
+            name: Some(context.config.inventory_configmap.clone()),
+            labels: Some(
+                [(
+                    "app.kubernetes.io/managed-by".to_string(),
+                    "robotlb".to_string(),
+                )]
+                .into(),
+            ),
+            ..Default::default()
+        },
+        data: Some([(key, value)].into()),
+        ..Default::default()
+    };
+    match config_map_api(context)
+        .create(&PostParams::default(), &config_map)
+        .await
+    {
+        // Another reconcile has just created it.
+        Err(kube::Error::Api(response)) if response.code == 409 => {
+            merge(context, &json!({ "data": config_map.data })).await?;
+            Ok(())
+        }
+        result => result.map(drop).map_err(Into::into),
+    }
+}
+
+/// Remove the entry of the service once its load balancer is deleted.
+pub async fn forget(context: &CurrentContext, namespace: &str, service: &str) -> RobotLBResult<()> {
+    merge(
+        context,
+        &json!({ "data": { key(namespace, service): null } }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Merge the patch into the `ConfigMap`.
+/// Returns `false` if the `ConfigMap` doesn't exist.
+async fn merge(context: &CurrentContext, patch: &Value) -> RobotLBResult<bool> {
+    match config_map_api(context)
+        .patch(
+            &context.config.inventory_configmap,
+            &PatchParams::default(),
+            &Patch::Merge(patch),
+        )
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(response)) if response.code == 404 => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
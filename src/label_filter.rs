@@ -1,6 +1,10 @@
 use std::{collections::BTreeMap, str::FromStr};
 
-use crate::error::RobotLBError;
+use k8s_openapi::{api::core::v1::Node, apimachinery::pkg::apis::meta::v1::LabelSelector};
+use kube::ResourceExt;
+use regex::Regex;
+
+use crate::{consts, error::RobotLBError};
 
 /// Enum of all possible rules for label filtering.
 #[derive(Debug, Clone)]
@@ -13,6 +17,18 @@ enum Rule {
     Exists(String),
     /// `DoesNotExist` rule checks if the key does not exist.
     DoesNotExist(String),
+    /// In rule checks if the key is equal to one of the values.
+    In(String, Vec<String>),
+    /// `NotIn` rule checks if the key is missing or not equal to any of the values.
+    NotIn(String, Vec<String>),
+    /// `GreaterThan` rule checks if the key is an integer greater than the value.
+    GreaterThan(String, i64),
+    /// `LessThan` rule checks if the key is an integer less than the value.
+    LessThan(String, i64),
+    /// Matches rule checks if the key matches the pattern.
+    Matches(String, Regex),
+    /// `NotMatches` rule checks if the key either does not exist or doesn't match the pattern.
+    NotMatches(String, Regex),
 }
 
 /// `LabelFilter` is a filter for Kubernetes labels.
@@ -23,6 +39,87 @@ pub struct LabelFilter {
 }
 
 impl LabelFilter {
+    /// Node filter of the service, from `robotlb/node-selector`,
+    /// `robotlb/node-selector-json` or both of them.
+    /// Returns `None` if neither of them is set.
+    pub fn from_annotations(
+        annotations: &BTreeMap<String, String>,
+    ) -> Result<Option<Self>, RobotLBError> {
+        let selector = annotations
+            .get(consts::LB_NODE_SELECTOR)
+            .map(|selector| Self::from_str(selector))
+            .transpose()?;
+        let document = annotations
+            .get(consts::LB_NODE_SELECTOR_JSON)
+            .map(|document| Self::from_label_selector(document))
+            .transpose()?;
+        Ok(match (selector, document) {
+            (Some(selector), Some(document)) => Some(Self {
+                rules: [selector.rules, document.rules].concat(),
+            }),
+            (selector, document) => selector.or(document),
+        })
+    }
+
+    /// Parse a Kubernetes `LabelSelector` with `matchLabels` and `matchExpressions`,
+    /// written either as JSON or YAML.
+    pub fn from_label_selector(document: &str) -> Result<Self, RobotLBError> {
+        let invalid = |problem: String| RobotLBError::InvalidNodeFilter(problem);
+        let selector = serde_yaml::from_str::<LabelSelector>(document)
+            .map_err(|err| invalid(format!("{document}: {err}")))?;
+        let mut rules = selector
+            .match_labels
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| Rule::Equal(key, value))
+            .collect::<Vec<_>>();
+        for expression in selector.match_expressions.into_iter().flatten() {
+            let values = expression.values.unwrap_or_default();
+            let key = expression.key;
+            let rule = match expression.operator.as_str() {
+                "In" if !values.is_empty() => Rule::In(key, values),
+                "NotIn" if !values.is_empty() => Rule::NotIn(key, values),
+                "Exists" if values.is_empty() => Rule::Exists(key),
+                "DoesNotExist" if values.is_empty() => Rule::DoesNotExist(key),
+                "In" | "NotIn" => return Err(invalid(format!("{key}: values are required"))),
+                "Exists" | "DoesNotExist" => {
+                    return Err(invalid(format!("{key}: values must be empty")))
+                }
+                operator => return Err(invalid(format!("{key}: unknown operator {operator}"))),
+            };
+            rules.push(rule);
+        }
+        Ok(Self { rules })
+    }
+
+    /// Check labels and fields of the node. Fields are matched
+    /// like labels with the following keys, taking precedence over labels:
+    /// * `metadata.name` -- name of the node;
+    /// * `spec.providerID` -- provider ID of the node, e.g. `hcloud://123`;
+    /// * `status.addresses.<type>` -- first address of the type, e.g. `status.addresses.ExternalIP`.
+    #[must_use]
+    pub fn check_node(&self, node: &Node) -> bool {
+        let mut values = node.labels().clone();
+        values.insert(consts::NODE_NAME_FIELD.to_string(), node.name_any());
+        if let Some(provider_id) = node.spec.as_ref().and_then(|spec| spec.provider_id.clone()) {
+            values.insert(consts::NODE_PROVIDER_ID_FIELD.to_string(), provider_id);
+        }
+        let addresses = node
+            .status
+            .as_ref()
+            .and_then(|status| status.addresses.as_ref());
+        for address in addresses.into_iter().flatten() {
+            values
+                .entry(format!(
+                    "{}{}",
+                    consts::NODE_ADDRESS_FIELD_PREFIX,
+                    address.type_
+                ))
+                .or_insert_with(|| address.address.clone());
+        }
+        self.check(&values)
+    }
+
     #[must_use]
     pub fn check(&self, labels: &BTreeMap<String, String>) -> bool {
         for rule in &self.rules {
@@ -47,45 +144,647 @@ impl LabelFilter {
                         return false;
                     }
                 }
+                Rule::In(key, values) => {
+                    if !labels.get(key).is_some_and(|value| values.contains(value)) {
+                        return false;
+                    }
+                }
+                Rule::NotIn(key, values) => {
+                    if labels.get(key).is_some_and(|value| values.contains(value)) {
+                        return false;
+                    }
+                }
+                Rule::GreaterThan(key, bound) => {
+                    if label_number(labels, key).is_none_or(|value| value <= *bound) {
+                        return false;
+                    }
+                }
+                Rule::LessThan(key, bound) => {
+                    if label_number(labels, key).is_none_or(|value| value >= *bound) {
+                        return false;
+                    }
+                }
+                Rule::Matches(key, pattern) => {
+                    if labels.get(key).is_none_or(|value| !pattern.is_match(value)) {
+                        return false;
+                    }
+                }
+                Rule::NotMatches(key, pattern) => {
+                    if labels.get(key).is_some_and(|value| pattern.is_match(value)) {
+                        return false;
+                    }
+                }
             }
         }
         true
     }
 }
 
+/// Value of the label as an integer. Labels which aren't integers
+/// don't match numeric rules, same as in Kubernetes node affinity.
+fn label_number(labels: &BTreeMap<String, String>, key: &str) -> Option<i64> {
+    labels.get(key).and_then(|value| value.parse().ok())
+}
+
 /// Parse label filter from string.
 /// The string should be in the following format:
-/// `key=value,key!=value,key,!key`
+/// `key=value,key!=value,key,!key,key in (value1,value2),key notin (value1,value2),key>1,key<1`
+///
+/// Values of `key=value` and `key!=value` may have `*` and `?` wildcards,
+/// and `key~=regex` matches the whole value against the regular expression.
+/// Patterns are compiled once, when the filter is parsed.
+/// Values in double or single quotes are taken literally, so they may
+/// contain commas and wildcards, e.g. `pool="a=b,c"`.
+/// Errors point at the offending token and its offset in the string.
 impl FromStr for LabelFilter {
     type Err = RobotLBError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut rules = Vec::new();
-        for rule in s.split(',') {
-            let parts = rule.split('=').collect::<Vec<_>>();
-            match *parts.as_slice() {
-                [key] => {
-                    if key.starts_with('!') {
-                        rules.push(Rule::DoesNotExist(
-                            key.strip_prefix('!').unwrap().to_string(),
-                        ));
-                        continue;
-                    }
-                    rules.push(Rule::Exists(key.to_string()));
+        let rules = parse(s).map_err(|err| {
+            let found = if err.token.is_empty() {
+                "end of filter".to_string()
+            } else {
+                format!("`{}`", err.token)
+            };
+            RobotLBError::InvalidNodeFilter(format!(
+                "{s:?}: expected {} at offset {}, found {found}",
+                err.expected, err.offset
+            ))
+        })?;
+        Ok(Self { rules })
+    }
+}
+
+/// Error of the parser, pointing at the offending token.
+#[derive(Debug, PartialEq, Eq)]
+struct ParseError {
+    /// Byte offset of the token in the filter.
+    offset: usize,
+    /// The offending token. Empty at the end of the filter.
+    token: String,
+    /// What was expected instead of the token.
+    expected: String,
+}
+
+/// Parse comma-separated rules. An empty filter has no rules and matches everything.
+fn parse(input: &str) -> Result<Vec<Rule>, ParseError> {
+    let mut parser = Parser { input, offset: 0 };
+    let mut rules = Vec::new();
+    parser.skip_whitespace();
+    if parser.rest().is_empty() {
+        return Ok(rules);
+    }
+    loop {
+        rules.push(parser.rule()?);
+        parser.skip_whitespace();
+        if parser.rest().is_empty() {
+            return Ok(rules);
+        }
+        if !parser.eat(",") {
+            return Err(parser.error("an operator, `,` or end of filter"));
+        }
+    }
+}
+
+/// Tokenizer of the filter, which only moves forward.
+struct Parser<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume the token if the rest of the filter starts with it.
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.offset += token.len();
+        }
+        found
+    }
+
+    /// Consume characters matching the predicate.
+    /// Returns the offset where they start and the characters.
+    fn take_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> (usize, &'a str) {
+        let start = self.offset;
+        let rest = self.rest();
+        self.offset += rest.find(|ch| !predicate(ch)).unwrap_or(rest.len());
+        (start, &self.input[start..self.offset])
+    }
+
+    /// Consume the value of the rule. Quoted values are taken literally,
+    /// otherwise the rest of the rule up to the next comma is the value, keeping commas
+    /// inside of brackets, so regular expressions like `a{1,2}` are read whole.
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        let offset = self.offset;
+        if let Some(text) = self.quoted()? {
+            return Ok(Value {
+                offset,
+                text,
+                quoted: true,
+            });
+        }
+        let (offset, text) = self.bare_value();
+        Ok(Value {
+            offset,
+            text: text.to_string(),
+            quoted: false,
+        })
+    }
+
+    /// Consume a value in double or single quotes, where `\` escapes
+    /// the next character. Returns `None` if there's no quote.
+    fn quoted(&mut self) -> Result<Option<String>, ParseError> {
+        let start = self.offset;
+        let mut chars = self.rest().char_indices();
+        let Some((_, quote @ ('"' | '\''))) = chars.next() else {
+            return Ok(None);
+        };
+        let mut text = String::new();
+        while let Some((idx, ch)) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => text.push(escaped),
+                    None => break,
+                },
+                _ if ch == quote => {
+                    self.offset += idx + ch.len_utf8();
+                    return Ok(Some(text));
                 }
-                [key, value] => {
-                    if key.ends_with('!') {
-                        rules.push(Rule::NotEqual(
-                            key.strip_suffix('!').unwrap().to_string(),
-                            value.to_string(),
-                        ));
-                        continue;
-                    }
-                    rules.push(Rule::Equal(key.to_string(), value.to_string()));
+                _ => text.push(ch),
+            }
+        }
+        Err(ParseError {
+            offset: start,
+            token: self.rest().to_string(),
+            expected: format!("closing {quote}"),
+        })
+    }
+
+    /// Returns the offset where the bare value starts and the value without surrounding whitespace.
+    fn bare_value(&mut self) -> (usize, &'a str) {
+        let mut depth = 0_i32;
+        let (start, value) = self.take_while(|ch| match ch {
+            '(' | '[' | '{' => {
+                depth += 1;
+                true
+            }
+            ')' | ']' | '}' => {
+                depth -= 1;
+                true
+            }
+            ',' => depth > 0,
+            _ => true,
+        });
+        (start, value.trim_end())
+    }
+
+    /// Error pointing at the token at the current offset.
+    fn error(&self, expected: &str) -> ParseError {
+        let rest = self.rest();
+        let token = rest
+            .split(|ch: char| ch == ',' || ch.is_whitespace())
+            .next()
+            .filter(|token| !token.is_empty())
+            .or_else(|| rest.get(..rest.chars().next().map_or(0, char::len_utf8)))
+            .unwrap_or_default();
+        ParseError {
+            offset: self.offset,
+            token: token.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        let (start, key) =
+            self.take_while(|ch| ch.is_alphanumeric() || matches!(ch, '-' | '_' | '.' | '/'));
+        if key.is_empty() {
+            self.offset = start;
+            return Err(self.error("a label key"));
+        }
+        Ok(key.to_string())
+    }
+
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        self.skip_whitespace();
+        if self.eat("!") {
+            return Ok(Rule::DoesNotExist(self.key()?));
+        }
+        let key = self.key()?;
+        let key_end = self.offset;
+        self.skip_whitespace();
+        if self.eat("!=") {
+            let value = self.value()?;
+            return Ok(match value.pattern()? {
+                Some(pattern) => Rule::NotMatches(key, pattern),
+                None => Rule::NotEqual(key, value.text),
+            });
+        }
+        if self.eat("~=") {
+            let value = self.value()?;
+            let pattern = compile(&value.text, &format!("^(?:{})$", value.text))
+                .map_err(|err| err.at(value.offset))?;
+            return Ok(Rule::Matches(key, pattern));
+        }
+        if self.eat("==") || self.eat("=") {
+            let value = self.value()?;
+            return Ok(match value.pattern()? {
+                Some(pattern) => Rule::Matches(key, pattern),
+                None => Rule::Equal(key, value.text),
+            });
+        }
+        if self.eat(">") {
+            return Ok(Rule::GreaterThan(key, self.number()?));
+        }
+        if self.eat("<") {
+            return Ok(Rule::LessThan(key, self.number()?));
+        }
+        // Set operators are words, so they must be separated from the key.
+        if self.offset > key_end {
+            let (start, operator) = self.take_while(|ch| ch.is_ascii_alphabetic());
+            match operator {
+                "in" => return Ok(Rule::In(key, self.values()?)),
+                "notin" => return Ok(Rule::NotIn(key, self.values()?)),
+                _ => self.offset = start,
+            }
+        }
+        Ok(Rule::Exists(key))
+    }
+
+    fn number(&mut self) -> Result<i64, ParseError> {
+        let value = self.value()?;
+        value.text.parse().map_err(|_| ParseError {
+            offset: value.offset,
+            token: value.text,
+            expected: "an integer".to_string(),
+        })
+    }
+
+    /// Values of set expressions like `(value1, value2)`.
+    fn values(&mut self) -> Result<Vec<String>, ParseError> {
+        self.skip_whitespace();
+        if !self.eat("(") {
+            return Err(self.error("`(`"));
+        }
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if let Some(value) = self.quoted()? {
+                values.push(value);
+                self.skip_whitespace();
+            } else {
+                let (start, value) = self.take_while(|ch| !matches!(ch, ',' | ')'));
+                let value = value.trim_end();
+                if value.is_empty() {
+                    self.offset = start;
+                    return Err(self.error("a value"));
                 }
-                _ => return Err(RobotLBError::InvalidNodeFilter(rule.to_string())),
+                values.push(value.to_string());
+            }
+            if self.eat(")") {
+                return Ok(values);
+            }
+            if !self.eat(",") {
+                return Err(self.error("`,` or `)`"));
             }
         }
-        Ok(Self { rules })
+    }
+}
+
+/// Value of a rule with its offset in the filter.
+struct Value {
+    offset: usize,
+    text: String,
+    /// Quoted values have no wildcards.
+    quoted: bool,
+}
+
+impl Value {
+    fn pattern(&self) -> Result<Option<Regex>, ParseError> {
+        if self.quoted {
+            return Ok(None);
+        }
+        glob(&self.text).map_err(|err| err.at(self.offset))
+    }
+}
+
+/// Pattern which failed to compile, without its position yet.
+struct PatternError(String);
+
+impl PatternError {
+    fn at(self, offset: usize) -> ParseError {
+        ParseError {
+            offset,
+            token: self.0,
+            expected: "a valid pattern".to_string(),
+        }
+    }
+}
+
+/// Compile the value with `*` and `?` wildcards into a pattern.
+/// Returns `None` if the value has no wildcards.
+fn glob(value: &str) -> Result<Option<Regex>, PatternError> {
+    if !value.contains(['*', '?']) {
+        return Ok(None);
+    }
+    let pattern = regex::escape(value)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    compile(value, &format!("^{pattern}$")).map(Some)
+}
+
+fn compile(value: &str, pattern: &str) -> Result<Regex, PatternError> {
+    Regex::new(pattern).map_err(|_| PatternError(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::{
+        api::core::v1::{Node, NodeAddress, NodeSpec, NodeStatus},
+        apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    };
+
+    use super::{parse, LabelFilter, ParseError};
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    fn matches(filter: &str, pairs: &[(&str, &str)]) -> bool {
+        filter.parse::<LabelFilter>().unwrap().check(&labels(pairs))
+    }
+
+    fn error(filter: &str) -> ParseError {
+        parse(filter).unwrap_err()
+    }
+
+    fn parse_error(offset: usize, token: &str, expected: &str) -> ParseError {
+        ParseError {
+            offset,
+            token: token.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(matches("", &[]));
+        assert!(matches("  ", &[("a", "b")]));
+    }
+
+    #[test]
+    fn equality() {
+        assert!(matches("a=b", &[("a", "b")]));
+        assert!(matches("a==b", &[("a", "b")]));
+        assert!(!matches("a=b", &[("a", "c")]));
+        assert!(!matches("a=b", &[]));
+        assert!(matches("a=", &[("a", "")]));
+    }
+
+    #[test]
+    fn inequality() {
+        assert!(matches("a!=b", &[("a", "c")]));
+        assert!(matches("a!=b", &[]));
+        assert!(!matches("a!=b", &[("a", "b")]));
+    }
+
+    #[test]
+    fn values_with_equals_sign() {
+        assert!(matches("a=b=c", &[("a", "b=c")]));
+        assert!(!matches("a=b=c", &[("a", "b")]));
+        assert!(matches("a!=b=c", &[("a", "b")]));
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert!(matches(r#"pool="a=b",region!=eu"#, &[("pool", "a=b")]));
+        assert!(!matches(
+            r#"pool="a=b",region!=eu"#,
+            &[("pool", "a=b"), ("region", "eu")]
+        ));
+        assert!(matches(r#"a = "b,c" "#, &[("a", "b,c")]));
+        assert!(matches("a='b,c'", &[("a", "b,c")]));
+        assert!(matches(r#"a="say \"hi\"""#, &[("a", r#"say "hi""#)]));
+        assert!(matches(r#"a="\\""#, &[("a", "\\")]));
+        assert!(matches(r#"a="""#, &[("a", "")]));
+        assert!(matches(r#"a!="b,c""#, &[("a", "b")]));
+        assert!(matches(r#"zone in ("a,b", 'c)', "")"#, &[("zone", "c)")]));
+        assert!(matches(r#"zone in ("a,b", 'c)', "")"#, &[("zone", "")]));
+        assert!(matches(r#"a~="b|c,d""#, &[("a", "c,d")]));
+        assert!(matches(r#"cpu>"4""#, &[("cpu", "5")]));
+    }
+
+    #[test]
+    fn quoted_values_have_no_wildcards() {
+        assert!(matches(r#"a="b*""#, &[("a", "b*")]));
+        assert!(!matches(r#"a="b*""#, &[("a", "bc")]));
+        assert!(matches(r#"a!="b?""#, &[("a", "bc")]));
+    }
+
+    #[test]
+    fn existence() {
+        assert!(matches("a", &[("a", "")]));
+        assert!(!matches("a", &[("b", "")]));
+        assert!(matches("!a", &[("b", "")]));
+        assert!(!matches("!a", &[("a", "")]));
+    }
+
+    #[test]
+    fn keys_with_prefixes() {
+        let filter = "node-role.kubernetes.io/control-plane!=true,kubernetes.io/arch=amd64";
+        assert!(matches(filter, &[("kubernetes.io/arch", "amd64")]));
+        assert!(!matches(
+            filter,
+            &[
+                ("node-role.kubernetes.io/control-plane", "true"),
+                ("kubernetes.io/arch", "amd64")
+            ]
+        ));
+    }
+
+    #[test]
+    fn whitespace_around_rules() {
+        assert!(matches(" a = b , !c ", &[("a", "b")]));
+        assert!(!matches(" a = b , !c ", &[("a", "b"), ("c", "")]));
+    }
+
+    #[test]
+    fn set_expressions() {
+        assert!(matches("zone in (hel1,fsn1)", &[("zone", "fsn1")]));
+        assert!(matches("zone in ( hel1 , fsn1 )", &[("zone", "hel1")]));
+        assert!(!matches("zone in (hel1,fsn1)", &[("zone", "nbg1")]));
+        assert!(!matches("zone in (hel1,fsn1)", &[]));
+        assert!(matches("pool notin (spot)", &[("pool", "main")]));
+        assert!(matches("pool notin (spot)", &[]));
+        assert!(!matches("pool notin (spot)", &[("pool", "spot")]));
+        assert!(matches(
+            "zone in (hel1,fsn1),pool notin (spot)",
+            &[("zone", "hel1"), ("pool", "main")]
+        ));
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert!(matches("cpu>4", &[("cpu", "8")]));
+        assert!(!matches("cpu>4", &[("cpu", "4")]));
+        assert!(!matches("cpu>4", &[("cpu", "many")]));
+        assert!(!matches("cpu>4", &[]));
+        assert!(matches("cpu<4", &[("cpu", "2")]));
+        assert!(matches("cpu < -1", &[("cpu", "-2")]));
+        assert!(!matches("cpu<4", &[("cpu", "4")]));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("hostname=edge-*", &[("hostname", "edge-1")]));
+        assert!(!matches("hostname=edge-*", &[("hostname", "core-1")]));
+        assert!(matches("hostname=edge-?", &[("hostname", "edge-1")]));
+        assert!(!matches("hostname=edge-?", &[("hostname", "edge-10")]));
+        assert!(matches("hostname!=edge-*", &[("hostname", "core-1")]));
+        assert!(matches("hostname!=edge-*", &[]));
+        assert!(!matches("hostname!=edge-*", &[("hostname", "edge-1")]));
+        assert!(matches("a=x.y*", &[("a", "x.yz")]));
+        assert!(!matches("a=x.y*", &[("a", "xzy")]));
+    }
+
+    #[test]
+    fn regular_expressions() {
+        assert!(matches(r"hostname~=edge-\d+", &[("hostname", "edge-10")]));
+        assert!(!matches(
+            r"hostname~=edge-\d+",
+            &[("hostname", "my-edge-10")]
+        ));
+        assert!(!matches(r"hostname~=edge-\d+", &[]));
+        assert!(matches("a~=(b,c|d),e", &[("a", "b,c"), ("e", "")]));
+        assert!(matches("a~=x{1,2},e", &[("a", "xx"), ("e", "")]));
+        assert!(!matches("a~=x{1,2},e", &[("a", "xx")]));
+    }
+
+    #[test]
+    fn node_fields() {
+        let node = Node {
+            metadata: ObjectMeta {
+                name: Some("edge-1".to_string()),
+                labels: Some(labels(&[("pool", "edge"), ("metadata.name", "label")])),
+                ..Default::default()
+            },
+            spec: Some(NodeSpec {
+                provider_id: Some("hcloud://42".to_string()),
+                ..Default::default()
+            }),
+            status: Some(NodeStatus {
+                addresses: Some(vec![
+                    NodeAddress {
+                        type_: "InternalIP".to_string(),
+                        address: "10.0.0.2".to_string(),
+                    },
+                    NodeAddress {
+                        type_: "InternalIP".to_string(),
+                        address: "10.0.0.3".to_string(),
+                    },
+                ]),
+                ..Default::default()
+            }),
+        };
+        let check = |filter: &str| filter.parse::<LabelFilter>().unwrap().check_node(&node);
+        assert!(check("metadata.name=edge-1,pool=edge"));
+        assert!(check("spec.providerID=hcloud://*"));
+        assert!(check("status.addresses.InternalIP=10.0.0.2"));
+        assert!(check("!status.addresses.ExternalIP"));
+        assert!(!check("status.addresses.ExternalIP"));
+        assert!(!check("metadata.name=label"));
+    }
+
+    #[test]
+    fn missing_key() {
+        assert_eq!(error("=b"), parse_error(0, "=b", "a label key"));
+        assert_eq!(error("a,,b"), parse_error(2, ",", "a label key"));
+        assert_eq!(error("a,"), parse_error(2, "", "a label key"));
+        assert_eq!(error("!"), parse_error(1, "", "a label key"));
+        assert_eq!(error("a, !=b"), parse_error(4, "=b", "a label key"));
+    }
+
+    #[test]
+    fn unknown_operator() {
+        assert_eq!(
+            error("a b"),
+            parse_error(2, "b", "an operator, `,` or end of filter")
+        );
+        assert_eq!(
+            error("zone inside (a)"),
+            parse_error(5, "inside", "an operator, `,` or end of filter")
+        );
+        assert_eq!(
+            error("a:b"),
+            parse_error(1, ":b", "an operator, `,` or end of filter")
+        );
+    }
+
+    #[test]
+    fn invalid_sets() {
+        assert_eq!(error("zone in hel1"), parse_error(8, "hel1", "`(`"));
+        assert_eq!(error("zone in (hel1"), parse_error(13, "", "`,` or `)`"));
+        assert_eq!(error("zone in ()"), parse_error(9, ")", "a value"));
+        assert_eq!(error("zone in (a,)"), parse_error(11, ")", "a value"));
+        assert_eq!(
+            error("zone in (a) b"),
+            parse_error(12, "b", "an operator, `,` or end of filter")
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes() {
+        assert_eq!(error(r#"a="b,c"#), parse_error(2, r#""b,c"#, "closing \""));
+        assert_eq!(error("a in ('b)"), parse_error(6, "'b)", "closing '"));
+        assert_eq!(error(r#"a="b\""#), parse_error(2, r#""b\""#, "closing \""));
+        assert_eq!(
+            error(r#"a="b"c"#),
+            parse_error(5, "c", "an operator, `,` or end of filter")
+        );
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        assert_eq!(error("cpu>four"), parse_error(4, "four", "an integer"));
+        assert_eq!(error("cpu< ,a"), parse_error(5, "", "an integer"));
+        assert_eq!(error("a=b,cpu<1.5"), parse_error(8, "1.5", "an integer"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(error("a~=(b"), parse_error(3, "(b", "a valid pattern"));
+        assert_eq!(
+            error("a=b, c~= [z-a]"),
+            parse_error(9, "[z-a]", "a valid pattern")
+        );
+    }
+
+    #[test]
+    fn error_message() {
+        let err = "a=b,cpu>four".parse::<LabelFilter>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Cannot parse node filter: "a=b,cpu>four": expected an integer at offset 8, found `four`"#
+        );
+        let err = "a,".parse::<LabelFilter>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Cannot parse node filter: "a,": expected a label key at offset 2, found end of filter"#
+        );
     }
 }
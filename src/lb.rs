@@ -1,28 +1,47 @@
 use hcloud::{
     apis::{
-        configuration::Configuration as HcloudConfig,
+        load_balancer_types_api::ListLoadBalancerTypesParams,
         load_balancers_api::{
             AddServiceParams, AddTargetParams, AttachLoadBalancerToNetworkParams,
             ChangeAlgorithmParams, ChangeTypeOfLoadBalancerParams, DeleteLoadBalancerParams,
-            DeleteServiceParams, DetachLoadBalancerFromNetworkParams, ListLoadBalancersParams,
-            RemoveTargetParams, UpdateServiceParams,
+            DeleteServiceParams, DetachLoadBalancerFromNetworkParams, GetLoadBalancerActionParams,
+            GetLoadBalancerParams, ListLoadBalancersParams, RemoveTargetParams,
+            ReplaceLoadBalancerParams, UpdateServiceParams,
         },
+        locations_api::ListLocationsParams,
         networks_api::ListNetworksParams,
     },
     models::{
+        action, load_balancer_algorithm, load_balancer_service, load_balancer_service_health_check,
+        update_load_balancer_service, update_load_balancer_service_health_check,
         AttachLoadBalancerToNetworkRequest, ChangeTypeOfLoadBalancerRequest, DeleteServiceRequest,
         DetachLoadBalancerFromNetworkRequest, LoadBalancerAddTarget, LoadBalancerAlgorithm,
-        LoadBalancerService, LoadBalancerServiceHealthCheck, RemoveTargetRequest,
-        UpdateLoadBalancerService,
+        LoadBalancerService, LoadBalancerServiceHealthCheck, LoadBalancerTargetIp,
+        RemoveTargetRequest, ReplaceLoadBalancerRequest, UpdateLoadBalancerService,
+        UpdateLoadBalancerServiceHealthCheck,
     },
 };
-use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::{core::v1::Service, networking::v1::Ingress};
 use kube::ResourceExt;
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    future::Future,
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::{
-    consts,
-    error::{RobotLBError, RobotLBResult},
+    audit, consts,
+    crds::hetzner_lb::HetznerLoadBalancer,
+    duration::parse_duration,
+    error::{HCloudErrorKind, RobotLBError, RobotLBResult},
+    hcloud_api::HcloudApi,
+    hcloud_span::{traced, HcloudResponse},
+    inventory,
+    state::DesiredSpec,
     CurrentContext,
 };
 
@@ -32,17 +51,88 @@ pub struct LBService {
     pub target_port: i32,
 }
 
-enum LBAlgorithm {
+pub enum LBAlgorithm {
     RoundRobin,
     LeastConnections,
 }
 
+/// Result of the load balancer reconcile.
+#[derive(Debug)]
+pub struct Reconciled {
+    /// The load balancer in Hetzner Cloud as it was
+    /// before the changes were applied.
+    pub hcloud_lb: hcloud::models::LoadBalancer,
+    /// Whether the load balancer has just been created.
+    pub created: bool,
+    /// Whether any changes were made to match the desired configuration.
+    pub changed: bool,
+    /// Targets which weren't added, since the balancer can't reach them,
+    /// with the reasons.
+    pub invalid_targets: Vec<String>,
+}
+
+/// Change the operator would make to bring the load balancer
+/// in Hetzner Cloud to the desired state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LBChange {
+    Create,
+    ChangeAlgorithm {
+        from: load_balancer_algorithm::Type,
+        to: load_balancer_algorithm::Type,
+    },
+    ChangeType {
+        from: String,
+        to: String,
+    },
+    AttachNetwork {
+        network: i64,
+        ip: Option<String>,
+    },
+    DetachNetwork {
+        network: i64,
+    },
+    AddService {
+        listen_port: i32,
+        destination_port: i32,
+    },
+    UpdateService {
+        listen_port: i32,
+        destination_port: i32,
+    },
+    DeleteService {
+        listen_port: i32,
+    },
+    AddTarget {
+        ip: String,
+    },
+    RemoveTarget {
+        ip: String,
+    },
+    /// Set labels, which are missing or have different values,
+    /// and remove the custom ones that are no longer requested.
+    UpdateLabels {
+        labels: BTreeMap<String, String>,
+        removed: BTreeSet<String>,
+    },
+}
+
 /// Struct representing a load balancer
 /// It holds all the necessary information to manage the load balancer
 /// in Hetzner Cloud.
 #[derive(Debug)]
 pub struct LoadBalancer {
     pub name: String,
+    /// ID of the load balancer recorded by a previous reconcile.
+    /// The balancer is fetched by it, and searched by name only if it's stale.
+    pub id: Option<i64>,
+    /// IDs of `HCloud` actions creating the balancer or attaching it to a network,
+    /// which were still running when they were last seen. The balancer isn't
+    /// changed until they finish, so they aren't repeated after a crash.
+    pub pending_actions: Vec<i64>,
+    /// Namespace of the service the load balancer belongs to.
+    pub namespace: String,
+    /// Name of the service the load balancer belongs to.
+    pub service: String,
     pub services: HashMap<i32, i32>,
     pub targets: Vec<String>,
     pub private_ip: Option<String>,
@@ -54,10 +144,94 @@ pub struct LoadBalancer {
 
     pub location: String,
     pub balancer_type: String,
+    /// The largest type the balancer may be upgraded to, if its targets
+    /// or services don't fit in `balancer_type`. No upgrades if not set.
+    pub max_balancer_type: Option<String>,
+    /// Types allowed by the policies of the namespace, so the balancer
+    /// is upgraded only to one of them. Any type is allowed if not set.
+    pub allowed_types: Option<BTreeSet<String>>,
     pub algorithm: LoadBalancerAlgorithm,
     pub network_name: Option<String>,
 
-    pub hcloud_config: HcloudConfig,
+    /// Labels identifying the cluster and the service the load balancer
+    /// belongs to, and the ones propagated from the service.
+    /// Other labels of the load balancer are left intact.
+    pub labels: HashMap<String, String>,
+    /// Keys of the labels requested in `robotlb/lb-labels`.
+    pub custom_labels: BTreeSet<String>,
+    /// Keys of the custom labels set by previous reconciles. The ones
+    /// that are no longer requested are removed from the load balancer.
+    pub applied_labels: BTreeSet<String>,
+
+    /// How often the load balancer is checked after successful reconcile.
+    /// Overrides the operator's resync and drift check intervals.
+    pub resync_interval: Option<Duration>,
+    /// Public IP requested in `spec.loadBalancerIP` of the service.
+    /// Hetzner can't assign a chosen IP, so only a balancer
+    /// that already has it can be used.
+    pub requested_ip: Option<String>,
+
+    pub hcloud: Arc<dyn HcloudApi>,
+}
+
+/// Parse the annotation if it's set, naming it in the error,
+/// so the malformed one is clear among the others.
+fn parse_annotation<T, E: Display>(
+    annotations: &BTreeMap<String, String>,
+    name: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> RobotLBResult<Option<T>> {
+    annotations
+        .get(name)
+        .map(|value| {
+            parse(value).map_err(|err| RobotLBError::InvalidAnnotation {
+                name: name.to_string(),
+                value: value.clone(),
+                reason: err.to_string(),
+            })
+        })
+        .transpose()
+}
+
+/// Parse labels in `key=value,key=value` format.
+fn parse_labels(value: &str) -> Result<HashMap<String, String>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok((key.trim().to_string(), value.trim().to_string()))
+            }
+            _ => Err(format!("expected `key=value`, got `{pair}`")),
+        })
+        .collect()
+}
+
+/// IDs of the actions recorded by the last reconcile of the service.
+fn pending_actions(svc: &Service) -> Vec<i64> {
+    svc.annotations()
+        .get(consts::PENDING_ACTIONS_ANN_NAME)
+        .map(|ids| {
+            ids.split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Keys of the custom labels recorded by the last reconcile of the service.
+fn applied_labels(svc: &Service) -> BTreeSet<String> {
+    svc.annotations()
+        .get(consts::APPLIED_LB_LABELS_ANN_NAME)
+        .map(|keys| {
+            keys.split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(ToString::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 impl LoadBalancer {
@@ -68,93 +242,276 @@ impl LoadBalancer {
     /// If some of the required information is missing, the method will
     /// try to use the default values from the context.
     pub fn try_from_svc(svc: &Service, context: &CurrentContext) -> RobotLBResult<Self> {
-        let retries = svc
-            .annotations()
-            .get(consts::LB_RETRIES_ANN_NAME)
-            .map(String::as_str)
-            .map(i32::from_str)
-            .transpose()?
+        let annotations = context.annotations(svc)?;
+        let retries = parse_annotation(&annotations, consts::LB_RETRIES_ANN_NAME, i32::from_str)?
             .unwrap_or(context.config.default_lb_retries);
 
-        let timeout = svc
-            .annotations()
-            .get(consts::LB_TIMEOUT_ANN_NAME)
-            .map(String::as_str)
-            .map(i32::from_str)
-            .transpose()?
+        let timeout = parse_annotation(&annotations, consts::LB_TIMEOUT_ANN_NAME, i32::from_str)?
             .unwrap_or(context.config.default_lb_timeout);
 
-        let check_interval = svc
-            .annotations()
-            .get(consts::LB_CHECK_INTERVAL_ANN_NAME)
-            .map(String::as_str)
-            .map(i32::from_str)
-            .transpose()?
-            .unwrap_or(context.config.default_lb_interval);
+        let check_interval = parse_annotation(
+            &annotations,
+            consts::LB_CHECK_INTERVAL_ANN_NAME,
+            i32::from_str,
+        )?
+        .unwrap_or(context.config.default_lb_interval);
 
-        let proxy_mode = svc
-            .annotations()
-            .get(consts::LB_PROXY_MODE_LABEL_NAME)
-            .map(String::as_str)
-            .map(bool::from_str)
-            .transpose()?
-            .unwrap_or(context.config.default_lb_proxy_mode_enabled);
+        let proxy_mode = parse_annotation(
+            &annotations,
+            consts::LB_PROXY_MODE_LABEL_NAME,
+            bool::from_str,
+        )?
+        .unwrap_or(context.config.default_lb_proxy_mode_enabled);
 
-        let location = svc
-            .annotations()
+        let location = annotations
             .get(consts::LB_LOCATION_LABEL_NAME)
             .cloned()
             .unwrap_or_else(|| context.config.default_lb_location.clone());
 
-        let balancer_type = svc
-            .annotations()
+        let balancer_type = annotations
             .get(consts::LB_BALANCER_TYPE_LABEL_NAME)
             .cloned()
             .unwrap_or_else(|| context.config.default_balancer_type.clone());
 
-        let algorithm = svc
-            .annotations()
-            .get(consts::LB_ALGORITHM_LABEL_NAME)
-            .map(String::as_str)
-            .or(Some(&context.config.default_lb_algorithm))
-            .map(LBAlgorithm::from_str)
-            .transpose()?
-            .unwrap_or(LBAlgorithm::LeastConnections);
-
-        let network_name = svc
-            .annotations()
+        let max_balancer_type = annotations.get(consts::MAX_LB_TYPE_ANN_NAME).cloned();
+
+        let algorithm = match parse_annotation(
+            &annotations,
+            consts::LB_ALGORITHM_LABEL_NAME,
+            LBAlgorithm::from_str,
+        )? {
+            Some(algorithm) => algorithm,
+            None => LBAlgorithm::from_str(&context.config.default_lb_algorithm)?,
+        };
+
+        let network_name = annotations
             .get(consts::LB_NETWORK_LABEL_NAME)
             .or(context.config.default_network.as_ref())
             .cloned();
 
-        let name = svc
-            .annotations()
+        let name = annotations
             .get(consts::LB_NAME_LABEL_NAME)
             .cloned()
-            .unwrap_or(svc.name_any());
+            .unwrap_or_else(|| context.default_lb_name(svc.name_any()));
 
-        let private_ip = svc
+        let private_ip = annotations.get(consts::LB_PRIVATE_IP_LABEL_NAME).cloned();
+
+        let resync_interval = parse_annotation(
+            &annotations,
+            consts::RESYNC_INTERVAL_ANN_NAME,
+            parse_duration,
+        )?;
+
+        // The ID, pending actions and applied labels are written by the operator itself,
+        // so defaults and profiles don't apply.
+        let id = svc
             .annotations()
-            .get(consts::LB_PRIVATE_IP_LABEL_NAME)
-            .cloned();
+            .get(consts::LB_ID_ANN_NAME)
+            .and_then(|id| id.parse().ok());
 
-        Ok(Self {
+        let custom_labels =
+            parse_annotation(&annotations, consts::LB_LABELS_ANN_NAME, parse_labels)?
+                .unwrap_or_default();
+        let mut labels = propagated_labels(svc.labels(), &context.config.propagate_labels);
+        labels.extend(custom_labels.clone());
+        labels.extend(owner_labels(
+            &context.config.cluster_name,
+            &svc.namespace().unwrap_or_default(),
+            &svc.name_any(),
+        ));
+
+        let lb = Self {
             name,
+            id,
+            pending_actions: pending_actions(svc),
+            namespace: svc.namespace().unwrap_or_default(),
+            service: svc.name_any(),
+            labels,
+            custom_labels: custom_labels.into_keys().collect(),
+            applied_labels: applied_labels(svc),
             private_ip,
             balancer_type,
+            max_balancer_type,
+            allowed_types: context
+                .policies
+                .allowed_types(&svc.namespace().unwrap_or_default()),
             check_interval,
             timeout,
             retries,
             location,
             proxy_mode,
             network_name,
+            resync_interval,
+            requested_ip: svc
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.load_balancer_ip.clone())
+                .filter(|ip| !ip.is_empty()),
             algorithm: algorithm.into(),
             services: HashMap::default(),
             targets: Vec::default(),
-            hcloud_config: context.hcloud_config.clone(),
+            hcloud: context.hcloud_api(),
+        };
+        // Balancers of deleted services are cleaned up regardless of policies,
+        // which could have been added after the balancers were created.
+        if svc.metadata.deletion_timestamp.is_none() {
+            context.policies.check(&lb)?;
+        }
+        Ok(lb)
+    }
+
+    /// Create a `LoadBalancer` instance from the service
+    /// with `HCloud` credentials the service refers to.
+    pub async fn resolve(svc: &Service, context: &CurrentContext) -> RobotLBResult<Self> {
+        let mut lb = Self::try_from_svc(svc, context)?;
+        lb.hcloud = context.hcloud_api_with(context.credentials.hcloud_config(svc, context).await?);
+        // Services recreated from manifests lose the annotation, but not the inventory entry.
+        if lb.id.is_none() && context.config.inventory {
+            match inventory::get(context, &lb.namespace, &lb.service).await {
+                Ok(entry) => lb.id = entry.map(|entry| entry.id),
+                Err(err) => tracing::warn!("Cannot read the inventory: {}", err),
+            }
+        }
+        Ok(lb)
+    }
+
+    /// Create a `LoadBalancer` instance from an ingress. It's configured
+    /// by the same annotations as services, but labeled as the ingress's.
+    /// Targets and services are the ones of the ingress controller.
+    pub async fn from_ingress(ingress: &Ingress, context: &CurrentContext) -> RobotLBResult<Self> {
+        let svc = Service {
+            metadata: ingress.metadata.clone(),
+            ..Default::default()
+        };
+        let mut lb = Self::try_from_svc(&svc, context)?;
+        lb.labels.remove(consts::LB_SERVICE_LABEL_NAME);
+        lb.labels.insert(
+            consts::LB_INGRESS_LABEL_NAME.to_string(),
+            ingress.name_any(),
+        );
+        lb.hcloud =
+            context.hcloud_api_with(context.credentials.hcloud_config(&svc, context).await?);
+        Ok(lb)
+    }
+
+    /// Create a `LoadBalancer` instance from a `HetznerLoadBalancer` resource.
+    /// Options missing in the resource are taken from the operator's defaults.
+    pub fn from_resource(
+        resource: &HetznerLoadBalancer,
+        context: &CurrentContext,
+    ) -> RobotLBResult<Self> {
+        let spec = &resource.spec;
+        let namespace = resource.namespace().unwrap_or_default();
+        let algorithm = LBAlgorithm::from_str(
+            spec.algorithm
+                .as_deref()
+                .unwrap_or(&context.config.default_lb_algorithm),
+        )?;
+        Ok(Self {
+            name: spec
+                .name
+                .clone()
+                .unwrap_or_else(|| context.default_lb_name(resource.name_any())),
+            labels: HashMap::from([
+                (
+                    consts::LB_CLUSTER_LABEL_NAME.to_string(),
+                    context.config.cluster_name.clone(),
+                ),
+                (
+                    consts::LB_NAMESPACE_LABEL_NAME.to_string(),
+                    namespace.clone(),
+                ),
+                (
+                    consts::LB_RESOURCE_LABEL_NAME.to_string(),
+                    resource.name_any(),
+                ),
+            ]),
+            namespace,
+            service: resource.name_any(),
+            services: spec
+                .services
+                .iter()
+                .map(|service| (service.listen_port, service.destination_port))
+                .collect(),
+            targets: spec.targets.clone(),
+            private_ip: spec.private_ip.clone(),
+            check_interval: spec
+                .health_check
+                .interval
+                .unwrap_or(context.config.default_lb_interval),
+            timeout: spec
+                .health_check
+                .timeout
+                .unwrap_or(context.config.default_lb_timeout),
+            retries: spec
+                .health_check
+                .retries
+                .unwrap_or(context.config.default_lb_retries),
+            proxy_mode: spec
+                .proxy_mode
+                .unwrap_or(context.config.default_lb_proxy_mode_enabled),
+            location: spec
+                .location
+                .clone()
+                .unwrap_or_else(|| context.config.default_lb_location.clone()),
+            balancer_type: spec
+                .balancer_type
+                .clone()
+                .unwrap_or_else(|| context.config.default_balancer_type.clone()),
+            max_balancer_type: None,
+            allowed_types: None,
+            algorithm: algorithm.into(),
+            network_name: spec
+                .network
+                .clone()
+                .or_else(|| context.config.default_network.clone()),
+            resync_interval: None,
+            requested_ip: None,
+            id: resource.status.as_ref().and_then(|status| status.id),
+            custom_labels: BTreeSet::new(),
+            applied_labels: BTreeSet::new(),
+            pending_actions: vec![],
+            hcloud: context.hcloud_api(),
         })
     }
 
+    /// Create a `LoadBalancer` instance from the desired configuration
+    /// saved by `export`, for services that no longer exist.
+    #[must_use]
+    pub fn from_desired(
+        desired: DesiredSpec,
+        namespace: &str,
+        service: &str,
+        context: &CurrentContext,
+    ) -> Self {
+        Self {
+            name: desired.lb_name,
+            id: None,
+            pending_actions: vec![],
+            namespace: namespace.to_string(),
+            service: service.to_string(),
+            labels: owner_labels(&context.config.cluster_name, namespace, service),
+            custom_labels: BTreeSet::new(),
+            applied_labels: BTreeSet::new(),
+            services: desired.services,
+            targets: desired.targets,
+            private_ip: desired.private_ip,
+            check_interval: desired.check_interval,
+            timeout: desired.timeout,
+            retries: desired.retries,
+            proxy_mode: desired.proxy_mode,
+            location: desired.location,
+            balancer_type: desired.balancer_type,
+            max_balancer_type: None,
+            allowed_types: None,
+            algorithm: desired.algorithm,
+            network_name: desired.network_name,
+            resync_interval: None,
+            requested_ip: None,
+            hcloud: context.hcloud_api(),
+        }
+    }
+
     /// Add a service to the load balancer.
     /// The service will listen on the `listen_port` and forward the
     /// traffic to the `target_port` to all targets.
@@ -170,372 +527,939 @@ impl LoadBalancer {
         self.targets.push(ip.to_string());
     }
 
+    /// Attach the service and the name of the balancer to the error,
+    /// so logs of many services can tell which one has failed.
+    /// Skipping the service isn't a failure, so it's kept as is.
+    fn attribute(&self, error: RobotLBError) -> RobotLBError {
+        if matches!(
+            error,
+            RobotLBError::WithLoadBalancer { .. } | RobotLBError::SkipService
+        ) {
+            return error;
+        }
+        RobotLBError::WithLoadBalancer {
+            namespace: self.namespace.clone(),
+            service: self.service.clone(),
+            balancer: self.name.clone(),
+            source: Box::new(error),
+        }
+    }
+
     /// Reconcile the load balancer to match the desired configuration.
     #[tracing::instrument(skip(self), fields(lb_name=self.name))]
-    pub async fn reconcile(&self) -> RobotLBResult<hcloud::models::LoadBalancer> {
-        let hcloud_balancer = self.get_or_create_hcloud_lb().await?;
-        self.reconcile_algorithm(&hcloud_balancer).await?;
-        self.reconcile_lb_type(&hcloud_balancer).await?;
-        self.reconcile_network(&hcloud_balancer).await?;
-        self.reconcile_services(&hcloud_balancer).await?;
-        self.reconcile_targets(&hcloud_balancer).await?;
-        Ok(hcloud_balancer)
-    }
-
-    /// Reconcile the services of the load balancer.
-    /// This method will compare the desired configuration of the services
-    /// with the current configuration of the services in the load balancer.
-    /// If the configuration does not match, the method will update the service.
-    async fn reconcile_services(
-        &self,
-        hcloud_balancer: &hcloud::models::LoadBalancer,
-    ) -> RobotLBResult<()> {
-        for service in &hcloud_balancer.services {
-            // Here we check that all the services are configured correctly.
-            // If the service is not configured correctly, we update it.
-            if let Some(destination_port) = self.services.get(&service.listen_port) {
-                if service.destination_port == *destination_port
-                    && service.health_check.port == *destination_port
-                    && service.health_check.interval == self.check_interval
-                    && service.health_check.retries == self.retries
-                    && service.health_check.timeout == self.timeout
-                    && service.proxyprotocol == self.proxy_mode
-                    && service.http.is_none()
-                    && service.health_check.protocol
-                        == hcloud::models::load_balancer_service_health_check::Protocol::Tcp
-                {
-                    // The desired configuration matches the current configuration.
-                    continue;
+    pub async fn reconcile(&mut self) -> RobotLBResult<Reconciled> {
+        self.reconcile_balancer()
+            .await
+            .map_err(|err| self.attribute(err))
+    }
+
+    async fn reconcile_balancer(&mut self) -> RobotLBResult<Reconciled> {
+        self.check_pending_actions().await?;
+        let hcloud_lb = self.get_hcloud_lb().await?;
+        if let Some(hcloud_lb) = &hcloud_lb {
+            self.check_owner(hcloud_lb)?;
+        }
+        // Nothing is changed, since `HCloud` would reject the extra targets
+        // or services anyway, one request at a time.
+        if let Some(limit) = self.fit_lb_type(hcloud_lb.as_ref()).await? {
+            return Err(RobotLBError::LimitExceeded(limit));
+        }
+        let network = self.get_network().await?;
+        let mut plan = self.plan(
+            hcloud_lb.as_ref(),
+            network.as_ref().map(|network| network.id),
+        );
+        // `HCloud` rejects such targets with errors which don't tell what's wrong,
+        // so they are skipped, and the rest of the plan is applied.
+        let network_range = network.as_ref().map(|network| network.ip_range.as_str());
+        let mut invalid_targets = vec![];
+        plan.retain(|change| {
+            let LBChange::AddTarget { ip } = change else {
+                return true;
+            };
+            let Err(reason) = check_target(ip, network_range) else {
+                return true;
+            };
+            tracing::warn!("Target {} is skipped: {}", ip, reason);
+            invalid_targets.push(format!("{ip}: {reason}"));
+            false
+        });
+        if !plan.is_empty() {
+            // The whole plan is logged at once, so the intent of the reconcile
+            // is visible in one line, before any of the calls is made.
+            let summary = plan
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            tracing::info!(plan = summary, "Applying changes: {}", summary);
+        }
+        let (hcloud_lb, created) = if let Some(hcloud_lb) = hcloud_lb {
+            (hcloud_lb, false)
+        } else {
+            self.check_location().await?;
+            self.check_lb_type().await?;
+            (self.create_hcloud_lb().await?, true)
+        };
+        for change in plan.iter().filter(|change| **change != LBChange::Create) {
+            if let Some(action) = self.apply(&hcloud_lb, change).await? {
+                self.pending_actions.push(action);
+            }
+        }
+        Ok(Reconciled {
+            hcloud_lb,
+            created,
+            changed: !plan.is_empty(),
+            invalid_targets,
+        })
+    }
+
+    /// Forget the pending actions which have finished, failing with
+    /// [`RobotLBError::ActionInProgress`] if any of them is still running.
+    /// Failed actions are only logged, the plan repeats what they haven't done.
+    async fn check_pending_actions(&mut self) -> RobotLBResult<()> {
+        let mut running = vec![];
+        for id in std::mem::take(&mut self.pending_actions) {
+            let result = traced(
+                "get_load_balancer_action",
+                self.id,
+                self.hcloud
+                    .get_load_balancer_action(GetLoadBalancerActionParams { id }),
+            )
+            .await;
+            let action = match result {
+                Ok(response) => response.action,
+                // Finished actions are kept by `HCloud` only for a while.
+                Err(err) if err.hcloud_kind() == Some(HCloudErrorKind::NotFound) => continue,
+                Err(err) => {
+                    self.pending_actions = vec![id];
+                    return Err(err);
                 }
-                tracing::info!(
-                    "Desired service configuration for port {} does not match current configuration. Updating ...",
-                    service.listen_port,
-                );
-                hcloud::apis::load_balancers_api::update_service(
-                        &self.hcloud_config,
-                    UpdateServiceParams {
-                        id: hcloud_balancer.id,
-                        body: Some(UpdateLoadBalancerService {
-                            http: None,
-                            protocol: Some(hcloud::models::update_load_balancer_service::Protocol::Tcp),
-                            listen_port: service.listen_port,
-                            destination_port: Some(*destination_port),
-                            proxyprotocol: Some(self.proxy_mode),
-                            health_check: Some(Box::new(
-                                hcloud::models::UpdateLoadBalancerServiceHealthCheck {
-                                    protocol: Some(hcloud::models::update_load_balancer_service_health_check::Protocol::Tcp),
-                                    http: None,
-                                    interval: Some(self.check_interval),
-                                    port: Some(*destination_port),
-                                    retries: Some(self.retries),
-                                    timeout: Some(self.timeout),
-                                },
-                            )),
-                        }),
-                    },
-                )
-                .await?;
-            } else {
-                tracing::info!(
-                    "Deleting service that listens for port {} from load-balancer {}",
-                    service.listen_port,
-                    hcloud_balancer.name,
-                );
-                hcloud::apis::load_balancers_api::delete_service(
-                    &self.hcloud_config,
-                    DeleteServiceParams {
-                        id: hcloud_balancer.id,
-                        delete_service_request: Some(DeleteServiceRequest {
-                            listen_port: service.listen_port,
-                        }),
-                    },
-                )
-                .await?;
+            };
+            match action.status {
+                action::Status::Running => running.push(id),
+                action::Status::Error => tracing::warn!(
+                    "Action {} ({}) of the previous reconcile has failed: {}",
+                    id,
+                    action.command,
+                    action.error.map_or_else(String::new, |error| error.message)
+                ),
+                action::Status::Success => {}
             }
         }
+        self.pending_actions = running;
+        self.pending_actions
+            .first()
+            .map_or(Ok(()), |id| Err(RobotLBError::ActionInProgress(*id)))
+    }
+
+    /// Compare the desired configuration with the load balancer in Hetzner Cloud
+    /// and list the changes a reconcile would make, without making them.
+    #[tracing::instrument(skip(self), fields(lb_name=self.name))]
+    pub async fn diff(&mut self) -> RobotLBResult<Vec<LBChange>> {
+        self.diff_balancer()
+            .await
+            .map_err(|err| self.attribute(err))
+    }
+
+    async fn diff_balancer(&mut self) -> RobotLBResult<Vec<LBChange>> {
+        let hcloud_lb = self.get_hcloud_lb().await?;
+        if let Some(hcloud_lb) = &hcloud_lb {
+            self.check_owner(hcloud_lb)?;
+        }
+        if let Some(limit) = self.fit_lb_type(hcloud_lb.as_ref()).await? {
+            tracing::warn!("Load balancer exceeds its limits: {}", limit);
+        }
+        let desired_network = self.get_network().await?.map(|network| network.id);
+        Ok(self.plan(hcloud_lb.as_ref(), desired_network))
+    }
 
-        for (listen_port, destination_port) in &self.services {
-            if !hcloud_balancer
-                .services
-                .iter()
-                .any(|s| s.listen_port == *listen_port)
+    /// List the changes needed to bring the load balancer to the desired state.
+    /// If the load balancer doesn't exist, it has to be created and configured
+    /// from scratch.
+    ///
+    /// Planning makes no calls, the changes are applied by [`Self::apply`]
+    /// in the order they are listed.
+    fn plan(
+        &self,
+        hcloud_lb: Option<&hcloud::models::LoadBalancer>,
+        desired_network: Option<i64>,
+    ) -> Vec<LBChange> {
+        let Some(hcloud_lb) = hcloud_lb else {
+            let mut changes = vec![LBChange::Create];
+            if let Some(network) = desired_network {
+                changes.push(LBChange::AttachNetwork {
+                    network,
+                    ip: self.private_ip.clone(),
+                });
+            }
+            let mut services = self.services.iter().collect::<Vec<_>>();
+            services.sort_unstable();
+            changes.extend(services.into_iter().map(|(listen_port, destination_port)| {
+                LBChange::AddService {
+                    listen_port: *listen_port,
+                    destination_port: *destination_port,
+                }
+            }));
+            changes.extend(
+                self.targets
+                    .iter()
+                    .map(|ip| LBChange::AddTarget { ip: ip.clone() }),
+            );
+            return changes;
+        };
+
+        let mut changes = vec![];
+        let labels = self.missing_labels(hcloud_lb);
+        let removed = self.removed_labels(hcloud_lb);
+        if !labels.is_empty() || !removed.is_empty() {
+            changes.push(LBChange::UpdateLabels { labels, removed });
+        }
+        if *hcloud_lb.algorithm != self.algorithm {
+            changes.push(LBChange::ChangeAlgorithm {
+                from: hcloud_lb.algorithm.r#type,
+                to: self.algorithm.r#type,
+            });
+        }
+        if hcloud_lb.load_balancer_type.name != self.balancer_type {
+            changes.push(LBChange::ChangeType {
+                from: hcloud_lb.load_balancer_type.name.clone(),
+                to: self.balancer_type.clone(),
+            });
+        }
+        changes.extend(self.network_changes(hcloud_lb, desired_network));
+        changes.extend(self.service_changes(hcloud_lb));
+        changes.extend(self.target_changes(hcloud_lb));
+        changes
+    }
+
+    /// Check that the load balancer with the same name in `HCloud`
+    /// doesn't belong to another service, ingress or `HetznerLoadBalancer`,
+    /// e.g. one in another namespace or in another cluster sharing the project,
+    /// which resolves to the same name.
+    ///
+    /// Balancers without the owner labels, e.g. created by hand,
+    /// are adopted by the service.
+    fn check_owner(&self, hcloud_balancer: &hcloud::models::LoadBalancer) -> RobotLBResult<()> {
+        let own_cluster = self.labels.get(consts::LB_CLUSTER_LABEL_NAME);
+        // Balancers created before the cluster label was added belong to this cluster.
+        let cluster = hcloud_balancer
+            .labels
+            .get(consts::LB_CLUSTER_LABEL_NAME)
+            .or(own_cluster)
+            .cloned()
+            .unwrap_or_default();
+        let namespace = hcloud_balancer
+            .labels
+            .get(consts::LB_NAMESPACE_LABEL_NAME)
+            .cloned()
+            .unwrap_or_default();
+        for (label, kind) in OWNER_LABELS {
+            let Some(owner) = hcloud_balancer.labels.get(label) else {
+                continue;
+            };
+            if Some(&cluster) != own_cluster
+                || namespace != self.namespace
+                || self.labels.get(label) != Some(owner)
             {
-                tracing::info!(
-                    "Found missing service. Adding service that listens for port {}",
-                    listen_port
-                );
-                hcloud::apis::load_balancers_api::add_service(
-                    &self.hcloud_config,
-                AddServiceParams {
-                    id: hcloud_balancer.id,
-                    body: Some(LoadBalancerService {
-                        http: None,
-                        listen_port: *listen_port,
-                        destination_port: *destination_port,
-                        protocol: hcloud::models::load_balancer_service::Protocol::Tcp,
-                        proxyprotocol: self.proxy_mode,
-                        health_check: Box::new(LoadBalancerServiceHealthCheck {
-                            http: None,
-                            interval: self.check_interval,
-                            port: *destination_port,
-                            protocol:
-                                hcloud::models::load_balancer_service_health_check::Protocol::Tcp,
-                            retries: self.retries,
-                            timeout: self.timeout,
-                        }),
-                    }),
-                },
-            )
-            .await?;
+                return Err(RobotLBError::LBNameConflict {
+                    name: self.name.clone(),
+                    kind,
+                    cluster,
+                    namespace,
+                    owner: owner.clone(),
+                });
             }
         }
         Ok(())
     }
 
-    /// Reconcile the targets of the load balancer.
-    /// This method will compare the desired configuration of the targets
-    /// with the current configuration of the targets in the load balancer.
-    /// If the configuration does not match, the method will update the target.
-    async fn reconcile_targets(
+    /// Labels that are missing on the load balancer or have different values.
+    fn missing_labels(
         &self,
         hcloud_balancer: &hcloud::models::LoadBalancer,
-    ) -> RobotLBResult<()> {
-        for target in &hcloud_balancer.targets {
-            let Some(target_ip) = target.ip.clone() else {
+    ) -> BTreeMap<String, String> {
+        self.labels
+            .iter()
+            .filter(|(key, value)| hcloud_balancer.labels.get(*key) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Custom labels set by previous reconciles, which are no longer requested,
+    /// but are still on the load balancer.
+    fn removed_labels(&self, hcloud_balancer: &hcloud::models::LoadBalancer) -> BTreeSet<String> {
+        self.applied_labels
+            .iter()
+            .filter(|key| !self.labels.contains_key(*key))
+            .filter(|key| hcloud_balancer.labels.contains_key(*key))
+            .cloned()
+            .collect()
+    }
+
+    /// Networks the load balancer has to be detached from or attached to.
+    fn network_changes(
+        &self,
+        hcloud_balancer: &hcloud::models::LoadBalancer,
+        desired_network: Option<i64>,
+    ) -> Vec<LBChange> {
+        let mut changes = vec![];
+        let mut contain_desired_network = false;
+        for private_net in &hcloud_balancer.private_net {
+            let Some(network) = private_net.network else {
                 continue;
             };
-            if !self.targets.contains(&target_ip.ip) {
-                tracing::info!("Removing target {}", target_ip.ip);
-                hcloud::apis::load_balancers_api::remove_target(
-                    &self.hcloud_config,
-                    RemoveTargetParams {
-                        id: hcloud_balancer.id,
-                        remove_target_request: Some(RemoveTargetRequest {
-                            ip: Some(target_ip),
-                            ..Default::default()
-                        }),
-                    },
-                )
-                .await?;
+            if desired_network == Some(network)
+                && (self.private_ip.is_none() || private_net.ip == self.private_ip)
+            {
+                contain_desired_network = true;
+                continue;
             }
+            changes.push(LBChange::DetachNetwork { network });
+        }
+        if let Some(network) = desired_network.filter(|_| !contain_desired_network) {
+            changes.push(LBChange::AttachNetwork {
+                network,
+                ip: self.private_ip.clone(),
+            });
         }
+        changes
+    }
 
-        for ip in &self.targets {
-            if !hcloud_balancer
-                .targets
-                .iter()
-                .any(|t| t.ip.as_ref().map(|i| i.ip.as_str()) == Some(ip))
-            {
-                tracing::info!("Adding target {}", ip);
-                hcloud::apis::load_balancers_api::add_target(
-                    &self.hcloud_config,
-                    AddTargetParams {
-                        id: hcloud_balancer.id,
-                        body: Some(LoadBalancerAddTarget {
-                            ip: Some(Box::new(hcloud::models::LoadBalancerTargetIp {
-                                ip: ip.clone(),
-                            })),
-                            ..Default::default()
-                        }),
-                    },
-                )
-                .await?;
+    /// Services of the load balancer that have to be added, updated or deleted.
+    fn service_changes(&self, hcloud_balancer: &hcloud::models::LoadBalancer) -> Vec<LBChange> {
+        let mut changes = vec![];
+        for service in &hcloud_balancer.services {
+            match self.services.get(&service.listen_port) {
+                Some(destination_port) if self.service_matches(service, *destination_port) => {}
+                Some(destination_port) => changes.push(LBChange::UpdateService {
+                    listen_port: service.listen_port,
+                    destination_port: *destination_port,
+                }),
+                None => changes.push(LBChange::DeleteService {
+                    listen_port: service.listen_port,
+                }),
             }
         }
-        Ok(())
+        let mut missing = self
+            .services
+            .iter()
+            .filter(|(listen_port, _)| {
+                !hcloud_balancer
+                    .services
+                    .iter()
+                    .any(|s| s.listen_port == **listen_port)
+            })
+            .collect::<Vec<_>>();
+        missing.sort_unstable();
+        changes.extend(missing.into_iter().map(|(listen_port, destination_port)| {
+            LBChange::AddService {
+                listen_port: *listen_port,
+                destination_port: *destination_port,
+            }
+        }));
+        changes
+    }
+
+    /// Targets that have to be removed from or added to the load balancer.
+    fn target_changes(&self, hcloud_balancer: &hcloud::models::LoadBalancer) -> Vec<LBChange> {
+        let current = hcloud_balancer
+            .targets
+            .iter()
+            .filter_map(|target| target.ip.as_ref().map(|ip| ip.ip.clone()))
+            .collect::<Vec<_>>();
+        let removed = current
+            .iter()
+            .filter(|ip| !self.targets.contains(ip))
+            .map(|ip| LBChange::RemoveTarget { ip: ip.clone() });
+        let added = self
+            .targets
+            .iter()
+            .filter(|ip| !current.contains(ip))
+            .map(|ip| LBChange::AddTarget { ip: ip.clone() });
+        removed.chain(added).collect()
     }
 
-    /// Reconcile the load balancer algorithm.
-    /// This method will compare the desired algorithm configuration
-    /// and update it if it does not match the current configuration.
-    async fn reconcile_algorithm(
+    /// Parameters to update the service listening on `listen_port`
+    /// to match the desired configuration.
+    fn update_service_params(
         &self,
-        hcloud_balancer: &hcloud::models::LoadBalancer,
-    ) -> RobotLBResult<()> {
-        if *hcloud_balancer.algorithm == self.algorithm.clone().into() {
-            return Ok(());
+        lb_id: i64,
+        listen_port: i32,
+        destination_port: i32,
+    ) -> UpdateServiceParams {
+        UpdateServiceParams {
+            id: lb_id,
+            body: Some(UpdateLoadBalancerService {
+                http: None,
+                protocol: Some(update_load_balancer_service::Protocol::Tcp),
+                listen_port,
+                destination_port: Some(destination_port),
+                proxyprotocol: Some(self.proxy_mode),
+                health_check: Some(Box::new(UpdateLoadBalancerServiceHealthCheck {
+                    protocol: Some(update_load_balancer_service_health_check::Protocol::Tcp),
+                    http: None,
+                    interval: Some(self.check_interval),
+                    port: Some(destination_port),
+                    retries: Some(self.retries),
+                    timeout: Some(self.timeout),
+                })),
+            }),
         }
-        tracing::info!(
-            "Changing load balancer algorithm from {:?} to {:?}",
-            hcloud_balancer.algorithm,
-            self.algorithm
-        );
-        hcloud::apis::load_balancers_api::change_algorithm(
-            &self.hcloud_config,
-            ChangeAlgorithmParams {
-                id: hcloud_balancer.id,
-                body: Some(self.algorithm.clone().into()),
-            },
-        )
-        .await?;
-        Ok(())
     }
 
-    /// Reconcile the load balancer type.
-    async fn reconcile_lb_type(
+    /// Parameters to add a service forwarding traffic
+    /// from `listen_port` to `destination_port` of targets.
+    fn add_service_params(
         &self,
-        hcloud_balancer: &hcloud::models::LoadBalancer,
-    ) -> RobotLBResult<()> {
-        if hcloud_balancer.load_balancer_type.name == self.balancer_type {
-            return Ok(());
-        }
-        tracing::info!(
-            "Changing load balancer type from {} to {}",
-            hcloud_balancer.load_balancer_type.name,
-            self.balancer_type
-        );
-        hcloud::apis::load_balancers_api::change_type_of_load_balancer(
-            &self.hcloud_config,
-            ChangeTypeOfLoadBalancerParams {
-                id: hcloud_balancer.id,
-                change_type_of_load_balancer_request: Some(ChangeTypeOfLoadBalancerRequest {
-                    load_balancer_type: self.balancer_type.clone(),
+        lb_id: i64,
+        listen_port: i32,
+        destination_port: i32,
+    ) -> AddServiceParams {
+        AddServiceParams {
+            id: lb_id,
+            body: Some(LoadBalancerService {
+                http: None,
+                listen_port,
+                destination_port,
+                protocol: load_balancer_service::Protocol::Tcp,
+                proxyprotocol: self.proxy_mode,
+                health_check: Box::new(LoadBalancerServiceHealthCheck {
+                    http: None,
+                    interval: self.check_interval,
+                    port: destination_port,
+                    protocol: load_balancer_service_health_check::Protocol::Tcp,
+                    retries: self.retries,
+                    timeout: self.timeout,
                 }),
+            }),
+        }
+    }
+
+    /// Run a mutating `HCloud` API call and record it in the audit log.
+    async fn mutate<T, E>(
+        &self,
+        endpoint: &'static str,
+        lb_id: Option<i64>,
+        summary: String,
+        call: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E>
+    where
+        T: HcloudResponse,
+        E: Display,
+    {
+        let result = traced(endpoint, lb_id, call).await;
+        audit::record(
+            &audit::Entry {
+                namespace: &self.namespace,
+                service: &self.service,
+                lb_name: &self.name,
+                endpoint,
+                lb_id,
+                summary: &summary,
             },
-        )
-        .await?;
-        Ok(())
+            &result,
+        );
+        result
     }
 
-    /// Reconcile the network of the load balancer.
-    /// This method will compare the desired network configuration
-    /// with the current network configuration of the load balancer.
-    /// If the configuration does not match, the method will update the
-    /// network configuration.
-    async fn reconcile_network(
+    /// Check that the service of the load balancer forwards traffic
+    /// to the `destination_port` with the desired health check settings.
+    fn service_matches(&self, service: &LoadBalancerService, destination_port: i32) -> bool {
+        service.destination_port == destination_port
+            && service.health_check.port == destination_port
+            && service.health_check.interval == self.check_interval
+            && service.health_check.retries == self.retries
+            && service.health_check.timeout == self.timeout
+            && service.proxyprotocol == self.proxy_mode
+            && service.http.is_none()
+            && service.health_check.protocol
+                == hcloud::models::load_balancer_service_health_check::Protocol::Tcp
+    }
+
+    /// Apply the change planned by [`Self::plan`] to the existing load balancer.
+    /// Returns the ID of the started action, if it has to be waited for.
+    // It's one call per kind of change.
+    #[allow(clippy::too_many_lines)]
+    async fn apply(
         &self,
         hcloud_balancer: &hcloud::models::LoadBalancer,
-    ) -> RobotLBResult<()> {
-        // If the network name is not provided, and laod balancer is not attached to any network,
-        // we can skip this step.
-        if self.network_name.is_none() && hcloud_balancer.private_net.is_empty() {
-            return Ok(());
-        }
-
-        let desired_network = self.get_network().await?.map(|network| network.id);
-        // If the network name is not provided, but the load balancer is attached to a network,
-        // we need to detach it from the network.
-        let mut contain_desired_network = false;
-        if !hcloud_balancer.private_net.is_empty() {
-            for private_net in &hcloud_balancer.private_net {
-                let Some(private_net_id) = private_net.network else {
-                    continue;
-                };
-                // The load balancer is attached to a target network.
-                if desired_network == Some(private_net_id) {
-                    // Specific IP was provided, we need to check if the IP is the same.
-                    if self.private_ip.is_some() {
-                        // if IPs match, we can leave everything as it is.
-                        if private_net.ip == self.private_ip {
-                            contain_desired_network = true;
-                            continue;
-                        }
-                    } else {
-                        // No specific IP was provided, we can leave everything as it is.
-                        contain_desired_network = true;
-                        continue;
-                    }
-                }
-                tracing::info!("Detaching balancer from network {}", private_net_id);
-                hcloud::apis::load_balancers_api::detach_load_balancer_from_network(
-                    &self.hcloud_config,
-                    DetachLoadBalancerFromNetworkParams {
-                        id: hcloud_balancer.id,
-                        detach_load_balancer_from_network_request: Some(
-                            DetachLoadBalancerFromNetworkRequest {
-                                network: private_net_id,
+        change: &LBChange,
+    ) -> RobotLBResult<Option<i64>> {
+        let id = hcloud_balancer.id;
+        let mut pending_action = None;
+        tracing::info!(
+            hcloud_action = change.action(),
+            "Applying change: {}",
+            change
+        );
+        match change {
+            // The balancer is created before the rest of the plan is applied.
+            LBChange::Create => {}
+            LBChange::UpdateLabels {
+                labels: missing,
+                removed,
+            } => {
+                let mut labels = hcloud_balancer.labels.clone();
+                labels.retain(|key, _| !removed.contains(key));
+                labels.extend(missing.clone());
+                self.mutate(
+                    "replace_load_balancer",
+                    Some(id),
+                    change.to_string(),
+                    self.hcloud
+                        .replace_load_balancer(ReplaceLoadBalancerParams {
+                            id,
+                            replace_load_balancer_request: Some(ReplaceLoadBalancerRequest {
+                                labels: Some(labels),
+                                name: None,
+                            }),
+                        }),
+                )
+                .await?;
+            }
+            LBChange::ChangeAlgorithm { to, .. } => {
+                self.mutate(
+                    "change_algorithm",
+                    Some(id),
+                    format!("algorithm={to:?}"),
+                    self.hcloud.change_algorithm(ChangeAlgorithmParams {
+                        id,
+                        body: Some(LoadBalancerAlgorithm { r#type: *to }),
+                    }),
+                )
+                .await?;
+            }
+            LBChange::ChangeType { to, .. } => {
+                self.check_lb_type().await?;
+                self.mutate(
+                    "change_type_of_load_balancer",
+                    Some(id),
+                    format!("type={to}"),
+                    self.hcloud
+                        .change_type_of_load_balancer(ChangeTypeOfLoadBalancerParams {
+                            id,
+                            change_type_of_load_balancer_request: Some(
+                                ChangeTypeOfLoadBalancerRequest {
+                                    load_balancer_type: to.clone(),
+                                },
+                            ),
+                        }),
+                )
+                .await?;
+            }
+            LBChange::AttachNetwork { network, ip } => {
+                let response = self
+                    .mutate(
+                        "attach_load_balancer_to_network",
+                        Some(id),
+                        format!("network={network} ip={}", ip.as_deref().unwrap_or("auto")),
+                        self.hcloud.attach_load_balancer_to_network(
+                            AttachLoadBalancerToNetworkParams {
+                                id,
+                                attach_load_balancer_to_network_request: Some(
+                                    AttachLoadBalancerToNetworkRequest {
+                                        ip: ip.clone(),
+                                        network: *network,
+                                    },
+                                ),
                             },
                         ),
-                    },
+                    )
+                    .await?;
+                pending_action = running_action(&response.action);
+            }
+            LBChange::DetachNetwork { network } => {
+                self.mutate(
+                    "detach_load_balancer_from_network",
+                    Some(id),
+                    format!("network={network}"),
+                    self.hcloud.detach_load_balancer_from_network(
+                        DetachLoadBalancerFromNetworkParams {
+                            id,
+                            detach_load_balancer_from_network_request: Some(
+                                DetachLoadBalancerFromNetworkRequest { network: *network },
+                            ),
+                        },
+                    ),
+                )
+                .await?;
+            }
+            LBChange::AddService {
+                listen_port,
+                destination_port,
+            } => {
+                self.mutate(
+                    "add_service",
+                    Some(id),
+                    format!("listen_port={listen_port} destination_port={destination_port}"),
+                    self.hcloud.add_service(self.add_service_params(
+                        id,
+                        *listen_port,
+                        *destination_port,
+                    )),
+                )
+                .await?;
+            }
+            LBChange::UpdateService {
+                listen_port,
+                destination_port,
+            } => {
+                self.mutate(
+                    "update_service",
+                    Some(id),
+                    format!("listen_port={listen_port} destination_port={destination_port}"),
+                    self.hcloud.update_service(self.update_service_params(
+                        id,
+                        *listen_port,
+                        *destination_port,
+                    )),
+                )
+                .await?;
+            }
+            LBChange::DeleteService { listen_port } => {
+                self.mutate(
+                    "delete_service",
+                    Some(id),
+                    format!("listen_port={listen_port}"),
+                    self.hcloud.delete_service(DeleteServiceParams {
+                        id,
+                        delete_service_request: Some(DeleteServiceRequest {
+                            listen_port: *listen_port,
+                        }),
+                    }),
+                )
+                .await?;
+            }
+            LBChange::AddTarget { ip } => {
+                self.mutate(
+                    "add_target",
+                    Some(id),
+                    format!("ip={ip}"),
+                    self.hcloud.add_target(AddTargetParams {
+                        id,
+                        body: Some(LoadBalancerAddTarget {
+                            ip: Some(Box::new(LoadBalancerTargetIp { ip: ip.clone() })),
+                            ..Default::default()
+                        }),
+                    }),
+                )
+                .await?;
+            }
+            LBChange::RemoveTarget { ip } => {
+                self.mutate(
+                    "remove_target",
+                    Some(id),
+                    format!("ip={ip}"),
+                    self.hcloud.remove_target(RemoveTargetParams {
+                        id,
+                        remove_target_request: Some(RemoveTargetRequest {
+                            ip: Some(Box::new(LoadBalancerTargetIp { ip: ip.clone() })),
+                            ..Default::default()
+                        }),
+                    }),
                 )
                 .await?;
             }
         }
-        if !contain_desired_network {
-            let Some(network_id) = desired_network else {
-                return Ok(());
-            };
-            tracing::info!("Attaching balancer to network {}", network_id);
-            hcloud::apis::load_balancers_api::attach_load_balancer_to_network(
-                &self.hcloud_config,
-                AttachLoadBalancerToNetworkParams {
-                    id: hcloud_balancer.id,
-                    attach_load_balancer_to_network_request: Some(
-                        AttachLoadBalancerToNetworkRequest {
-                            ip: self.private_ip.clone(),
-                            network: network_id,
-                        },
-                    ),
-                },
-            )
-            .await?;
+        Ok(pending_action)
+    }
+
+    /// Upgrade the desired type to the smallest one the targets and services
+    /// fit in, up to the type from `robotlb/max-lb-type`.
+    ///
+    /// Targets and services attached to the existing balancer must fit too,
+    /// since they are removed only after the type is changed. So the balancer
+    /// is downgraded back only after extra targets and services are removed.
+    ///
+    /// Returns the limits the balancer exceeds, if no allowed type fits.
+    async fn fit_lb_type(
+        &mut self,
+        hcloud_lb: Option<&hcloud::models::LoadBalancer>,
+    ) -> RobotLBResult<Option<String>> {
+        let count = |len: usize| i64::try_from(len).unwrap_or(i64::MAX);
+        let targets = count(
+            self.targets
+                .len()
+                .max(hcloud_lb.map_or(0, |hcloud_lb| hcloud_lb.targets.len())),
+        );
+        let services = count(
+            self.services
+                .len()
+                .max(hcloud_lb.map_or(0, |hcloud_lb| hcloud_lb.services.len())),
+        );
+        let fits = |lb_type: &hcloud::models::LoadBalancerType| {
+            lb_type.max_targets >= targets && lb_type.max_services >= services
+        };
+        // Limits of the current type are known without listing the types.
+        if hcloud_lb.is_some_and(|hcloud_lb| {
+            hcloud_lb.load_balancer_type.name == self.balancer_type
+                && fits(&hcloud_lb.load_balancer_type)
+        }) {
+            return Ok(None);
         }
-        Ok(())
+        let response = traced(
+            "list_load_balancer_types",
+            None,
+            self.hcloud
+                .list_load_balancer_types(ListLoadBalancerTypesParams::default()),
+        )
+        .await?;
+        let mut lb_types = response.load_balancer_types;
+        lb_types.sort_by_key(|lb_type| (lb_type.max_targets, lb_type.max_services));
+        let position = |name: &str| lb_types.iter().position(|lb_type| lb_type.name == name);
+        // Unknown desired type is reported when the balancer is created or changed.
+        let Some(desired) = position(&self.balancer_type) else {
+            return Ok(None);
+        };
+        let desired_type = &lb_types[desired];
+        if fits(desired_type) {
+            return Ok(None);
+        }
+        if let Some(max_balancer_type) = &self.max_balancer_type {
+            let max = position(max_balancer_type).ok_or_else(|| {
+                RobotLBError::UnknownBalancerType(format!(
+                    "{max_balancer_type} in {}",
+                    consts::MAX_LB_TYPE_ANN_NAME
+                ))
+            })?;
+            let upgrade = lb_types
+                .get(desired..=max)
+                .unwrap_or_default()
+                .iter()
+                .filter(|lb_type| lb_type.deprecated.is_none())
+                .filter(|lb_type| {
+                    self.allowed_types
+                        .as_ref()
+                        .is_none_or(|allowed| allowed.contains(&lb_type.name))
+                })
+                .find(|lb_type| fits(lb_type));
+            if let Some(lb_type) = upgrade {
+                tracing::info!(
+                    "{} targets and {} services don't fit in load balancer type {}, using {}",
+                    targets,
+                    services,
+                    self.balancer_type,
+                    lb_type.name
+                );
+                self.balancer_type.clone_from(&lb_type.name);
+                return Ok(None);
+            }
+        }
+        let mut exceeded = vec![];
+        if targets > desired_type.max_targets {
+            exceeded.push(format!(
+                "{targets} targets exceed the limit of {} targets",
+                desired_type.max_targets
+            ));
+        }
+        if services > desired_type.max_services {
+            exceeded.push(format!(
+                "{services} services exceed the limit of {} services",
+                desired_type.max_services
+            ));
+        }
+        let no_upgrade = self
+            .max_balancer_type
+            .as_ref()
+            .map(|max_balancer_type| {
+                format!(", and no allowed type up to {max_balancer_type} fits them")
+            })
+            .unwrap_or_default();
+        Ok(Some(format!(
+            "{} of load balancer type {}{no_upgrade}",
+            exceeded.join(" and "),
+            self.balancer_type
+        )))
+    }
+
+    /// Check that the desired type of the load balancer exists,
+    /// so an unknown type is reported along with the known ones
+    /// instead of an opaque error of creating or changing the balancer.
+    async fn check_lb_type(&self) -> RobotLBResult<()> {
+        let response = traced(
+            "list_load_balancer_types",
+            None,
+            self.hcloud
+                .list_load_balancer_types(ListLoadBalancerTypesParams::default()),
+        )
+        .await?;
+        let lb_types = response.load_balancer_types;
+        if lb_types
+            .iter()
+            .any(|lb_type| lb_type.name == self.balancer_type)
+        {
+            return Ok(());
+        }
+        let known = lb_types
+            .iter()
+            .map(|lb_type| {
+                format!(
+                    "{} (up to {} targets and {} services)",
+                    lb_type.name, lb_type.max_targets, lb_type.max_services
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(RobotLBError::UnknownBalancerType(format!(
+            "{}, known types are {known}",
+            self.balancer_type
+        )))
+    }
+
+    /// Check that the desired location exists, so an unknown location
+    /// is reported along with the known ones instead of an opaque error
+    /// of creating the balancer.
+    async fn check_location(&self) -> RobotLBResult<()> {
+        let response = traced(
+            "list_locations",
+            None,
+            self.hcloud.list_locations(ListLocationsParams::default()),
+        )
+        .await?;
+        let locations = response.locations;
+        if locations
+            .iter()
+            .any(|location| location.name == self.location)
+        {
+            return Ok(());
+        }
+        let known = locations
+            .iter()
+            .map(|location| format!("{} ({})", location.name, location.city))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(RobotLBError::UnknownLocation(format!(
+            "{}, known locations are {known}",
+            self.location
+        )))
     }
 
     /// Cleanup the load balancer.
     /// This method will remove all the services and targets from the
-    /// load balancer.
-    pub async fn cleanup(&self) -> RobotLBResult<()> {
+    /// load balancer. Returns whether the load balancer existed and was deleted.
+    #[tracing::instrument(skip(self), fields(lb_name=self.name))]
+    pub async fn cleanup(&self) -> RobotLBResult<bool> {
+        self.cleanup_balancer()
+            .await
+            .map_err(|err| self.attribute(err))
+    }
+
+    async fn cleanup_balancer(&self) -> RobotLBResult<bool> {
         let Some(hcloud_balancer) = self.get_hcloud_lb().await? else {
-            return Ok(());
+            return Ok(false);
         };
-        for service in &hcloud_balancer.services {
-            tracing::info!(
-                "Deleting service that listens for port {} from load-balancer {}",
-                service.listen_port,
-                hcloud_balancer.name,
-            );
-            hcloud::apis::load_balancers_api::delete_service(
-                &self.hcloud_config,
-                DeleteServiceParams {
-                    id: hcloud_balancer.id,
-                    delete_service_request: Some(DeleteServiceRequest {
-                        listen_port: service.listen_port,
-                    }),
-                },
-            )
-            .await?;
-        }
-        for target in &hcloud_balancer.targets {
-            if let Some(target_ip) = target.ip.clone() {
-                tracing::info!("Removing target {}", target_ip.ip);
-                hcloud::apis::load_balancers_api::remove_target(
-                    &self.hcloud_config,
-                    RemoveTargetParams {
-                        id: hcloud_balancer.id,
-                        remove_target_request: Some(RemoveTargetRequest {
-                            ip: Some(target_ip),
-                            ..Default::default()
-                        }),
-                    },
-                )
-                .await?;
-            }
+        if let Err(err) = self.check_owner(&hcloud_balancer) {
+            tracing::info!("Load balancer is left as is: {}", err);
+            return Ok(false);
+        }
+        for change in cleanup_changes(&hcloud_balancer) {
+            self.apply(&hcloud_balancer, &change).await?;
         }
-        hcloud::apis::load_balancers_api::delete_load_balancer(
-            &self.hcloud_config,
-            DeleteLoadBalancerParams {
+        tracing::info!(
+            hcloud_action = "delete_load_balancer",
+            "Deleting load balancer"
+        );
+        self.mutate(
+            "delete_load_balancer",
+            Some(hcloud_balancer.id),
+            format!("name={}", hcloud_balancer.name),
+            self.hcloud.delete_load_balancer(DeleteLoadBalancerParams {
                 id: hcloud_balancer.id,
-            },
+            }),
         )
         .await?;
-        Ok(())
+        Ok(true)
     }
 
     /// Get the load balancer from Hetzner Cloud.
-    /// This method will try to find the load balancer with the name
-    /// specified in the `LoadBalancer` struct.
+    /// This method will try to find the load balancer by its recorded ID,
+    /// or by the name specified in the `LoadBalancer` struct.
     ///
     /// The method might return an error if the load balancer is not found
     /// or if there are multiple load balancers with the same name.
-    async fn get_hcloud_lb(&self) -> RobotLBResult<Option<hcloud::models::LoadBalancer>> {
-        let hcloud_balancers = hcloud::apis::load_balancers_api::list_load_balancers(
-            &self.hcloud_config,
-            ListLoadBalancersParams {
-                name: Some(self.name.to_string()),
+    pub async fn get_hcloud_lb(&self) -> RobotLBResult<Option<hcloud::models::LoadBalancer>> {
+        let hcloud_lb = match self.get_recorded_lb().await? {
+            Some(hcloud_lb) => Some(hcloud_lb),
+            None => self.find_hcloud_lb().await?,
+        };
+        let Some(requested_ip) = &self.requested_ip else {
+            return Ok(hcloud_lb);
+        };
+        if let Some(hcloud_lb) = hcloud_lb {
+            if !has_public_ip(&hcloud_lb, requested_ip) {
+                return Err(RobotLBError::UnavailableLoadBalancerIP(format!(
+                    "{requested_ip}, load balancer {} has other IPs and Hetzner can't assign a chosen one",
+                    hcloud_lb.name
+                )));
+            }
+            return Ok(Some(hcloud_lb));
+        }
+        // The balancer that already has the IP is adopted,
+        // unless it belongs to another service.
+        let hcloud_lb = list_load_balancers(self.hcloud.as_ref(), None)
+            .await?
+            .into_iter()
+            .find(|hcloud_lb| has_public_ip(hcloud_lb, requested_ip))
+            .ok_or_else(|| {
+                RobotLBError::UnavailableLoadBalancerIP(format!(
+                    "{requested_ip}, no load balancer has it and Hetzner can't assign a chosen one"
+                ))
+            })?;
+        self.check_owner(&hcloud_lb)?;
+        tracing::debug!(
+            "Load balancer {} has the requested IP {}",
+            hcloud_lb.name,
+            requested_ip
+        );
+        Ok(Some(hcloud_lb))
+    }
+
+    /// Get the load balancer by the recorded ID, which is cheaper than a search by name.
+    /// The ID is stale if the balancer was deleted or has another name,
+    /// e.g. the name annotation was changed, and `None` is returned.
+    async fn get_recorded_lb(&self) -> RobotLBResult<Option<hcloud::models::LoadBalancer>> {
+        let Some(id) = self.id else {
+            return Ok(None);
+        };
+        let result = traced(
+            "get_load_balancer",
+            Some(id),
+            self.hcloud.get_load_balancer(GetLoadBalancerParams { id }),
+        )
+        .await;
+        match result {
+            Ok(response) if response.load_balancer.name == self.name => {
+                Ok(Some(*response.load_balancer))
+            }
+            Ok(response) => {
+                tracing::info!(
+                    "Recorded load balancer {} is named {}, searching by name",
+                    id,
+                    response.load_balancer.name
+                );
+                Ok(None)
+            }
+            Err(err) if err.hcloud_kind() == Some(HCloudErrorKind::NotFound) => {
+                tracing::warn!(
+                    "Recorded load balancer {} doesn't exist anymore, searching by name",
+                    id
+                );
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Search the load balancer by name.
+    async fn find_hcloud_lb(&self) -> RobotLBResult<Option<hcloud::models::LoadBalancer>> {
+        let hcloud_balancers = traced(
+            "list_load_balancers",
+            None,
+            self.hcloud.list_load_balancers(ListLoadBalancersParams {
+                name: Some(self.name.clone()),
                 ..Default::default()
-            },
+            }),
         )
         .await?;
         if hcloud_balancers.load_balancers.len() > 1 {
@@ -550,45 +1474,47 @@ impl LoadBalancer {
         Ok(hcloud_balancers.load_balancers.into_iter().next())
     }
 
-    /// Get or create the load balancer in Hetzner Cloud.
-    ///
-    /// this method will try to find the load balancer with the name
-    /// specified in the `LoadBalancer` struct. If the load balancer
-    /// is not found, the method will create a new load balancer
+    /// Create the load balancer in Hetzner Cloud
     /// with the specified configuration in service's annotations.
-    async fn get_or_create_hcloud_lb(&self) -> RobotLBResult<hcloud::models::LoadBalancer> {
-        let hcloud_lb = self.get_hcloud_lb().await?;
-        if let Some(balancer) = hcloud_lb {
-            return Ok(balancer);
-        }
-
-        let response = hcloud::apis::load_balancers_api::create_load_balancer(
-            &self.hcloud_config,
-            hcloud::apis::load_balancers_api::CreateLoadBalancerParams {
-                create_load_balancer_request: Some(hcloud::models::CreateLoadBalancerRequest {
-                    algorithm: Some(Box::new(self.algorithm.clone())),
-                    labels: None,
-                    load_balancer_type: self.balancer_type.clone(),
-                    location: Some(self.location.clone()),
-                    name: self.name.clone(),
-                    network: None,
-                    network_zone: None,
-                    public_interface: Some(true),
-                    services: Some(vec![]),
-                    targets: Some(vec![]),
-                }),
-            },
-        )
-        .await;
-        if let Err(e) = response {
-            tracing::error!("Failed to create load balancer: {:?}", e);
-            return Err(RobotLBError::HCloudError(format!(
-                "Failed to create load balancer: {:?}",
-                e
-            )));
-        }
-
-        Ok(*response.unwrap().load_balancer)
+    async fn create_hcloud_lb(&mut self) -> RobotLBResult<hcloud::models::LoadBalancer> {
+        tracing::info!(
+            hcloud_action = "create_load_balancer",
+            "Creating load balancer"
+        );
+        let response = self
+            .mutate(
+                "create_load_balancer",
+                None,
+                format!(
+                    "name={} type={} location={}",
+                    self.name, self.balancer_type, self.location
+                ),
+                self.hcloud.create_load_balancer(
+                    hcloud::apis::load_balancers_api::CreateLoadBalancerParams {
+                        create_load_balancer_request: Some(
+                            hcloud::models::CreateLoadBalancerRequest {
+                                algorithm: Some(Box::new(self.algorithm.clone())),
+                                labels: Some(self.labels.clone()),
+                                load_balancer_type: self.balancer_type.clone(),
+                                location: Some(self.location.clone()),
+                                name: self.name.clone(),
+                                network: None,
+                                network_zone: None,
+                                public_interface: Some(true),
+                                services: Some(vec![]),
+                                targets: Some(vec![]),
+                            },
+                        ),
+                    },
+                ),
+            )
+            .await;
+        let response = response.inspect_err(|err| {
+            tracing::error!("Failed to create load balancer: {:?}", err);
+        })?;
+        self.pending_actions
+            .extend(running_action(&response.action));
+        Ok(*response.load_balancer)
     }
 
     /// Get the network from Hetzner Cloud.
@@ -600,37 +1526,215 @@ impl LoadBalancer {
         let Some(network_name) = self.network_name.clone() else {
             return Ok(None);
         };
-        let response = hcloud::apis::networks_api::list_networks(
-            &self.hcloud_config,
-            ListNetworksParams {
+        let response = traced(
+            "list_networks",
+            None,
+            self.hcloud.list_networks(ListNetworksParams {
                 name: Some(network_name.clone()),
                 ..Default::default()
-            },
+            }),
         )
         .await?;
 
         if response.networks.len() > 1 {
-            tracing::warn!(
+            tracing::debug!(
                 "Found more than one network with name {}, skipping",
                 network_name
             );
-            return Err(RobotLBError::HCloudError(format!(
-                "Found more than one network with name {}",
-                network_name,
-            )));
+            return Err(RobotLBError::HCloudError {
+                kind: HCloudErrorKind::InvalidInput,
+                message: format!("Found more than one network with name {network_name}"),
+            });
         }
         if response.networks.is_empty() {
-            tracing::warn!("Network with name {} not found", network_name);
-            return Err(RobotLBError::HCloudError(format!(
-                "Network with name {} not found",
-                network_name,
-            )));
+            tracing::debug!("Network with name {} not found", network_name);
+            return Err(RobotLBError::HCloudError {
+                kind: HCloudErrorKind::NotFound,
+                message: format!("Network with name {network_name} not found"),
+            });
         }
 
         Ok(response.networks.into_iter().next())
     }
 }
 
+/// Current name of the annotation, if the name is a legacy one.
+#[must_use]
+pub fn renamed_annotation(name: &str) -> Option<&'static str> {
+    consts::ANNOTATION_ALIASES
+        .iter()
+        .find(|(legacy, _)| *legacy == name)
+        .map(|(_, current)| *current)
+}
+
+/// Replace legacy names of renamed annotations with the current ones.
+/// If both names are set, the value of the current one is kept.
+///
+/// Returns pairs of the legacy and the current name of the replaced
+/// annotations, so callers can report them as deprecated.
+pub fn resolve_aliases(
+    annotations: &mut BTreeMap<String, String>,
+) -> Vec<(&'static str, &'static str)> {
+    let mut replaced = vec![];
+    for &(legacy, current) in consts::ANNOTATION_ALIASES {
+        if let Some(value) = annotations.remove(legacy) {
+            annotations.entry(current.to_string()).or_insert(value);
+            replaced.push((legacy, current));
+        }
+    }
+    replaced
+}
+
+/// Check that the target is an address the balancer can reach: an address
+/// inside the range of the network the balancer is attached to,
+/// or any unicast address if there's no network.
+///
+/// Targets of services are already picked by the IP family,
+/// while targets of `HetznerLoadBalancer` resources are taken as they are,
+/// so IPv6 targets are rejected here as outside of the IPv4 network range.
+fn check_target(ip: &str, network_range: Option<&str>) -> Result<(), String> {
+    let ip = IpAddr::from_str(ip).map_err(|_| "not an IP address".to_string())?;
+    if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+        return Err("not a unicast address of a node".to_string());
+    }
+    let Some(range) = network_range else {
+        return Ok(());
+    };
+    let outside = || format!("outside of the network range {range}");
+    let IpAddr::V4(ip) = ip else {
+        return Err(outside());
+    };
+    let Some((network, prefix)) = range
+        .split_once('/')
+        .and_then(|(network, prefix)| {
+            Some((
+                Ipv4Addr::from_str(network).ok()?,
+                prefix.parse::<u32>().ok()?,
+            ))
+        })
+        .filter(|(_, prefix)| *prefix <= 32)
+    else {
+        // Ranges are validated by `HCloud`, so it shouldn't happen.
+        return Ok(());
+    };
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    if u32::from(ip) & mask != u32::from(network) & mask {
+        return Err(outside());
+    }
+    Ok(())
+}
+
+/// ID of the action, if it hasn't finished yet.
+fn running_action(action: &hcloud::models::Action) -> Option<i64> {
+    (action.status == action::Status::Running).then_some(action.id)
+}
+
+/// Labels of the service starting with one of the prefixes.
+/// They are copied to the load balancer along with the owner labels.
+fn propagated_labels(
+    labels: &BTreeMap<String, String>,
+    prefixes: &[String],
+) -> HashMap<String, String> {
+    labels
+        .iter()
+        .filter(|(key, _)| prefixes.iter().any(|prefix| key.starts_with(prefix)))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Labels naming the owner of a load balancer, with the kinds of owners.
+const OWNER_LABELS: [(&str, &str); 3] = [
+    (consts::LB_SERVICE_LABEL_NAME, "service"),
+    (consts::LB_INGRESS_LABEL_NAME, "ingress"),
+    (consts::LB_RESOURCE_LABEL_NAME, "HetznerLoadBalancer"),
+];
+
+/// Labels identifying the cluster and the service the load balancer belongs to.
+fn owner_labels(cluster_name: &str, namespace: &str, service: &str) -> HashMap<String, String> {
+    HashMap::from([
+        (
+            consts::LB_CLUSTER_LABEL_NAME.to_string(),
+            cluster_name.to_string(),
+        ),
+        (
+            consts::LB_NAMESPACE_LABEL_NAME.to_string(),
+            namespace.to_string(),
+        ),
+        (
+            consts::LB_SERVICE_LABEL_NAME.to_string(),
+            service.to_string(),
+        ),
+    ])
+}
+
+/// Services and targets to remove before the load balancer is deleted.
+fn cleanup_changes(hcloud_balancer: &hcloud::models::LoadBalancer) -> Vec<LBChange> {
+    let services = hcloud_balancer
+        .services
+        .iter()
+        .map(|service| LBChange::DeleteService {
+            listen_port: service.listen_port,
+        });
+    let targets = hcloud_balancer
+        .targets
+        .iter()
+        .filter_map(|target| target.ip.as_ref())
+        .map(|ip| LBChange::RemoveTarget { ip: ip.ip.clone() });
+    services.chain(targets).collect()
+}
+
+/// List load balancers in `HCloud` created by the operator of the cluster.
+pub async fn list_managed(
+    hcloud: &dyn HcloudApi,
+    cluster_name: &str,
+) -> RobotLBResult<Vec<hcloud::models::LoadBalancer>> {
+    list_load_balancers(
+        hcloud,
+        Some(format!("{}={cluster_name}", consts::LB_CLUSTER_LABEL_NAME)),
+    )
+    .await
+}
+
+/// Whether the IPv4 or IPv6 address of the load balancer is the `ip`.
+fn has_public_ip(hcloud_lb: &hcloud::models::LoadBalancer, ip: &str) -> bool {
+    let public_net = &hcloud_lb.public_net;
+    [&public_net.ipv4.ip, &public_net.ipv6.ip]
+        .into_iter()
+        .any(|public_ip| public_ip.as_ref().and_then(Option::as_deref) == Some(ip))
+}
+
+/// List load balancers created by hcloud-cloud-controller-manager.
+pub async fn list_ccm(hcloud: &dyn HcloudApi) -> RobotLBResult<Vec<hcloud::models::LoadBalancer>> {
+    list_load_balancers(hcloud, Some(consts::CCM_SERVICE_UID_LABEL_NAME.to_string())).await
+}
+
+/// List all load balancers in `HCloud` matching the label selector.
+async fn list_load_balancers(
+    hcloud: &dyn HcloudApi,
+    label_selector: Option<String>,
+) -> RobotLBResult<Vec<hcloud::models::LoadBalancer>> {
+    let mut load_balancers = vec![];
+    let mut page = 1;
+    loop {
+        let response = traced(
+            "list_load_balancers",
+            None,
+            hcloud.list_load_balancers(ListLoadBalancersParams {
+                label_selector: label_selector.clone(),
+                page: Some(page),
+                per_page: Some(50),
+                ..Default::default()
+            }),
+        )
+        .await?;
+        load_balancers.extend(response.load_balancers);
+        match response.meta.pagination.next_page {
+            Some(next_page) => page = next_page,
+            None => return Ok(load_balancers),
+        }
+    }
+}
+
 impl FromStr for LBAlgorithm {
     type Err = RobotLBError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -653,3 +1757,465 @@ impl From<LBAlgorithm> for LoadBalancerAlgorithm {
         Self { r#type }
     }
 }
+
+impl LBChange {
+    /// Name of the change in the `hcloud_action` field of logs.
+    #[must_use]
+    pub const fn action(&self) -> &'static str {
+        match self {
+            Self::Create => "create_load_balancer",
+            Self::ChangeAlgorithm { .. } => "change_algorithm",
+            Self::ChangeType { .. } => "change_type",
+            Self::AttachNetwork { .. } => "attach_to_network",
+            Self::DetachNetwork { .. } => "detach_from_network",
+            Self::AddService { .. } => "add_service",
+            Self::UpdateService { .. } => "update_service",
+            Self::DeleteService { .. } => "delete_service",
+            Self::AddTarget { .. } => "add_target",
+            Self::RemoveTarget { .. } => "remove_target",
+            Self::UpdateLabels { .. } => "update_labels",
+        }
+    }
+}
+
+impl Display for LBChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Create => write!(f, "create load balancer"),
+            Self::ChangeAlgorithm { from, to } => write!(
+                f,
+                "change algorithm {} -> {}",
+                algorithm_name(*from),
+                algorithm_name(*to)
+            ),
+            Self::ChangeType { from, to } => write!(f, "change type {from} -> {to}"),
+            Self::AttachNetwork { network, ip } => write!(
+                f,
+                "attach network {network} (ip {})",
+                ip.as_deref().unwrap_or("auto")
+            ),
+            Self::DetachNetwork { network } => write!(f, "detach network {network}"),
+            Self::AddService {
+                listen_port,
+                destination_port,
+            } => write!(f, "add service {listen_port} -> {destination_port}"),
+            Self::UpdateService {
+                listen_port,
+                destination_port,
+            } => write!(f, "update service {listen_port} -> {destination_port}"),
+            Self::DeleteService { listen_port } => write!(f, "delete service {listen_port}"),
+            Self::AddTarget { ip } => write!(f, "add target {ip}"),
+            Self::RemoveTarget { ip } => write!(f, "remove target {ip}"),
+            Self::UpdateLabels { labels, removed } => {
+                let mut parts = vec![];
+                if !labels.is_empty() {
+                    let labels = labels
+                        .iter()
+                        .map(|(key, value)| format!("{key}={value}"))
+                        .collect::<Vec<_>>();
+                    parts.push(format!("set labels {}", labels.join(",")));
+                }
+                if !removed.is_empty() {
+                    let removed = removed.iter().cloned().collect::<Vec<_>>();
+                    parts.push(format!("remove labels {}", removed.join(",")));
+                }
+                write!(f, "{}", parts.join(", "))
+            }
+        }
+    }
+}
+
+/// Name of the algorithm as it's written in the annotation.
+#[must_use]
+pub const fn algorithm_name(algorithm: load_balancer_algorithm::Type) -> &'static str {
+    match algorithm {
+        load_balancer_algorithm::Type::RoundRobin => "round-robin",
+        load_balancer_algorithm::Type::LeastConnections => "least-connections",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, BTreeSet, HashMap},
+        sync::Arc,
+    };
+
+    use hcloud::{
+        apis::configuration::Configuration,
+        models::{load_balancer_algorithm::Type, LoadBalancerAlgorithm},
+    };
+    use k8s_openapi::serde_json::{self, json};
+
+    use super::{
+        check_target, cleanup_changes, owner_labels, parse_labels, propagated_labels, LBChange,
+        LoadBalancer,
+    };
+    use crate::hcloud_api::HcloudClient;
+
+    fn desired() -> LoadBalancer {
+        LoadBalancer {
+            name: "web".to_string(),
+            namespace: "default".to_string(),
+            service: "web".to_string(),
+            services: HashMap::from([(80, 30080)]),
+            targets: vec!["10.0.1.1".to_string()],
+            private_ip: None,
+            check_interval: 15,
+            timeout: 10,
+            retries: 3,
+            proxy_mode: false,
+            location: "hel1".to_string(),
+            balancer_type: "lb11".to_string(),
+            max_balancer_type: None,
+            allowed_types: None,
+            algorithm: LoadBalancerAlgorithm {
+                r#type: Type::RoundRobin,
+            },
+            network_name: None,
+            labels: owner_labels("test", "default", "web"),
+            custom_labels: BTreeSet::new(),
+            applied_labels: BTreeSet::new(),
+            id: None,
+            pending_actions: vec![],
+            resync_interval: None,
+            requested_ip: None,
+            hcloud: Arc::new(HcloudClient::new(Configuration::new())),
+        }
+    }
+
+    /// Balancer in `HCloud` matching [`desired`], with `changes` applied.
+    fn current(changes: &serde_json::Value) -> hcloud::models::LoadBalancer {
+        let service = |listen_port: i32, destination_port: i32| {
+            json!({
+                "listen_port": listen_port,
+                "destination_port": destination_port,
+                "protocol": "tcp",
+                "proxyprotocol": false,
+                "health_check": {
+                    "protocol": "tcp",
+                    "port": destination_port,
+                    "interval": 15,
+                    "timeout": 10,
+                    "retries": 3,
+                },
+            })
+        };
+        let mut hcloud_lb = json!({
+            "id": 1,
+            "name": "web",
+            "created": "2024-01-01T00:00:00+00:00",
+            "algorithm": {"type": "round_robin"},
+            "included_traffic": 0,
+            "ingoing_traffic": null,
+            "outgoing_traffic": null,
+            "labels": owner_labels("test", "default", "web"),
+            "load_balancer_type": {
+                "id": 1,
+                "name": "lb11",
+                "description": "LB11",
+                "deprecated": null,
+                "max_assigned_certificates": 10,
+                "max_connections": 10000,
+                "max_services": 5,
+                "max_targets": 25,
+                "prices": [],
+            },
+            "location": {
+                "id": 1,
+                "name": "hel1",
+                "city": "Helsinki",
+                "country": "FI",
+                "description": "Helsinki",
+                "latitude": 0.0,
+                "longitude": 0.0,
+                "network_zone": "eu-central",
+            },
+            "private_net": [],
+            "protection": {"delete": false},
+            "public_net": {"enabled": true, "ipv4": {}, "ipv6": {}},
+            "services": [service(80, 30080)],
+            "targets": [{"type": "ip", "ip": {"ip": "10.0.1.1"}}],
+        });
+        for (key, value) in changes.as_object().unwrap() {
+            hcloud_lb[key] = if key == "services" {
+                serde_json::from_value::<Vec<(i32, i32)>>(value.clone())
+                    .unwrap()
+                    .into_iter()
+                    .map(|(listen_port, destination_port)| service(listen_port, destination_port))
+                    .collect()
+            } else {
+                value.clone()
+            };
+        }
+        serde_json::from_value(hcloud_lb).unwrap()
+    }
+
+    #[test]
+    fn missing_balancer_is_created_and_configured() {
+        let mut lb = desired();
+        lb.private_ip = Some("10.0.0.5".to_string());
+        lb.services.insert(443, 30443);
+
+        assert_eq!(
+            lb.plan(None, Some(7)),
+            [
+                LBChange::Create,
+                LBChange::AttachNetwork {
+                    network: 7,
+                    ip: Some("10.0.0.5".to_string()),
+                },
+                LBChange::AddService {
+                    listen_port: 80,
+                    destination_port: 30080,
+                },
+                LBChange::AddService {
+                    listen_port: 443,
+                    destination_port: 30443,
+                },
+                LBChange::AddTarget {
+                    ip: "10.0.1.1".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_balancer_needs_no_changes() {
+        assert_eq!(desired().plan(Some(&current(&json!({}))), None), []);
+    }
+
+    #[test]
+    fn balancers_of_other_owners_conflict() {
+        let lb = desired();
+        let owned_by = |label: &str, namespace: &str| {
+            current(&json!({"labels": {
+                "robotlb/cluster": "test",
+                "robotlb/namespace": namespace,
+                label: "web",
+            }}))
+        };
+
+        assert!(lb
+            .check_owner(&owned_by("robotlb/service", "default"))
+            .is_ok());
+        assert!(lb
+            .check_owner(&current(&json!({"labels": {"team": "a"}})))
+            .is_ok());
+        for (label, namespace) in [
+            ("robotlb/service", "other"),
+            ("robotlb/ingress", "default"),
+            ("robotlb/hetzner-load-balancer", "default"),
+        ] {
+            assert!(
+                lb.check_owner(&owned_by(label, namespace)).is_err(),
+                "{label} in {namespace}"
+            );
+        }
+    }
+
+    #[test]
+    fn foreign_labels_are_kept() {
+        let mut labels = owner_labels("test", "default", "web");
+        labels.insert("team".to_string(), "a".to_string());
+        let hcloud_lb = current(&json!({ "labels": labels }));
+
+        assert_eq!(desired().plan(Some(&hcloud_lb), None), []);
+    }
+
+    #[test]
+    fn labels_with_prefixes_are_propagated() {
+        let labels = BTreeMap::from([
+            ("team".to_string(), "payments".to_string()),
+            ("example.com/cost-center".to_string(), "42".to_string()),
+            ("app".to_string(), "web".to_string()),
+        ]);
+        let prefixes = ["team".to_string(), "example.com/".to_string()];
+
+        let mut propagated = propagated_labels(&labels, &prefixes)
+            .into_keys()
+            .collect::<Vec<_>>();
+        propagated.sort();
+
+        assert_eq!(propagated, ["example.com/cost-center", "team"]);
+        assert!(propagated_labels(&labels, &[]).is_empty());
+    }
+
+    #[test]
+    fn custom_labels_are_parsed() {
+        assert_eq!(
+            parse_labels("team=payments, env=prod,").unwrap(),
+            HashMap::from([
+                ("team".to_string(), "payments".to_string()),
+                ("env".to_string(), "prod".to_string()),
+            ])
+        );
+        assert!(parse_labels("team").is_err());
+        assert!(parse_labels("=prod").is_err());
+    }
+
+    #[test]
+    fn targets_are_checked_against_the_network() {
+        assert!(check_target("203.0.113.5", None).is_ok());
+        assert!(check_target("2001:db8::5", None).is_ok());
+        assert!(check_target("10.0.1.5", Some("10.0.0.0/16")).is_ok());
+
+        for (ip, range) in [
+            ("node-1", None),
+            ("127.0.0.1", None),
+            ("::", None),
+            ("10.1.0.5", Some("10.0.0.0/16")),
+            ("2001:db8::5", Some("10.0.0.0/16")),
+        ] {
+            assert!(check_target(ip, range).is_err(), "{ip} in {range:?}");
+        }
+    }
+
+    #[test]
+    fn changed_labels_algorithm_and_type_are_planned() {
+        let hcloud_lb = current(&json!({
+            "labels": {"robotlb/cluster": "test"},
+            "algorithm": {"type": "least_connections"},
+        }));
+        let mut lb = desired();
+        lb.balancer_type = "lb21".to_string();
+
+        let plan = lb.plan(Some(&hcloud_lb), None);
+
+        assert_eq!(
+            plan[1..],
+            [
+                LBChange::ChangeAlgorithm {
+                    from: Type::LeastConnections,
+                    to: Type::RoundRobin,
+                },
+                LBChange::ChangeType {
+                    from: "lb11".to_string(),
+                    to: "lb21".to_string(),
+                },
+            ]
+        );
+        let LBChange::UpdateLabels { labels, .. } = &plan[0] else {
+            panic!("labels aren't updated first: {plan:?}");
+        };
+        assert_eq!(
+            labels.keys().collect::<Vec<_>>(),
+            ["robotlb/namespace", "robotlb/service"]
+        );
+    }
+
+    #[test]
+    fn services_are_updated_deleted_and_added() {
+        let hcloud_lb = current(&json!({"services": [[80, 30080], [8080, 31080]]}));
+        let mut lb = desired();
+        lb.services = HashMap::from([(80, 30081), (443, 30443)]);
+
+        assert_eq!(
+            lb.plan(Some(&hcloud_lb), None),
+            [
+                LBChange::UpdateService {
+                    listen_port: 80,
+                    destination_port: 30081,
+                },
+                LBChange::DeleteService { listen_port: 8080 },
+                LBChange::AddService {
+                    listen_port: 443,
+                    destination_port: 30443,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_health_check_updates_service() {
+        let mut lb = desired();
+        lb.retries = 5;
+        lb.proxy_mode = true;
+
+        assert_eq!(
+            lb.plan(Some(&current(&json!({}))), None),
+            [LBChange::UpdateService {
+                listen_port: 80,
+                destination_port: 30080,
+            }]
+        );
+    }
+
+    #[test]
+    fn targets_are_removed_before_added() {
+        let mut lb = desired();
+        lb.targets = vec!["10.0.1.2".to_string()];
+
+        assert_eq!(
+            lb.plan(Some(&current(&json!({}))), None),
+            [
+                LBChange::RemoveTarget {
+                    ip: "10.0.1.1".to_string(),
+                },
+                LBChange::AddTarget {
+                    ip: "10.0.1.2".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn balancer_is_moved_between_networks() {
+        let hcloud_lb = current(&json!({
+            "private_net": [{"network": 3, "ip": "10.0.0.2"}],
+        }));
+        let lb = desired();
+
+        assert_eq!(lb.plan(Some(&hcloud_lb), Some(3)), []);
+        assert_eq!(
+            lb.plan(Some(&hcloud_lb), Some(4)),
+            [
+                LBChange::DetachNetwork { network: 3 },
+                LBChange::AttachNetwork {
+                    network: 4,
+                    ip: None,
+                },
+            ]
+        );
+        assert_eq!(
+            lb.plan(Some(&hcloud_lb), None),
+            [LBChange::DetachNetwork { network: 3 }]
+        );
+    }
+
+    #[test]
+    fn changed_private_ip_reattaches_network() {
+        let hcloud_lb = current(&json!({
+            "private_net": [{"network": 3, "ip": "10.0.0.2"}],
+        }));
+        let mut lb = desired();
+        lb.private_ip = Some("10.0.0.9".to_string());
+
+        assert_eq!(
+            lb.plan(Some(&hcloud_lb), Some(3)),
+            [
+                LBChange::DetachNetwork { network: 3 },
+                LBChange::AttachNetwork {
+                    network: 3,
+                    ip: Some("10.0.0.9".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cleanup_removes_services_and_targets() {
+        let hcloud_lb = current(&json!({"services": [[80, 30080], [443, 30443]]}));
+
+        assert_eq!(
+            cleanup_changes(&hcloud_lb),
+            [
+                LBChange::DeleteService { listen_port: 80 },
+                LBChange::DeleteService { listen_port: 443 },
+                LBChange::RemoveTarget {
+                    ip: "10.0.1.1".to_string(),
+                },
+            ]
+        );
+    }
+}
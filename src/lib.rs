@@ -0,0 +1,1639 @@
+//! Operator that manages Hetzner Cloud load balancers for `LoadBalancer` services.
+//!
+//! The binary is a thin wrapper around [`run`]. Other tools can reuse
+//! the reconcile of services, e.g. [`reconcile_service`], and the load balancer
+//! diffing of [`lb::LoadBalancer`] programmatically.
+#![warn(
+    // Base lints.
+    clippy::all,
+    // Some pedantic lints.
+    clippy::pedantic,
+    // New lints which are cool.
+    clippy::nursery,
+)]
+#![
+    allow(
+        // I don't care about this.
+        clippy::module_name_repetitions,
+        // Yo, the hell you should put
+        // it in docs, if signature is clear as sky.
+        clippy::missing_errors_doc,
+        // `Duration::from_mins` is not available in
+        // the rust version used to build the image.
+        clippy::duration_suboptimal_units,
+    )
+]
+
+use backoff::ErrorBackoff;
+use clap::ArgMatches;
+use conditions::Condition;
+use config::{Cli, Command, OperatorConfig, OperatorMode};
+use crds::lb_policy::PolicyStore;
+use credentials::{Credentials, SecretBackend};
+use defaults::AnnotationDefaults;
+use error::{ErrorClass, HCloudErrorKind, RobotLBError, RobotLBResult};
+use futures::StreamExt;
+use hcloud::apis::configuration::Configuration as HCloudConfig;
+use hcloud_api::{HcloudApi, HcloudClient};
+use health::Health;
+use k8s_openapi::{
+    api::core::v1::{Node, Pod, Service},
+    serde_json::json,
+};
+use kube::{
+    api::{ListParams, PatchParams},
+    runtime::{
+        controller::{Action, Config as ControllerConfig},
+        events::EventType,
+        watcher, Controller,
+    },
+    Resource, ResourceExt,
+};
+use label_filter::LabelFilter;
+use lb::{LoadBalancer, Reconciled};
+use logging::LogSampler;
+use metrics::Metrics;
+use notify::{LBEvent, Notifier};
+use quota::TrafficQuotaMonitor;
+use rustls::ServerConfig;
+use state::StateStore;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+use tracing::Instrument;
+
+pub mod admission;
+pub mod audit;
+pub mod backoff;
+pub mod ccm;
+pub mod clusters;
+pub mod collector;
+pub mod commands;
+pub mod conditions;
+pub mod config;
+pub mod consts;
+pub mod crds;
+pub mod credentials;
+pub mod defaults;
+pub mod duration;
+pub mod error;
+pub mod events;
+pub mod faults;
+pub mod finalizers;
+pub mod firewall;
+pub mod hcloud_api;
+pub mod hcloud_span;
+pub mod health;
+pub mod ingress;
+pub mod inventory;
+pub mod label_filter;
+pub mod lb;
+pub mod logging;
+pub mod metrics;
+pub mod node_labels;
+pub mod notify;
+pub mod preflight;
+pub mod provider_annotations;
+pub mod quota;
+pub mod reporting;
+pub mod server;
+pub mod startup;
+pub mod state;
+pub mod vault;
+
+/// Run the command of the operator.
+/// Returns whether it has succeeded, e.g. all services were reconciled.
+pub async fn run(cli: Cli, matches: &ArgMatches) -> RobotLBResult<bool> {
+    let operator_config = cli.config.clone();
+    logging::init(&operator_config)?;
+    if operator_config.print_config {
+        tracing::info!("Resolved configuration:");
+        for option in OperatorConfig::describe(matches) {
+            tracing::info!("  {}", option);
+        }
+    }
+    let _sentry = reporting::init(&operator_config);
+    // Misconfigured metrics TLS fails the startup, instead of the server only.
+    let metrics_tls = if matches!(cli.resolved_command(), Command::Run) && !operator_config.once {
+        operator_config.check_requeue_intervals()?;
+        operator_config.check_watchdog_window()?;
+        server::metrics_tls_config(&operator_config)?
+    } else {
+        None
+    };
+
+    let mut secret_backend = SecretBackend::from_config(&operator_config)?;
+    let hcloud_token = match &mut secret_backend {
+        Some(backend) => backend.token().await?,
+        None => operator_config.hcloud_token.clone().unwrap_or_default(),
+    };
+    let mut hcloud_conf = HCloudConfig::new();
+    hcloud_conf.bearer_access_token = Some(hcloud_token);
+    hcloud_conf
+        .base_path
+        .clone_from(&operator_config.hcloud_endpoint);
+
+    tracing::info!(
+        "Starting robotlb operator v{} ({})",
+        consts::VERSION,
+        consts::GIT_SHA
+    );
+    let (context, clusters) = connect(operator_config.clone(), hcloud_conf).await?;
+    let profiles = operator_config
+        .profiles_file
+        .as_deref()
+        .map(defaults::read_profiles)
+        .transpose()?;
+    for context in std::iter::once(&context).chain(&clusters) {
+        if let Some(profiles) = &profiles {
+            context.defaults.set_profiles(profiles.clone());
+        }
+        if let Err(err) = defaults::load(context).await {
+            tracing::warn!(
+                "Cannot read default annotations of services in cluster {}: {}",
+                context.config.cluster_name,
+                err
+            );
+        }
+    }
+    if matches!(cli.resolved_command(), Command::Run) && operator_config.preflight {
+        preflight::run(&context).await?;
+    }
+    let succeeded = match cli.resolved_command() {
+        Command::Run if operator_config.once => {
+            let mut failed = 0;
+            for context in std::iter::once(context).chain(clusters) {
+                failed += reconcile_all_once(context).await?;
+            }
+            failed == 0
+        }
+        Command::Run => {
+            run_controller(context, clusters, secret_backend, metrics_tls).await;
+            true
+        }
+        Command::AdmissionWebhook(args) => {
+            admission::run(&args, context).await?;
+            true
+        }
+        Command::Tool(command) => commands::run(command, context).await?,
+    };
+    logging::shutdown();
+    Ok(succeeded)
+}
+
+/// Connect to the cluster the operator runs in, or to all configured clusters.
+/// Returns the context of the first cluster and contexts of the rest of them.
+async fn connect(
+    config: OperatorConfig,
+    hcloud_config: HCloudConfig,
+) -> RobotLBResult<(Arc<CurrentContext>, Vec<Arc<CurrentContext>>)> {
+    let Some((first, rest)) = config.clusters.split_first() else {
+        let kube_client = clusters::kube_client(
+            config.kubeconfig.as_deref(),
+            config.kube_context.as_deref(),
+            &config,
+        )
+        .await?;
+        tracing::info!("Kube client is connected");
+        let context = CurrentContext::new(kube_client, config, hcloud_config, Metrics::new()?);
+        return Ok((Arc::new(context), vec![]));
+    };
+    let kube_client = first.client(&config).await?;
+    tracing::info!("Kube client of cluster {} is connected", first.name);
+    let mut first_config = config.clone();
+    first_config.cluster_name.clone_from(&first.name);
+    let context = CurrentContext::new(kube_client, first_config, hcloud_config, Metrics::new()?);
+    let mut clusters = vec![];
+    for cluster in rest {
+        let kube_client = cluster.client(&config).await?;
+        tracing::info!("Kube client of cluster {} is connected", cluster.name);
+        clusters.push(Arc::new(context.for_cluster(&cluster.name, kube_client)));
+    }
+    Ok((Arc::new(context), clusters))
+}
+
+/// Watch services of all clusters and reconcile them until the process is stopped.
+async fn run_controller(
+    context: Arc<CurrentContext>,
+    clusters: Vec<Arc<CurrentContext>>,
+    secret_backend: Option<SecretBackend>,
+    metrics_tls: Option<ServerConfig>,
+) {
+    if !context.config.inject_faults.is_empty() {
+        tracing::warn!(
+            "Failures are injected for testing: {:?}",
+            context.config.inject_faults
+        );
+    }
+    spawn_background_tasks(&context, metrics_tls);
+    if let Some(interval) = context.config.lb_metrics_interval {
+        tracing::info!("Starting load balancer traffic metrics collector");
+        // Every cluster scrapes only its own load balancers.
+        for context in std::iter::once(&context).chain(&clusters) {
+            tokio::spawn(collector::run(
+                context.clone(),
+                Duration::from_secs(interval),
+            ));
+        }
+    }
+    if let Some(backend) = secret_backend {
+        tokio::spawn(credentials::watch_token(context.clone(), backend));
+    }
+    tokio::spawn(health::watchdog(
+        context.clone(),
+        Duration::from_secs(context.config.watchdog_window),
+    ));
+    if let Some(name) = context
+        .config
+        .nodeport_firewall
+        .as_ref()
+        .filter(|_| context.config.mode != OperatorMode::Observe)
+    {
+        // Every cluster updates only the rules of its own load balancers.
+        for context in std::iter::once(&context).chain(&clusters) {
+            tokio::spawn(firewall::run(
+                context.clone(),
+                name.clone(),
+                Duration::from_secs(context.config.nodeport_firewall_interval),
+            ));
+        }
+    }
+    if context.config.node_lb_labels && context.config.mode == OperatorMode::Reconcile {
+        for context in std::iter::once(&context).chain(&clusters) {
+            tokio::spawn(node_labels::run(
+                context.clone(),
+                Duration::from_secs(context.config.node_lb_labels_interval),
+            ));
+        }
+    }
+    if clusters.is_empty() {
+        watch_services(context).await;
+        return;
+    }
+    futures::future::join_all(std::iter::once(context).chain(clusters).map(|context| {
+        let span = tracing::info_span!("cluster", name = context.config.cluster_name);
+        watch_services(context).instrument(span)
+    }))
+    .await;
+}
+
+/// Watch services of the cluster and reconcile them until the process is stopped.
+async fn watch_services(context: Arc<CurrentContext>) {
+    let (defaults_tx, defaults_rx) = futures::channel::mpsc::channel(1);
+    if context.config.enable_crds {
+        tokio::spawn(crds::robotlb_config::run(
+            context.clone(),
+            defaults_tx.clone(),
+        ));
+        let mut policy_updates = vec![defaults_tx.clone()];
+        // Standalone balancers aren't touched in observe mode.
+        if context.config.mode == OperatorMode::Reconcile {
+            let (resources_tx, resources_rx) = futures::channel::mpsc::channel(1);
+            policy_updates.push(resources_tx);
+            tokio::spawn(crds::hetzner_lb::run(context.clone(), resources_rx));
+        }
+        tokio::spawn(crds::lb_policy::watch(context.clone(), policy_updates));
+    }
+    tokio::spawn(defaults::watch(context.clone(), defaults_tx));
+    if let Some(controller_service) = context
+        .config
+        .ingress_controller_service
+        .clone()
+        .filter(|_| context.config.mode == OperatorMode::Reconcile)
+    {
+        tokio::spawn(ingress::run(context.clone(), controller_service));
+    }
+    if context.config.startup_resync {
+        if let Err(err) = startup::resync(&context).await {
+            tracing::warn!("Startup resync has failed: {}", err);
+        }
+    }
+    tracing::info!("Starting the controller");
+    let mut watcher_config = watcher::Config::default();
+    if let Some(timeout) = faults::watch_timeout(&context.config.inject_faults) {
+        watcher_config = watcher_config.timeout(timeout);
+    }
+    let controller = Controller::new(
+        kube::Api::<Service>::all(context.client.clone()),
+        watcher_config,
+    )
+    .with_config(ControllerConfig::default().concurrency(context.config.max_concurrent_reconciles))
+    .reconcile_all_on(defaults_rx);
+    tokio::spawn({
+        let store = controller.store();
+        let health = context.health.clone();
+        async move {
+            if store.wait_until_ready().await.is_ok() {
+                tracing::info!("Watch of services is established");
+                health.set_watch_ready();
+            }
+        }
+    });
+    let health = context.health.clone();
+    let metrics = context.metrics.clone();
+    let sampler = LogSampler::new(Duration::from_secs(context.config.log_sampling_window));
+    controller
+        .run(reconcile_service, on_error, context)
+        .for_each(|reconcilation_result| {
+            health.heartbeat();
+            match reconcilation_result {
+                Ok((service, _action)) => {
+                    tracing::info!("Reconcilation of a service {} was successful", service.name);
+                }
+                // During reconcilation process,
+                // the controller has decided to skip the service.
+                Err(kube::runtime::controller::Error::ReconcilerFailed(
+                    RobotLBError::SkipService,
+                    _,
+                )) => {}
+                // The same error is usually reported for many services on every
+                // requeue, so only a summary is logged from time to time.
+                Err(kube::runtime::controller::Error::ReconcilerFailed(err, obj)) => {
+                    // Errors are sampled without the service they relate to.
+                    match sampler.sample(&err.root().to_string()) {
+                        Some(0) => {
+                            tracing::error!("Error reconciling service {}: {}", obj.name, err);
+                        }
+                        Some(suppressed) => {
+                            tracing::error!(
+                                "Error reconciling service {}: {} ({} similar errors suppressed)",
+                                obj.name,
+                                err,
+                                suppressed
+                            );
+                        }
+                        None => {}
+                    }
+                }
+                // The service was deleted while its reconcile was queued.
+                Err(kube::runtime::controller::Error::ObjectNotFound(obj)) => {
+                    metrics.reconcile_dequeued(&obj.namespace.unwrap_or_default(), &obj.name);
+                }
+                Err(err) => {
+                    tracing::error!("Error reconciling service: {:#?}", err);
+                }
+            }
+            futures::future::ready(())
+        })
+        .await;
+}
+
+/// Reconcile every service a single time, one after another.
+/// Returns the number of services that failed to reconcile.
+async fn reconcile_all_once(context: Arc<CurrentContext>) -> RobotLBResult<usize> {
+    let services = kube::Api::<Service>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?;
+    tracing::info!("Reconciling {} services once", services.items.len());
+    let mut failed = 0;
+    for svc in services {
+        let svc = Arc::new(svc);
+        let name = format!("{}/{}", svc.namespace().unwrap_or_default(), svc.name_any());
+        match reconcile_service(svc.clone(), context.clone()).await {
+            Ok(_) => tracing::info!("Reconcilation of a service {} was successful", name),
+            Err(RobotLBError::SkipService) => {}
+            Err(err) => {
+                tracing::error!("Error reconciling service {}: {}", name, err);
+                reporting::capture(&svc, &err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        tracing::error!("{} services failed to reconcile", failed);
+    }
+    Ok(failed)
+}
+
+/// Spawn the tasks running alongside the controller:
+/// health checks and HTTP servers.
+fn spawn_background_tasks(context: &Arc<CurrentContext>, metrics_tls: Option<ServerConfig>) {
+    tokio::spawn(health::watch_hcloud(
+        context.clone(),
+        Duration::from_secs(context.config.hcloud_check_interval),
+    ));
+    tokio::spawn({
+        let health = context.health.clone();
+        let address = context.config.probes_bind_address;
+        async move {
+            if let Err(err) = health::run(address, health).await {
+                tracing::error!("Health probes server has failed: {}", err);
+            }
+        }
+    });
+    tokio::spawn({
+        let context = context.clone();
+        async move {
+            if let Err(err) = server::run(context, metrics_tls).await {
+                tracing::error!("Metrics server has failed: {}", err);
+            }
+        }
+    });
+}
+
+#[derive(Clone)]
+pub struct CurrentContext {
+    pub client: kube::Client,
+    pub config: OperatorConfig,
+    /// Shared by all clones, so the token can be rotated.
+    hcloud_config: Arc<RwLock<Arc<HCloudConfig>>>,
+    pub metrics: Metrics,
+    pub traffic_monitor: TrafficQuotaMonitor,
+    pub health: Health,
+    pub error_backoff: ErrorBackoff,
+    pub notifier: Notifier,
+    pub state: StateStore,
+    pub defaults: AnnotationDefaults,
+    pub policies: PolicyStore,
+    pub credentials: Credentials,
+}
+impl CurrentContext {
+    #[must_use]
+    pub fn new(
+        client: kube::Client,
+        config: OperatorConfig,
+        hcloud_config: HCloudConfig,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            traffic_monitor: TrafficQuotaMonitor::new(config.traffic_warning_threshold),
+            health: Health::default(),
+            error_backoff: ErrorBackoff::new(
+                Duration::from_secs(config.permanent_error_requeue_delay),
+                Duration::from_secs(config.max_error_requeue_delay),
+            ),
+            notifier: Notifier::new(config.webhook_url.clone()),
+            state: StateStore::default().for_cluster(&config.cluster_name),
+            metrics: metrics.for_cluster(&config.cluster_name),
+            defaults: AnnotationDefaults::default(),
+            policies: PolicyStore::default(),
+            credentials: Credentials::default(),
+            client,
+            config,
+            hcloud_config: Arc::new(RwLock::new(Arc::new(hcloud_config))),
+        }
+    }
+
+    /// Context of another cluster reconciled by the same operator.
+    ///
+    /// The operator's `HCloud` token, metrics, health and state are shared
+    /// with this context, while defaults, policies and tokens of services
+    /// are read from the cluster itself, since secrets of the same name
+    /// may hold tokens of different projects in every cluster.
+    /// Metrics and state of its services are told apart by the name of the cluster.
+    #[must_use]
+    pub fn for_cluster(&self, name: &str, client: kube::Client) -> Self {
+        let mut config = self.config.clone();
+        config.cluster_name = name.to_string();
+        Self {
+            hcloud_config: self.hcloud_config.clone(),
+            health: self.health.clone(),
+            state: self.state.for_cluster(name),
+            ..Self::new(
+                client,
+                config,
+                HCloudConfig::clone(&self.hcloud_config()),
+                self.metrics.clone(),
+            )
+        }
+    }
+
+    /// Name of the load balancer, unless it's set explicitly.
+    /// Load balancers of several clusters share `HCloud` projects,
+    /// so their names are prefixed with names of the clusters.
+    #[must_use]
+    pub fn default_lb_name(&self, name: String) -> String {
+        if self.config.clusters.is_empty() {
+            return name;
+        }
+        format!("{}-{name}", self.config.cluster_name)
+    }
+
+    /// `HCloud` configuration with the operator's token.
+    /// It's shared rather than copied, since it's needed by every reconcile.
+    #[must_use]
+    pub fn hcloud_config(&self) -> Arc<HCloudConfig> {
+        self.hcloud_config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// `HCloud` API with the operator's token.
+    #[must_use]
+    pub fn hcloud_api(&self) -> Arc<dyn HcloudApi> {
+        self.hcloud_api_with(self.hcloud_config())
+    }
+
+    /// `HCloud` API with the configuration, e.g. another project's token.
+    /// Failures are injected into it if configured.
+    #[must_use]
+    pub fn hcloud_api_with(&self, hcloud_config: Arc<HCloudConfig>) -> Arc<dyn HcloudApi> {
+        faults::wrap_hcloud(
+            &self.config.inject_faults,
+            Arc::new(HcloudClient::new(hcloud_config)),
+        )
+    }
+
+    /// Replace the operator's `HCloud` token.
+    pub fn set_hcloud_token(&self, token: String) {
+        let mut hcloud_config = self
+            .hcloud_config
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Configurations handed out before keep the old token.
+        Arc::make_mut(&mut hcloud_config).bearer_access_token = Some(token);
+    }
+
+    /// Annotations of the service merged with all the defaults
+    /// that apply to it.
+    pub fn annotations(&self, svc: &Service) -> RobotLBResult<BTreeMap<String, String>> {
+        let namespace_defaults = self.policies.defaults(&svc.namespace().unwrap_or_default());
+        let mut annotations = self.defaults.merged(svc, namespace_defaults)?;
+        if self.config.provider_annotations {
+            provider_annotations::apply(svc, &mut annotations);
+        }
+        if self.config.ccm_compat {
+            ccm::apply(svc, &mut annotations, &self.config)?;
+        }
+        Ok(annotations)
+    }
+}
+
+/// Reconcile the service.
+///
+/// This function is called by the controller for each service.
+/// It will create or update the load balancer based on the service.
+/// If the service is being deleted, it will clean up the resources.
+#[tracing::instrument(
+    skip(svc, context),
+    fields(service = svc.name_any(), namespace = svc.namespace().unwrap_or_default())
+)]
+pub async fn reconcile_service(
+    svc: Arc<Service>,
+    context: Arc<CurrentContext>,
+) -> RobotLBResult<Action> {
+    let _in_flight = context
+        .metrics
+        .reconcile_started(&svc.namespace().unwrap_or_default(), &svc.name_any());
+    if !is_managed(&svc, &context.config) {
+        // The service could stop being managed after the finalizer was added,
+        // e.g. when its label was removed. Its load balancer is left as is,
+        // but the service must still be deletable.
+        if svc.meta().deletion_timestamp.is_some()
+            && context.config.mode != OperatorMode::Observe
+            && finalizers::check(&svc)
+        {
+            finalizers::remove(context.client.clone(), &svc).await?;
+        }
+        return Err(RobotLBError::SkipService);
+    }
+
+    tracing::info!("Starting service reconcilation");
+
+    let lb = match LoadBalancer::resolve(&svc, &context).await {
+        // The secret with the token may be deleted before the service,
+        // e.g. along with the namespace. The balancer can't be deleted
+        // without the token, but the service must still be deletable.
+        Err(err) if svc.meta().deletion_timestamp.is_some() && err.is_missing_credentials() => {
+            return release_service(&svc, &context, &err).await;
+        }
+        lb => lb?,
+    };
+
+    // In observe mode nothing is ever cleaned up, and finalizers
+    // are left to the operator instance that manages the balancers.
+    if context.config.mode == OperatorMode::Observe {
+        if svc.meta().deletion_timestamp.is_some() {
+            let namespace = svc.namespace().unwrap_or_default();
+            context.metrics.untrack_lb(&namespace, &svc.name_any());
+            context.metrics.forget_service(&namespace, &svc.name_any());
+            context.state.forget(&svc);
+            return Ok(Action::await_change());
+        }
+        return reconcile_load_balancer(lb, svc.clone(), context).await;
+    }
+
+    // If the service is being deleted, we need to clean up the resources.
+    if svc.meta().deletion_timestamp.is_some() {
+        tracing::info!("Service deletion detected. Cleaning up resources.");
+        if lb.cleanup().await? {
+            context.notifier.notify(&svc, &lb.name, &LBEvent::Deleted);
+        }
+        forget_deleted_service(&svc, &context).await;
+        finalizers::remove(context.client.clone(), &svc).await?;
+        return Ok(Action::await_change());
+    }
+
+    // Add finalizer if it's not there yet.
+    if !finalizers::check(&svc) {
+        finalizers::add(context.client.clone(), &svc).await?;
+    }
+
+    // Based on the service type, we will reconcile the load balancer.
+    reconcile_load_balancer(lb, svc.clone(), context).await
+}
+
+/// Forget everything recorded about the service once it's cleaned up.
+async fn forget_deleted_service(svc: &Service, context: &CurrentContext) {
+    let namespace = svc.namespace().unwrap_or_default();
+    if context.config.inventory {
+        if let Err(err) = inventory::forget(context, &namespace, &svc.name_any()).await {
+            tracing::warn!(
+                "Cannot remove the load balancer from the inventory: {}",
+                err
+            );
+        }
+    }
+    context.metrics.untrack_lb(&namespace, &svc.name_any());
+    context.metrics.forget_service(&namespace, &svc.name_any());
+    context.traffic_monitor.forget(svc);
+    context.error_backoff.reset(svc);
+    context.state.forget(svc);
+}
+
+/// Let the deleted service go without deleting its load balancer,
+/// since the `HCloud` token the service refers to can't be read anymore.
+/// The balancer is left in Hetzner Cloud, which is reported in an event.
+async fn release_service(
+    svc: &Service,
+    context: &CurrentContext,
+    err: &RobotLBError,
+) -> RobotLBResult<Action> {
+    tracing::warn!(
+        "Cannot read HCloud token of the deleted service, its load balancer is left intact: {}",
+        err
+    );
+    if context.config.mode == OperatorMode::Observe {
+        let namespace = svc.namespace().unwrap_or_default();
+        context.metrics.untrack_lb(&namespace, &svc.name_any());
+        context.metrics.forget_service(&namespace, &svc.name_any());
+        context.state.forget(svc);
+        return Ok(Action::await_change());
+    }
+    let note = format!(
+        "Load balancer wasn't deleted and must be deleted manually, \
+         because the HCloud token can't be read: {err}"
+    );
+    if let Err(err) = events::warn(
+        context.client.clone(),
+        svc,
+        "CleanupSkipped",
+        "Cleanup",
+        note,
+    )
+    .await
+    {
+        tracing::warn!("Cannot publish cleanup event: {}", err);
+    }
+    forget_deleted_service(svc, context).await;
+    if finalizers::check(svc) {
+        finalizers::remove(context.client.clone(), svc).await?;
+    }
+    Ok(Action::await_change())
+}
+
+/// Check that the service is of `LoadBalancer` type,
+/// its load balancer class is robotlb and it matches the service selector.
+pub fn is_managed(svc: &Service, config: &OperatorConfig) -> bool {
+    let svc_type = svc
+        .spec
+        .as_ref()
+        .and_then(|s| s.type_.as_deref())
+        .unwrap_or("ClusterIP");
+    if svc_type != "LoadBalancer" {
+        tracing::debug!("Service type is not LoadBalancer. Skipping...");
+        return false;
+    }
+
+    let lb_type = svc
+        .spec
+        .as_ref()
+        .and_then(|s| s.load_balancer_class.as_deref())
+        .unwrap_or(consts::ROBOTLB_LB_CLASS);
+    if lb_type != consts::ROBOTLB_LB_CLASS {
+        tracing::debug!("Load balancer class is not robotlb. Skipping...");
+        return false;
+    }
+    if svc
+        .annotations()
+        .get(consts::EXTERNALLY_MANAGED_ANN_NAME)
+        .is_some_and(|value| value == "true")
+    {
+        tracing::debug!("Load balancer is managed externally. Skipping...");
+        return false;
+    }
+    if config
+        .service_selector
+        .as_ref()
+        .is_some_and(|selector| !selector.check(svc.labels()))
+    {
+        tracing::debug!("Service labels don't match the service selector. Skipping...");
+        return false;
+    }
+    true
+}
+
+/// Method to get nodes dynamically based on the pods.
+/// This method will find the nodes where the target pods are deployed.
+/// It will use the pod selector to find the pods and then get the nodes.
+async fn get_nodes_dynamically(
+    svc: &Arc<Service>,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<Vec<Node>> {
+    let pod_api = kube::Api::<Pod>::namespaced(
+        context.client.clone(),
+        svc.namespace()
+            .as_deref()
+            .unwrap_or_else(|| context.client.default_namespace()),
+    );
+
+    let Some(pod_selector) = svc.spec.as_ref().and_then(|spec| spec.selector.as_ref()) else {
+        return Err(RobotLBError::ServiceWithoutSelector);
+    };
+
+    let label_selector = pod_selector
+        .iter()
+        .map(|(key, val)| format!("{key}={val}"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    // Only nodes running the pods receive traffic, so pending pods
+    // and pods that aren't scheduled yet are left out by the API server.
+    let mut params = ListParams::default()
+        .labels(&label_selector)
+        .fields("status.phase=Running,spec.nodeName!=")
+        .limit(context.config.pod_list_page_size);
+    let mut target_nodes = HashSet::new();
+    loop {
+        let pods = pod_api.list(&params).await?;
+        target_nodes.extend(
+            pods.items
+                .iter()
+                .filter_map(|pod| pod.spec.as_ref()?.node_name.clone()),
+        );
+        match pods.metadata.continue_.filter(|token| !token.is_empty()) {
+            Some(token) => params = params.continue_token(&token),
+            None => break,
+        }
+    }
+
+    let nodes_api = kube::Api::<Node>::all(context.client.clone());
+    let nodes = nodes_api
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .filter(|node| target_nodes.contains(&node.name_any()))
+        .collect::<Vec<_>>();
+
+    Ok(nodes)
+}
+
+/// Get nodes based on the node selector.
+/// This method will find the nodes based on the node selector
+/// from the service annotations.
+async fn get_nodes_by_selector(
+    svc: &Arc<Service>,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<Vec<Node>> {
+    let annotations = context.annotations(svc)?;
+    let label_filter = LabelFilter::from_annotations(&annotations)?
+        .or_else(|| context.config.default_node_selector.clone())
+        .ok_or(RobotLBError::ServiceWithoutSelector)?;
+    let nodes_api = kube::Api::<Node>::all(context.client.clone());
+    let nodes = nodes_api
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .filter(|node| label_filter.check_node(node))
+        .collect::<Vec<_>>();
+    Ok(nodes)
+}
+
+const IPV4_FAMILY: &str = "IPv4";
+const IPV6_FAMILY: &str = "IPv6";
+
+/// IP families of the service, which its ingress IPs are published for.
+/// The first one is the primary family.
+///
+/// The families come from `spec.ipFamilies`, set by the API server according
+/// to `spec.ipFamilyPolicy`. The balancer always has addresses of both families,
+/// so dual-stack services get both even in single-stack clusters.
+/// Single-stack IPv4 services also get IPv6 with `ROBOTLB_IPV6_INGRESS`,
+/// since the API server sets their families even if they don't ask for any.
+fn ip_families(svc: &Service, config: &OperatorConfig) -> Vec<&'static str> {
+    let spec = svc.spec.as_ref();
+    let mut families = spec
+        .and_then(|spec| spec.ip_families.as_ref())
+        .into_iter()
+        .flatten()
+        .filter_map(|family| {
+            [IPV4_FAMILY, IPV6_FAMILY]
+                .into_iter()
+                .find(|known| known == family)
+        })
+        .collect::<Vec<_>>();
+    if families.is_empty() {
+        families.push(IPV4_FAMILY);
+    }
+    if config.ipv6_ingress && families == [IPV4_FAMILY] {
+        families.push(IPV6_FAMILY);
+    }
+    let policy = spec.and_then(|spec| spec.ip_family_policy.as_deref());
+    if matches!(policy, Some("PreferDualStack" | "RequireDualStack")) {
+        for family in [IPV4_FAMILY, IPV6_FAMILY] {
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+    }
+    families
+}
+
+/// Family of the IP address, if it's valid.
+fn ip_family(address: &str) -> Option<&'static str> {
+    address.parse::<std::net::IpAddr>().ok().map(|ip| {
+        if ip.is_ipv6() {
+            IPV6_FAMILY
+        } else {
+            IPV4_FAMILY
+        }
+    })
+}
+
+/// Add targets and services to the load balancer.
+///
+/// Targets are addresses of the nodes selected for the service,
+/// services are its TCP ports forwarded to the node ports.
+pub async fn resolve_targets_and_services(
+    lb: &mut LoadBalancer,
+    svc: &Arc<Service>,
+    context: &Arc<CurrentContext>,
+) -> RobotLBResult<()> {
+    let node_ip_type = if lb.network_name.is_none() {
+        "ExternalIP"
+    } else {
+        "InternalIP"
+    };
+    // The balancer accepts clients of both families whatever its targets are,
+    // so nodes are targeted only by addresses of the primary family.
+    // Private networks in Hetzner only have IPv4 addresses.
+    let target_family = if lb.network_name.is_none() {
+        ip_families(svc, &context.config)[0]
+    } else {
+        IPV4_FAMILY
+    };
+
+    let nodes = if context.config.dynamic_node_selector {
+        get_nodes_dynamically(svc, context).await?
+    } else {
+        get_nodes_by_selector(svc, context).await?
+    };
+
+    for node in nodes {
+        let Some(status) = node.status else {
+            continue;
+        };
+        let Some(addresses) = status.addresses else {
+            continue;
+        };
+        for addr in addresses {
+            if addr.type_ == node_ip_type && ip_family(&addr.address) == Some(target_family) {
+                lb.add_target(&addr.address);
+            }
+        }
+    }
+
+    for port in svc.spec.iter().flat_map(|spec| spec.ports.iter().flatten()) {
+        let protocol = port.protocol.as_deref().unwrap_or("TCP");
+        if protocol != "TCP" {
+            tracing::warn!("Protocol {} is not supported. Skipping...", protocol);
+            continue;
+        }
+        let Some(node_port) = port.node_port else {
+            tracing::warn!(
+                "Node port is not set for target_port {}. Skipping...",
+                port.port
+            );
+            continue;
+        };
+        lb.add_service(port.port, node_port);
+    }
+    Ok(())
+}
+
+/// Fields of the service the load balancer can't honor, described for users.
+fn unsupported_fields(svc: &Service) -> Vec<String> {
+    let Some(spec) = &svc.spec else {
+        return vec![];
+    };
+    let mut unsupported = vec![];
+    if spec.session_affinity.as_deref() == Some("ClientIP") {
+        unsupported
+            .push("sessionAffinity ClientIP: clients aren't kept on the same node".to_string());
+    }
+    if spec
+        .load_balancer_source_ranges
+        .as_ref()
+        .is_some_and(|ranges| !ranges.is_empty())
+    {
+        unsupported.push(
+            "loadBalancerSourceRanges: connections are accepted from any address".to_string(),
+        );
+    }
+    for port in spec.ports.iter().flatten() {
+        let protocol = port.protocol.as_deref().unwrap_or("TCP");
+        if protocol != "TCP" {
+            unsupported.push(format!(
+                "{protocol} port {}: only TCP ports are balanced",
+                port.port
+            ));
+        }
+    }
+    unsupported
+}
+
+/// Report fields of the service the load balancer can't honor with
+/// the `robotlb/UnsupportedFields` condition, and with an event when they change.
+async fn report_unsupported_fields(svc: &Service, context: &CurrentContext) {
+    let unsupported = unsupported_fields(svc);
+    let condition = if unsupported.is_empty() {
+        Condition {
+            type_: consts::UNSUPPORTED_FIELDS_CONDITION,
+            status: false,
+            reason: "AllFieldsSupported",
+            message: "All fields of the service are supported".to_string(),
+        }
+    } else {
+        Condition {
+            type_: consts::UNSUPPORTED_FIELDS_CONDITION,
+            status: true,
+            reason: "UnsupportedFields",
+            message: unsupported.join("; "),
+        }
+    };
+    match conditions::update(context.client.clone(), svc, condition).await {
+        Ok(true) if !unsupported.is_empty() => {
+            let note = format!(
+                "Fields of the service are ignored: {}",
+                unsupported.join("; ")
+            );
+            if let Err(err) = events::warn(
+                context.client.clone(),
+                svc,
+                "UnsupportedFields",
+                "Reconcile",
+                note,
+            )
+            .await
+            {
+                tracing::warn!("Cannot publish unsupported fields event: {}", err);
+            }
+        }
+        Ok(_) => {}
+        Err(err) => tracing::warn!("Cannot set unsupported fields condition: {}", err),
+    }
+}
+
+/// Reconcile the `LoadBalancer` type of service.
+/// This function will find the nodes based on the node selector
+/// and create or update the load balancer.
+pub async fn reconcile_load_balancer(
+    mut lb: LoadBalancer,
+    svc: Arc<Service>,
+    context: Arc<CurrentContext>,
+) -> RobotLBResult<Action> {
+    resolve_targets_and_services(&mut lb, &svc, &context).await?;
+
+    if context.config.mode == OperatorMode::Observe {
+        return report_drift(&mut lb, &svc, &context).await;
+    }
+
+    // Targets are resolved with the resync interval, but a balancer which
+    // matched them is only fetched from `HCloud` with the drift check interval.
+    let resync_interval = Duration::from_secs(context.config.resync_interval);
+    let drift_check_interval = Duration::from_secs(context.config.drift_check_interval);
+    if lb.resync_interval.is_none() && context.state.is_converged(&svc, &lb, drift_check_interval) {
+        tracing::debug!("Desired state is unchanged, the drift check isn't due yet");
+        context
+            .metrics
+            .reconcile_succeeded(&svc.namespace().unwrap_or_default(), &svc.name_any());
+        return Ok(requeue_service(&svc, &context, Some(resync_interval)));
+    }
+
+    report_unsupported_fields(&svc, &context).await;
+
+    let reconciled = lb.reconcile().await;
+    // Recorded whether the reconcile has failed or not, since the failure
+    // may have happened after a create or an attach was started.
+    record_pending_actions(&svc, &context, &lb).await;
+    let Reconciled {
+        hcloud_lb,
+        created,
+        changed,
+        invalid_targets,
+    } = reconciled?;
+    notify_changes(&svc, &context, &lb, &hcloud_lb, created);
+    // Invalid targets are usually left as they are for a while,
+    // so the event is only published when they change.
+    if context.state.record_invalid_targets(&svc, &invalid_targets) && !invalid_targets.is_empty() {
+        let note = format!("Targets are skipped: {}", invalid_targets.join("; "));
+        if let Err(err) = events::warn(
+            context.client.clone(),
+            &svc,
+            "InvalidTargets",
+            "Reconcile",
+            note,
+        )
+        .await
+        {
+            tracing::warn!("Cannot publish invalid targets event: {}", err);
+        }
+    }
+    context.state.record_lb(&svc, &lb, &hcloud_lb);
+    context.state.record_converged(&svc, !changed);
+    record_lb_id(&svc, &context, &hcloud_lb).await;
+    record_applied_labels(&svc, &context, &lb).await;
+    context.metrics.track_lb(
+        &svc.namespace().unwrap_or_default(),
+        &svc.name_any(),
+        &lb,
+        &hcloud_lb,
+    );
+    if let Err(err) = context
+        .traffic_monitor
+        .check(context.client.clone(), &svc, &hcloud_lb)
+        .await
+    {
+        tracing::warn!("Cannot check included traffic usage: {}", err);
+    }
+
+    update_ingress_status(&svc, &context, &hcloud_lb).await?;
+    update_limit_condition(&svc, context.client.clone(), None).await?;
+
+    context
+        .metrics
+        .reconcile_succeeded(&svc.namespace().unwrap_or_default(), &svc.name_any());
+    context.error_backoff.reset(svc.as_ref());
+    context.state.record_result(&svc, None);
+    Ok(requeue_service(
+        &svc,
+        &context,
+        Some(lb.resync_interval.unwrap_or(resync_interval)),
+    ))
+}
+
+/// Compare the load balancer with the desired state and report the drift
+/// through metrics, events and logs, without changing anything.
+async fn report_drift(
+    lb: &mut LoadBalancer,
+    svc: &Service,
+    context: &CurrentContext,
+) -> RobotLBResult<Action> {
+    let changes = lb.diff().await?;
+    let namespace = svc.namespace().unwrap_or_default();
+    context
+        .metrics
+        .set_drift(&namespace, &svc.name_any(), changes.len());
+    // Events are only published when the drift changes,
+    // otherwise every check would produce a new one.
+    if context.state.record_drift(svc, &changes) {
+        if changes.is_empty() {
+            tracing::info!("Load balancer matches the desired state");
+        } else {
+            let summary = changes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            tracing::warn!(
+                "Load balancer has drifted from the desired state: {}",
+                summary
+            );
+            if let Err(err) = events::warn(
+                context.client.clone(),
+                svc,
+                "DriftDetected",
+                "ObserveDrift",
+                format!(
+                    "Load balancer {} differs from the desired state: {summary}",
+                    lb.name
+                ),
+            )
+            .await
+            {
+                tracing::warn!("Cannot publish drift event: {}", err);
+            }
+        }
+    }
+    context
+        .metrics
+        .reconcile_succeeded(&namespace, &svc.name_any());
+    context.error_backoff.reset(svc);
+    context.state.record_result(svc, None);
+    let interval = lb
+        .resync_interval
+        .unwrap_or_else(|| Duration::from_secs(context.config.drift_check_interval));
+    Ok(requeue_service(svc, context, Some(interval)))
+}
+
+/// Notify about changes of the load balancer, which users may not expect:
+/// creation, resizing and change of its public IPs.
+fn notify_changes(
+    svc: &Service,
+    context: &CurrentContext,
+    lb: &LoadBalancer,
+    hcloud_lb: &hcloud::models::LoadBalancer,
+    created: bool,
+) {
+    let ipv4 = hcloud_lb.public_net.ipv4.ip.clone().flatten();
+    if created {
+        let event = LBEvent::Created { ip: ipv4 };
+        context.notifier.notify(svc, &lb.name, &event);
+        return;
+    }
+    if hcloud_lb.load_balancer_type.name != lb.balancer_type {
+        let event = LBEvent::Resized {
+            from: hcloud_lb.load_balancer_type.name.clone(),
+            to: lb.balancer_type.clone(),
+        };
+        context.notifier.notify(svc, &lb.name, &event);
+    }
+    let previous_ips = svc
+        .status
+        .as_ref()
+        .and_then(|status| status.load_balancer.as_ref())
+        .and_then(|status| status.ingress.as_ref())
+        .map(|ingress| {
+            ingress
+                .iter()
+                .filter_map(|ingress| ingress.ip.clone())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let families = ip_families(svc, &context.config);
+    let mut current_ips = vec![];
+    if families.contains(&IPV4_FAMILY) {
+        current_ips.extend(ipv4);
+    }
+    if families.contains(&IPV6_FAMILY) {
+        current_ips.extend(hcloud_lb.public_net.ipv6.ip.clone().flatten());
+    }
+    // IPs are assigned to the service for the first time, it's not a change.
+    if !previous_ips.is_empty() && previous_ips != current_ips {
+        let event = LBEvent::IpChanged {
+            from: previous_ips,
+            to: current_ips,
+        };
+        context.notifier.notify(svc, &lb.name, &event);
+    }
+}
+
+/// Requeue the service after the `interval` extended by a random
+/// amount of up to `jitter_percent` percent of it.
+///
+/// This spreads requeues of services created at the same time,
+/// so they don't hit the `HCloud` API in synchronized bursts.
+fn requeue_with_jitter(interval: Duration, jitter_percent: f64) -> Action {
+    Action::requeue(with_jitter(interval, jitter_percent))
+}
+
+/// Extend the `interval` by a random amount of up to `jitter_percent` percent of it.
+fn with_jitter(interval: Duration, jitter_percent: f64) -> Duration {
+    let max_jitter = interval.mul_f64(jitter_percent / 100.0);
+    interval + max_jitter.mul_f64(rand::random::<f64>())
+}
+
+/// Requeue the service after the `delay` with jitter, or wait for it to change
+/// if there's no delay. The time when the reconcile is due is recorded,
+/// so the watchdog knows there's work to do.
+fn requeue_service(svc: &Service, context: &CurrentContext, delay: Option<Duration>) -> Action {
+    let namespace = svc.namespace().unwrap_or_default();
+    let Some(delay) = delay else {
+        context
+            .metrics
+            .reconcile_dequeued(&namespace, &svc.name_any());
+        return Action::await_change();
+    };
+    let delay = with_jitter(delay, context.config.requeue_jitter);
+    context
+        .metrics
+        .reconcile_queued(&namespace, &svc.name_any(), delay);
+    Action::requeue(delay)
+}
+
+/// Record the ID of the service's load balancer in its annotation and the inventory,
+/// so the next reconcile fetches the balancer by ID. Failures are only logged,
+/// since the balancer can still be found by name.
+async fn record_lb_id(
+    svc: &Service,
+    context: &CurrentContext,
+    hcloud_lb: &hcloud::models::LoadBalancer,
+) {
+    let id = hcloud_lb.id.to_string();
+    if svc.annotations().get(consts::LB_ID_ANN_NAME) != Some(&id) {
+        let svc_api = kube::Api::<Service>::namespaced(
+            context.client.clone(),
+            &svc.namespace().unwrap_or_default(),
+        );
+        let patch = json!({"metadata": {"annotations": {consts::LB_ID_ANN_NAME: id}}});
+        if let Err(err) = svc_api
+            .patch(
+                &svc.name_any(),
+                &PatchParams::default(),
+                &kube::api::Patch::Merge(patch),
+            )
+            .await
+        {
+            tracing::warn!(
+                "Cannot annotate the service with the load balancer ID: {}",
+                err
+            );
+        }
+    }
+    if context.config.inventory {
+        if let Err(err) = inventory::record(context, svc, hcloud_lb).await {
+            tracing::warn!("Cannot record the load balancer in the inventory: {}", err);
+        }
+    }
+}
+
+/// Record IDs of the still running actions of the load balancer in the service's annotation,
+/// or remove it if there are none. Failures are only logged, as for [`record_lb_id`].
+async fn record_pending_actions(svc: &Service, context: &CurrentContext, lb: &LoadBalancer) {
+    let ids = lb
+        .pending_actions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let recorded = svc.annotations().get(consts::PENDING_ACTIONS_ANN_NAME);
+    if recorded.map_or("", String::as_str) == ids {
+        return;
+    }
+    let value = Some(ids).filter(|ids| !ids.is_empty());
+    let svc_api = kube::Api::<Service>::namespaced(
+        context.client.clone(),
+        &svc.namespace().unwrap_or_default(),
+    );
+    let patch = json!({"metadata": {"annotations": {consts::PENDING_ACTIONS_ANN_NAME: value}}});
+    if let Err(err) = svc_api
+        .patch(
+            &svc.name_any(),
+            &PatchParams::default(),
+            &kube::api::Patch::Merge(patch),
+        )
+        .await
+    {
+        tracing::warn!(
+            "Cannot record pending actions of the load balancer: {}",
+            err
+        );
+    }
+}
+
+/// Record keys of the custom labels set on the load balancer in the service's annotation,
+/// so they are removed once dropped from `robotlb/lb-labels`.
+/// Failures are only logged, as for [`record_lb_id`].
+async fn record_applied_labels(svc: &Service, context: &CurrentContext, lb: &LoadBalancer) {
+    let keys = lb
+        .custom_labels
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(",");
+    let recorded = svc.annotations().get(consts::APPLIED_LB_LABELS_ANN_NAME);
+    if recorded.map_or("", String::as_str) == keys {
+        return;
+    }
+    let value = Some(keys).filter(|keys| !keys.is_empty());
+    let svc_api = kube::Api::<Service>::namespaced(
+        context.client.clone(),
+        &svc.namespace().unwrap_or_default(),
+    );
+    let patch = json!({"metadata": {"annotations": {consts::APPLIED_LB_LABELS_ANN_NAME: value}}});
+    if let Err(err) = svc_api
+        .patch(
+            &svc.name_any(),
+            &PatchParams::default(),
+            &kube::api::Patch::Merge(patch),
+        )
+        .await
+    {
+        tracing::warn!("Cannot record custom labels of the load balancer: {}", err);
+    }
+}
+
+/// Publish IPs of the load balancer in the service's status.
+async fn update_ingress_status(
+    svc: &Service,
+    context: &CurrentContext,
+    hcloud_lb: &hcloud::models::LoadBalancer,
+) -> RobotLBResult<()> {
+    let svc_api = kube::Api::<Service>::namespaced(
+        context.client.clone(),
+        svc.namespace()
+            .unwrap_or_else(|| context.client.default_namespace().to_string())
+            .as_str(),
+    );
+
+    let mut ingress = vec![];
+
+    let dns_ipv4 = hcloud_lb.public_net.ipv4.dns_ptr.clone().flatten();
+    let ipv4 = hcloud_lb.public_net.ipv4.ip.clone().flatten();
+    let dns_ipv6 = hcloud_lb.public_net.ipv6.dns_ptr.clone().flatten();
+    let ipv6 = hcloud_lb.public_net.ipv6.ip.clone().flatten();
+    let families = ip_families(svc, &context.config);
+    if families.contains(&IPV4_FAMILY) {
+        if let Some(ipv4) = &ipv4 {
+            ingress.push(json!({
+                "ip": ipv4,
+                "dns": dns_ipv4,
+                "ip_mode": "VIP"
+            }));
+        }
+    }
+    if families.contains(&IPV6_FAMILY) {
+        if let Some(ipv6) = &ipv6 {
+            ingress.push(json!({
+                "ip": ipv6,
+                "dns": dns_ipv6,
+                "ip_mode": "VIP"
+            }));
+        }
+    }
+
+    // Patching the same status again would only trigger another watch event.
+    // Only IPs are compared, the other fields of the patch aren't kept in the status.
+    let current_ips = svc
+        .status
+        .as_ref()
+        .and_then(|status| status.load_balancer.as_ref())
+        .and_then(|load_balancer| load_balancer.ingress.as_ref())
+        .map(|ingress| {
+            ingress
+                .iter()
+                .filter_map(|ingress| ingress.ip.as_deref())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let desired_ips = ingress
+        .iter()
+        .filter_map(|ingress| ingress["ip"].as_str())
+        .collect::<Vec<_>>();
+    if current_ips == desired_ips {
+        tracing::debug!("Ingress status is up to date");
+        return Ok(());
+    }
+
+    if !ingress.is_empty() {
+        svc_api
+            .patch_status(
+                svc.name_any().as_str(),
+                &PatchParams::default(),
+                &kube::api::Patch::Merge(json!({
+                    "status" :{
+                        "loadBalancer": {
+                            "ingress": ingress
+                        }
+                    }
+                })),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Set the `robotlb/LimitExceeded` condition of the service, if the load balancer
+/// exceeds the limits of its type, or clear it once it fits in them.
+async fn update_limit_condition(
+    svc: &Service,
+    client: kube::Client,
+    limit: Option<String>,
+) -> RobotLBResult<()> {
+    let condition = limit.map_or_else(
+        || Condition {
+            type_: consts::LIMIT_EXCEEDED_CONDITION,
+            status: false,
+            reason: "WithinLimits",
+            message: "Load balancer fits in the limits of its type".to_string(),
+        },
+        |limit| Condition {
+            type_: consts::LIMIT_EXCEEDED_CONDITION,
+            status: true,
+            reason: "LimitExceeded",
+            message: limit,
+        },
+    );
+    conditions::update(client, svc, condition).await?;
+    Ok(())
+}
+
+/// Report that the load balancer exceeds the limits of its type
+/// with an event and the `robotlb/LimitExceeded` condition of the service.
+async fn report_limit_exceeded(svc: Arc<Service>, client: kube::Client, limit: String) {
+    let note = format!(
+        "{limit}. Use a larger type, or allow upgrading to one with {}",
+        consts::MAX_LB_TYPE_ANN_NAME
+    );
+    if let Err(err) = events::warn(client.clone(), &svc, "LimitExceeded", "Reconcile", note).await {
+        tracing::warn!("Cannot publish limit event: {}", err);
+    }
+    if let Err(err) = update_limit_condition(&svc, client, Some(limit)).await {
+        tracing::warn!("Cannot set limit condition: {}", err);
+    }
+}
+
+/// Report that the service resolves to the name of the load balancer
+/// of another service, with events on both of them.
+async fn report_name_conflict(
+    svc: Arc<Service>,
+    context: Arc<CurrentContext>,
+    name: String,
+    (owner_kind, owner_cluster, owner_namespace, owner_name): (&str, String, String, String),
+) {
+    let client = context.client.clone();
+    let owner_in_cluster = owner_cluster == context.config.cluster_name;
+    let note = if owner_in_cluster {
+        format!(
+            "Load balancer name {name} is already used by {owner_kind} {owner_namespace}/{owner_name}, the service isn't reconciled. Choose another name with {}",
+            consts::LB_NAME_LABEL_NAME
+        )
+    } else {
+        format!(
+            "Load balancer name {name} is already used by {owner_kind} {owner_namespace}/{owner_name} of cluster {owner_cluster}, the service isn't reconciled. Choose another name with {}",
+            consts::LB_NAME_LABEL_NAME
+        )
+    };
+    if let Err(err) = events::warn(client.clone(), &svc, "NameConflict", "Reconcile", note).await {
+        tracing::warn!("Cannot publish name conflict event: {}", err);
+    }
+    // Only services of the same cluster get events about balancers of each other.
+    if owner_kind != "service" || !owner_in_cluster {
+        return;
+    }
+    let owner = kube::Api::<Service>::namespaced(client.clone(), &owner_namespace)
+        .get_opt(&owner_name)
+        .await;
+    let owner = match owner {
+        Ok(Some(owner)) => owner,
+        // The balancer is left from a deleted service.
+        Ok(None) => return,
+        Err(err) => {
+            tracing::warn!(
+                "Cannot get service {}/{}: {}",
+                owner_namespace,
+                owner_name,
+                err
+            );
+            return;
+        }
+    };
+    let note = format!(
+        "Service {}/{} resolves to the same load balancer name {name}, it isn't reconciled until the conflict is resolved",
+        svc.namespace().unwrap_or_default(),
+        svc.name_any()
+    );
+    if let Err(err) = events::warn(client, &owner, "NameConflict", "Reconcile", note).await {
+        tracing::warn!("Cannot publish name conflict event: {}", err);
+    }
+}
+
+/// Publish an event about the failed `HCloud` request. Failures which
+/// go away by themselves, like rate limits, aren't reported as warnings.
+async fn report_hcloud_error(
+    svc: Arc<Service>,
+    client: kube::Client,
+    kind: HCloudErrorKind,
+    note: String,
+) {
+    let type_ = if kind.is_expected() {
+        EventType::Normal
+    } else {
+        EventType::Warning
+    };
+    if let Err(err) = events::publish(client, &svc, type_, kind.reason(), "Reconcile", note).await {
+        tracing::warn!("Cannot publish HCloud error event: {}", err);
+    }
+}
+
+/// Handle the error during reconcilation.
+#[allow(clippy::needless_pass_by_value)]
+fn on_error(svc: Arc<Service>, error: &RobotLBError, context: Arc<CurrentContext>) -> Action {
+    if matches!(error.root(), RobotLBError::SkipService) {
+        // The service is no longer managed, e.g. its type was changed.
+        context
+            .metrics
+            .forget_service(&svc.namespace().unwrap_or_default(), &svc.name_any());
+        return Action::await_change();
+    }
+    context
+        .metrics
+        .reconcile_failed(&svc.namespace().unwrap_or_default(), &svc.name_any());
+    reporting::capture(&svc, error);
+    let changed = context.state.record_result(&svc, Some(error));
+    // These problems persist until targets, services or names are changed,
+    // so they are only reported once.
+    if changed {
+        match error.root() {
+            RobotLBError::LimitExceeded(limit) => {
+                tokio::spawn(report_limit_exceeded(
+                    svc.clone(),
+                    context.client.clone(),
+                    limit.clone(),
+                ));
+            }
+            RobotLBError::LBNameConflict {
+                name,
+                kind,
+                cluster,
+                namespace,
+                owner,
+            } => {
+                tokio::spawn(report_name_conflict(
+                    svc.clone(),
+                    context.clone(),
+                    name.clone(),
+                    (kind, cluster.clone(), namespace.clone(), owner.clone()),
+                ));
+            }
+            _ => {}
+        }
+        if let Some(kind) = error.hcloud_kind() {
+            tokio::spawn(report_hcloud_error(
+                svc.clone(),
+                context.client.clone(),
+                kind,
+                error.to_string(),
+            ));
+        }
+    }
+    let action = requeue_service(
+        &svc,
+        &context,
+        error_requeue_delay(svc.as_ref(), error, &context),
+    );
+    if error.class() == ErrorClass::Config {
+        let deleted = svc.meta().deletion_timestamp.is_some();
+        if deleted {
+            tracing::warn!("Deleted service is misconfigured, retrying the cleanup");
+        } else {
+            tracing::warn!("Service is misconfigured, waiting for it to change");
+        }
+        // The service isn't retried until it changes, so the event is published
+        // once per change. Deleted services are retried, so for them it's published
+        // only when the error changes.
+        if !deleted || changed {
+            tokio::spawn({
+                let client = context.client.clone();
+                let note = error.to_string();
+                let reason = if matches!(error.root(), RobotLBError::InvalidAnnotation { .. }) {
+                    "InvalidAnnotation"
+                } else {
+                    "InvalidConfiguration"
+                };
+                async move {
+                    if let Err(err) = events::warn(client, &svc, reason, "Reconcile", note).await {
+                        tracing::warn!("Cannot publish misconfiguration event: {}", err);
+                    }
+                }
+            });
+        }
+    }
+    action
+}
+
+/// Decide when to retry the failed reconcile of the resource by the class of the error.
+pub fn requeue_after_error<K: Resource<DynamicType = ()>>(
+    resource: &K,
+    error: &RobotLBError,
+    context: &CurrentContext,
+) -> Action {
+    error_requeue_delay(resource, error, context).map_or_else(Action::await_change, |delay| {
+        requeue_with_jitter(delay, context.config.requeue_jitter)
+    })
+}
+
+/// Delay before retrying the failed reconcile of the resource,
+/// or `None` if it's retried only after it changes.
+fn error_requeue_delay<K: Resource<DynamicType = ()>>(
+    resource: &K,
+    error: &RobotLBError,
+    context: &CurrentContext,
+) -> Option<Duration> {
+    match error.class() {
+        // Deleted resources don't change anymore, so waiting for a change
+        // would leave their finalizers in place forever.
+        ErrorClass::Config if resource.meta().deletion_timestamp.is_some() => {
+            Some(Duration::from_secs(context.config.error_requeue_delay))
+        }
+        ErrorClass::Config => None,
+        ErrorClass::Transient => Some(Duration::from_secs(context.config.error_requeue_delay)),
+        ErrorClass::Permanent => Some(context.error_backoff.next_delay(resource)),
+        ErrorClass::RateLimited => {
+            Some(Duration::from_secs(context.config.rate_limit_requeue_delay))
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing::Level;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{
+    filter::Targets,
+    fmt::{writer::BoxMakeWriter, MakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+use crate::{
+    audit,
+    config::{LogFormat, LogRotation, OperatorConfig},
+    consts,
+    error::{RobotLBError, RobotLBResult},
+};
+
+type BoxedLayer = Box<dyn Layer<tracing_subscriber::Registry> + Send + Sync>;
+
+/// Initialize the global logger.
+///
+/// Logs are always written to stdout and additionally to a rotated
+/// file, if it's configured. If the OTLP endpoint is set, spans
+/// are exported there as distributed traces. Audit records of changes
+/// made in `HCloud` can be written to their own file.
+///
+/// In JSON format every line carries the fields of the current span,
+/// so records can be filtered by `service`, `namespace`, `lb_name`
+/// and `hcloud_action`.
+pub fn init(config: &OperatorConfig) -> RobotLBResult<()> {
+    let mut layers = vec![fmt_layer(config.log_format, std::io::stdout, true)];
+    if let Some(path) = &config.log_file {
+        let appender = file_appender(path, config)?;
+        layers.push(fmt_layer(config.log_format, appender, false));
+    }
+    if let Some(endpoint) = &config.otlp_endpoint {
+        layers.push(otel_layer(endpoint)?);
+    }
+    let mut layers = vec![layers.with_filter(config.log_level).boxed()];
+    // Audit records are written regardless of the log level.
+    if let Some(path) = &config.audit_log_file {
+        let appender = file_appender(path, config)?;
+        layers.push(
+            fmt_layer(LogFormat::Json, appender, false)
+                .with_filter(Targets::new().with_target(audit::TARGET, Level::INFO))
+                .boxed(),
+        );
+    }
+    // Console needs all tokio's events regardless of the log level.
+    #[cfg(feature = "tokio-console")]
+    if config.tokio_console {
+        layers.push(console_subscriber::spawn().boxed());
+    }
+    tracing_subscriber::registry().with(layers).init();
+    Ok(())
+}
+
+/// Create an appender of the log file, rotated according to the config.
+fn file_appender(path: &Path, config: &OperatorConfig) -> RobotLBResult<BoxMakeWriter> {
+    if let Some(max_size) = config.log_max_size {
+        let file = SizeRollingFile::open(path, max_size, config.log_max_files)?;
+        return Ok(BoxMakeWriter::new(file));
+    }
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| RobotLBError::InvalidLogFile(path.display().to_string()))?;
+    let directory = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let mut appender = RollingFileAppender::builder()
+        .rotation(config.log_rotation.into())
+        .filename_prefix(file_name);
+    if let Some(max_files) = config.log_max_files {
+        appender = appender.max_log_files(max_files);
+    }
+    Ok(BoxMakeWriter::new(appender.build(directory)?))
+}
+
+/// Log file which is rotated once it grows over the size limit.
+/// Rotated files get `.1`, `.2` and so on appended to their name,
+/// `.1` being the latest one.
+struct SizeRollingFile {
+    path: PathBuf,
+    max_size: u64,
+    /// Number of rotated files to keep, all of them are kept if not set.
+    max_files: Option<usize>,
+    /// The file being written and its size.
+    current: Mutex<(File, u64)>,
+}
+
+impl SizeRollingFile {
+    fn open(path: &Path, max_size: u64, max_files: Option<usize>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            max_files,
+            current: Mutex::new((file, size)),
+        })
+    }
+
+    /// Path of the rotated file with the `index`.
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        PathBuf::from(path)
+    }
+
+    /// Index of the oldest rotated file after the rotation,
+    /// removing the file that would be over the limit.
+    fn last_rotated(&self) -> io::Result<usize> {
+        let Some(max_files) = self.max_files else {
+            let mut last = 1;
+            while self.rotated(last).exists() {
+                last += 1;
+            }
+            return Ok(last);
+        };
+        let oldest = self.rotated(max_files.max(1));
+        if oldest.exists() {
+            std::fs::remove_file(oldest)?;
+        }
+        Ok(max_files)
+    }
+
+    /// Shift the rotated files, dropping the oldest one over the limit,
+    /// and start a new file.
+    fn rotate(&self) -> io::Result<File> {
+        let last = self.last_rotated()?;
+        for index in (1..last).rev() {
+            let from = self.rotated(index);
+            if from.exists() {
+                std::fs::rename(from, self.rotated(index + 1))?;
+            }
+        }
+        if last == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+    }
+}
+
+impl Write for &SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        let (file, size) = &mut *current;
+        // A record larger than the limit is still written whole.
+        if *size > 0 && *size + buf.len() as u64 > self.max_size {
+            file.flush()?;
+            *file = self.rotate()?;
+            *size = 0;
+        }
+        let written = file.write(buf)?;
+        *size += written as u64;
+        drop(current);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+            .flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for SizeRollingFile {
+    type Writer = &'a Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+/// Flush traces that haven't been exported yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Create a layer which exports spans to the OTLP collector over gRPC.
+fn otel_layer(endpoint: &str) -> RobotLBResult<BoxedLayer> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new_with_defaults([
+            KeyValue::new("service.name", "robotlb"),
+            KeyValue::new("service.version", consts::VERSION),
+        ]))
+        .build();
+    let tracer = provider.tracer("robotlb");
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer).boxed())
+}
+
+/// Create a formatting layer. Colors are only used for terminals,
+/// log files must stay plain text.
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_ansi(ansi)
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Self::HOURLY,
+            LogRotation::Daily => Self::DAILY,
+            LogRotation::Weekly => Self::WEEKLY,
+            LogRotation::Never => Self::NEVER,
+        }
+    }
+}
+
+/// Sampler of repeated log messages.
+///
+/// Persistent conditions, like a missing network, are reported on every
+/// requeue of every affected service. The sampler lets a message with
+/// the same key through once per window and counts the suppressed ones,
+/// so the log shows a summary instead of a flood.
+#[derive(Clone)]
+pub struct LogSampler {
+    window: Duration,
+    entries: Arc<Mutex<HashMap<String, SampleEntry>>>,
+}
+
+struct SampleEntry {
+    logged_at: Instant,
+    suppressed: u64,
+}
+
+impl LogSampler {
+    /// Create a sampler. Zero `window` disables sampling.
+    #[must_use]
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: Arc::default(),
+        }
+    }
+
+    /// Check whether the message with the `key` should be logged.
+    /// Returns the number of messages suppressed since it was logged last time,
+    /// or `None` if this one must be suppressed as well.
+    pub fn sample(&self, key: &str) -> Option<u64> {
+        if self.window.is_zero() {
+            return Some(0);
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = entries.get_mut(key) {
+            if now.duration_since(entry.logged_at) < self.window {
+                entry.suppressed += 1;
+                return None;
+            }
+        }
+        let suppressed = entries.remove(key).map_or(0, |entry| entry.suppressed);
+        // Forget messages that haven't been seen for a while.
+        entries.retain(|_, entry| now.duration_since(entry.logged_at) < self.window * 2);
+        entries.insert(
+            key.to_string(),
+            SampleEntry {
+                logged_at: now,
+                suppressed: 0,
+            },
+        );
+        drop(entries);
+        Some(suppressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, path::PathBuf};
+
+    use super::SizeRollingFile;
+
+    /// Empty directory for the files of the test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("robotlb-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn rotates_files_by_size() {
+        let dir = test_dir("rotates-files-by-size");
+        let path = dir.join("robotlb.log");
+        let file = SizeRollingFile::open(&path, 10, Some(2)).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            (&file).write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("robotlb.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("robotlb.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("robotlb.log.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,654 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use prometheus::{
+    core::Collector, Gauge, GaugeVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+};
+
+use hcloud::models::load_balancer_target_health_status::Status as HealthStatus;
+
+use crate::{consts, error::RobotLBResult, lb::LoadBalancer};
+
+/// Time series of load balancer metrics exposed by Hetzner Cloud,
+/// mapped to the names and descriptions of the exported gauges.
+const LB_TRAFFIC_SERIES: &[(&str, &str, &str)] = &[
+    (
+        "open_connections",
+        "lb_open_connections",
+        "Number of open connections of the load balancer",
+    ),
+    (
+        "connections_per_second",
+        "lb_connections_per_second",
+        "Number of new connections per second of the load balancer",
+    ),
+    (
+        "requests_per_second",
+        "lb_requests_per_second",
+        "Number of HTTP requests per second of the load balancer",
+    ),
+    (
+        "bandwidth.in",
+        "lb_bandwidth_in_bytes",
+        "Incoming bandwidth of the load balancer in bytes per second",
+    ),
+    (
+        "bandwidth.out",
+        "lb_bandwidth_out_bytes",
+        "Outgoing bandwidth of the load balancer in bytes per second",
+    ),
+];
+
+/// Labels of per-namespace metrics. Services of several clusters
+/// may share namespaces and names, so all of them are labeled with the cluster.
+const NAMESPACE_LABELS: &[&str] = &["cluster", "namespace"];
+/// Labels of per-service metrics.
+const SERVICE_LABELS: &[&str] = &["cluster", "namespace", "service"];
+/// Labels of per-balancer metrics.
+const LB_LABELS: &[&str] = &["cluster", "namespace", "service", "load_balancer"];
+
+/// Information about a single load balancer managed by the operator.
+#[derive(Debug, Clone, Default)]
+pub struct ManagedLoadBalancer {
+    pub cluster: String,
+    pub namespace: String,
+    pub service: String,
+    pub lb_name: String,
+    pub lb_id: i64,
+    pub targets: usize,
+    pub services: usize,
+    pub monthly_cost: Option<f64>,
+}
+
+/// Prometheus metrics of the operator.
+///
+/// The struct is cheap to clone, all the clones
+/// share the same registry and state.
+#[derive(Clone)]
+pub struct Metrics {
+    pub registry: Registry,
+    /// Cluster of the services whose metrics are recorded by this instance.
+    cluster: String,
+
+    managed_load_balancers: IntGaugeVec,
+    managed_targets: IntGaugeVec,
+    managed_services: IntGaugeVec,
+
+    reconciles_in_flight: IntGauge,
+    reconciles_queued: IntGauge,
+    requeues: IntCounterVec,
+    error_requeues: IntCounterVec,
+    consecutive_errors: IntGaugeVec,
+    drift: IntGaugeVec,
+
+    healthy_targets: IntGaugeVec,
+    unhealthy_targets: IntGaugeVec,
+
+    included_traffic: IntGaugeVec,
+    ingoing_traffic: IntGaugeVec,
+    outgoing_traffic: IntGaugeVec,
+    included_traffic_usage: GaugeVec,
+
+    monthly_cost: GaugeVec,
+    monthly_cost_total: Gauge,
+
+    /// Traffic gauges keyed by the name of hcloud time series.
+    traffic: HashMap<&'static str, GaugeVec>,
+
+    /// Load balancers managed by the operator,
+    /// keyed by cluster, namespace and name of the service.
+    managed: Arc<Mutex<HashMap<ServiceKey, ManagedLoadBalancer>>>,
+
+    /// Time when the next reconcile of a service is due,
+    /// keyed by cluster, namespace and name of the service.
+    queued: Arc<Mutex<HashMap<ServiceKey, Instant>>>,
+}
+
+/// Cluster, namespace and name of a service.
+type ServiceKey = (String, String, String);
+
+impl Metrics {
+    // It's just a long list of metrics.
+    #[allow(clippy::too_many_lines)]
+    pub fn new() -> RobotLBResult<Self> {
+        let registry = Registry::new_custom(Some("robotlb".to_string()), None)?;
+        int_gauge_vec(
+            &registry,
+            "build_info",
+            "Build information of the running operator",
+            &["version", "git_sha", "rustc"],
+        )?
+        .with_label_values(&[consts::VERSION, consts::GIT_SHA, consts::RUSTC_VERSION])
+        .set(1);
+        let mut traffic = HashMap::new();
+        for (series, name, help) in LB_TRAFFIC_SERIES {
+            traffic.insert(*series, gauge_vec(&registry, name, help, LB_LABELS)?);
+        }
+        Ok(Self {
+            managed_load_balancers: int_gauge_vec(
+                &registry,
+                "managed_load_balancers",
+                "Number of load balancers managed by the operator",
+                NAMESPACE_LABELS,
+            )?,
+            managed_targets: int_gauge_vec(
+                &registry,
+                "managed_targets",
+                "Number of targets in all managed load balancers",
+                NAMESPACE_LABELS,
+            )?,
+            managed_services: int_gauge_vec(
+                &registry,
+                "managed_services",
+                "Number of services in all managed load balancers",
+                NAMESPACE_LABELS,
+            )?,
+            reconciles_in_flight: register(
+                &registry,
+                IntGauge::new(
+                    "reconciles_in_flight",
+                    "Number of reconciles that are currently running",
+                )?,
+            )?,
+            reconciles_queued: register(
+                &registry,
+                IntGauge::new(
+                    "reconciles_queued",
+                    "Number of reconciles that are due, but haven't started yet",
+                )?,
+            )?,
+            requeues: int_counter_vec(
+                &registry,
+                "requeues_total",
+                "Number of services scheduled for another reconcile",
+                &["reason"],
+            )?,
+            error_requeues: int_counter_vec(
+                &registry,
+                "error_requeues_total",
+                "Number of requeues caused by reconcile errors",
+                SERVICE_LABELS,
+            )?,
+            consecutive_errors: int_gauge_vec(
+                &registry,
+                "consecutive_errors",
+                "Number of reconciles of a service that failed in a row",
+                SERVICE_LABELS,
+            )?,
+            drift: int_gauge_vec(
+                &registry,
+                "lb_drift_changes",
+                "Number of changes needed to bring the load balancer to the desired state, \
+                 reported in observe mode",
+                SERVICE_LABELS,
+            )?,
+            healthy_targets: int_gauge_vec(
+                &registry,
+                "lb_healthy_targets",
+                "Number of load balancer targets that pass all health checks",
+                LB_LABELS,
+            )?,
+            unhealthy_targets: int_gauge_vec(
+                &registry,
+                "lb_unhealthy_targets",
+                "Number of load balancer targets that fail any health check",
+                LB_LABELS,
+            )?,
+            included_traffic: int_gauge_vec(
+                &registry,
+                "lb_included_traffic_bytes",
+                "Free traffic of the load balancer for the billing period",
+                LB_LABELS,
+            )?,
+            ingoing_traffic: int_gauge_vec(
+                &registry,
+                "lb_ingoing_traffic_bytes",
+                "Inbound traffic of the load balancer for the billing period",
+                LB_LABELS,
+            )?,
+            outgoing_traffic: int_gauge_vec(
+                &registry,
+                "lb_outgoing_traffic_bytes",
+                "Outbound traffic of the load balancer for the billing period",
+                LB_LABELS,
+            )?,
+            included_traffic_usage: gauge_vec(
+                &registry,
+                "lb_included_traffic_usage_ratio",
+                "Ratio of outbound traffic to the free traffic of the load balancer",
+                LB_LABELS,
+            )?,
+            monthly_cost: gauge_vec(
+                &registry,
+                "lb_estimated_monthly_cost",
+                "Estimated monthly net cost of the load balancer",
+                LB_LABELS,
+            )?,
+            monthly_cost_total: register(
+                &registry,
+                Gauge::new(
+                    "estimated_monthly_cost_total",
+                    "Estimated monthly net cost of all managed load balancers",
+                )?,
+            )?,
+            traffic,
+            registry,
+            cluster: String::new(),
+            managed: Arc::default(),
+            queued: Arc::default(),
+        })
+    }
+
+    /// Metrics of services of the cluster, sharing the registry with these ones.
+    #[must_use]
+    pub fn for_cluster(self, cluster: &str) -> Self {
+        Self {
+            cluster: cluster.to_string(),
+            ..self
+        }
+    }
+
+    fn service_key(&self, namespace: &str, name: &str) -> ServiceKey {
+        (
+            self.cluster.clone(),
+            namespace.to_string(),
+            name.to_string(),
+        )
+    }
+
+    /// Record the current state of a managed load balancer.
+    ///
+    /// The `lb` is the desired state of the balancer and
+    /// the `hcloud_lb` is the balancer as it's seen by Hetzner Cloud.
+    pub fn track_lb(
+        &self,
+        namespace: &str,
+        name: &str,
+        lb: &LoadBalancer,
+        hcloud_lb: &hcloud::models::LoadBalancer,
+    ) {
+        let lb_labels = [self.cluster.as_str(), namespace, name, lb.name.as_str()];
+        let (healthy, unhealthy) = count_target_health(hcloud_lb);
+        self.healthy_targets
+            .with_label_values(&lb_labels)
+            .set(healthy);
+        self.unhealthy_targets
+            .with_label_values(&lb_labels)
+            .set(unhealthy);
+        self.included_traffic
+            .with_label_values(&lb_labels)
+            .set(hcloud_lb.included_traffic);
+        if let Some(ingoing) = hcloud_lb.ingoing_traffic {
+            self.ingoing_traffic
+                .with_label_values(&lb_labels)
+                .set(ingoing);
+        }
+        if let Some(outgoing) = hcloud_lb.outgoing_traffic {
+            self.outgoing_traffic
+                .with_label_values(&lb_labels)
+                .set(outgoing);
+        }
+        if let Some(usage) = included_traffic_usage(hcloud_lb) {
+            self.included_traffic_usage
+                .with_label_values(&lb_labels)
+                .set(usage);
+        }
+        let monthly_cost = estimate_monthly_cost(hcloud_lb);
+        if let Some(cost) = monthly_cost {
+            self.monthly_cost.with_label_values(&lb_labels).set(cost);
+        }
+
+        self.update_managed(|managed| {
+            let previous = managed.insert(
+                self.service_key(namespace, name),
+                ManagedLoadBalancer {
+                    cluster: self.cluster.clone(),
+                    namespace: namespace.to_string(),
+                    service: name.to_string(),
+                    lb_name: lb.name.clone(),
+                    lb_id: hcloud_lb.id,
+                    targets: lb.targets.len(),
+                    services: lb.services.len(),
+                    monthly_cost,
+                },
+            );
+            // The balancer of the service was renamed.
+            if let Some(previous) = previous.filter(|prev| prev.lb_name != lb.name) {
+                self.remove_lb_metrics(&previous);
+            }
+        });
+    }
+
+    /// Forget about the load balancer of the service.
+    /// This is called when the load balancer was removed.
+    pub fn untrack_lb(&self, namespace: &str, name: &str) {
+        // Result is ignored, because drift is only reported in observe mode.
+        let _ = self
+            .drift
+            .remove_label_values(&[&self.cluster, namespace, name]);
+        self.update_managed(|managed| {
+            if let Some(lb) = managed.remove(&self.service_key(namespace, name)) {
+                self.remove_lb_metrics(&lb);
+            }
+        });
+    }
+
+    /// Remove all per-balancer metrics of the load balancer.
+    fn remove_lb_metrics(&self, lb: &ManagedLoadBalancer) {
+        let lb_labels = [
+            lb.cluster.as_str(),
+            lb.namespace.as_str(),
+            lb.service.as_str(),
+            lb.lb_name.as_str(),
+        ];
+        // Results are ignored, because metrics might not be set yet.
+        let _ = self.healthy_targets.remove_label_values(&lb_labels);
+        let _ = self.unhealthy_targets.remove_label_values(&lb_labels);
+        let _ = self.included_traffic.remove_label_values(&lb_labels);
+        let _ = self.ingoing_traffic.remove_label_values(&lb_labels);
+        let _ = self.outgoing_traffic.remove_label_values(&lb_labels);
+        let _ = self.included_traffic_usage.remove_label_values(&lb_labels);
+        let _ = self.monthly_cost.remove_label_values(&lb_labels);
+        for gauge in self.traffic.values() {
+            let _ = gauge.remove_label_values(&lb_labels);
+        }
+    }
+
+    /// Get all load balancers currently managed in the cluster of these metrics.
+    #[must_use]
+    pub fn managed_lbs(&self) -> Vec<ManagedLoadBalancer> {
+        self.managed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .filter(|lb| lb.cluster == self.cluster)
+            .cloned()
+            .collect()
+    }
+
+    /// Set the latest value of the hcloud traffic time series
+    /// for the managed load balancer.
+    /// Unknown time series are ignored.
+    pub fn set_lb_traffic(&self, lb: &ManagedLoadBalancer, series: &str, value: f64) {
+        let Some(gauge) = self.traffic.get(series) else {
+            return;
+        };
+        gauge
+            .with_label_values(&[
+                lb.cluster.as_str(),
+                lb.namespace.as_str(),
+                lb.service.as_str(),
+                lb.lb_name.as_str(),
+            ])
+            .set(value);
+    }
+
+    /// Mark the start of a reconcile of the service.
+    /// The reconcile is considered finished once the returned guard is dropped.
+    #[must_use]
+    pub fn reconcile_started(&self, namespace: &str, name: &str) -> InFlightGuard {
+        self.reconcile_dequeued(namespace, name);
+        self.reconciles_in_flight.inc();
+        InFlightGuard {
+            gauge: self.reconciles_in_flight.clone(),
+        }
+    }
+
+    /// Number of reconciles that are currently running.
+    #[must_use]
+    pub fn reconciles_in_flight(&self) -> i64 {
+        self.reconciles_in_flight.get()
+    }
+
+    /// Record that the next reconcile of the service is due after the `delay`.
+    pub fn reconcile_queued(&self, namespace: &str, name: &str, delay: Duration) {
+        self.update_queued(|queued| {
+            queued.insert(self.service_key(namespace, name), Instant::now() + delay);
+        });
+    }
+
+    /// Record that the service isn't reconciled again until it changes,
+    /// e.g. because it was deleted.
+    pub fn reconcile_dequeued(&self, namespace: &str, name: &str) {
+        self.update_queued(|queued| {
+            queued.remove(&self.service_key(namespace, name));
+        });
+    }
+
+    /// Number of reconciles of services of all clusters, which are due,
+    /// but haven't started yet.
+    #[must_use]
+    pub fn reconciles_queued(&self) -> usize {
+        self.update_queued(|_| {})
+    }
+
+    /// Update gauges which change with time rather than with reconciles,
+    /// so they are current when the metrics are gathered.
+    pub fn refresh(&self) {
+        self.update_queued(|_| {});
+    }
+
+    /// Apply the update to the queued reconciles and recalculate
+    /// the number of the due ones, which is returned.
+    fn update_queued(&self, update: impl FnOnce(&mut HashMap<ServiceKey, Instant>)) -> usize {
+        let mut queued = self.queued.lock().unwrap_or_else(PoisonError::into_inner);
+        update(&mut queued);
+        let now = Instant::now();
+        let due = queued.values().filter(|due| **due <= now).count();
+        drop(queued);
+        self.reconciles_queued
+            .set(i64::try_from(due).unwrap_or(i64::MAX));
+        due
+    }
+
+    /// Record a successful reconcile that scheduled the next one.
+    pub fn reconcile_succeeded(&self, namespace: &str, name: &str) {
+        self.requeues.with_label_values(&["success"]).inc();
+        // Result is ignored, because the service might have never failed before.
+        let _ = self
+            .consecutive_errors
+            .remove_label_values(&[&self.cluster, namespace, name]);
+    }
+
+    /// Record a failed reconcile which is going to be retried.
+    pub fn reconcile_failed(&self, namespace: &str, name: &str) {
+        self.requeues.with_label_values(&["error"]).inc();
+        self.error_requeues
+            .with_label_values(&[&self.cluster, namespace, name])
+            .inc();
+        self.consecutive_errors
+            .with_label_values(&[&self.cluster, namespace, name])
+            .inc();
+    }
+
+    /// Remove the error series of the service.
+    /// This is called when the service was deleted or is no longer managed.
+    pub fn forget_service(&self, namespace: &str, name: &str) {
+        // Results are ignored, because the service might have never failed.
+        let _ = self
+            .error_requeues
+            .remove_label_values(&[&self.cluster, namespace, name]);
+        let _ = self
+            .consecutive_errors
+            .remove_label_values(&[&self.cluster, namespace, name]);
+    }
+
+    /// Set the number of changes the load balancer of the service
+    /// has drifted by from the desired state.
+    pub fn set_drift(&self, namespace: &str, name: &str, changes: usize) {
+        self.drift
+            .with_label_values(&[&self.cluster, namespace, name])
+            .set(i64::try_from(changes).unwrap_or(i64::MAX));
+    }
+
+    /// Apply the update to the managed load balancers
+    /// and recalculate per-namespace gauges.
+    fn update_managed(&self, update: impl FnOnce(&mut HashMap<ServiceKey, ManagedLoadBalancer>)) {
+        let mut managed = self.managed.lock().unwrap_or_else(PoisonError::into_inner);
+        update(&mut managed);
+        self.managed_load_balancers.reset();
+        self.managed_targets.reset();
+        self.managed_services.reset();
+        self.monthly_cost_total
+            .set(managed.values().filter_map(|lb| lb.monthly_cost).sum());
+        for lb in managed.values() {
+            let labels = [lb.cluster.as_str(), lb.namespace.as_str()];
+            self.managed_load_balancers.with_label_values(&labels).inc();
+            self.managed_targets
+                .with_label_values(&labels)
+                .add(i64::try_from(lb.targets).unwrap_or(i64::MAX));
+            self.managed_services
+                .with_label_values(&labels)
+                .add(i64::try_from(lb.services).unwrap_or(i64::MAX));
+        }
+    }
+}
+
+/// Register the metric in the registry.
+fn register<T: Collector + Clone + 'static>(registry: &Registry, metric: T) -> RobotLBResult<T> {
+    registry.register(Box::new(metric.clone()))?;
+    Ok(metric)
+}
+
+/// Create and register a new integer gauge with labels.
+fn int_gauge_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> RobotLBResult<IntGaugeVec> {
+    register(registry, IntGaugeVec::new(Opts::new(name, help), labels)?)
+}
+
+/// Create and register a new gauge with labels.
+fn gauge_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> RobotLBResult<GaugeVec> {
+    register(registry, GaugeVec::new(Opts::new(name, help), labels)?)
+}
+
+/// Create and register a new integer counter with labels.
+fn int_counter_vec(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> RobotLBResult<IntCounterVec> {
+    register(registry, IntCounterVec::new(Opts::new(name, help), labels)?)
+}
+
+/// Count healthy and unhealthy targets of the load balancer.
+///
+/// Target is considered healthy only if it's healthy for every service
+/// of the balancer. Targets that have at least one failing health check
+/// are unhealthy. Targets with unknown status are not counted at all.
+fn count_target_health(hcloud_lb: &hcloud::models::LoadBalancer) -> (i64, i64) {
+    let mut healthy = 0;
+    let mut unhealthy = 0;
+    for target in &hcloud_lb.targets {
+        let statuses = target
+            .health_status
+            .iter()
+            .flatten()
+            .filter_map(|health| health.status)
+            .collect::<Vec<_>>();
+        if statuses.contains(&HealthStatus::Unhealthy) {
+            unhealthy += 1;
+        } else if !statuses.is_empty() && statuses.iter().all(|s| *s == HealthStatus::Healthy) {
+            healthy += 1;
+        }
+    }
+    (healthy, unhealthy)
+}
+
+/// Get the ratio of outgoing traffic to the traffic included
+/// into the price of the load balancer.
+///
+/// Returns `None` if the traffic is unknown.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn included_traffic_usage(hcloud_lb: &hcloud::models::LoadBalancer) -> Option<f64> {
+    let outgoing = hcloud_lb.outgoing_traffic?;
+    if hcloud_lb.included_traffic <= 0 {
+        return None;
+    }
+    Some(outgoing as f64 / hcloud_lb.included_traffic as f64)
+}
+
+/// Estimate the monthly net cost of the load balancer.
+///
+/// The cost consists of the price of the balancer type in its location and
+/// the price of the traffic that exceeded the included quota so far.
+/// Prices are in the currency of the `HCloud` project.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn estimate_monthly_cost(hcloud_lb: &hcloud::models::LoadBalancer) -> Option<f64> {
+    /// Hetzner charges for the traffic per terabyte.
+    const TB: f64 = 1_000_000_000_000.0;
+
+    let price = hcloud_lb
+        .load_balancer_type
+        .prices
+        .iter()
+        .find(|price| price.location == hcloud_lb.location.name)?;
+    let base = price.price_monthly.net.parse::<f64>().ok()?;
+    let overage = hcloud_lb
+        .outgoing_traffic
+        .map_or(0, |outgoing| (outgoing - price.included_traffic).max(0));
+    let traffic = price
+        .price_per_tb_traffic
+        .net
+        .parse::<f64>()
+        .map_or(0.0, |per_tb| overage as f64 / TB * per_tb);
+    Some(base + traffic)
+}
+
+/// Guard that tracks a running reconcile.
+pub struct InFlightGuard {
+    gauge: IntGauge,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Metrics;
+
+    /// Value of the gauge as exported to Prometheus.
+    fn gauge(metrics: &Metrics, name: &str) -> f64 {
+        metrics
+            .registry
+            .gather()
+            .iter()
+            .find(|family| family.get_name() == name)
+            .map(|family| family.get_metric()[0].get_gauge().get_value())
+            .unwrap()
+    }
+
+    #[test]
+    fn exports_due_reconciles() {
+        let metrics = Metrics::new().unwrap().for_cluster("test");
+        metrics.reconcile_queued("default", "web", Duration::ZERO);
+        metrics.reconcile_queued("default", "api", Duration::ZERO);
+        metrics.reconcile_queued("default", "db", Duration::from_secs(600));
+        assert_eq!(metrics.reconciles_queued(), 2);
+        assert!((gauge(&metrics, "robotlb_reconciles_queued") - 2.0).abs() < f64::EPSILON);
+
+        let _in_flight = metrics.reconcile_started("default", "web");
+        metrics.reconcile_dequeued("default", "api");
+        assert_eq!(metrics.reconciles_queued(), 0);
+        assert!(gauge(&metrics, "robotlb_reconciles_queued").abs() < f64::EPSILON);
+    }
+}
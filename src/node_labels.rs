@@ -0,0 +1,82 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use k8s_openapi::{api::core::v1::Node, serde_json::json};
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    ResourceExt,
+};
+
+use crate::{consts, error::RobotLBResult, lb::list_managed, CurrentContext};
+
+/// Periodically label and annotate the nodes targeted by load balancers
+/// of the cluster with the names of the balancers.
+pub async fn run(context: Arc<CurrentContext>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = sync(&context).await {
+            tracing::warn!("Cannot update load balancer labels of nodes: {}", err);
+        }
+    }
+}
+
+async fn sync(context: &CurrentContext) -> RobotLBResult<()> {
+    // Balancers are listed from `HCloud`, so their targets are the actual ones,
+    // including balancers of ingresses and `HetznerLoadBalancer` resources.
+    let mut balancers_by_ip = BTreeMap::<String, BTreeSet<String>>::new();
+    for hcloud_lb in
+        list_managed(context.hcloud_api().as_ref(), &context.config.cluster_name).await?
+    {
+        for target in &hcloud_lb.targets {
+            if let Some(ip) = &target.ip {
+                balancers_by_ip
+                    .entry(ip.ip.clone())
+                    .or_default()
+                    .insert(hcloud_lb.name.clone());
+            }
+        }
+    }
+
+    let api = kube::Api::<Node>::all(context.client.clone());
+    for node in api.list(&ListParams::default()).await? {
+        let balancers = node
+            .status
+            .iter()
+            .flat_map(|status| status.addresses.iter().flatten())
+            .filter_map(|address| balancers_by_ip.get(&address.address))
+            .flatten()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let names = Some(balancers.into_iter().collect::<Vec<_>>().join(","))
+            .filter(|names| !names.is_empty());
+        if node.annotations().get(consts::NODE_LB_NAMES_ANN_NAME) == names.as_ref() {
+            continue;
+        }
+        tracing::info!(
+            "Load balancers targeting node {}: {}",
+            node.name_any(),
+            names.as_deref().unwrap_or("none")
+        );
+        // Labels and annotations are removed with nulls once no balancer targets the node.
+        api.patch(
+            &node.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({
+                "metadata": {
+                    "labels": {
+                        consts::NODE_LB_TARGET_LABEL_NAME: names.as_ref().map(|_| "true"),
+                    },
+                    "annotations": {
+                        consts::NODE_LB_NAMES_ANN_NAME: names,
+                    },
+                }
+            })),
+        )
+        .await?;
+    }
+    Ok(())
+}
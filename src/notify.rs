@@ -0,0 +1,87 @@
+use k8s_openapi::{api::core::v1::Service, serde_json::json};
+use kube::ResourceExt;
+
+/// Change of a load balancer worth notifying the platform team about.
+#[derive(Debug, Clone)]
+pub enum LBEvent {
+    Created { ip: Option<String> },
+    Deleted,
+    Resized { from: String, to: String },
+    IpChanged { from: Vec<String>, to: Vec<String> },
+}
+
+impl LBEvent {
+    const fn kind(&self) -> &'static str {
+        match self {
+            Self::Created { .. } => "created",
+            Self::Deleted => "deleted",
+            Self::Resized { .. } => "resized",
+            Self::IpChanged { .. } => "ip_changed",
+        }
+    }
+
+    fn describe(&self, lb_name: &str) -> String {
+        match self {
+            Self::Created { ip } => format!(
+                "Load balancer {lb_name} was created with IP {}",
+                ip.as_deref().unwrap_or("unknown")
+            ),
+            Self::Deleted => format!("Load balancer {lb_name} was deleted"),
+            Self::Resized { from, to } => {
+                format!("Load balancer {lb_name} was resized from {from} to {to}")
+            }
+            Self::IpChanged { from, to } => format!(
+                "IP of load balancer {lb_name} changed from {} to {}",
+                from.join(", "),
+                to.join(", ")
+            ),
+        }
+    }
+}
+
+/// Notifier which posts changes of load balancers to a webhook.
+///
+/// The payload has a `text` field, so it can be sent
+/// to Slack incoming webhooks as is.
+#[derive(Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    webhook_url: Option<String>,
+}
+
+impl Notifier {
+    #[must_use]
+    pub fn new(webhook_url: Option<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            webhook_url,
+        }
+    }
+
+    /// Send the notification about the event in background.
+    /// Failures are only logged, they never fail the reconcile.
+    pub fn notify(&self, svc: &Service, lb_name: &str, event: &LBEvent) {
+        let Some(url) = self.webhook_url.clone() else {
+            return;
+        };
+        let payload = json!({
+            "text": event.describe(lb_name),
+            "event": event.kind(),
+            "namespace": svc.namespace().unwrap_or_default(),
+            "service": svc.name_any(),
+            "lb_name": lb_name,
+        });
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let response = client
+                .post(url)
+                .json(&payload)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(err) = response {
+                tracing::warn!("Cannot send webhook notification: {}", err);
+            }
+        });
+    }
+}
@@ -0,0 +1,130 @@
+use std::{fmt::Debug, str::FromStr};
+
+use hcloud::apis::{
+    load_balancer_types_api::ListLoadBalancerTypesParams, locations_api::ListLocationsParams,
+    networks_api::ListNetworksParams,
+};
+
+use crate::{
+    error::{RobotLBError, RobotLBResult},
+    hcloud_span::traced,
+    lb::LBAlgorithm,
+    CurrentContext,
+};
+
+/// Check the token and the defaults against `HCloud` before the operator starts,
+/// so their problems aren't first reported as errors of every service.
+///
+/// Only definite problems fail the check. If the API is unreachable,
+/// the operator starts anyway and reports it through the readiness probe.
+pub async fn run(context: &CurrentContext) -> RobotLBResult<()> {
+    let config = &context.config;
+    let hcloud_config = context.hcloud_config();
+    let mut problems = vec![];
+
+    if LBAlgorithm::from_str(&config.default_lb_algorithm).is_err() {
+        problems.push(format!(
+            "default algorithm {} is unknown, expected round-robin or least-connections",
+            config.default_lb_algorithm
+        ));
+    }
+
+    let locations = match traced(
+        "list_locations",
+        None,
+        hcloud::apis::locations_api::list_locations(&hcloud_config, ListLocationsParams::default()),
+    )
+    .await
+    {
+        Ok(response) => response.locations,
+        Err(err) => return unless_unreachable(err, "list locations"),
+    };
+    if !locations
+        .iter()
+        .any(|location| location.name == config.default_lb_location)
+    {
+        problems.push(format!(
+            "default location {} doesn't exist, known locations are {}",
+            config.default_lb_location,
+            locations
+                .iter()
+                .map(|location| location.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let lb_types = match traced(
+        "list_load_balancer_types",
+        None,
+        hcloud::apis::load_balancer_types_api::list_load_balancer_types(
+            &hcloud_config,
+            ListLoadBalancerTypesParams::default(),
+        ),
+    )
+    .await
+    {
+        Ok(response) => response.load_balancer_types,
+        Err(err) => return unless_unreachable(err, "list load balancer types"),
+    };
+    if !lb_types
+        .iter()
+        .any(|lb_type| lb_type.name == config.default_balancer_type)
+    {
+        problems.push(format!(
+            "default load balancer type {} doesn't exist, known types are {}",
+            config.default_balancer_type,
+            lb_types
+                .iter()
+                .map(|lb_type| lb_type.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    if let Some(network) = &config.default_network {
+        let networks = match traced(
+            "list_networks",
+            None,
+            hcloud::apis::networks_api::list_networks(
+                &hcloud_config,
+                ListNetworksParams {
+                    name: Some(network.clone()),
+                    ..Default::default()
+                },
+            ),
+        )
+        .await
+        {
+            Ok(response) => response.networks,
+            Err(err) => return unless_unreachable(err, "list networks"),
+        };
+        if networks.is_empty() {
+            problems.push(format!("default network {network} doesn't exist"));
+        }
+    }
+
+    if problems.is_empty() {
+        tracing::info!("Preflight checks have passed");
+        return Ok(());
+    }
+    Err(RobotLBError::PreflightFailed(problems.join("; ")))
+}
+
+/// Fail if `HCloud` has rejected the request. Problems of the connection
+/// are only logged, since they may go away by themselves.
+fn unless_unreachable<T: Debug>(err: hcloud::apis::Error<T>, action: &str) -> RobotLBResult<()> {
+    let hcloud::apis::Error::ResponseError(response) = err else {
+        tracing::warn!(
+            "Preflight checks are skipped, HCloud API is unreachable: {}",
+            err
+        );
+        return Ok(());
+    };
+    let problem = match response.status.as_u16() {
+        401 => "HCloud token is invalid or was revoked".to_string(),
+        403 => format!("HCloud token has no permission to {action}"),
+        status => format!("HCloud API responded with status {status} when trying to {action}"),
+    };
+    Err(RobotLBError::PreflightFailed(problem))
+}
@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Service;
+
+use crate::{consts, lb};
+
+/// Add annotations of robotlb translated from the `service.beta.kubernetes.io/*`
+/// annotations of other cloud providers set on the service.
+///
+/// Manifests ported from them keep their proxy protocol and health check settings.
+/// Own robotlb annotations of the service take precedence
+/// over the translated ones, and they take precedence over the defaults.
+/// Annotations without an equivalent in robotlb are ignored.
+pub fn apply(svc: &Service, annotations: &mut BTreeMap<String, String>) {
+    let mut own = svc.metadata.annotations.clone().unwrap_or_default();
+    lb::resolve_aliases(&mut own);
+    for (name, value) in &own {
+        if !name.starts_with(consts::PROVIDER_ANNOTATION_PREFIX) {
+            continue;
+        }
+        let (robotlb_name, value) = match name.as_str() {
+            consts::AWS_PROXY_PROTOCOL_ANN_NAME if value == "*" => {
+                (consts::LB_PROXY_MODE_LABEL_NAME, "true".to_string())
+            }
+            consts::AWS_PROXY_PROTOCOL_ANN_NAME => {
+                tracing::warn!("{} is {}, only * is supported, ignoring", name, value);
+                continue;
+            }
+            consts::DO_ALGORITHM_ANN_NAME => {
+                (consts::LB_ALGORITHM_LABEL_NAME, value.replace('_', "-"))
+            }
+            _ => {
+                let Some((_, robotlb_name)) = consts::PROVIDER_ANNOTATIONS
+                    .iter()
+                    .find(|(provider_name, _)| provider_name == name)
+                else {
+                    continue;
+                };
+                (*robotlb_name, value.clone())
+            }
+        };
+        if !own.contains_key(robotlb_name) {
+            annotations.insert(robotlb_name.to_string(), value);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use k8s_openapi::api::core::v1::Service;
+use kube::{Client, ResourceExt};
+
+use crate::{error::RobotLBResult, events, metrics::included_traffic_usage};
+
+/// Monitor of the traffic included into the price of load balancers.
+///
+/// Hetzner bills the traffic that exceeds the included quota,
+/// so the monitor warns users when their balancers are close to it.
+#[derive(Clone)]
+pub struct TrafficQuotaMonitor {
+    /// Usage ratio after which the warning is emitted.
+    threshold: f64,
+    /// Services that were already warned about during
+    /// the current billing period.
+    warned: Arc<Mutex<HashSet<(String, String)>>>,
+}
+
+impl TrafficQuotaMonitor {
+    /// Create a new monitor which warns about balancers that
+    /// used more than `threshold_percent` percent of the included traffic.
+    #[must_use]
+    pub fn new(threshold_percent: f64) -> Self {
+        Self {
+            threshold: threshold_percent / 100.0,
+            warned: Arc::default(),
+        }
+    }
+
+    /// Check the traffic of the service's load balancer and emit a warning
+    /// event, if the usage has just crossed the threshold.
+    ///
+    /// The event is emitted only once, until the usage drops below the
+    /// threshold again, which happens when the next billing period starts.
+    pub async fn check(
+        &self,
+        client: Client,
+        svc: &Service,
+        hcloud_lb: &hcloud::models::LoadBalancer,
+    ) -> RobotLBResult<()> {
+        let Some(usage) = included_traffic_usage(hcloud_lb) else {
+            return Ok(());
+        };
+        let key = (svc.namespace().unwrap_or_default(), svc.name_any());
+        let just_exceeded = {
+            let mut warned = self.warned.lock().unwrap_or_else(PoisonError::into_inner);
+            if usage < self.threshold {
+                warned.remove(&key);
+                false
+            } else {
+                warned.insert(key)
+            }
+        };
+        if !just_exceeded {
+            return Ok(());
+        }
+        tracing::warn!(
+            "Load balancer {} used {:.1}% of its included traffic",
+            hcloud_lb.name,
+            usage * 100.0,
+        );
+        events::warn(
+            client,
+            svc,
+            "IncludedTrafficAlmostExceeded",
+            "CheckTraffic",
+            format!(
+                "Load balancer {} used {:.1}% of the included traffic for the current billing period. Traffic above the quota is billed separately.",
+                hcloud_lb.name,
+                usage * 100.0,
+            ),
+        )
+        .await
+    }
+
+    /// Forget about the service.
+    /// This is called when the load balancer was removed.
+    pub fn forget(&self, svc: &Service) {
+        self.warned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&(svc.namespace().unwrap_or_default(), svc.name_any()));
+    }
+}
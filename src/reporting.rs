@@ -0,0 +1,41 @@
+use k8s_openapi::api::core::v1::Service;
+use kube::ResourceExt;
+
+use crate::{config::OperatorConfig, consts, error::RobotLBError};
+
+/// Initialize Sentry error reporting, if the DSN is configured.
+///
+/// The returned guard flushes pending reports when dropped,
+/// so it must be kept alive until the operator exits.
+#[must_use]
+pub fn init(config: &OperatorConfig) -> Option<sentry::ClientInitGuard> {
+    let dsn = config.sentry_dsn.as_deref()?;
+    tracing::info!("Reporting errors to Sentry");
+    Some(sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            release: Some(format!("robotlb@{}", consts::VERSION).into()),
+            environment: config.sentry_environment.clone().map(Into::into),
+            ..Default::default()
+        },
+    )))
+}
+
+/// Report the reconcile error of the service to Sentry.
+/// Does nothing if Sentry is not initialized.
+pub fn capture(svc: &Service, error: &RobotLBError) {
+    let lb_name = svc
+        .annotations()
+        .get(consts::LB_NAME_LABEL_NAME)
+        .cloned()
+        .unwrap_or_else(|| svc.name_any());
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("namespace", svc.namespace().unwrap_or_default());
+            scope.set_tag("service", svc.name_any());
+            scope.set_tag("lb_name", lb_name);
+            scope.set_tag("error_class", format!("{:?}", error.class()));
+        },
+        || sentry::capture_error(error),
+    );
+}
@@ -0,0 +1,162 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use k8s_openapi::serde_json::{json, Value};
+use prometheus::{Encoder, TextEncoder};
+use rustls::{server::WebPkiClientVerifier, RootCertStore, ServerConfig};
+
+use crate::{
+    config::OperatorConfig,
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    CurrentContext,
+};
+
+/// Build TLS configuration of the metrics server,
+/// if TLS certificate and key are configured.
+pub fn metrics_tls_config(config: &OperatorConfig) -> RobotLBResult<Option<ServerConfig>> {
+    match (&config.metrics_tls_cert, &config.metrics_tls_key) {
+        (Some(cert), Some(key)) => Ok(Some(tls_config(
+            cert,
+            key,
+            config.metrics_tls_client_ca.as_deref(),
+        )?)),
+        (None, None) => Ok(None),
+        _ => Err(RobotLBError::InvalidTlsConfig(
+            "both certificate and key must be set".to_string(),
+        )),
+    }
+}
+
+/// Run HTTP server that exposes operator's metrics.
+///
+/// If `tls_config` is set, the server is served over HTTPS,
+/// optionally requiring client certificates.
+pub async fn run(
+    context: Arc<CurrentContext>,
+    tls_config: Option<ServerConfig>,
+) -> RobotLBResult<()> {
+    let config = &context.config;
+    let addr = config.metrics_bind_address;
+
+    let mut app = Router::new()
+        .route("/metrics", get(metrics))
+        .route("/version", get(version));
+    if config.debug_endpoint {
+        app = app.route("/debug/state", get(debug_state));
+    }
+    let app = app
+        .route_layer(middleware::from_fn_with_state(context.clone(), authorize))
+        .with_state(context.clone());
+
+    if let Some(tls_config) = tls_config {
+        tracing::info!("Serving metrics on {} over TLS", addr);
+        axum_server::bind_rustls(addr, RustlsConfig::from_config(Arc::new(tls_config)))
+            .serve(app.into_make_service())
+            .await?;
+    } else {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!("Serving metrics on {}", addr);
+        axum::serve(listener, app).await?;
+    }
+    Ok(())
+}
+
+/// Build TLS configuration of the server.
+/// If `client_ca` is set, clients must present a certificate signed by it.
+pub fn tls_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> RobotLBResult<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key =
+        rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?.ok_or_else(|| {
+            RobotLBError::InvalidTlsConfig(format!("no private key found in {}", key.display()))
+        })?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = if let Some(client_ca) = client_ca {
+        let mut roots = RootCertStore::empty();
+        for ca_cert in rustls_pemfile::certs(&mut BufReader::new(File::open(client_ca)?)) {
+            roots.add(ca_cert?)?;
+        }
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .map_err(|err| RobotLBError::InvalidTlsConfig(err.to_string()))?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut config = builder.with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Check the bearer token of the request, if the token is configured.
+async fn authorize(
+    State(context): State<Arc<CurrentContext>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = &context.config.metrics_bearer_token else {
+        return next.run(request).await;
+    };
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+/// Compare two byte strings in time that doesn't depend on their contents,
+/// so the token cannot be guessed by measuring response times.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Render all metrics in Prometheus text format.
+async fn metrics(State(context): State<Arc<CurrentContext>>) -> impl IntoResponse {
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    context.metrics.refresh();
+    if let Err(err) = encoder.encode(&context.metrics.registry.gather(), &mut buffer) {
+        tracing::error!("Cannot encode metrics: {}", err);
+    }
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+}
+
+/// Operator's view of managed services: desired configuration
+/// of load balancers, their state in `HCloud` and results of last reconciles.
+async fn debug_state(State(context): State<Arc<CurrentContext>>) -> impl IntoResponse {
+    Json(context.state.snapshot())
+}
+
+/// Information about the build of the running operator.
+async fn version() -> Json<Value> {
+    Json(json!({
+        "version": consts::VERSION,
+        "git_sha": consts::GIT_SHA,
+        "rustc": consts::RUSTC_VERSION,
+    }))
+}
@@ -0,0 +1,83 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Service;
+use kube::{api::ListParams, ResourceExt};
+
+use crate::{
+    consts,
+    error::{RobotLBError, RobotLBResult},
+    is_managed, lb, reconcile_service, CurrentContext,
+};
+
+/// Reconcile services of the load balancers managed in `HCloud` before the controller starts.
+///
+/// Balancers changed or broken while the operator was down are fixed first,
+/// rather than whenever the controller gets to their services.
+///
+/// Balancers whose services are gone are only reported,
+/// they are deleted by the `cleanup-orphans` command.
+pub async fn resync(context: &Arc<CurrentContext>) -> RobotLBResult<()> {
+    let hcloud_lbs =
+        lb::list_managed(context.hcloud_api().as_ref(), &context.config.cluster_name).await?;
+    let services = kube::Api::<Service>::all(context.client.clone())
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .map(|svc| ((svc.namespace().unwrap_or_default(), svc.name_any()), svc))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut owners = BTreeMap::new();
+    for hcloud_lb in &hcloud_lbs {
+        let label = |name| hcloud_lb.labels.get(name).cloned().unwrap_or_default();
+        let owner = (
+            label(consts::LB_NAMESPACE_LABEL_NAME),
+            label(consts::LB_SERVICE_LABEL_NAME),
+        );
+        // Balancers of `HetznerLoadBalancer` resources are synced by their own controller.
+        if owner.0.is_empty()
+            || owner.1.is_empty()
+            || hcloud_lb
+                .labels
+                .contains_key(consts::LB_RESOURCE_LABEL_NAME)
+        {
+            continue;
+        }
+        match services.get(&owner) {
+            Some(svc) if is_managed(svc, &context.config) => {
+                owners.insert(owner, Arc::new(svc.clone()));
+            }
+            _ => tracing::warn!(
+                "Load balancer {} belongs to service {}/{}, which is not managed anymore. \
+                Run `robotlb cleanup-orphans` to delete it",
+                hcloud_lb.name,
+                owner.0,
+                owner.1,
+            ),
+        }
+    }
+
+    tracing::info!(
+        "Resyncing {} services of {} load balancers",
+        owners.len(),
+        hcloud_lbs.len()
+    );
+    let limit = usize::from(context.config.max_concurrent_reconciles);
+    futures::stream::iter(owners)
+        .for_each_concurrent(
+            (limit > 0).then_some(limit),
+            |((namespace, name), svc)| async move {
+                match reconcile_service(svc, context.clone()).await {
+                    Ok(_) | Err(RobotLBError::SkipService) => {}
+                    // The controller retries the service once it starts.
+                    Err(err) => {
+                        tracing::warn!("Cannot resync service {}/{}: {}", namespace, name, err);
+                    }
+                }
+                // The sweep might take a while with many balancers.
+                context.health.heartbeat();
+            },
+        )
+        .await;
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::{api::core::v1::Service, chrono::Utc};
+use kube::ResourceExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::RobotLBError,
+    lb::{LBChange, LoadBalancer},
+};
+
+/// Operator's view of the managed services.
+///
+/// It's only used for debugging, to answer questions like
+/// "why didn't it update my LB". See `/debug/state` endpoint.
+#[derive(Clone, Default)]
+pub struct StateStore {
+    /// Cluster of the services recorded by this instance.
+    cluster: String,
+    services: Arc<Mutex<BTreeMap<String, ServiceState>>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServiceState {
+    /// Desired configuration of the load balancer resolved
+    /// from the service, its annotations and the operator's defaults.
+    pub desired: Option<DesiredSpec>,
+    /// The load balancer as it was in `HCloud` during the last reconcile.
+    pub hcloud_lb: Option<hcloud::models::LoadBalancer>,
+    pub last_reconcile: Option<ReconcileResult>,
+    /// Changes the load balancer has drifted by, reported in observe mode.
+    pub drift: Option<Vec<String>>,
+    /// Targets skipped by the last reconcile, with the reasons.
+    pub invalid_targets: Vec<String>,
+    /// When the load balancer was last found matching the desired state
+    /// in `HCloud`, and the version of the service it was found for.
+    #[serde(skip)]
+    pub converged: Option<(Instant, Option<String>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DesiredSpec {
+    pub lb_name: String,
+    pub location: String,
+    pub balancer_type: String,
+    pub algorithm: hcloud::models::LoadBalancerAlgorithm,
+    pub network_name: Option<String>,
+    pub private_ip: Option<String>,
+    pub proxy_mode: bool,
+    pub check_interval: i32,
+    pub timeout: i32,
+    pub retries: i32,
+    /// Listen ports mapped to target ports.
+    pub services: HashMap<i32, i32>,
+    pub targets: Vec<String>,
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileResult {
+    /// Time when the reconcile finished in RFC 3339 format.
+    pub finished_at: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl From<&LoadBalancer> for DesiredSpec {
+    fn from(lb: &LoadBalancer) -> Self {
+        Self {
+            lb_name: lb.name.clone(),
+            location: lb.location.clone(),
+            balancer_type: lb.balancer_type.clone(),
+            algorithm: lb.algorithm.clone(),
+            network_name: lb.network_name.clone(),
+            private_ip: lb.private_ip.clone(),
+            proxy_mode: lb.proxy_mode,
+            check_interval: lb.check_interval,
+            timeout: lb.timeout,
+            retries: lb.retries,
+            services: lb.services.clone(),
+            targets: lb.targets.clone(),
+            labels: lb.labels.clone(),
+        }
+    }
+}
+
+impl StateStore {
+    /// Store of services of the cluster, sharing the state with this one.
+    #[must_use]
+    pub fn for_cluster(&self, cluster: &str) -> Self {
+        Self {
+            cluster: cluster.to_string(),
+            services: self.services.clone(),
+        }
+    }
+
+    /// Record the desired state of the service's load balancer
+    /// and its state in `HCloud`.
+    pub fn record_lb(
+        &self,
+        svc: &Service,
+        lb: &LoadBalancer,
+        hcloud_lb: &hcloud::models::LoadBalancer,
+    ) {
+        self.update(svc, |state| {
+            state.desired = Some(lb.into());
+            state.hcloud_lb = Some(hcloud_lb.clone());
+        });
+    }
+
+    /// Record the result of the service's reconcile.
+    /// Returns whether the error differs from the previously recorded one.
+    pub fn record_result(&self, svc: &Service, error: Option<&RobotLBError>) -> bool {
+        let result = ReconcileResult {
+            finished_at: Utc::now().to_rfc3339(),
+            success: error.is_none(),
+            error: error.map(ToString::to_string),
+        };
+        let mut differs = false;
+        self.update(svc, |state| {
+            if error.is_some() {
+                state.converged = None;
+            }
+            differs = state
+                .last_reconcile
+                .as_ref()
+                .is_none_or(|last| last.error != result.error);
+            state.last_reconcile = Some(result);
+        });
+        differs
+    }
+
+    /// Record the drift of the service's load balancer from the desired state.
+    /// Returns whether the drift differs from the previously recorded one.
+    pub fn record_drift(&self, svc: &Service, changes: &[LBChange]) -> bool {
+        let drift = changes.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let mut differs = false;
+        self.update(svc, |state| {
+            differs = state.drift.as_ref() != Some(&drift);
+            state.drift = Some(drift);
+        });
+        differs
+    }
+
+    /// Record whether the reconcile has found the load balancer of the service
+    /// matching the desired state, so nothing had to be changed.
+    pub fn record_converged(&self, svc: &Service, converged: bool) {
+        self.update(svc, |state| {
+            state.converged = converged.then(|| (Instant::now(), svc.resource_version()));
+        });
+    }
+
+    /// Whether the load balancer of the service was found matching the desired
+    /// state within the `max_age`, and neither the service nor the desired state
+    /// have changed since. Such balancers don't need to be fetched from `HCloud`.
+    #[must_use]
+    pub fn is_converged(&self, svc: &Service, lb: &LoadBalancer, max_age: Duration) -> bool {
+        let desired = DesiredSpec::from(lb);
+        self.services
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&self.key(svc))
+            .is_some_and(|state| {
+                state
+                    .converged
+                    .as_ref()
+                    .is_some_and(|(checked_at, version)| {
+                        checked_at.elapsed() < max_age && *version == svc.resource_version()
+                    })
+                    && state.desired.as_ref() == Some(&desired)
+            })
+    }
+
+    /// Record the targets skipped by the reconcile of the service.
+    /// Returns whether they differ from the previously recorded ones.
+    #[must_use]
+    pub fn record_invalid_targets(&self, svc: &Service, invalid_targets: &[String]) -> bool {
+        let mut differs = false;
+        self.update(svc, |state| {
+            differs = state.invalid_targets != invalid_targets;
+            state.invalid_targets = invalid_targets.to_vec();
+        });
+        differs
+    }
+
+    /// Forget the service after it was deleted.
+    pub fn forget(&self, svc: &Service) {
+        self.services
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key(svc));
+    }
+
+    /// Get a copy of the state of all services keyed by `cluster/namespace/name`.
+    #[must_use]
+    pub fn snapshot(&self) -> BTreeMap<String, ServiceState> {
+        self.services
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn update(&self, svc: &Service, update: impl FnOnce(&mut ServiceState)) {
+        let mut services = self.services.lock().unwrap_or_else(PoisonError::into_inner);
+        update(services.entry(self.key(svc)).or_default());
+    }
+
+    fn key(&self, svc: &Service) -> String {
+        format!(
+            "{}/{}/{}",
+            self.cluster,
+            svc.namespace().unwrap_or_default(),
+            svc.name_any()
+        )
+    }
+}
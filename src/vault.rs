@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::serde_json::json;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    config::{VaultAuthMethod, VaultConfig},
+    error::{RobotLBError, RobotLBResult},
+};
+
+/// Token of the pod's service account, used for Kubernetes auth.
+const SERVICE_ACCOUNT_TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// Client reading the `HCloud` token from a KV v2 secret in `HashiCorp` Vault.
+///
+/// The client token is renewed while its lease allows it.
+/// Once it can't be renewed anymore, the client logs in again.
+pub struct Vault {
+    http: reqwest::Client,
+    address: String,
+    auth: VaultAuth,
+    auth_mount: String,
+    kv_mount: String,
+    secret_path: String,
+    secret_key: String,
+    session: Option<Session>,
+}
+
+enum VaultAuth {
+    Kubernetes { role: String },
+    AppRole { role_id: String, secret_id: String },
+}
+
+/// Client token with its lease.
+struct Session {
+    token: String,
+    renewable: bool,
+    lease: Duration,
+    obtained_at: Instant,
+}
+
+impl Session {
+    /// Whether the token should be renewed. Tokens without a lease never expire.
+    fn expiring(&self) -> bool {
+        !self.lease.is_zero() && self.obtained_at.elapsed() > self.lease * 2 / 3
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    auth: Auth,
+}
+
+#[derive(Deserialize)]
+struct Auth {
+    client_token: String,
+    lease_duration: u64,
+    renewable: bool,
+}
+
+impl From<Auth> for Session {
+    fn from(auth: Auth) -> Self {
+        Self {
+            token: auth.client_token,
+            renewable: auth.renewable,
+            lease: Duration::from_secs(auth.lease_duration),
+            obtained_at: Instant::now(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SecretResponse {
+    data: SecretData,
+}
+
+#[derive(Deserialize)]
+struct SecretData {
+    data: HashMap<String, String>,
+}
+
+impl Vault {
+    /// Create a client of Vault at the `address`.
+    /// Credentials required by the auth method must be configured.
+    pub fn new(address: &str, config: &VaultConfig) -> RobotLBResult<Self> {
+        let missing = |option: &str| {
+            RobotLBError::VaultError(format!(
+                "--{option} is required for {} auth",
+                config.vault_auth.mount()
+            ))
+        };
+        let auth = match config.vault_auth {
+            VaultAuthMethod::Kubernetes => VaultAuth::Kubernetes {
+                role: config
+                    .vault_role
+                    .clone()
+                    .ok_or_else(|| missing("vault-role"))?,
+            },
+            VaultAuthMethod::AppRole => VaultAuth::AppRole {
+                role_id: config
+                    .vault_role_id
+                    .clone()
+                    .ok_or_else(|| missing("vault-role-id"))?,
+                secret_id: config
+                    .vault_secret_id
+                    .clone()
+                    .ok_or_else(|| missing("vault-secret-id"))?,
+            },
+        };
+        Ok(Self {
+            http: reqwest::Client::new(),
+            address: address.trim_end_matches('/').to_string(),
+            auth,
+            auth_mount: config
+                .vault_auth_mount
+                .clone()
+                .unwrap_or_else(|| config.vault_auth.mount().to_string()),
+            kv_mount: config.vault_kv_mount.clone(),
+            secret_path: config.vault_secret_path.clone(),
+            secret_key: config.vault_secret_key.clone(),
+            session: None,
+        })
+    }
+
+    /// Read the `HCloud` token from the secret.
+    pub async fn token(&mut self) -> RobotLBResult<String> {
+        let client_token = self.client_token().await?;
+        let url = format!(
+            "{}/v1/{}/data/{}",
+            self.address, self.kv_mount, self.secret_path
+        );
+        let response: SecretResponse =
+            send(self.http.get(url).header("X-Vault-Token", client_token)).await?;
+        response
+            .data
+            .data
+            .get(&self.secret_key)
+            .map(|token| token.trim().to_string())
+            .ok_or_else(|| {
+                RobotLBError::VaultError(format!(
+                    "secret {}/{} has no key {}",
+                    self.kv_mount, self.secret_path, self.secret_key
+                ))
+            })
+    }
+
+    /// Token of the current session. It's renewed if it's about to expire,
+    /// or replaced with a new one if it can't be renewed.
+    async fn client_token(&mut self) -> RobotLBResult<String> {
+        if let Some(session) = &self.session {
+            if !session.expiring() {
+                return Ok(session.token.clone());
+            }
+            if session.renewable {
+                let token = session.token.clone();
+                match self.renew(&token).await {
+                    Ok(renewed) => return Ok(self.session.insert(renewed).token.clone()),
+                    Err(err) => tracing::warn!("Cannot renew Vault token: {}", err),
+                }
+            }
+        }
+        let session = self.login().await?;
+        tracing::info!("Logged in to Vault");
+        Ok(self.session.insert(session).token.clone())
+    }
+
+    async fn login(&self) -> RobotLBResult<Session> {
+        let body = match &self.auth {
+            VaultAuth::Kubernetes { role } => json!({
+                "role": role,
+                "jwt": std::fs::read_to_string(SERVICE_ACCOUNT_TOKEN_PATH)?.trim(),
+            }),
+            VaultAuth::AppRole { role_id, secret_id } => json!({
+                "role_id": role_id,
+                "secret_id": secret_id,
+            }),
+        };
+        let url = format!("{}/v1/auth/{}/login", self.address, self.auth_mount);
+        let response: AuthResponse = send(self.http.post(url).json(&body)).await?;
+        Ok(response.auth.into())
+    }
+
+    async fn renew(&self, token: &str) -> RobotLBResult<Session> {
+        let url = format!("{}/v1/auth/token/renew-self", self.address);
+        let response: AuthResponse =
+            send(self.http.post(url).header("X-Vault-Token", token)).await?;
+        Ok(response.auth.into())
+    }
+}
+
+async fn send<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> RobotLBResult<T> {
+    request
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|err| RobotLBError::VaultError(err.to_string()))?
+        .json()
+        .await
+        .map_err(|err| RobotLBError::VaultError(err.to_string()))
+}
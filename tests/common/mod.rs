@@ -0,0 +1,603 @@
+//! Fake of the `HCloud` REST API for integration tests.
+//!
+//! Load balancers and networks are kept in memory and served over HTTP,
+//! so the reconcile logic runs with the real hcloud client,
+//! without touching real infrastructure.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use hcloud::{
+    apis::configuration::Configuration,
+    models::{
+        self, action, load_balancer_target, AttachLoadBalancerToNetworkRequest,
+        ChangeTypeOfLoadBalancerRequest, CreateLoadBalancerRequest, DeleteServiceRequest,
+        LoadBalancer, LoadBalancerAddTarget, LoadBalancerAlgorithm, LoadBalancerPrivateNet,
+        LoadBalancerService, LoadBalancerType, Location, Network, RemoveTargetRequest,
+        ReplaceLoadBalancerRequest, UpdateLoadBalancerService,
+    },
+};
+use k8s_openapi::serde_json::{self, json, Value};
+use serde::de::DeserializeOwned;
+
+const TIMESTAMP: &str = "2024-01-01T00:00:00+00:00";
+
+/// Running fake of the `HCloud` API.
+#[derive(Clone)]
+pub struct FakeHcloud {
+    address: SocketAddr,
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    load_balancers: Vec<LoadBalancer>,
+    networks: Vec<Network>,
+    /// Names of the called hcloud API functions, in order.
+    calls: Vec<String>,
+    /// Statuses returned instead of calling the function with the name.
+    failures: HashMap<String, StatusCode>,
+    /// Actions started by the calls, with their commands and statuses.
+    actions: Vec<(String, action::Status)>,
+    /// Whether new actions keep running until they are finished explicitly.
+    hold_actions: bool,
+    last_id: i64,
+}
+
+/// Status and body of a failed call.
+type Failure = (StatusCode, Json<Value>);
+
+type FakeResult = Result<Json<Value>, Failure>;
+
+impl FakeHcloud {
+    /// Start the fake on a random local port.
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(FakeState::default()));
+        let app = Router::new()
+            .route(
+                "/load_balancers",
+                get(list_load_balancers).post(create_load_balancer),
+            )
+            .route(
+                "/load_balancers/:id",
+                get(get_load_balancer)
+                    .put(replace_load_balancer)
+                    .delete(delete_load_balancer),
+            )
+            .route("/load_balancers/:id/actions/:action", post(run_action))
+            .route("/load_balancers/actions/:id", get(get_load_balancer_action))
+            .route("/load_balancer_types", get(list_load_balancer_types))
+            .route("/locations", get(list_locations))
+            .route("/networks", get(list_networks))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind fake hcloud");
+        let address = listener.local_addr().expect("address of fake hcloud");
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self { address, state }
+    }
+
+    /// Configuration of the hcloud client calling the fake.
+    pub fn config(&self) -> Configuration {
+        let mut config = Configuration::new();
+        config.base_path = format!("http://{}", self.address);
+        config.bearer_access_token = Some("fake-token".to_string());
+        config
+    }
+
+    /// Address the fake listens on.
+    pub const fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Add a network with the name, returning its ID.
+    pub fn add_network(&self, name: &str) -> i64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        state.networks.push(Network {
+            created: TIMESTAMP.to_string(),
+            expose_routes_to_vswitch: false,
+            id,
+            ip_range: "10.0.0.0/16".to_string(),
+            labels: HashMap::new(),
+            load_balancers: Some(vec![]),
+            name: name.to_string(),
+            protection: Box::new(models::Protection::new(false)),
+            routes: vec![],
+            servers: vec![],
+            subnets: vec![],
+        });
+        id
+    }
+
+    /// Add a load balancer, e.g. one created by hand or by another service.
+    pub fn add_load_balancer(&self, name: &str, labels: HashMap<String, String>) -> i64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        let load_balancer = new_load_balancer(id, name, labels, "lb11", "hel1");
+        state.load_balancers.push(load_balancer);
+        id
+    }
+
+    /// Current state of the load balancer with the name.
+    pub fn load_balancer(&self, name: &str) -> Option<LoadBalancer> {
+        self.state
+            .lock()
+            .unwrap()
+            .load_balancers
+            .iter()
+            .find(|lb| lb.name == name)
+            .cloned()
+    }
+
+    pub fn load_balancers(&self) -> Vec<LoadBalancer> {
+        self.state.lock().unwrap().load_balancers.clone()
+    }
+
+    /// Names of the hcloud API functions called since the last take.
+    pub fn take_calls(&self) -> Vec<String> {
+        std::mem::take(&mut self.state.lock().unwrap().calls)
+    }
+
+    /// Calls that change anything in `HCloud`, since the last take.
+    pub fn take_mutations(&self) -> Vec<String> {
+        self.take_calls()
+            .into_iter()
+            .filter(|call| !call.starts_with("list_") && !call.starts_with("get_"))
+            .collect()
+    }
+
+    /// Keep new actions running, or finish all of them successfully.
+    pub fn hold_actions(&self, hold: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.hold_actions = hold;
+        if !hold {
+            for (_, status) in &mut state.actions {
+                *status = action::Status::Success;
+            }
+        }
+    }
+
+    /// Respond with the status to calls of the hcloud API function.
+    pub fn fail(&self, call: &str, status: StatusCode) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .insert(call.to_string(), status);
+    }
+}
+
+impl FakeState {
+    const fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// Record the call, failing it if a failure is set for it.
+    fn call(&mut self, name: &str) -> Result<(), Failure> {
+        self.calls.push(name.to_string());
+        match self.failures.get(name) {
+            Some(status) => Err(error(*status, "injected_failure", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Start an action, which finishes at once unless actions are held.
+    fn start_action(&mut self, command: &str) -> Value {
+        let status = if self.hold_actions {
+            action::Status::Running
+        } else {
+            action::Status::Success
+        };
+        self.actions.push((command.to_string(), status));
+        action(self.actions.len() as i64, command, status)
+    }
+
+    fn load_balancer(&mut self, id: i64) -> Result<&mut LoadBalancer, Failure> {
+        self.load_balancers
+            .iter_mut()
+            .find(|lb| lb.id == id)
+            .ok_or_else(|| {
+                error(
+                    StatusCode::NOT_FOUND,
+                    "not_found",
+                    "load balancer not found",
+                )
+            })
+    }
+}
+
+fn error(status: StatusCode, code: &str, message: &str) -> Failure {
+    (
+        status,
+        Json(json!({"error": {"code": code, "message": message, "details": {}}})),
+    )
+}
+
+fn parse<T: DeserializeOwned>(body: Value) -> Result<T, Failure> {
+    serde_json::from_value(body)
+        .map_err(|err| error(StatusCode::BAD_REQUEST, "invalid_input", &err.to_string()))
+}
+
+fn meta() -> Value {
+    json!({"pagination": {"page": 1, "per_page": 50, "next_page": null, "previous_page": null, "last_page": 1, "total_entries": null}})
+}
+
+fn action(id: i64, command: &str, status: action::Status) -> Value {
+    let finished = status != action::Status::Running;
+    json!({
+        "id": id,
+        "command": command,
+        "status": status,
+        "progress": if finished { 100 } else { 0 },
+        "started": TIMESTAMP,
+        "finished": finished.then_some(TIMESTAMP),
+        "resources": [],
+        "error": null,
+    })
+}
+
+fn load_balancer_types() -> Vec<LoadBalancerType> {
+    [
+        ("lb11", 1, 5, 25),
+        ("lb21", 2, 15, 75),
+        ("lb31", 3, 30, 150),
+    ]
+    .into_iter()
+    .map(|(name, id, max_services, max_targets)| LoadBalancerType {
+        deprecated: None,
+        description: name.to_uppercase(),
+        id,
+        max_assigned_certificates: 10,
+        max_connections: 10_000,
+        max_services,
+        max_targets,
+        name: name.to_string(),
+        prices: vec![],
+    })
+    .collect()
+}
+
+fn locations() -> Vec<Location> {
+    [("hel1", 1, "Helsinki"), ("fsn1", 2, "Falkenstein")]
+        .into_iter()
+        .map(|(name, id, city)| Location {
+            city: city.to_string(),
+            country: "FI".to_string(),
+            description: name.to_string(),
+            id,
+            latitude: 0.0,
+            longitude: 0.0,
+            name: name.to_string(),
+            network_zone: "eu-central".to_string(),
+        })
+        .collect()
+}
+
+fn new_load_balancer(
+    id: i64,
+    name: &str,
+    labels: HashMap<String, String>,
+    lb_type: &str,
+    location: &str,
+) -> LoadBalancer {
+    let lb_type = load_balancer_types()
+        .into_iter()
+        .find(|known| known.name == lb_type)
+        .expect("known load balancer type");
+    let location = locations()
+        .into_iter()
+        .find(|known| known.name == location)
+        .expect("known location");
+    LoadBalancer {
+        algorithm: Box::new(LoadBalancerAlgorithm::default()),
+        created: TIMESTAMP.to_string(),
+        id,
+        included_traffic: 0,
+        ingoing_traffic: None,
+        labels,
+        load_balancer_type: Box::new(lb_type),
+        location: Box::new(location),
+        name: name.to_string(),
+        outgoing_traffic: None,
+        private_net: vec![],
+        protection: Box::new(models::Protection::new(false)),
+        public_net: Box::new(models::LoadBalancerPublicNet {
+            enabled: true,
+            ipv4: Box::new(models::LoadBalancerPublicNetIpv4 {
+                dns_ptr: None,
+                ip: Some(Some(format!("203.0.113.{id}"))),
+            }),
+            ipv6: Box::new(models::LoadBalancerPublicNetIpv6 {
+                dns_ptr: None,
+                ip: Some(Some(format!("2001:db8::{id}"))),
+            }),
+        }),
+        services: vec![],
+        targets: vec![],
+    }
+}
+
+/// Whether the labels match the selector of `key=value` pairs.
+fn matches_selector(labels: &HashMap<String, String>, selector: &str) -> bool {
+    selector.split(',').all(|requirement| {
+        requirement
+            .split_once('=')
+            .is_some_and(|(key, value)| labels.get(key).map(String::as_str) == Some(value))
+    })
+}
+
+type SharedState = State<Arc<Mutex<FakeState>>>;
+
+async fn list_load_balancers(
+    State(state): SharedState,
+    Query(query): Query<HashMap<String, String>>,
+) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    state.call("list_load_balancers")?;
+    let load_balancers = state
+        .load_balancers
+        .iter()
+        .filter(|lb| query.get("name").is_none_or(|name| lb.name == *name))
+        .filter(|lb| {
+            query
+                .get("label_selector")
+                .is_none_or(|selector| matches_selector(&lb.labels, selector))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    Ok(Json(
+        json!({"load_balancers": load_balancers, "meta": meta()}),
+    ))
+}
+
+async fn get_load_balancer(State(state): SharedState, Path(id): Path<i64>) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    state.call("get_load_balancer")?;
+    let load_balancer = state.load_balancer(id)?.clone();
+    Ok(Json(json!({"load_balancer": load_balancer})))
+}
+
+async fn create_load_balancer(State(state): SharedState, Json(body): Json<Value>) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    state.call("create_load_balancer")?;
+    let request: CreateLoadBalancerRequest = parse(body)?;
+    if state
+        .load_balancers
+        .iter()
+        .any(|lb| lb.name == request.name)
+    {
+        return Err(error(
+            StatusCode::CONFLICT,
+            "uniqueness_error",
+            "name is already used",
+        ));
+    }
+    let id = state.next_id();
+    let mut load_balancer = new_load_balancer(
+        id,
+        &request.name,
+        request.labels.unwrap_or_default(),
+        &request.load_balancer_type,
+        request.location.as_deref().unwrap_or("hel1"),
+    );
+    if let Some(algorithm) = request.algorithm {
+        load_balancer.algorithm = algorithm;
+    }
+    state.load_balancers.push(load_balancer.clone());
+    Ok(Json(json!({
+        "load_balancer": load_balancer,
+        "action": state.start_action("create_load_balancer"),
+    })))
+}
+
+async fn replace_load_balancer(
+    State(state): SharedState,
+    Path(id): Path<i64>,
+    Json(body): Json<Value>,
+) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    state.call("replace_load_balancer")?;
+    let request: ReplaceLoadBalancerRequest = parse(body)?;
+    let load_balancer = state.load_balancer(id)?;
+    if let Some(labels) = request.labels {
+        load_balancer.labels = labels;
+    }
+    if let Some(name) = request.name {
+        load_balancer.name = name;
+    }
+    Ok(Json(json!({"load_balancer": load_balancer})))
+}
+
+async fn delete_load_balancer(
+    State(state): SharedState,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, Failure> {
+    let mut state = state.lock().unwrap();
+    state.call("delete_load_balancer")?;
+    state.load_balancer(id)?;
+    state.load_balancers.retain(|lb| lb.id != id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn run_action(
+    State(state): SharedState,
+    Path((id, name)): Path<(i64, String)>,
+    Json(body): Json<Value>,
+) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    let (call, command) = match name.as_str() {
+        "change_type" => ("change_type_of_load_balancer", "change_load_balancer_type"),
+        "attach_to_network" => (
+            "attach_load_balancer_to_network",
+            "attach_load_balancer_to_network",
+        ),
+        "detach_from_network" => (
+            "detach_load_balancer_from_network",
+            "detach_load_balancer_from_network",
+        ),
+        other => (other, other),
+    };
+    state.call(call)?;
+    let networks = state
+        .networks
+        .iter()
+        .map(|network| network.id)
+        .collect::<Vec<_>>();
+    let load_balancer = state.load_balancer(id)?;
+    match name.as_str() {
+        "add_service" => {
+            let service: LoadBalancerService = parse(body)?;
+            if load_balancer
+                .services
+                .iter()
+                .any(|existing| existing.listen_port == service.listen_port)
+            {
+                return Err(error(
+                    StatusCode::CONFLICT,
+                    "source_port_already_used",
+                    "listen port is already used",
+                ));
+            }
+            load_balancer.services.push(service);
+        }
+        "update_service" => {
+            let update: UpdateLoadBalancerService = parse(body)?;
+            let service = load_balancer
+                .services
+                .iter_mut()
+                .find(|service| service.listen_port == update.listen_port)
+                .ok_or_else(|| error(StatusCode::NOT_FOUND, "not_found", "service not found"))?;
+            if let Some(destination_port) = update.destination_port {
+                service.destination_port = destination_port;
+            }
+            if let Some(proxyprotocol) = update.proxyprotocol {
+                service.proxyprotocol = proxyprotocol;
+            }
+            service.http = update.http;
+            if let Some(health_check) = update.health_check {
+                let current = &mut service.health_check;
+                current.interval = health_check.interval.unwrap_or(current.interval);
+                current.port = health_check.port.unwrap_or(current.port);
+                current.retries = health_check.retries.unwrap_or(current.retries);
+                current.timeout = health_check.timeout.unwrap_or(current.timeout);
+            }
+        }
+        "delete_service" => {
+            let request: DeleteServiceRequest = parse(body)?;
+            load_balancer
+                .services
+                .retain(|service| service.listen_port != request.listen_port);
+        }
+        "add_target" => {
+            let request: LoadBalancerAddTarget = parse(body)?;
+            let mut target = models::LoadBalancerTarget::new(load_balancer_target::Type::Ip);
+            target.ip = request.ip;
+            load_balancer.targets.push(target);
+        }
+        "remove_target" => {
+            let request: RemoveTargetRequest = parse(body)?;
+            let ip = request.ip.map(|ip| ip.ip);
+            load_balancer
+                .targets
+                .retain(|target| target.ip.as_ref().map(|target_ip| &target_ip.ip) != ip.as_ref());
+        }
+        "change_algorithm" => {
+            *load_balancer.algorithm = parse(body)?;
+        }
+        "change_type" => {
+            let request: ChangeTypeOfLoadBalancerRequest = parse(body)?;
+            let lb_type = load_balancer_types()
+                .into_iter()
+                .find(|known| known.name == request.load_balancer_type)
+                .ok_or_else(|| {
+                    error(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_input",
+                        "unknown load balancer type",
+                    )
+                })?;
+            *load_balancer.load_balancer_type = lb_type;
+        }
+        "attach_to_network" => {
+            let request: AttachLoadBalancerToNetworkRequest = parse(body)?;
+            if !networks.contains(&request.network) {
+                return Err(error(
+                    StatusCode::NOT_FOUND,
+                    "not_found",
+                    "network not found",
+                ));
+            }
+            let ip = request
+                .ip
+                .unwrap_or_else(|| format!("10.0.0.{}", 100 + load_balancer.id));
+            load_balancer.private_net.push(LoadBalancerPrivateNet {
+                ip: Some(ip),
+                network: Some(request.network),
+            });
+        }
+        "detach_from_network" => {
+            let request: models::DetachLoadBalancerFromNetworkRequest = parse(body)?;
+            load_balancer
+                .private_net
+                .retain(|private_net| private_net.network != Some(request.network));
+        }
+        _ => {
+            return Err(error(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "action not supported by the fake",
+            ))
+        }
+    }
+    Ok(Json(json!({"action": state.start_action(command)})))
+}
+
+async fn get_load_balancer_action(State(state): SharedState, Path(id): Path<i64>) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    state.call("get_load_balancer_action")?;
+    let (command, status) = usize::try_from(id - 1)
+        .ok()
+        .and_then(|index| state.actions.get(index))
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "not_found", "action not found"))?;
+    Ok(Json(json!({"action": action(id, command, *status)})))
+}
+
+async fn list_load_balancer_types(State(state): SharedState) -> FakeResult {
+    state.lock().unwrap().call("list_load_balancer_types")?;
+    Ok(Json(
+        json!({"load_balancer_types": load_balancer_types(), "meta": meta()}),
+    ))
+}
+
+async fn list_locations(State(state): SharedState) -> FakeResult {
+    state.lock().unwrap().call("list_locations")?;
+    Ok(Json(json!({"locations": locations(), "meta": meta()})))
+}
+
+async fn list_networks(
+    State(state): SharedState,
+    Query(query): Query<HashMap<String, String>>,
+) -> FakeResult {
+    let mut state = state.lock().unwrap();
+    state.call("list_networks")?;
+    let networks = state
+        .networks
+        .iter()
+        .filter(|network| query.get("name").is_none_or(|name| network.name == *name))
+        .cloned()
+        .collect::<Vec<_>>();
+    Ok(Json(json!({"networks": networks, "meta": meta()})))
+}
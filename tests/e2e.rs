@@ -0,0 +1,227 @@
+//! End-to-end test of the operator against the cluster of the current kubeconfig,
+//! e.g. a kind cluster, and the fake of the `HCloud` API.
+//!
+//! Run with `cargo test --features e2e --test e2e`.
+#![cfg(feature = "e2e")]
+
+mod common;
+
+use std::{future::Future, time::Duration};
+
+use clap::{CommandFactory, FromArgMatches};
+use common::FakeHcloud;
+use k8s_openapi::{
+    api::core::v1::{Namespace, Node, Service},
+    serde_json::{self, json},
+};
+use kube::{
+    api::{DeleteParams, Patch, PatchParams, PostParams},
+    Api, Client, ResourceExt,
+};
+use robotlb::{config::Cli, consts};
+
+/// How long the operator has to bring a service to the expected state.
+const TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Label of the services created by the test. Only they are managed
+/// by the operator, so services of other tests in the cluster are left alone.
+const SERVICE_LABEL: &str = "robotlb-e2e";
+
+#[tokio::test]
+async fn service_lifecycle() {
+    let fake = FakeHcloud::start().await;
+    let network = fake.add_network("e2e");
+    let client = Client::try_default()
+        .await
+        .expect("kubeconfig of a test cluster, e.g. `kind create cluster`");
+    let namespace = create_namespace(&client).await;
+
+    let args = [
+        "robotlb".to_string(),
+        "--hcloud-token=fake".to_string(),
+        format!("--hcloud-endpoint=http://{}", fake.address()),
+        "--cluster-name=e2e".to_string(),
+        format!("--service-selector={SERVICE_LABEL}=true"),
+        "--metrics-bind-address=127.0.0.1:0".to_string(),
+        "--probes-bind-address=127.0.0.1:0".to_string(),
+    ];
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap();
+    // Nodes are selected by the annotation, since no pods back the services.
+    cli.config.dynamic_node_selector = false;
+
+    tokio::select! {
+        result = robotlb::run(cli, &matches) => panic!("operator has stopped: {result:?}"),
+        () = scenario(&client, &fake, &namespace, network) => {}
+    }
+    Api::<Namespace>::all(client)
+        .delete(&namespace, &DeleteParams::default())
+        .await
+        .unwrap();
+}
+
+/// Create, update and delete a service, checking the balancer
+/// in the fake and the service in the cluster at every step.
+async fn scenario(client: &Client, fake: &FakeHcloud, namespace: &str, network: i64) {
+    let services = Api::<Service>::namespaced(client.clone(), namespace);
+    services
+        .create(&PostParams::default(), &service("ignored", Some("other")))
+        .await
+        .unwrap();
+    services
+        .create(&PostParams::default(), &service("web", None))
+        .await
+        .unwrap();
+
+    // The balancer is created and its IP is published in the status.
+    let hcloud_lb = eventually("balancer is created", || async {
+        let hcloud_lb = fake.load_balancer("web")?;
+        (hcloud_lb.services.len() == 1 && !hcloud_lb.targets.is_empty()).then_some(hcloud_lb)
+    })
+    .await;
+    let svc = eventually("status is updated", || async {
+        let svc = services.get("web").await.ok()?;
+        (!ingress_ips(&svc).is_empty()).then_some(svc)
+    })
+    .await;
+    assert!(svc
+        .finalizers()
+        .contains(&consts::FINALIZER_NAME.to_string()));
+    assert_eq!(
+        svc.annotations().get(consts::LB_ID_ANN_NAME),
+        Some(&hcloud_lb.id.to_string())
+    );
+    assert_eq!(
+        ingress_ips(&svc),
+        [hcloud_lb.public_net.ipv4.ip.clone().flatten().unwrap()]
+    );
+    assert_eq!(hcloud_lb.services[0].listen_port, 80);
+    assert_eq!(hcloud_lb.services[0].destination_port, node_ports(&svc)[0]);
+    assert_eq!(hcloud_lb.private_net[0].network, Some(network));
+    let mut targets = hcloud_lb
+        .targets
+        .iter()
+        .filter_map(|target| target.ip.as_ref().map(|ip| ip.ip.clone()))
+        .collect::<Vec<_>>();
+    targets.sort();
+    assert_eq!(targets, node_ips(client).await);
+
+    // Ports of the service are followed by the balancer.
+    services
+        .patch(
+            "web",
+            &PatchParams::default(),
+            &Patch::Merge(json!({"spec": {"ports": [
+                {"name": "http", "port": 80, "targetPort": 8080, "protocol": "TCP"},
+                {"name": "https", "port": 443, "targetPort": 8443, "protocol": "TCP"},
+            ]}})),
+        )
+        .await
+        .unwrap();
+    eventually("service is added", || async {
+        let hcloud_lb = fake.load_balancer("web")?;
+        (hcloud_lb.services.len() == 2).then_some(())
+    })
+    .await;
+
+    // Services of other classes are never touched.
+    let ignored = services.get("ignored").await.unwrap();
+    assert!(ignored.finalizers().is_empty());
+    assert!(ingress_ips(&ignored).is_empty());
+    assert_eq!(fake.load_balancers().len(), 1);
+
+    // The balancer is deleted before the finalizer lets the service go.
+    services
+        .delete("web", &DeleteParams::default())
+        .await
+        .unwrap();
+    eventually("service is deleted", || async {
+        services.get_opt("web").await.ok()?.is_none().then_some(())
+    })
+    .await;
+    assert!(fake.load_balancers().is_empty());
+}
+
+async fn create_namespace(client: &Client) -> String {
+    let namespace = serde_json::from_value::<Namespace>(json!({
+        "metadata": {"generateName": "robotlb-e2e-"},
+    }))
+    .unwrap();
+    Api::<Namespace>::all(client.clone())
+        .create(&PostParams::default(), &namespace)
+        .await
+        .unwrap()
+        .name_any()
+}
+
+fn service(name: &str, class: Option<&str>) -> Service {
+    serde_json::from_value(json!({
+        "metadata": {
+            "name": name,
+            "labels": {SERVICE_LABEL: "true"},
+            "annotations": {
+                consts::LB_NETWORK_LABEL_NAME: "e2e",
+                consts::LB_NODE_SELECTOR: "kubernetes.io/os=linux",
+            },
+        },
+        "spec": {
+            "type": "LoadBalancer",
+            "loadBalancerClass": class.unwrap_or(consts::ROBOTLB_LB_CLASS),
+            "selector": {"app": name},
+            "ports": [{"name": "http", "port": 80, "targetPort": 8080, "protocol": "TCP"}],
+        },
+    }))
+    .unwrap()
+}
+
+fn ingress_ips(svc: &Service) -> Vec<String> {
+    svc.status
+        .iter()
+        .filter_map(|status| status.load_balancer.as_ref())
+        .flat_map(|load_balancer| load_balancer.ingress.iter().flatten())
+        .filter_map(|ingress| ingress.ip.clone())
+        .collect()
+}
+
+fn node_ports(svc: &Service) -> Vec<i32> {
+    svc.spec
+        .iter()
+        .flat_map(|spec| spec.ports.iter().flatten())
+        .filter_map(|port| port.node_port)
+        .collect()
+}
+
+/// Internal IPv4 addresses of the Linux nodes, sorted.
+async fn node_ips(client: &Client) -> Vec<String> {
+    let nodes = Api::<Node>::all(client.clone())
+        .list(&kube::api::ListParams::default().labels("kubernetes.io/os=linux"))
+        .await
+        .unwrap();
+    let mut ips = nodes
+        .into_iter()
+        .filter_map(|node| node.status?.addresses)
+        .flatten()
+        .filter(|address| address.type_ == "InternalIP" && !address.address.contains(':'))
+        .map(|address| address.address)
+        .collect::<Vec<_>>();
+    ips.sort();
+    ips
+}
+
+/// Poll the check until it returns a value, failing after [`TIMEOUT`].
+async fn eventually<T, F, Fut>(what: &str, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Some(value) = check().await {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting until {what}"))
+}
@@ -0,0 +1,596 @@
+//! Reconcile of load balancers against a fake of the `HCloud` API.
+
+mod common;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+use axum::http::StatusCode;
+use common::FakeHcloud;
+use hcloud::models::{load_balancer_algorithm, LoadBalancerAlgorithm};
+use k8s_openapi::{api::core::v1::Service, apimachinery::pkg::apis::meta::v1::ObjectMeta};
+use robotlb::{
+    consts,
+    error::{ErrorClass, HCloudErrorKind, RobotLBError},
+    faults::{self, Fault},
+    hcloud_api::HcloudClient,
+    lb::{LBChange, LoadBalancer},
+    state::StateStore,
+};
+
+/// Load balancer of the `default/web` service
+/// forwarding port 80 to node port 30080 of two nodes.
+fn web_balancer(fake: &FakeHcloud) -> LoadBalancer {
+    LoadBalancer {
+        name: "web".to_string(),
+        id: None,
+        pending_actions: vec![],
+        namespace: "default".to_string(),
+        service: "web".to_string(),
+        services: HashMap::from([(80, 30080)]),
+        targets: vec!["10.0.1.1".to_string(), "10.0.1.2".to_string()],
+        private_ip: None,
+        check_interval: 15,
+        timeout: 10,
+        retries: 3,
+        proxy_mode: false,
+        location: "hel1".to_string(),
+        balancer_type: "lb11".to_string(),
+        max_balancer_type: None,
+        allowed_types: None,
+        algorithm: LoadBalancerAlgorithm {
+            r#type: load_balancer_algorithm::Type::RoundRobin,
+        },
+        network_name: None,
+        labels: owner_labels("default", "web"),
+        custom_labels: BTreeSet::new(),
+        applied_labels: BTreeSet::new(),
+        resync_interval: None,
+        requested_ip: None,
+        hcloud: Arc::new(HcloudClient::new(fake.config())),
+    }
+}
+
+fn owner_labels(namespace: &str, service: &str) -> HashMap<String, String> {
+    HashMap::from([
+        (
+            consts::LB_CLUSTER_LABEL_NAME.to_string(),
+            "test".to_string(),
+        ),
+        (
+            consts::LB_NAMESPACE_LABEL_NAME.to_string(),
+            namespace.to_string(),
+        ),
+        (
+            consts::LB_SERVICE_LABEL_NAME.to_string(),
+            service.to_string(),
+        ),
+    ])
+}
+
+fn target_ips(hcloud_lb: &hcloud::models::LoadBalancer) -> Vec<String> {
+    let mut ips = hcloud_lb
+        .targets
+        .iter()
+        .filter_map(|target| target.ip.as_ref().map(|ip| ip.ip.clone()))
+        .collect::<Vec<_>>();
+    ips.sort();
+    ips
+}
+
+#[tokio::test]
+async fn creates_balancer_with_services_and_targets() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+
+    let reconciled = lb.reconcile().await.unwrap();
+
+    assert!(reconciled.created);
+    assert!(reconciled.changed);
+    assert_eq!(
+        fake.take_mutations(),
+        [
+            "create_load_balancer",
+            "add_service",
+            "add_target",
+            "add_target"
+        ]
+    );
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(hcloud_lb.labels, owner_labels("default", "web"));
+    assert_eq!(hcloud_lb.services.len(), 1);
+    assert_eq!(hcloud_lb.services[0].listen_port, 80);
+    assert_eq!(hcloud_lb.services[0].destination_port, 30080);
+    assert_eq!(hcloud_lb.services[0].health_check.interval, 15);
+    assert_eq!(target_ips(&hcloud_lb), ["10.0.1.1", "10.0.1.2"]);
+}
+
+#[tokio::test]
+async fn reconcile_of_matching_balancer_changes_nothing() {
+    let fake = FakeHcloud::start().await;
+    web_balancer(&fake).reconcile().await.unwrap();
+    fake.take_calls();
+
+    let mut lb = web_balancer(&fake);
+    assert_eq!(lb.diff().await.unwrap(), []);
+    let reconciled = lb.reconcile().await.unwrap();
+
+    assert!(!reconciled.created);
+    assert!(!reconciled.changed);
+    assert_eq!(fake.take_mutations(), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn updates_services_targets_and_algorithm() {
+    let fake = FakeHcloud::start().await;
+    web_balancer(&fake).reconcile().await.unwrap();
+    fake.take_calls();
+
+    let mut lb = web_balancer(&fake);
+    lb.services = HashMap::from([(80, 30081), (443, 30443)]);
+    lb.targets = vec!["10.0.1.2".to_string(), "10.0.1.3".to_string()];
+    lb.algorithm = LoadBalancerAlgorithm {
+        r#type: load_balancer_algorithm::Type::LeastConnections,
+    };
+
+    assert_eq!(
+        lb.diff().await.unwrap(),
+        [
+            LBChange::ChangeAlgorithm {
+                from: load_balancer_algorithm::Type::RoundRobin,
+                to: load_balancer_algorithm::Type::LeastConnections,
+            },
+            LBChange::UpdateService {
+                listen_port: 80,
+                destination_port: 30081,
+            },
+            LBChange::AddService {
+                listen_port: 443,
+                destination_port: 30443,
+            },
+            LBChange::RemoveTarget {
+                ip: "10.0.1.1".to_string(),
+            },
+            LBChange::AddTarget {
+                ip: "10.0.1.3".to_string(),
+            },
+        ]
+    );
+    assert_eq!(fake.take_mutations(), Vec::<String>::new());
+
+    let reconciled = lb.reconcile().await.unwrap();
+
+    assert!(!reconciled.created);
+    assert!(reconciled.changed);
+    assert_eq!(
+        fake.take_mutations(),
+        [
+            "change_algorithm",
+            "update_service",
+            "add_service",
+            "remove_target",
+            "add_target",
+        ]
+    );
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(
+        hcloud_lb.algorithm.r#type,
+        load_balancer_algorithm::Type::LeastConnections
+    );
+    let services = hcloud_lb
+        .services
+        .iter()
+        .map(|service| (service.listen_port, service.destination_port))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(services, BTreeMap::from([(80, 30081), (443, 30443)]));
+    assert_eq!(target_ips(&hcloud_lb), ["10.0.1.2", "10.0.1.3"]);
+}
+
+#[tokio::test]
+async fn fetches_recorded_balancer_by_id() {
+    let fake = FakeHcloud::start().await;
+    let id = web_balancer(&fake).reconcile().await.unwrap().hcloud_lb.id;
+    fake.take_calls();
+
+    let mut lb = web_balancer(&fake);
+    lb.id = Some(id);
+    let reconciled = lb.reconcile().await.unwrap();
+
+    assert!(!reconciled.created);
+    let calls = fake.take_calls();
+    assert!(calls.contains(&"get_load_balancer".to_string()));
+    assert!(!calls.contains(&"list_load_balancers".to_string()));
+}
+
+#[tokio::test]
+async fn stale_recorded_id_falls_back_to_name() {
+    let fake = FakeHcloud::start().await;
+    web_balancer(&fake).reconcile().await.unwrap();
+    let other = fake.add_load_balancer("api", owner_labels("default", "api"));
+
+    // The recorded balancer was deleted or belongs to another name by now.
+    for id in [other + 100, other] {
+        let mut lb = web_balancer(&fake);
+        lb.id = Some(id);
+        let reconciled = lb.reconcile().await.unwrap();
+
+        assert!(!reconciled.created);
+        assert_eq!(reconciled.hcloud_lb.name, "web");
+    }
+    assert_eq!(fake.load_balancers().len(), 2);
+}
+
+#[tokio::test]
+async fn waits_for_actions_of_previous_reconcile() {
+    let fake = FakeHcloud::start().await;
+    fake.add_network("private");
+    fake.hold_actions(true);
+    let mut lb = web_balancer(&fake);
+    lb.network_name = Some("private".to_string());
+    lb.reconcile().await.unwrap();
+    // The create and the attach are still running, e.g. when the operator restarts.
+    assert_eq!(lb.pending_actions.len(), 2);
+    fake.take_calls();
+
+    let mut lb = LoadBalancer {
+        pending_actions: lb.pending_actions,
+        network_name: Some("private".to_string()),
+        ..web_balancer(&fake)
+    };
+    let err = lb.reconcile().await.unwrap_err();
+
+    assert!(matches!(err.root(), RobotLBError::ActionInProgress(_)));
+    assert_eq!(lb.pending_actions.len(), 2);
+    assert_eq!(fake.take_mutations(), Vec::<String>::new());
+
+    fake.hold_actions(false);
+    let reconciled = lb.reconcile().await.unwrap();
+
+    assert!(!reconciled.changed);
+    assert!(lb.pending_actions.is_empty());
+}
+
+#[tokio::test]
+async fn removes_services_no_longer_desired() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.services = HashMap::from([(80, 30080), (443, 30443)]);
+    lb.reconcile().await.unwrap();
+    fake.take_calls();
+
+    web_balancer(&fake).reconcile().await.unwrap();
+
+    assert_eq!(fake.take_mutations(), ["delete_service"]);
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(hcloud_lb.services.len(), 1);
+    assert_eq!(hcloud_lb.services[0].listen_port, 80);
+}
+
+#[tokio::test]
+async fn adopts_balancer_without_owner_labels() {
+    let fake = FakeHcloud::start().await;
+    fake.add_load_balancer(
+        "web",
+        HashMap::from([("team".to_string(), "a".to_string())]),
+    );
+
+    let reconciled = web_balancer(&fake).reconcile().await.unwrap();
+
+    assert!(!reconciled.created);
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    let mut labels = owner_labels("default", "web");
+    labels.insert("team".to_string(), "a".to_string());
+    assert_eq!(hcloud_lb.labels, labels);
+    assert_eq!(fake.load_balancers().len(), 1);
+}
+
+#[tokio::test]
+async fn removes_custom_labels_no_longer_requested() {
+    let fake = FakeHcloud::start().await;
+    let mut labels = owner_labels("default", "web");
+    labels.extend([
+        ("team".to_string(), "a".to_string()),
+        ("env".to_string(), "prod".to_string()),
+        ("manual".to_string(), "x".to_string()),
+    ]);
+    fake.add_load_balancer("web", labels);
+    // `env` was dropped from the annotation, `manual` was set by hand.
+    let mut lb = web_balancer(&fake);
+    lb.labels.insert("team".to_string(), "a".to_string());
+    lb.custom_labels = BTreeSet::from(["team".to_string()]);
+    lb.applied_labels = BTreeSet::from(["team".to_string(), "env".to_string()]);
+
+    lb.reconcile().await.unwrap();
+
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    let mut labels = owner_labels("default", "web");
+    labels.extend([
+        ("team".to_string(), "a".to_string()),
+        ("manual".to_string(), "x".to_string()),
+    ]);
+    assert_eq!(hcloud_lb.labels, labels);
+}
+
+#[tokio::test]
+async fn attaches_and_moves_balancer_between_networks() {
+    let fake = FakeHcloud::start().await;
+    let first = fake.add_network("first");
+    let second = fake.add_network("second");
+
+    let mut lb = web_balancer(&fake);
+    lb.network_name = Some("first".to_string());
+    lb.private_ip = Some("10.0.0.5".to_string());
+    assert_eq!(
+        lb.diff().await.unwrap()[..2],
+        [
+            LBChange::Create,
+            LBChange::AttachNetwork {
+                network: first,
+                ip: Some("10.0.0.5".to_string()),
+            },
+        ]
+    );
+    lb.reconcile().await.unwrap();
+
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(hcloud_lb.private_net.len(), 1);
+    assert_eq!(hcloud_lb.private_net[0].network, Some(first));
+    assert_eq!(hcloud_lb.private_net[0].ip.as_deref(), Some("10.0.0.5"));
+    fake.take_calls();
+
+    let mut lb = web_balancer(&fake);
+    lb.network_name = Some("second".to_string());
+    lb.reconcile().await.unwrap();
+
+    assert_eq!(
+        fake.take_mutations(),
+        [
+            "detach_load_balancer_from_network",
+            "attach_load_balancer_to_network",
+        ]
+    );
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(hcloud_lb.private_net.len(), 1);
+    assert_eq!(hcloud_lb.private_net[0].network, Some(second));
+
+    web_balancer(&fake).reconcile().await.unwrap();
+
+    assert_eq!(fake.take_mutations(), ["detach_load_balancer_from_network"]);
+    assert!(fake.load_balancer("web").unwrap().private_net.is_empty());
+}
+
+#[tokio::test]
+async fn missing_network_fails_reconcile() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.network_name = Some("missing".to_string());
+
+    let err = lb.reconcile().await.unwrap_err();
+
+    assert_eq!(err.hcloud_kind(), Some(HCloudErrorKind::NotFound));
+    assert!(err.to_string().contains("service default/web"));
+    assert!(fake.load_balancers().is_empty());
+}
+
+#[tokio::test]
+async fn target_outside_network_is_skipped() {
+    let fake = FakeHcloud::start().await;
+    fake.add_network("private");
+    let mut lb = web_balancer(&fake);
+    lb.network_name = Some("private".to_string());
+    lb.targets = vec![
+        "10.0.1.1".to_string(),
+        "192.168.0.1".to_string(),
+        "2001:db8::1".to_string(),
+    ];
+
+    let reconciled = lb.reconcile().await.unwrap();
+
+    assert_eq!(reconciled.invalid_targets.len(), 2);
+    assert!(reconciled.invalid_targets[0].starts_with("192.168.0.1: outside of the network range"));
+    assert!(reconciled.invalid_targets[1].starts_with("2001:db8::1: outside of the network range"));
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(target_ips(&hcloud_lb), ["10.0.1.1"]);
+    assert_eq!(hcloud_lb.services.len(), 1);
+}
+
+#[tokio::test]
+async fn cleanup_deletes_services_targets_and_balancer() {
+    let fake = FakeHcloud::start().await;
+    web_balancer(&fake).reconcile().await.unwrap();
+    fake.take_calls();
+
+    assert!(web_balancer(&fake).cleanup().await.unwrap());
+
+    assert_eq!(
+        fake.take_mutations(),
+        [
+            "delete_service",
+            "remove_target",
+            "remove_target",
+            "delete_load_balancer",
+        ]
+    );
+    assert!(fake.load_balancers().is_empty());
+    assert!(!web_balancer(&fake).cleanup().await.unwrap());
+}
+
+#[tokio::test]
+async fn balancer_of_another_service_is_left_intact() {
+    let fake = FakeHcloud::start().await;
+    fake.add_load_balancer("web", owner_labels("other", "web"));
+
+    let err = web_balancer(&fake).reconcile().await.unwrap_err();
+    assert!(matches!(err.root(), RobotLBError::LBNameConflict { .. }));
+    assert!(!web_balancer(&fake).cleanup().await.unwrap());
+
+    assert_eq!(fake.take_mutations(), Vec::<String>::new());
+    assert!(fake.load_balancer("web").is_some());
+}
+
+#[tokio::test]
+async fn balancer_of_another_cluster_is_left_intact() {
+    let fake = FakeHcloud::start().await;
+    let mut labels = owner_labels("default", "web");
+    labels.insert(
+        consts::LB_CLUSTER_LABEL_NAME.to_string(),
+        "other".to_string(),
+    );
+    fake.add_load_balancer("web", labels);
+
+    let err = web_balancer(&fake).reconcile().await.unwrap_err();
+    assert!(matches!(
+        err.root(),
+        RobotLBError::LBNameConflict { cluster, .. } if cluster == "other"
+    ));
+    assert!(!web_balancer(&fake).cleanup().await.unwrap());
+
+    assert_eq!(fake.take_mutations(), Vec::<String>::new());
+    assert!(fake.load_balancer("web").is_some());
+}
+
+#[tokio::test]
+async fn unknown_location_is_reported_before_creating() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.location = "nowhere".to_string();
+
+    let err = lb.reconcile().await.unwrap_err();
+
+    assert!(matches!(err.root(), RobotLBError::UnknownLocation(_)));
+    assert!(err.to_string().contains("hel1 (Helsinki)"));
+    assert_eq!(fake.take_mutations(), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn upgrades_type_when_targets_do_not_fit() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.targets = (1..=30).map(|i| format!("10.0.1.{i}")).collect();
+    lb.max_balancer_type = Some("lb31".to_string());
+
+    lb.reconcile().await.unwrap();
+
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(hcloud_lb.load_balancer_type.name, "lb21");
+    assert_eq!(hcloud_lb.targets.len(), 30);
+}
+
+#[tokio::test]
+async fn upgrades_only_to_types_allowed_by_policies() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.targets = (1..=30).map(|i| format!("10.0.1.{i}")).collect();
+    lb.max_balancer_type = Some("lb31".to_string());
+    lb.allowed_types = Some(BTreeSet::from(["lb11".to_string(), "lb31".to_string()]));
+
+    lb.reconcile().await.unwrap();
+
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert_eq!(hcloud_lb.load_balancer_type.name, "lb31");
+}
+
+#[tokio::test]
+async fn policies_prevent_upgrades_beyond_allowed_types() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.targets = (1..=30).map(|i| format!("10.0.1.{i}")).collect();
+    lb.max_balancer_type = Some("lb31".to_string());
+    lb.allowed_types = Some(BTreeSet::from(["lb11".to_string()]));
+
+    let err = lb.reconcile().await.unwrap_err();
+
+    assert!(matches!(err.root(), RobotLBError::LimitExceeded(_)));
+    assert!(fake.load_balancers().is_empty());
+}
+
+#[tokio::test]
+async fn failed_calls_are_classified_by_status() {
+    let fake = FakeHcloud::start().await;
+    for (status, kind, class) in [
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            HCloudErrorKind::RateLimited,
+            ErrorClass::RateLimited,
+        ),
+        (
+            StatusCode::LOCKED,
+            HCloudErrorKind::Conflict,
+            ErrorClass::Transient,
+        ),
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            HCloudErrorKind::ServerError,
+            ErrorClass::Transient,
+        ),
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            HCloudErrorKind::InvalidInput,
+            ErrorClass::Permanent,
+        ),
+    ] {
+        fake.fail("create_load_balancer", status);
+
+        let err = web_balancer(&fake).reconcile().await.unwrap_err();
+
+        assert_eq!(err.hcloud_kind(), Some(kind), "status {status}");
+        assert_eq!(err.class(), class, "status {status}");
+    }
+    assert!(fake.load_balancers().is_empty());
+}
+
+#[tokio::test]
+async fn injected_faults_fail_calls_with_server_errors() {
+    let fake = FakeHcloud::start().await;
+    let mut lb = web_balancer(&fake);
+    lb.hcloud = faults::wrap_hcloud(&[Fault::HCloudErrors(1.0)], lb.hcloud);
+
+    let err = lb.reconcile().await.unwrap_err();
+
+    assert_eq!(err.hcloud_kind(), Some(HCloudErrorKind::ServerError));
+    assert!(fake.load_balancers().is_empty());
+}
+
+#[tokio::test]
+async fn failed_service_leaves_targets_untouched() {
+    let fake = FakeHcloud::start().await;
+    fake.fail("add_service", StatusCode::CONFLICT);
+
+    let err = web_balancer(&fake).reconcile().await.unwrap_err();
+
+    assert_eq!(err.hcloud_kind(), Some(HCloudErrorKind::Conflict));
+    let hcloud_lb = fake.load_balancer("web").unwrap();
+    assert!(hcloud_lb.services.is_empty());
+    assert!(hcloud_lb.targets.is_empty());
+}
+
+#[tokio::test]
+async fn converged_balancer_is_checked_again_once_targets_change() {
+    let fake = FakeHcloud::start().await;
+    let state = StateStore::default();
+    let svc = Service {
+        metadata: ObjectMeta {
+            name: Some("web".to_string()),
+            namespace: Some("default".to_string()),
+            resource_version: Some("1".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut lb = web_balancer(&fake);
+    let reconciled = lb.reconcile().await.unwrap();
+    state.record_lb(&svc, &lb, &reconciled.hcloud_lb);
+    state.record_converged(&svc, true);
+
+    let max_age = Duration::from_secs(600);
+    assert!(state.is_converged(&svc, &web_balancer(&fake), max_age));
+    assert!(!state.is_converged(&svc, &web_balancer(&fake), Duration::ZERO));
+    lb.targets.push("10.0.0.3".to_string());
+    assert!(!state.is_converged(&svc, &lb, max_age));
+    let mut changed_svc = svc.clone();
+    changed_svc.metadata.resource_version = Some("2".to_string());
+    assert!(!state.is_converged(&changed_svc, &web_balancer(&fake), max_age));
+}